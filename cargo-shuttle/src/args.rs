@@ -7,10 +7,15 @@ use std::{
 
 use anyhow::Context;
 use cargo_metadata::MetadataCommand;
+use chrono::{DateTime, Duration, Utc};
 use clap::builder::{OsStringValueParser, PossibleValue, TypedValueParser};
 use clap::Parser;
 use clap_complete::Shell;
-use shuttle_common::{models::project::IDLE_MINUTES, project::ProjectName};
+use shuttle_common::{
+    log,
+    models::project::{Limits, IDLE_MINUTES},
+    project::ProjectName,
+};
 use uuid::Uuid;
 
 use crate::init::Framework;
@@ -116,13 +121,21 @@ pub enum Command {
         #[arg(short, long)]
         /// Follow log output
         follow: bool,
+        #[command(flatten)]
+        filter: LogsFilterArgs,
+        #[arg(long)]
+        /// Output every log line as a JSON object
+        json: bool,
     },
     /// Remove cargo build artifacts in the shuttle environment
     Clean,
     /// Stop this shuttle service
     Stop,
     /// Manage secrets for this shuttle service
-    Secrets,
+    Secrets {
+        #[command(subcommand)]
+        cmd: Option<SecretsCommand>,
+    },
     /// Login to the shuttle platform
     Login(LoginArgs),
     /// Log out of the shuttle platform
@@ -134,6 +147,18 @@ pub enum Command {
     /// List or manage projects on shuttle
     #[command(subcommand)]
     Project(ProjectCommand),
+    /// Manage the custom domains of this project
+    #[command(subcommand)]
+    Domain(DomainCommand),
+    /// Manage the public port raw TCP connections to this project come in on
+    #[command(subcommand)]
+    Tcp(TcpCommand),
+    /// View the traffic to this project over the last hour
+    Stats {
+        #[arg(long)]
+        /// Show the traffic over the last day instead
+        day: bool,
+    },
 }
 
 #[derive(Parser)]
@@ -145,6 +170,47 @@ pub enum DeploymentCommand {
         /// ID of deployment to get status for
         id: Uuid,
     },
+    /// Restart a previous deployment from its stored build
+    Rollback {
+        /// ID of deployment to roll back to. Defaults to the most recent stopped deployment
+        id: Option<Uuid>,
+    },
+}
+
+#[derive(Parser)]
+pub enum SecretsCommand {
+    /// List the secrets of this service (the default)
+    List,
+    /// Add or update secrets for this service
+    Set {
+        /// Secrets to set in the form KEY=VALUE
+        #[arg(required = true, value_parser = parse_secret)]
+        secrets: Vec<(String, String)>,
+        #[command(flatten)]
+        restart: SecretsRestartArgs,
+    },
+    /// Remove secrets from this service
+    Unset {
+        /// Keys of the secrets to remove
+        #[arg(required = true)]
+        keys: Vec<String>,
+        #[command(flatten)]
+        restart: SecretsRestartArgs,
+    },
+    /// Add or update all the secrets in a TOML file, such as Secrets.toml
+    Import {
+        /// Path to the secrets file
+        file: PathBuf,
+        #[command(flatten)]
+        restart: SecretsRestartArgs,
+    },
+}
+
+#[derive(Parser, Debug)]
+pub struct SecretsRestartArgs {
+    /// Restart the running deployment so that it loads the updated secrets
+    #[arg(long)]
+    pub restart: bool,
 }
 
 #[derive(Parser)]
@@ -153,6 +219,39 @@ pub enum ResourceCommand {
     List,
 }
 
+#[derive(Parser)]
+pub enum DomainCommand {
+    /// Add a custom domain to this project and issue a certificate for it. The domain needs to
+    /// point to the shuttle address of the project already, with a CNAME record for example
+    Add {
+        /// The fully qualified domain name, such as api.example.com
+        fqdn: String,
+    },
+    /// List the custom domains of this project
+    List,
+    /// Remove a custom domain from this project
+    Remove {
+        /// The fully qualified domain name to remove
+        fqdn: String,
+    },
+    /// Renew the certificate of a custom domain when it expires soon
+    Renew {
+        /// The fully qualified domain name to renew the certificate of
+        fqdn: String,
+    },
+}
+
+#[derive(Parser)]
+pub enum TcpCommand {
+    /// Get a public port for the raw TCP connections to this project. The service accepting them
+    /// needs a `[tcp]` table in its Shuttle.toml
+    Open,
+    /// Show the public port of this project
+    Status,
+    /// Give up the public port of this project
+    Close,
+}
+
 #[derive(Parser)]
 pub enum ProjectCommand {
     /// Create an environment for this project on shuttle
@@ -160,6 +259,8 @@ pub enum ProjectCommand {
         #[arg(long, default_value_t = IDLE_MINUTES)]
         /// How long to wait before putting the project in an idle state due to inactivity. 0 means the project will never idle
         idle_minutes: u64,
+        #[command(flatten)]
+        limits: ProjectLimitsArgs,
     },
     /// Check the status of this project's environment on shuttle
     Status {
@@ -174,11 +275,36 @@ pub enum ProjectCommand {
         #[arg(long, default_value_t = IDLE_MINUTES)]
         /// How long to wait before putting the project in an idle state due to inactivity. 0 means the project will never idle
         idle_minutes: u64,
+        #[command(flatten)]
+        limits: ProjectLimitsArgs,
     },
     /// List all projects belonging to the calling account
     List,
 }
 
+#[derive(Parser, Clone, Copy, Debug)]
+pub struct ProjectLimitsArgs {
+    /// Requests per second to allow from a single client IP, with bursts of up to this many
+    #[arg(long)]
+    pub requests_per_second: Option<u32>,
+    /// Largest request body to allow, in bytes
+    #[arg(long)]
+    pub max_body_bytes: Option<u64>,
+    /// Requests to allow in flight to the project at the same time
+    #[arg(long)]
+    pub max_connections: Option<u32>,
+}
+
+impl From<ProjectLimitsArgs> for Limits {
+    fn from(args: ProjectLimitsArgs) -> Self {
+        Self {
+            requests_per_second: args.requests_per_second,
+            max_body_bytes: args.max_body_bytes,
+            max_connections: args.max_connections,
+        }
+    }
+}
+
 #[derive(Parser, Clone, Debug)]
 pub struct LoginArgs {
     /// API key for the shuttle platform
@@ -196,6 +322,41 @@ pub struct DeployArgs {
     pub no_test: bool,
 }
 
+#[derive(Parser, Debug)]
+pub struct LogsFilterArgs {
+    /// Only show logs at this level or a more severe one
+    #[arg(long)]
+    pub level: Option<log::Level>,
+    /// Only show logs with a target starting with this, like `my_crate::handlers`
+    #[arg(long)]
+    pub target: Option<String>,
+    /// Only show logs from this moment onwards, either a duration ago (30s, 15m, 1h, 2d) or an RFC 3339 timestamp
+    #[arg(long, value_parser = parse_time)]
+    pub since: Option<DateTime<Utc>>,
+    /// Only show logs up to this moment, either a duration ago (30s, 15m, 1h, 2d) or an RFC 3339 timestamp
+    #[arg(long, value_parser = parse_time)]
+    pub until: Option<DateTime<Utc>>,
+    /// Only show logs with a message containing this
+    #[arg(long)]
+    pub search: Option<String>,
+    /// Match the `--search` text as a regular expression
+    #[arg(long, requires = "search")]
+    pub regex: bool,
+}
+
+impl From<LogsFilterArgs> for log::Query {
+    fn from(args: LogsFilterArgs) -> Self {
+        Self {
+            level: args.level,
+            target: args.target,
+            since: args.since,
+            until: args.until,
+            search: args.search,
+            regex: args.regex,
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
 pub struct RunArgs {
     /// Port to start service on
@@ -212,40 +373,43 @@ pub struct RunArgs {
 #[derive(Parser, Debug)]
 pub struct InitArgs {
     /// Initialize with actix-web framework
-    #[arg(long="actix_web", conflicts_with_all = &["axum", "rocket", "tide", "tower", "poem", "serenity", "poise", "warp", "salvo", "thruster", "no_framework"])]
+    #[arg(long="actix_web", conflicts_with_all = &["axum", "rocket", "tide", "tower", "poem", "serenity", "poise", "warp", "salvo", "thruster", "tonic", "no_framework"])]
     pub actix_web: bool,
     /// Initialize with axum framework
-    #[arg(long, conflicts_with_all = &["actix_web","rocket", "tide", "tower", "poem", "serenity", "poise", "warp", "salvo", "thruster", "no_framework"])]
+    #[arg(long, conflicts_with_all = &["actix_web","rocket", "tide", "tower", "poem", "serenity", "poise", "warp", "salvo", "thruster", "tonic", "no_framework"])]
     pub axum: bool,
     /// Initialize with rocket framework
-    #[arg(long, conflicts_with_all = &["actix_web","axum", "tide", "tower", "poem", "serenity", "poise", "warp", "salvo", "thruster", "no_framework"])]
+    #[arg(long, conflicts_with_all = &["actix_web","axum", "tide", "tower", "poem", "serenity", "poise", "warp", "salvo", "thruster", "tonic", "no_framework"])]
     pub rocket: bool,
     /// Initialize with tide framework
-    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tower", "poem", "serenity", "poise", "warp", "salvo", "thruster", "no_framework"])]
+    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tower", "poem", "serenity", "poise", "warp", "salvo", "thruster", "tonic", "no_framework"])]
     pub tide: bool,
     /// Initialize with tower framework
-    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "poem", "serenity", "poise", "warp", "salvo", "thruster", "no_framework"])]
+    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "poem", "serenity", "poise", "warp", "salvo", "thruster", "tonic", "no_framework"])]
     pub tower: bool,
     /// Initialize with poem framework
-    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "tower", "serenity", "poise", "warp", "salvo", "thruster", "no_framework"])]
+    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "tower", "serenity", "poise", "warp", "salvo", "thruster", "tonic", "no_framework"])]
     pub poem: bool,
     /// Initialize with salvo framework
-    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "tower", "poem", "warp", "serenity", "poise", "thruster", "no_framework"])]
+    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "tower", "poem", "warp", "serenity", "poise", "thruster", "tonic", "no_framework"])]
     pub salvo: bool,
     /// Initialize with serenity framework
-    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "tower", "poem", "warp", "poise", "salvo", "thruster", "no_framework"])]
+    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "tower", "poem", "warp", "poise", "salvo", "thruster", "tonic", "no_framework"])]
     pub serenity: bool,
     /// Initialize with poise framework
-    #[clap(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "tower", "poem", "warp", "serenity", "salvo", "thruster", "no_framework"])]
+    #[clap(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "tower", "poem", "warp", "serenity", "salvo", "thruster", "tonic", "no_framework"])]
     pub poise: bool,
     /// Initialize with warp framework
-    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "tower", "poem", "serenity", "poise", "salvo", "thruster", "no_framework"])]
+    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "tower", "poem", "serenity", "poise", "salvo", "thruster", "tonic", "no_framework"])]
     pub warp: bool,
     /// Initialize with thruster framework
-    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "tower", "poem", "warp", "salvo", "serenity", "poise", "no_framework"])]
+    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "tower", "poem", "warp", "salvo", "serenity", "poise", "tonic", "no_framework"])]
     pub thruster: bool,
+    /// Initialize with tonic framework
+    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "tower", "poem", "warp", "salvo", "serenity", "poise", "thruster", "no_framework"])]
+    pub tonic: bool,
     /// Initialize without a framework
-    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "tower", "poem", "warp", "salvo", "serenity", "poise", "thruster"])]
+    #[arg(long, conflicts_with_all = &["actix_web","axum", "rocket", "tide", "tower", "poem", "warp", "salvo", "serenity", "poise", "thruster", "tonic"])]
     pub no_framework: bool,
     /// Whether to create the environment for this project on shuttle
     #[arg(long)]
@@ -281,6 +445,8 @@ impl InitArgs {
             Some(Framework::Warp)
         } else if self.thruster {
             Some(Framework::Thruster)
+        } else if self.tonic {
+            Some(Framework::Tonic)
         } else if self.no_framework {
             Some(Framework::None)
         } else {
@@ -289,6 +455,38 @@ impl InitArgs {
     }
 }
 
+// Helper function to parse a secret in the KEY=VALUE format
+fn parse_secret(secret: &str) -> Result<(String, String), String> {
+    match secret.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("invalid secret {secret:?}, expected KEY=VALUE")),
+    }
+}
+
+// Helper function to parse a moment either as a duration before now or as an RFC 3339 timestamp
+fn parse_time(time: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(time) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+
+    let error =
+        || format!("invalid time {time:?}, expected a duration like 15m or an RFC 3339 timestamp");
+
+    let split = time.find(|c: char| !c.is_ascii_digit()).ok_or_else(error)?;
+    let (amount, unit) = time.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| error())?;
+
+    let duration = match unit {
+        "s" => Duration::seconds(amount),
+        "m" => Duration::minutes(amount),
+        "h" => Duration::hours(amount),
+        "d" => Duration::days(amount),
+        _ => return Err(error()),
+    };
+
+    Ok(Utc::now() - duration)
+}
+
 // Helper function to parse and return the absolute path
 fn parse_path(path: OsString) -> Result<PathBuf, String> {
     dunce::canonicalize(&path).map_err(|e| format!("could not turn {path:?} into a real path: {e}"))
@@ -328,6 +526,7 @@ mod tests {
             poise: false,
             warp: false,
             thruster: false,
+            tonic: false,
             no_framework: false,
             new: false,
             login_args: LoginArgs { api_key: None },
@@ -346,6 +545,7 @@ mod tests {
             "poise" => init_args.poise = true,
             "warp" => init_args.warp = true,
             "thruster" => init_args.thruster = true,
+            "tonic" => init_args.tonic = true,
             "none" => init_args.no_framework = true,
             _ => unreachable!(),
         }
@@ -361,6 +561,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn secret_parsing() {
+        assert_eq!(
+            parse_secret("API_KEY=abc=123").unwrap(),
+            ("API_KEY".to_string(), "abc=123".to_string())
+        );
+        assert_eq!(
+            parse_secret("EMPTY=").unwrap(),
+            ("EMPTY".to_string(), String::new())
+        );
+        assert!(parse_secret("NO_VALUE").is_err());
+        assert!(parse_secret("=value").is_err());
+    }
+
+    #[test]
+    fn time_parsing() {
+        assert_eq!(
+            parse_time("2023-04-25T04:43:33Z").unwrap(),
+            DateTime::parse_from_rfc3339("2023-04-25T04:43:33+00:00").unwrap()
+        );
+
+        let ago = Utc::now() - parse_time("90m").unwrap();
+        assert!(ago >= Duration::minutes(90) && ago < Duration::minutes(91));
+
+        assert!(parse_time("15").is_err());
+        assert!(parse_time("m").is_err());
+        assert!(parse_time("2w").is_err());
+        assert!(parse_time("yesterday").is_err());
+    }
+
     #[test]
     fn workspace_path() {
         let project_args = ProjectArgs {
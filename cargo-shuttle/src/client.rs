@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fmt::Write;
 
 use anyhow::{Context, Result};
@@ -7,13 +8,14 @@ use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, RequestBuilder};
 use reqwest_retry::policies::ExponentialBackoff;
 use reqwest_retry::RetryTransientMiddleware;
 use serde::{Deserialize, Serialize};
-use shuttle_common::models::{deployment, project, secret, service, ToJson};
+use shuttle_common::models::{deployment, domain, project, secret, service, stats, tcp, ToJson};
 use shuttle_common::project::ProjectName;
-use shuttle_common::{resource, ApiKey, ApiUrl, LogItem};
+use shuttle_common::{log, resource, ApiKey, ApiUrl};
 use tokio::net::TcpStream;
 use tokio_tungstenite::tungstenite::client::IntoClientRequest;
 use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
 use tracing::error;
+use url::form_urlencoded;
 use uuid::Uuid;
 
 pub struct Client {
@@ -140,6 +142,74 @@ impl Client {
         self.delete(path).await
     }
 
+    pub async fn get_domains(&self, project: &ProjectName) -> Result<Vec<domain::Response>> {
+        let path = format!("/domains/{}", project.as_str());
+
+        self.get(path).await
+    }
+
+    pub async fn get_traffic(
+        &self,
+        project: &ProjectName,
+        period: stats::TrafficPeriod,
+    ) -> Result<stats::TrafficResponse> {
+        let path = format!("/traffic/{}?period={period}", project.as_str());
+
+        self.get(path).await
+    }
+
+    pub async fn add_domain(&self, project: &ProjectName, fqdn: &str) -> Result<domain::Response> {
+        let path = format!("/domains/{}/{}", project.as_str(), fqdn);
+
+        self.post(path, Option::<String>::None)
+            .await
+            .context("failed to make add domain request")?
+            .to_json()
+            .await
+    }
+
+    pub async fn remove_domain(
+        &self,
+        project: &ProjectName,
+        fqdn: &str,
+    ) -> Result<domain::Response> {
+        let path = format!("/domains/{}/{}", project.as_str(), fqdn);
+
+        self.delete(path).await
+    }
+
+    pub async fn renew_domain(&self, project: &ProjectName, fqdn: &str) -> Result<String> {
+        let path = format!("/domains/{}/{}/renew", project.as_str(), fqdn);
+
+        self.post(path, Option::<String>::None)
+            .await
+            .context("failed to make renew domain request")?
+            .to_json()
+            .await
+    }
+
+    pub async fn get_tcp(&self, project: &ProjectName) -> Result<tcp::Response> {
+        let path = format!("/tcp/{}", project.as_str());
+
+        self.get(path).await
+    }
+
+    pub async fn open_tcp(&self, project: &ProjectName) -> Result<tcp::Response> {
+        let path = format!("/tcp/{}", project.as_str());
+
+        self.post(path, Option::<String>::None)
+            .await
+            .context("failed to make open tcp request")?
+            .to_json()
+            .await
+    }
+
+    pub async fn close_tcp(&self, project: &ProjectName) -> Result<tcp::Response> {
+        let path = format!("/tcp/{}", project.as_str());
+
+        self.delete(path).await
+    }
+
     pub async fn get_secrets(&self, project: &ProjectName) -> Result<Vec<secret::Response>> {
         let path = format!(
             "/projects/{}/secrets/{}",
@@ -150,17 +220,70 @@ impl Client {
         self.get(path).await
     }
 
-    pub async fn get_logs(
+    pub async fn set_secrets(
+        &self,
+        project: &ProjectName,
+        secrets: &BTreeMap<String, String>,
+        restart: bool,
+    ) -> Result<Vec<secret::Response>> {
+        let mut path = format!(
+            "/projects/{}/secrets/{}",
+            project.as_str(),
+            project.as_str()
+        );
+
+        if restart {
+            let _ = write!(path, "?restart");
+        }
+
+        self.post(path, Some(secrets))
+            .await
+            .context("failed to make set secrets request")?
+            .to_json()
+            .await
+    }
+
+    pub async fn delete_secret(
+        &self,
+        project: &ProjectName,
+        key: &str,
+        restart: bool,
+    ) -> Result<Vec<secret::Response>> {
+        let mut path = format!(
+            "/projects/{}/secrets/{}/{}",
+            project.as_str(),
+            project.as_str(),
+            path_segment(key)
+        );
+
+        if restart {
+            let _ = write!(path, "?restart");
+        }
+
+        self.delete(path).await
+    }
+
+    pub async fn get_logs_page(
         &self,
         project: &ProjectName,
         deployment_id: &Uuid,
-    ) -> Result<Vec<LogItem>> {
-        let path = format!(
-            "/projects/{}/deployments/{}/logs",
+        query: &log::Query,
+        cursor: Option<&log::Cursor>,
+    ) -> Result<log::Page> {
+        let mut path = format!(
+            "/projects/{}/deployments/{}/logs/page{}",
             project.as_str(),
-            deployment_id
+            deployment_id,
+            log_query_string(query)
         );
 
+        if let Some(cursor) = cursor {
+            let separator = if path.contains('?') { '&' } else { '?' };
+            let cursor: String =
+                form_urlencoded::byte_serialize(cursor.to_string().as_bytes()).collect();
+            let _ = write!(path, "{separator}cursor={cursor}");
+        }
+
         self.get(path).await
     }
 
@@ -168,11 +291,13 @@ impl Client {
         &self,
         project: &ProjectName,
         deployment_id: &Uuid,
+        query: &log::Query,
     ) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
         let path = format!(
-            "/projects/{}/ws/deployments/{}/logs",
+            "/projects/{}/ws/deployments/{}/logs{}",
             project.as_str(),
-            deployment_id
+            deployment_id,
+            log_query_string(query)
         );
 
         self.ws_get(path).await
@@ -201,6 +326,24 @@ impl Client {
         self.get(path).await
     }
 
+    pub async fn rollback_deployment(
+        &self,
+        project: &ProjectName,
+        deployment_id: &Uuid,
+    ) -> Result<deployment::Response> {
+        let path = format!(
+            "/projects/{}/deployments/{}/rollback",
+            project.as_str(),
+            deployment_id
+        );
+
+        self.post(path, Option::<String>::None)
+            .await
+            .context("failed to make rollback request")?
+            .to_json()
+            .await
+    }
+
     async fn ws_get(&self, path: String) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
         let ws_scheme = self.api_url.clone().replace("http", "ws");
         let url = format!("{ws_scheme}{path}");
@@ -287,3 +430,56 @@ impl Client {
             .build()
     }
 }
+
+/// Percent-encode a value to use as a single segment of a path, so characters such as `/`, `?` and
+/// `#` stay part of it
+fn path_segment(value: &str) -> String {
+    // Form encoding turns spaces into `+`, which only means a space in a query string
+    form_urlencoded::byte_serialize(value.as_bytes())
+        .map(|part| if part == "+" { "%20" } else { part })
+        .collect()
+}
+
+/// Turn the log filters into a query string, which is empty when there are no filters
+fn log_query_string(query: &log::Query) -> String {
+    let mut serializer = form_urlencoded::Serializer::new(String::new());
+
+    if let Some(level) = &query.level {
+        serializer.append_pair("level", &level.to_string());
+    }
+    if let Some(target) = &query.target {
+        serializer.append_pair("target", target);
+    }
+    if let Some(since) = &query.since {
+        serializer.append_pair("since", &since.to_rfc3339());
+    }
+    if let Some(until) = &query.until {
+        serializer.append_pair("until", &until.to_rfc3339());
+    }
+    if let Some(search) = &query.search {
+        serializer.append_pair("search", search);
+    }
+    if query.regex {
+        serializer.append_pair("regex", "true");
+    }
+
+    let query = serializer.finish();
+
+    if query.is_empty() {
+        query
+    } else {
+        format!("?{query}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::path_segment;
+
+    #[test]
+    fn path_segments_are_encoded() {
+        assert_eq!(path_segment("API_KEY"), "API_KEY");
+        assert_eq!(path_segment("a/b?c#d"), "a%2Fb%3Fc%23d");
+        assert_eq!(path_segment("with space+plus"), "with%20space%2Bplus");
+    }
+}
@@ -4,7 +4,9 @@ use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
+use shuttle_common::deployment::{HealthCheck, Next, Readiness, RestartPolicy, Tcp};
 use shuttle_common::project::ProjectName;
+use shuttle_common::secrets::SecretSchema;
 use shuttle_common::{ApiKey, ApiUrl, API_URL_DEFAULT};
 use tracing::trace;
 
@@ -148,6 +150,18 @@ impl GlobalConfig {
 #[derive(Deserialize, Serialize, Default)]
 pub struct ProjectConfig {
     pub name: Option<ProjectName>,
+    #[serde(default, skip_serializing_if = "SecretSchema::is_empty")]
+    pub secrets: SecretSchema,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_check: Option<HealthCheck>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub readiness: Option<Readiness>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart: Option<RestartPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp: Option<Tcp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<Next>,
 }
 
 /// A handler for configuration files. The type parameter `M` is the [`ConfigManager`] which handles
@@ -383,6 +397,14 @@ impl RequestContext {
             .as_ref()
             .unwrap()
     }
+
+    /// Get the secrets declared in the `[secrets]` table of the project configuration.
+    ///
+    /// # Panics
+    /// Panics if the project configuration has not been loaded.
+    pub fn secrets_schema(&self) -> &SecretSchema {
+        &self.project.as_ref().unwrap().as_ref().unwrap().secrets
+    }
 }
 
 #[cfg(test)]
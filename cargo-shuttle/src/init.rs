@@ -22,6 +22,7 @@ pub enum Framework {
     Poise,
     Warp,
     Thruster,
+    Tonic,
     None,
 }
 
@@ -42,6 +43,7 @@ impl Framework {
             Framework::Poise => Box::new(ShuttleInitPoise),
             Framework::Warp => Box::new(ShuttleInitWarp),
             Framework::Thruster => Box::new(ShuttleInitThruster),
+            Framework::Tonic => Box::new(ShuttleInitTonic),
             Framework::None => Box::new(ShuttleInitNoOp),
         }
     }
@@ -847,6 +849,63 @@ impl ShuttleInit for ShuttleInitThruster {
     }
 }
 
+/// Versions of tonic and tonic-health which shuttle-tonic is built on
+const TONIC_VERSION: &str = "0.8.3";
+const TONIC_HEALTH_VERSION: &str = "0.8.0";
+
+pub struct ShuttleInitTonic;
+
+impl ShuttleInit for ShuttleInitTonic {
+    fn set_cargo_dependencies(
+        &self,
+        dependencies: &mut Table,
+        manifest_path: &Path,
+        url: &Url,
+        get_dependency_version_fn: GetDependencyVersionFn,
+    ) {
+        set_key_value_dependency_version(
+            "shuttle-tonic",
+            dependencies,
+            manifest_path,
+            url,
+            true,
+            get_dependency_version_fn,
+        );
+
+        set_key_value_dependency_version(
+            "tokio",
+            dependencies,
+            manifest_path,
+            url,
+            true,
+            get_dependency_version_fn,
+        );
+
+        // A router can only be turned into a `ShuttleTonic` when it comes from the same version of
+        // tonic as the one shuttle-tonic is built on, which need not be the latest one
+        dependencies["tonic"] = value(TONIC_VERSION);
+        dependencies["tonic-health"] = value(TONIC_HEALTH_VERSION);
+    }
+
+    fn get_boilerplate_code_for_framework(&self) -> &'static str {
+        indoc! {r#"
+        use tonic::transport::Server;
+        use tonic_health::server::health_reporter;
+        use tonic_health::ServingStatus;
+
+        #[shuttle_runtime::main]
+        async fn tonic() -> shuttle_tonic::ShuttleTonic {
+            // Add your own services, generated by `tonic-build`, next to the health service
+            let (mut reporter, health_service) = health_reporter();
+            reporter.set_service_status("", ServingStatus::Serving).await;
+
+            let router = Server::builder().add_service(health_service);
+
+            Ok(router.into())
+        }"#}
+    }
+}
+
 pub struct ShuttleInitNoOp;
 impl ShuttleInit for ShuttleInitNoOp {
     fn set_cargo_dependencies(
@@ -1466,6 +1525,41 @@ mod shuttle_init_tests {
         assert_eq!(cargo_toml.to_string(), expected);
     }
 
+    #[test]
+    fn test_set_cargo_dependencies_tonic() {
+        let mut cargo_toml = cargo_toml_factory();
+        let dependencies = cargo_toml["dependencies"].as_table_mut().unwrap();
+        let manifest_path = PathBuf::new();
+        let url = Url::parse("https://shuttle.rs").unwrap();
+
+        set_key_value_dependency_version(
+            "shuttle-runtime",
+            dependencies,
+            &manifest_path,
+            &url,
+            true,
+            mock_get_latest_dependency_version,
+        );
+
+        ShuttleInitTonic.set_cargo_dependencies(
+            dependencies,
+            &manifest_path,
+            &url,
+            mock_get_latest_dependency_version,
+        );
+
+        let expected = indoc! {r#"
+            [dependencies]
+            shuttle-runtime = "1.0"
+            shuttle-tonic = "1.0"
+            tokio = "1.0"
+            tonic = "0.8.3"
+            tonic-health = "0.8.0"
+        "#};
+
+        assert_eq!(cargo_toml.to_string(), expected);
+    }
+
     // TODO: unignore this test when we publish shuttle-rocket
     #[ignore]
     #[test]
@@ -6,11 +6,13 @@ mod provisioner_server;
 
 use indicatif::ProgressBar;
 use shuttle_common::claims::{ClaimService, InjectPropagation};
+use shuttle_common::log;
 use shuttle_common::models::deployment::get_deployments_table;
 use shuttle_common::models::project::IDLE_MINUTES;
 use shuttle_common::models::resource::get_resources_table;
 use shuttle_common::project::ProjectName;
 use shuttle_common::resource;
+use shuttle_common::secrets::ValidationError;
 use shuttle_proto::runtime::runtime_client::RuntimeClient;
 use shuttle_proto::runtime::{self, LoadRequest, StartRequest, StopRequest, SubscribeLogsRequest};
 
@@ -19,7 +21,7 @@ use tokio::task::JoinHandle;
 use tonic::transport::Channel;
 use tonic::Status;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ffi::OsString;
 use std::fs::{read_to_string, File};
 use std::io::stdout;
@@ -34,7 +36,7 @@ pub use args::{Args, Command, DeployArgs, InitArgs, LoginArgs, ProjectArgs, RunA
 use cargo_metadata::Message;
 use clap::CommandFactory;
 use clap_complete::{generate, Shell};
-use config::RequestContext;
+use config::{Config, LocalConfigManager, ProjectConfig, RequestContext};
 use crossterm::style::Stylize;
 use dialoguer::{theme::ColorfulTheme, Confirm, FuzzySelect, Input, Password};
 use flate2::write::GzEncoder;
@@ -43,7 +45,8 @@ use futures::{StreamExt, TryFutureExt};
 use git2::{Repository, StatusOptions};
 use ignore::overrides::OverrideBuilder;
 use ignore::WalkBuilder;
-use shuttle_common::models::{project, secret};
+use reqwest::StatusCode;
+use shuttle_common::models::{domain, error::ApiError, project, secret, stats};
 use shuttle_service::builder::{build_workspace, BuiltService};
 use std::fmt::Write;
 use strum::IntoEnumIterator;
@@ -51,7 +54,9 @@ use tar::Builder;
 use tracing::{error, trace, warn};
 use uuid::Uuid;
 
-use crate::args::{DeploymentCommand, ProjectCommand, ResourceCommand};
+use crate::args::{
+    DeploymentCommand, DomainCommand, ProjectCommand, ResourceCommand, SecretsCommand, TcpCommand,
+};
 use crate::client::Client;
 use crate::provisioner_server::LocalProvisioner;
 
@@ -82,9 +87,12 @@ impl Shuttle {
                         | ProjectCommand::Restart { .. }
                         | ProjectCommand::Status { .. }
                 )
+                | Command::Domain(..)
+                | Command::Tcp(..)
+                | Command::Stats { .. }
                 | Command::Stop
                 | Command::Clean
-                | Command::Secrets
+                | Command::Secrets { .. }
                 | Command::Status
                 | Command::Logs { .. }
                 | Command::Run(..)
@@ -105,8 +113,15 @@ impl Shuttle {
                 return self.deploy(deploy_args, &self.client()?).await;
             }
             Command::Status => self.status(&self.client()?).await,
-            Command::Logs { id, latest, follow } => {
-                self.logs(&self.client()?, id, latest, follow).await
+            Command::Logs {
+                id,
+                latest,
+                follow,
+                filter,
+                json,
+            } => {
+                self.logs(&self.client()?, id, latest, follow, filter.into(), json)
+                    .await
             }
             Command::Deployment(DeploymentCommand::List) => {
                 self.deployments_list(&self.client()?).await
@@ -114,21 +129,64 @@ impl Shuttle {
             Command::Deployment(DeploymentCommand::Status { id }) => {
                 self.deployment_get(&self.client()?, id).await
             }
+            Command::Deployment(DeploymentCommand::Rollback { id }) => {
+                self.deployment_rollback(&self.client()?, id).await
+            }
             Command::Resource(ResourceCommand::List) => self.resources_list(&self.client()?).await,
             Command::Stop => self.stop(&self.client()?).await,
             Command::Clean => self.clean(&self.client()?).await,
-            Command::Secrets => self.secrets(&self.client()?).await,
-            Command::Project(ProjectCommand::Start { idle_minutes }) => {
-                self.project_create(&self.client()?, idle_minutes).await
+            Command::Secrets { cmd } => match cmd {
+                None | Some(SecretsCommand::List) => self.secrets(&self.client()?).await,
+                Some(SecretsCommand::Set { secrets, restart }) => {
+                    self.secrets_set(
+                        &self.client()?,
+                        secrets.into_iter().collect(),
+                        restart.restart,
+                    )
+                    .await
+                }
+                Some(SecretsCommand::Unset { keys, restart }) => {
+                    self.secrets_unset(&self.client()?, keys, restart.restart)
+                        .await
+                }
+                Some(SecretsCommand::Import { file, restart }) => {
+                    self.secrets_import(&self.client()?, file, restart.restart)
+                        .await
+                }
+            },
+            Command::Project(ProjectCommand::Start {
+                idle_minutes,
+                limits,
+            }) => {
+                self.project_create(&self.client()?, idle_minutes, limits.into())
+                    .await
             }
-            Command::Project(ProjectCommand::Restart { idle_minutes }) => {
-                self.project_recreate(&self.client()?, idle_minutes).await
+            Command::Project(ProjectCommand::Restart {
+                idle_minutes,
+                limits,
+            }) => {
+                self.project_recreate(&self.client()?, idle_minutes, limits.into())
+                    .await
             }
             Command::Project(ProjectCommand::Status { follow }) => {
                 self.project_status(&self.client()?, follow).await
             }
             Command::Project(ProjectCommand::List) => self.projects_list(&self.client()?).await,
             Command::Project(ProjectCommand::Stop) => self.project_delete(&self.client()?).await,
+            Command::Domain(DomainCommand::Add { fqdn }) => {
+                self.domain_add(&self.client()?, &fqdn).await
+            }
+            Command::Domain(DomainCommand::List) => self.domains_list(&self.client()?).await,
+            Command::Domain(DomainCommand::Remove { fqdn }) => {
+                self.domain_remove(&self.client()?, &fqdn).await
+            }
+            Command::Domain(DomainCommand::Renew { fqdn }) => {
+                self.domain_renew(&self.client()?, &fqdn).await
+            }
+            Command::Tcp(TcpCommand::Open) => self.tcp_open(&self.client()?).await,
+            Command::Tcp(TcpCommand::Status) => self.tcp_status(&self.client()?).await,
+            Command::Tcp(TcpCommand::Close) => self.tcp_close(&self.client()?).await,
+            Command::Stats { day } => self.stats(&self.client()?, day).await,
         }
         .map(|_| CommandOutcome::Ok)
     }
@@ -235,7 +293,8 @@ impl Shuttle {
             project_args.working_directory = path;
 
             self.load_project(&mut project_args)?;
-            self.project_create(&self.client()?, IDLE_MINUTES).await?;
+            self.project_create(&self.client()?, IDLE_MINUTES, Default::default())
+                .await?;
         }
 
         Ok(())
@@ -359,6 +418,64 @@ impl Shuttle {
         Ok(())
     }
 
+    async fn secrets_set(
+        &self,
+        client: &Client,
+        secrets: BTreeMap<String, String>,
+        restart: bool,
+    ) -> Result<()> {
+        let secrets = client
+            .set_secrets(self.ctx.project_name(), &secrets, restart)
+            .await?;
+        let table = secret::get_table(&secrets);
+
+        println!("{table}");
+
+        if restart {
+            println!("Restarting the running deployment to load the new secrets");
+        }
+
+        Ok(())
+    }
+
+    async fn secrets_unset(&self, client: &Client, keys: Vec<String>, restart: bool) -> Result<()> {
+        let mut secrets = Vec::new();
+
+        for (i, key) in keys.iter().enumerate() {
+            // Only restart once all the secrets have been removed
+            let is_last = i == keys.len() - 1;
+
+            secrets = client
+                .delete_secret(self.ctx.project_name(), key, restart && is_last)
+                .await?;
+        }
+
+        let table = secret::get_table(&secrets);
+
+        println!("{table}");
+
+        if restart {
+            println!("Restarting the running deployment to load the new secrets");
+        }
+
+        Ok(())
+    }
+
+    async fn secrets_import(&self, client: &Client, file: PathBuf, restart: bool) -> Result<()> {
+        let secrets_str = read_to_string(&file)
+            .with_context(|| format!("failed to read secrets file {}", file.display()))?;
+        let secrets: BTreeMap<String, String> = secrets_str
+            .parse::<toml::Value>()?
+            .try_into()
+            .context("secrets file should only contain string values")?;
+
+        if secrets.is_empty() {
+            bail!("No secrets found in {}", file.display());
+        }
+
+        self.secrets_set(client, secrets, restart).await
+    }
+
     async fn clean(&self, client: &Client) -> Result<()> {
         let lines = client.clean_project(self.ctx.project_name()).await?;
 
@@ -377,6 +494,8 @@ impl Shuttle {
         id: Option<Uuid>,
         latest: bool,
         follow: bool,
+        query: log::Query,
+        json: bool,
     ) -> Result<()> {
         let id = if let Some(id) = id {
             id
@@ -402,20 +521,34 @@ impl Shuttle {
         };
 
         if follow {
-            let mut stream = client.get_logs_ws(self.ctx.project_name(), &id).await?;
+            let mut stream = client
+                .get_logs_ws(self.ctx.project_name(), &id, &query)
+                .await?;
 
             while let Some(Ok(msg)) = stream.next().await {
                 if let tokio_tungstenite::tungstenite::Message::Text(line) = msg {
                     let log_item: shuttle_common::LogItem =
                         serde_json::from_str(&line).expect("to parse log line");
-                    println!("{log_item}")
+                    print_log(&log_item, json);
                 }
             }
         } else {
-            let logs = client.get_logs(self.ctx.project_name(), &id).await?;
+            // Print every page as soon as it arrives instead of waiting for all of them
+            let mut cursor = None;
 
-            for log in logs.into_iter() {
-                println!("{log}");
+            loop {
+                let page = client
+                    .get_logs_page(self.ctx.project_name(), &id, &query, cursor.as_ref())
+                    .await?;
+
+                for log in page.logs.iter() {
+                    print_log(log, json);
+                }
+
+                match page.next {
+                    Some(next) => cursor = Some(next),
+                    None => break,
+                }
             }
         }
 
@@ -442,6 +575,46 @@ impl Shuttle {
         Ok(())
     }
 
+    async fn deployment_rollback(&self, client: &Client, id: Option<Uuid>) -> Result<()> {
+        let proj_name = self.ctx.project_name();
+
+        let id = if let Some(id) = id {
+            id
+        } else {
+            let active_id = client
+                .get_service(proj_name)
+                .await?
+                .deployment
+                .map(|deployment| deployment.id);
+
+            // The last deployment that was running before being replaced or stopped
+            let deployments = client.get_deployments(proj_name).await?;
+            let previous = deployments
+                .iter()
+                .rev()
+                .filter(|deployment| Some(deployment.id) != active_id)
+                .find(|deployment| {
+                    matches!(
+                        deployment.state,
+                        shuttle_common::deployment::State::Stopped
+                            | shuttle_common::deployment::State::Completed
+                    )
+                })
+                .context(format!(
+                    "Could not find a previous deployment for '{proj_name}' to roll back to. Try passing a deployment ID manually",
+                ))?;
+
+            previous.id
+        };
+
+        let deployment = client.rollback_deployment(proj_name, &id).await?;
+
+        println!("{} {}", "Rolling back to deployment".bold(), deployment.id);
+        println!("Run `cargo shuttle status` to check when it is running");
+
+        Ok(())
+    }
+
     async fn resources_list(&self, client: &Client) -> Result<()> {
         let resources = client
             .get_service_resources(self.ctx.project_name())
@@ -544,7 +717,6 @@ impl Shuttle {
         };
 
         let (mut runtime, mut runtime_client) = runtime::start(
-            is_wasm,
             runtime::StorageManagerType::WorkingDir(working_directory.to_path_buf()),
             &format!("http://localhost:{provisioner_port}"),
             None,
@@ -626,8 +798,27 @@ impl Shuttle {
             addr
         );
 
+        // The `[next]` table of the service decides how large request bodies it accepts, where
+        // zero leaves the default to the runtime
+        let mut project_config: Config<_, ProjectConfig> = Config::new(LocalConfigManager::new(
+            &working_directory,
+            "Shuttle.toml".to_string(),
+        ));
+        let body_limit = if is_wasm && project_config.exists() {
+            project_config.open()?;
+
+            project_config
+                .as_ref()
+                .and_then(|config| config.next.as_ref())
+                .map(|next| next.body_limit)
+                .unwrap_or_default()
+        } else {
+            0
+        };
+
         let start_request = StartRequest {
             ip: addr.to_string(),
+            body_limit,
         };
 
         trace!(?start_request, "starting service");
@@ -892,6 +1083,8 @@ impl Shuttle {
             self.is_dirty()?;
         }
 
+        self.check_secrets(client).await?;
+
         let data = self.make_archive()?;
 
         let deployment = client
@@ -899,7 +1092,7 @@ impl Shuttle {
             .await?;
 
         let mut stream = client
-            .get_logs_ws(self.ctx.project_name(), &deployment.id)
+            .get_logs_ws(self.ctx.project_name(), &deployment.id, &Default::default())
             .await?;
 
         while let Some(Ok(msg)) = stream.next().await {
@@ -911,7 +1104,8 @@ impl Shuttle {
                     shuttle_common::deployment::State::Queued
                     | shuttle_common::deployment::State::Building
                     | shuttle_common::deployment::State::Built
-                    | shuttle_common::deployment::State::Loading => {
+                    | shuttle_common::deployment::State::Loading
+                    | shuttle_common::deployment::State::Restarting => {
                         println!("{log_item}");
                     }
                     shuttle_common::deployment::State::Crashed => {
@@ -958,8 +1152,16 @@ impl Shuttle {
         }
     }
 
-    async fn project_create(&self, client: &Client, idle_minutes: u64) -> Result<()> {
-        let config = project::Config { idle_minutes };
+    async fn project_create(
+        &self,
+        client: &Client,
+        idle_minutes: u64,
+        limits: project::Limits,
+    ) -> Result<()> {
+        let config = project::Config {
+            idle_minutes,
+            limits,
+        };
 
         self.wait_with_spinner(
             &[
@@ -977,9 +1179,14 @@ impl Shuttle {
         Ok(())
     }
 
-    async fn project_recreate(&self, client: &Client, idle_minutes: u64) -> Result<()> {
+    async fn project_recreate(
+        &self,
+        client: &Client,
+        idle_minutes: u64,
+        limits: project::Limits,
+    ) -> Result<()> {
         self.project_delete(client).await?;
-        self.project_create(client, idle_minutes).await?;
+        self.project_create(client, idle_minutes, limits).await?;
 
         Ok(())
     }
@@ -1033,6 +1240,88 @@ impl Shuttle {
         Ok(())
     }
 
+    async fn domain_add(&self, client: &Client, fqdn: &str) -> Result<()> {
+        let domain = client.add_domain(self.ctx.project_name(), fqdn).await?;
+
+        println!(
+            "{} {} {}",
+            "Added custom domain".bold(),
+            domain.fqdn,
+            format!(
+                "(certificate expires {})",
+                domain.expires.format("%Y-%m-%dT%H:%M:%SZ")
+            )
+            .dim()
+        );
+        println!("The project is restarting, run `cargo shuttle project status` to check when it is ready");
+
+        Ok(())
+    }
+
+    async fn domains_list(&self, client: &Client) -> Result<()> {
+        let domains = client.get_domains(self.ctx.project_name()).await?;
+        let table = domain::get_table(&domains, self.ctx.project_name().as_str());
+
+        println!("{table}");
+
+        Ok(())
+    }
+
+    async fn domain_remove(&self, client: &Client, fqdn: &str) -> Result<()> {
+        let domain = client.remove_domain(self.ctx.project_name(), fqdn).await?;
+
+        println!("{} {}", "Removed custom domain".bold(), domain.fqdn);
+        println!("The project is restarting, run `cargo shuttle project status` to check when it is ready");
+
+        Ok(())
+    }
+
+    async fn domain_renew(&self, client: &Client, fqdn: &str) -> Result<()> {
+        let message = client.renew_domain(self.ctx.project_name(), fqdn).await?;
+
+        println!("{message}");
+
+        Ok(())
+    }
+
+    async fn tcp_open(&self, client: &Client) -> Result<()> {
+        let tcp = client.open_tcp(self.ctx.project_name()).await?;
+
+        println!("{tcp}");
+
+        Ok(())
+    }
+
+    async fn tcp_status(&self, client: &Client) -> Result<()> {
+        let tcp = client.get_tcp(self.ctx.project_name()).await?;
+
+        println!("{tcp}");
+
+        Ok(())
+    }
+
+    async fn tcp_close(&self, client: &Client) -> Result<()> {
+        let tcp = client.close_tcp(self.ctx.project_name()).await?;
+
+        println!("{} {}", "Closed public TCP port".bold(), tcp.port);
+
+        Ok(())
+    }
+
+    async fn stats(&self, client: &Client, day: bool) -> Result<()> {
+        let period = if day {
+            stats::TrafficPeriod::Day
+        } else {
+            stats::TrafficPeriod::Hour
+        };
+        let traffic = client.get_traffic(self.ctx.project_name(), period).await?;
+        let table = stats::get_traffic_table(&traffic, self.ctx.project_name().as_str(), period);
+
+        println!("{table}");
+
+        Ok(())
+    }
+
     async fn wait_with_spinner<'a, Fut>(
         &self,
         states_to_check: &[project::State],
@@ -1059,6 +1348,63 @@ impl Shuttle {
         Ok(())
     }
 
+    /// Check the Secrets.toml being deployed, together with the secrets already set on the project,
+    /// against the secrets declared in Shuttle.toml
+    async fn check_secrets(&self, client: &Client) -> Result<()> {
+        let schema = self.ctx.secrets_schema();
+
+        if schema.is_empty() {
+            return Ok(());
+        }
+
+        let secrets_path = self.ctx.working_directory().join("Secrets.toml");
+        let local: BTreeMap<String, String> = if secrets_path.exists() {
+            read_to_string(secrets_path)
+                .context("failed to read Secrets.toml")?
+                .parse::<toml::Value>()
+                .context("failed to parse Secrets.toml")?
+                .try_into()
+                .context("Secrets.toml should only contain string values")?
+        } else {
+            Default::default()
+        };
+
+        // A project which has never been deployed does not have a service with secrets yet
+        let remote = match client.get_secrets(self.ctx.project_name()).await {
+            Ok(remote) => remote,
+            Err(error)
+                if error
+                    .downcast_ref::<ApiError>()
+                    .map_or(false, |error| error.status() == StatusCode::NOT_FOUND) =>
+            {
+                Vec::new()
+            }
+            Err(error) => {
+                return Err(error.context("failed to get the secrets already set on the project"))
+            }
+        };
+
+        let keys = local
+            .keys()
+            .map(String::as_str)
+            .chain(remote.iter().map(|secret| secret.key.as_str()));
+
+        let result = ValidationError {
+            missing: schema.missing(keys),
+            invalid: schema.invalid(&local),
+        }
+        .into_result();
+
+        if let Err(error) = result {
+            bail!(
+                "the secrets for this deploy do not match the ones declared in Shuttle.toml: {error}. \
+                Add them to Secrets.toml or set them with `cargo shuttle secrets set`"
+            );
+        }
+
+        Ok(())
+    }
+
     fn make_archive(&self) -> Result<Vec<u8>> {
         let encoder = GzEncoder::new(Vec::new(), Compression::fast());
         let mut tar = Builder::new(encoder);
@@ -1247,6 +1593,28 @@ fn create_spinner() -> ProgressBar {
     pb
 }
 
+/// Print a log line either for humans or as a JSON object for other tools
+fn print_log(log_item: &shuttle_common::LogItem, json: bool) {
+    if json {
+        let fields: serde_json::Value =
+            serde_json::from_slice(&log_item.fields).unwrap_or_default();
+        let line = serde_json::json!({
+            "id": log_item.id,
+            "timestamp": log_item.timestamp,
+            "state": log_item.state,
+            "level": log_item.level,
+            "file": log_item.file,
+            "line": log_item.line,
+            "target": log_item.target,
+            "fields": fields,
+        });
+
+        println!("{line}");
+    } else {
+        println!("{log_item}");
+    }
+}
+
 pub enum CommandOutcome {
     Ok,
     DeploymentFailure,
@@ -17,7 +17,7 @@ use portpicker::pick_unused_port;
 use shuttle_common::database::{AwsRdsEngine, SharedEngine};
 use shuttle_proto::provisioner::{
     provisioner_server::{Provisioner, ProvisionerServer},
-    DatabaseDeletionResponse, DatabaseRequest, DatabaseResponse,
+    BucketRequest, BucketResponse, DatabaseDeletionResponse, DatabaseRequest, DatabaseResponse,
 };
 use shuttle_service::database::Type;
 use std::{collections::HashMap, io::stdout, net::SocketAddr, time::Duration};
@@ -28,8 +28,12 @@ use tonic::{
 };
 use tracing::{error, trace};
 
+/// Credentials of the local object storage
+const MINIO_USER: &str = "minio";
+const MINIO_PASSWORD: &str = "password";
+
 /// A provisioner for local runs
-/// It uses Docker to create Databases
+/// It uses Docker to create Databases and object storage
 pub struct LocalProvisioner {
     docker: Docker,
 }
@@ -70,24 +74,96 @@ impl LocalProvisioner {
         } = db_type_to_config(db_type);
         let container_name = format!("shuttle_{service_name}_{type}");
 
-        let container = match self.docker.inspect_container(&container_name, None).await {
+        let port = self
+            .start_container(&container_name, image, &port, env, None, is_ready_cmd)
+            .await?;
+
+        let res = DatabaseResponse {
+            engine,
+            username,
+            password,
+            database_name,
+            port,
+            address_private: "localhost".to_string(),
+            address_public: "localhost".to_string(),
+        };
+
+        Ok(res)
+    }
+
+    async fn get_bucket(&self, service_name: &str) -> Result<BucketResponse, Status> {
+        trace!("getting bucket for service '{}'", service_name);
+
+        let bucket_name = format!("bucket-{}", service_name.to_lowercase().replace('_', "-"));
+        let container_name = format!("shuttle_{service_name}_minio");
+
+        // The ready command also creates the bucket, so it is only ready once the bucket exists
+        let is_ready_cmd = vec![
+            "/bin/sh".to_string(),
+            "-c".to_string(),
+            format!(
+                "mc alias set local http://localhost:9000 {MINIO_USER} {MINIO_PASSWORD} > /dev/null \
+                && mc mb --ignore-existing local/{bucket_name} > /dev/null \
+                && echo ready"
+            ),
+        ];
+
+        let port = self
+            .start_container(
+                &container_name,
+                "docker.io/minio/minio:RELEASE.2023-03-24T21-41-23Z".to_string(),
+                "9000/tcp",
+                Some(vec![
+                    format!("MINIO_ROOT_USER={MINIO_USER}"),
+                    format!("MINIO_ROOT_PASSWORD={MINIO_PASSWORD}"),
+                ]),
+                Some(vec!["server".to_string(), "/data".to_string()]),
+                is_ready_cmd,
+            )
+            .await?;
+
+        let endpoint = format!("http://localhost:{port}");
+
+        Ok(BucketResponse {
+            bucket_name,
+            access_key_id: MINIO_USER.to_string(),
+            secret_access_key: MINIO_PASSWORD.to_string(),
+            session_token: String::new(),
+            region: "us-east-1".to_string(),
+            endpoint_private: endpoint.clone(),
+            endpoint_public: endpoint,
+        })
+    }
+
+    /// Start the container with `container_name`, creating it first if it does not exist yet,
+    /// and wait for it to be ready. Returns the port on the host which is bound to `port`.
+    async fn start_container(
+        &self,
+        container_name: &str,
+        image: String,
+        port: &str,
+        env: Option<Vec<String>>,
+        cmd: Option<Vec<String>>,
+        is_ready_cmd: Vec<String>,
+    ) -> Result<String, Status> {
+        let container = match self.docker.inspect_container(container_name, None).await {
             Ok(container) => {
-                trace!("found DB container {container_name}");
+                trace!("found container {container_name}");
                 container
             }
             Err(bollard::errors::Error::DockerResponseServerError { status_code, .. })
                 if status_code == 404 =>
             {
                 self.pull_image(&image).await.expect("failed to pull image");
-                trace!("will create DB container {container_name}");
+                trace!("will create container {container_name}");
                 let options = Some(CreateContainerOptions {
-                    name: container_name.clone(),
+                    name: container_name.to_string(),
                     platform: None,
                 });
                 let mut port_bindings = HashMap::new();
                 let host_port = pick_unused_port().expect("system to have a free port");
                 port_bindings.insert(
-                    port.clone(),
+                    port.to_string(),
                     Some(vec![PortBinding {
                         host_port: Some(host_port.to_string()),
                         ..Default::default()
@@ -101,6 +177,7 @@ impl LocalProvisioner {
                 let config = Config {
                     image: Some(image),
                     env,
+                    cmd,
                     host_config: Some(host_config),
                     ..Default::default()
                 };
@@ -111,7 +188,7 @@ impl LocalProvisioner {
                     .expect("to be able to create container");
 
                 self.docker
-                    .inspect_container(&container_name, None)
+                    .inspect_container(container_name, None)
                     .await
                     .expect("container to be created")
             }
@@ -121,12 +198,12 @@ impl LocalProvisioner {
             }
         };
 
-        let port = container
+        let host_port = container
             .host_config
             .expect("container to have host config")
             .port_bindings
             .expect("port bindings on container")
-            .get(&port)
+            .get(port)
             .expect("a port bindings entry")
             .as_ref()
             .expect("a port bindings")
@@ -143,26 +220,16 @@ impl LocalProvisioner {
             .running
             .expect("state to have a running key")
         {
-            trace!("DB container '{container_name}' not running, so starting it");
+            trace!("container '{container_name}' not running, so starting it");
             self.docker
-                .start_container(&container_name, None::<StartContainerOptions<String>>)
+                .start_container(container_name, None::<StartContainerOptions<String>>)
                 .await
                 .expect("failed to start none running container");
         }
 
-        self.wait_for_ready(&container_name, is_ready_cmd).await?;
-
-        let res = DatabaseResponse {
-            engine,
-            username,
-            password,
-            database_name,
-            port,
-            address_private: "localhost".to_string(),
-            address_public: "localhost".to_string(),
-        };
+        self.wait_for_ready(container_name, is_ready_cmd).await?;
 
-        Ok(res)
+        Ok(host_port)
     }
 
     async fn wait_for_ready(
@@ -273,6 +340,17 @@ impl Provisioner for LocalProvisioner {
     ) -> Result<Response<DatabaseDeletionResponse>, Status> {
         panic!("local runner should not try to delete databases");
     }
+
+    async fn provision_bucket(
+        &self,
+        request: Request<BucketRequest>,
+    ) -> Result<Response<BucketResponse>, Status> {
+        let BucketRequest { project_name } = request.into_inner();
+
+        let res = self.get_bucket(&project_name).await?;
+
+        Ok(Response::new(res))
+    }
 }
 
 fn print_layers(layers: &Vec<CreateImageInfo>) {
@@ -412,3 +490,70 @@ fn db_type_to_config(db_type: Type) -> EngineConfig {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::{LocalProvisioner, MINIO_PASSWORD, MINIO_USER};
+
+    /// Run a MinIO client command against the local object storage in its container
+    fn exec_mc(container_name: &str, command: &str) -> String {
+        let output = Command::new("docker")
+            .args([
+                "exec",
+                container_name,
+                "/bin/sh",
+                "-c",
+                &format!(
+                    "mc alias set local http://localhost:9000 {MINIO_USER} {MINIO_PASSWORD} > /dev/null && {command}"
+                ),
+            ])
+            .output()
+            .unwrap();
+
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    #[tokio::test]
+    async fn bucket_on_local_minio() {
+        let provisioner = LocalProvisioner::new().unwrap();
+        let container_name = "shuttle_local-provisioner-test_minio";
+
+        let bucket = provisioner
+            .get_bucket("local-provisioner-test")
+            .await
+            .unwrap();
+
+        assert_eq!(bucket.bucket_name, "bucket-local-provisioner-test");
+        assert_eq!(bucket.access_key_id, MINIO_USER);
+        assert_eq!(bucket.secret_access_key, MINIO_PASSWORD);
+        assert_eq!(bucket.endpoint_private, bucket.endpoint_public);
+
+        exec_mc(
+            container_name,
+            "echo matrix | mc pipe local/bucket-local-provisioner-test/note.txt > /dev/null",
+        );
+
+        // A second run gets the same container and bucket, with what was already in it
+        let again = provisioner
+            .get_bucket("local-provisioner-test")
+            .await
+            .unwrap();
+
+        assert_eq!(again.bucket_name, bucket.bucket_name);
+        assert_eq!(again.endpoint_private, bucket.endpoint_private);
+        assert_eq!(
+            exec_mc(
+                container_name,
+                "mc cat local/bucket-local-provisioner-test/note.txt"
+            ),
+            "matrix"
+        );
+
+        Command::new("docker")
+            .args(["rm", "--force", container_name])
+            .output()
+            .unwrap();
+    }
+}
@@ -13,6 +13,16 @@ pub fn main(attr: TokenStream, item: TokenStream) -> TokenStream {
     shuttle_main::r#impl(attr, item)
 }
 
+/// Build an axum router for the shuttle-next runtime from the endpoints, resources, app state and
+/// layers declared in the macro.
+///
+/// Every instance of the module builds its router once, and keeps it for all the requests it
+/// handles. So the `#[app_state]` function and the `#[layer]` functions are called once per
+/// instance rather than once per request. The runtime can run several instances at the same time
+/// though, which each have a state of their own, so state is not shared by every request.
+///
+/// Every layer wraps the router along with the layers declared before it, so the last one declared
+/// is the outermost.
 #[cfg(feature = "next")]
 #[proc_macro_error]
 #[proc_macro]
@@ -1,10 +1,11 @@
 use std::collections::HashMap;
 
 use proc_macro_error::emit_error;
-use quote::{quote, ToTokens};
+use quote::{format_ident, quote, ToTokens};
 use syn::{
-    parenthesized, parse::Parse, parse2, punctuated::Punctuated, token::Paren, Expr, ExprLit, File,
-    Ident, Item, ItemFn, Lit, LitStr, Token,
+    parenthesized, parse::Parse, parse2, parse_quote, punctuated::Punctuated, token::Paren,
+    Attribute, Expr, ExprLit, File, FnArg, Ident, Item, ItemFn, Lit, LitStr, Path, ReturnType,
+    Token, Type,
 };
 
 #[derive(Debug, Eq, PartialEq)]
@@ -47,43 +48,193 @@ impl Parse for Params {
     }
 }
 
-impl Endpoint {
-    fn from_item_fn(item: &mut ItemFn) -> Option<Self> {
-        let function = item.sig.ident.clone();
+/// A resource an endpoint asks for by annotating one of its parameters with a builder, such as
+/// `#[shuttle_secrets::Secrets] secrets: SecretStore`
+#[derive(Debug, Eq, PartialEq)]
+struct Resource {
+    /// Path to the builder
+    builder: Path,
 
-        let mut endpoint_index = None;
-
-        // Find the index of an attribute that is an endpoint
-        for index in 0..item.attrs.len() {
-            // The endpoint ident should be the last segment in the path
-            if let Some(segment) = item.attrs[index].path.segments.last() {
-                if segment.ident.to_string().as_str() == "endpoint" {
-                    // TODO: we should allow multiple endpoint attributes per handler.
-                    // We could refactor this to return a Vec<Endpoint> and then check
-                    // that the combination of endpoints is valid.
-                    if endpoint_index.is_some() {
-                        emit_error!(
-                            item,
-                            "extra endpoint attribute";
-                            hint = "There should only be one endpoint annotation per handler function."
-                        );
-                        return None;
+    /// Options to call on the builder
+    options: Option<Params>,
+
+    /// The type of the parameter, which is the resource the builder builds
+    ty: Type,
+}
+
+impl Resource {
+    /// Take the resources out of the parameters of an endpoint, and have those parameters extract
+    /// the resources from the router instead
+    fn from_item_fn(item: &mut ItemFn) -> Vec<Self> {
+        let mut resources = Vec::new();
+
+        for input in item.sig.inputs.iter_mut() {
+            let FnArg::Typed(typed) = input else {
+                continue;
+            };
+
+            if typed.attrs.is_empty() {
+                continue;
+            }
+
+            let attr = typed.attrs.remove(0);
+
+            if let Some(extra) = typed.attrs.first() {
+                emit_error!(
+                    extra,
+                    "extra resource attribute";
+                    hint = "There should only be one resource annotation per parameter."
+                );
+                typed.attrs.clear();
+            }
+
+            let options = if attr.tokens.is_empty() {
+                None
+            } else {
+                match parse2(attr.tokens) {
+                    Ok(options) => Some(options),
+                    Err(err) => {
+                        emit_error!(err.span(), err);
+                        continue;
                     }
-                    endpoint_index = Some(index);
                 }
-            } else {
-                return None;
-            }
+            };
+
+            let pat = &typed.pat;
+            let ty = &typed.ty;
+
+            resources.push(Resource {
+                builder: attr.path,
+                options,
+                ty: ty.as_ref().clone(),
+            });
+
+            *typed.pat = parse_quote!(shuttle_next::Extension(#pat));
+            *typed.ty = parse_quote!(shuttle_next::Extension<#ty>);
         }
 
-        // Strip the endpoint attribute if it exists
-        let endpoint = if let Some(index) = endpoint_index {
-            item.attrs.remove(index)
-        } else {
-            // This item does not have an endpoint attribute
+        resources
+    }
+}
+
+/// A function called once per instance to build part of the router, such as its state or one of
+/// its layers
+#[derive(Debug, Eq, PartialEq)]
+struct Constructor {
+    function: Ident,
+    is_async: bool,
+}
+
+impl Constructor {
+    /// Strip the `kind` attribute from a function, and check that the function can be called
+    /// without arguments to build what it returns
+    fn from_item_fn(item: &mut ItemFn, kind: &str) -> Option<Self> {
+        let mut attrs = take_attributes(item, kind);
+
+        if attrs.is_empty() {
             return None;
+        }
+
+        let attr = attrs.remove(0);
+
+        if let Some(extra) = attrs.first() {
+            emit_error!(
+                extra,
+                "extra {} attribute", kind;
+                hint = "A function can only be annotated with `{}` once.", kind
+            );
+        }
+
+        if !attr.tokens.is_empty() {
+            emit_error!(
+                &attr.tokens,
+                "unexpected {} arguments", kind;
+                hint = "Remove the arguments: `#[{}]`", kind
+            );
+        }
+
+        let mut has_err = false;
+
+        if !item.sig.inputs.is_empty() {
+            emit_error!(
+                item.sig.paren_token.span,
+                "{} functions cannot take arguments", kind;
+                hint = "These functions are called once per instance, without any arguments."
+            );
+            has_err = true;
+        }
+
+        if let ReturnType::Default = item.sig.output {
+            emit_error!(
+                &item.sig.ident,
+                "{} functions must return a value", kind;
+                hint = "Return what the router should use: `fn {}() -> ...`", item.sig.ident
+            );
+            has_err = true;
+        }
+
+        if has_err {
+            None
+        } else {
+            Some(Self {
+                function: item.sig.ident.clone(),
+                is_async: item.sig.asyncness.is_some(),
+            })
+        }
+    }
+}
+
+impl ToTokens for Constructor {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        let function = &self.function;
+
+        let call = if self.is_async {
+            quote!(#function().await)
+        } else {
+            quote!(#function())
         };
 
+        call.to_tokens(tokens);
+    }
+}
+
+/// Check if a function has an attribute whose path ends in `name`, without stripping it
+fn has_attribute(item: &ItemFn, name: &str) -> bool {
+    item.attrs.iter().any(|attr| {
+        attr.path
+            .segments
+            .last()
+            .map_or(false, |segment| segment.ident == name)
+    })
+}
+
+/// Strip the attributes whose path ends in `name` from a function
+fn take_attributes(item: &mut ItemFn, name: &str) -> Vec<Attribute> {
+    let (taken, kept) = item.attrs.drain(..).partition(|attr| {
+        attr.path
+            .segments
+            .last()
+            .map_or(false, |segment| segment.ident == name)
+    });
+
+    item.attrs = kept;
+
+    taken
+}
+
+impl Endpoint {
+    /// Strip the endpoint attributes from a handler, with one endpoint for each of them which is
+    /// valid
+    fn from_item_fn(item: &mut ItemFn) -> Vec<Self> {
+        let function = item.sig.ident.clone();
+
+        take_attributes(item, "endpoint")
+            .into_iter()
+            .filter_map(|attr| Self::from_attribute(attr, &function))
+            .collect()
+    }
+
+    fn from_attribute(endpoint: Attribute, function: &Ident) -> Option<Self> {
         // Parse the endpoint's parameters
         let params: Params = match parse2(endpoint.tokens) {
             Ok(params) => params,
@@ -159,6 +310,29 @@ impl Endpoint {
                         ..
                     }) = value
                     {
+                        if !literal.value().starts_with('/') {
+                            emit_error!(
+                                literal,
+                                "route must start with a `/`";
+                                hint = "Routes are absolute paths: `route = \"/hello\"`"
+                            );
+                            has_err = true;
+                        }
+
+                        // Captures are extracted by name with `Path`, so they need one
+                        if literal
+                            .value()
+                            .split('/')
+                            .any(|segment| segment == ":" || segment == "*")
+                        {
+                            emit_error!(
+                                literal,
+                                "route has a path parameter without a name";
+                                hint = "Name the path parameter to extract it: `route = \"/users/:id\"`"
+                            );
+                            has_err = true;
+                        }
+
                         route = Some(literal);
                     }
                 }
@@ -198,7 +372,7 @@ impl Endpoint {
             Some(Endpoint {
                 route: route.unwrap(),
                 method: method.unwrap(),
-                function,
+                function: function.clone(),
             })
         }
     }
@@ -253,30 +427,103 @@ impl<'a> ToTokens for EndpointChain<'a> {
 #[derive(Debug, Eq, PartialEq)]
 pub(crate) struct App {
     endpoints: Vec<Endpoint>,
+    resources: Vec<Resource>,
+    state: Option<Constructor>,
+    layers: Vec<Constructor>,
 }
 
 impl App {
     pub(crate) fn from_file(file: &mut File) -> Self {
-        let endpoints = file
-            .items
-            .iter_mut()
-            .filter_map(|item| {
-                if let Item::Fn(item_fn) = item {
-                    Some(item_fn)
+        let mut endpoints = Vec::new();
+        let mut resources: Vec<Resource> = Vec::new();
+        let mut state: Option<Constructor> = None;
+        let mut layers = Vec::new();
+
+        let item_fns = file.items.iter_mut().filter_map(|item| {
+            if let Item::Fn(item_fn) = item {
+                Some(item_fn)
+            } else {
+                None
+            }
+        });
+
+        for item_fn in item_fns {
+            let roles: Vec<_> = ["app_state", "layer", "endpoint"]
+                .into_iter()
+                .filter(|role| has_attribute(item_fn, role))
+                .collect();
+
+            if let [first, second, ..] = roles[..] {
+                emit_error!(
+                    item_fn.sig.ident,
+                    "`{}` and `{}` attributes cannot be combined", first, second;
+                    hint = "A function builds either the app state, a layer or an endpoint of the app."
+                );
+            }
+
+            if let Some(constructor) = Constructor::from_item_fn(item_fn, "app_state") {
+                if state.is_some() {
+                    emit_error!(
+                        constructor.function,
+                        "extra app state";
+                        hint = "There should only be one `app_state` function per app."
+                    );
                 } else {
-                    None
+                    state = Some(constructor);
                 }
-            })
-            .filter_map(Endpoint::from_item_fn)
-            .collect();
+            }
 
-        Self { endpoints }
+            // Every layer wraps the router along with the layers declared before it, so the last
+            // one declared is the outermost and sees requests first and responses last
+            layers.extend(Constructor::from_item_fn(item_fn, "layer"));
+
+            let handler_endpoints = Endpoint::from_item_fn(item_fn);
+
+            if handler_endpoints.is_empty() {
+                continue;
+            }
+
+            // Resources are handed to the endpoints by their type, so endpoints asking for the
+            // same type share a single resource
+            for resource in Resource::from_item_fn(item_fn) {
+                match resources.iter().find(|existing| existing.ty == resource.ty) {
+                    Some(existing) if *existing != resource => {
+                        let ty = &resource.ty;
+
+                        emit_error!(
+                            resource.builder,
+                            "this resource type is already built differently by another endpoint";
+                            hint = format!(
+                                "Annotate every `{}` parameter with the same builder and options.",
+                                quote!(#ty)
+                            )
+                        );
+                    }
+                    Some(_) => {}
+                    None => resources.push(resource),
+                }
+            }
+
+            endpoints.extend(handler_endpoints);
+        }
+
+        Self {
+            endpoints,
+            resources,
+            state,
+            layers,
+        }
     }
 }
 
 impl ToTokens for App {
     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
-        let Self { endpoints } = self;
+        let Self {
+            endpoints,
+            resources,
+            state,
+            layers,
+        } = self;
 
         let mut endpoint_chains = endpoints
             .iter()
@@ -311,49 +558,155 @@ impl ToTokens for App {
         // use a HashMap and then sort the endpoint chains to ensure the output is deterministic.
         endpoint_chains.sort_by(|a, b| a.route.value().cmp(&b.route.value()));
 
-        let app = quote!(
-            async fn __app(request: shuttle_next::Request<shuttle_next::body::BoxBody>,) -> shuttle_next::response::Response
-            {
-                use shuttle_next::Service;
+        // Instances build their router once and keep it for every request they handle
+        let state = state.as_ref().map(|state| quote!(.with_state(#state)));
+
+        let app = if resources.is_empty() {
+            quote!(
+                async fn __app(
+                    _resources: Vec<shuttle_next::Value>,
+                ) -> Result<
+                    shuttle_next::Router<(), shuttle_next::body::BoxBody>,
+                    shuttle_next::BoxError,
+                > {
+                    let router = shuttle_next::Router::new()
+                        #(#endpoint_chains)*
+                        #(.layer(#layers))*
+                        #state;
+
+                    Ok(router)
+                }
+            )
+        } else {
+            let idents: Vec<_> = (0..resources.len())
+                .map(|index| format_ident!("__resource_{}", index))
+                .collect();
+            let builders = resources.iter().map(|resource| &resource.builder);
+            let types = resources.iter().map(|resource| &resource.ty);
+
+            quote!(
+                async fn __app(
+                    resources: Vec<shuttle_next::Value>,
+                ) -> Result<
+                    shuttle_next::Router<(), shuttle_next::body::BoxBody>,
+                    shuttle_next::BoxError,
+                > {
+                    let mut resources = resources.into_iter();
+
+                    #(let #idents = shuttle_next::build_resource::<#builders, #types>(
+                        resources.next()
+                    )
+                    .await?;)*
+
+                    let router = shuttle_next::Router::new()
+                        #(#endpoint_chains)*
+                        #(.layer(#layers))*
+                        #(.layer(shuttle_next::Extension(#idents)))*
+                        #state;
+
+                    Ok(router)
+                }
+            )
+        };
+
+        app.to_tokens(tokens);
+    }
+}
 
-                let mut router = shuttle_next::Router::new()
-                    #(#endpoint_chains)*;
+/// Provision the resources of the app when the runtime loads the module. The runtime records their
+/// outputs and sends them to every instance it starts, for the instance to build its router with.
+fn load_binding(resources: &[Resource]) -> Option<proc_macro2::TokenStream> {
+    if resources.is_empty() {
+        return None;
+    }
 
-                let response = router.call(request).await.unwrap();
+    let builders = resources.iter().map(|resource| &resource.builder);
+    let types = resources.iter().map(|resource| &resource.ty);
+    let options = resources.iter().map(|resource| {
+        let (methods, values): (Vec<_>, Vec<_>) = resource
+            .options
+            .iter()
+            .flat_map(|options| options.params.iter())
+            .map(|Parameter { key, value, .. }| (key, value))
+            .unzip();
 
-                response
-            }
-        );
+        quote!(#(.#methods(#values))*)
+    });
 
-        app.to_tokens(tokens);
-    }
+    Some(quote!(
+        #[cfg(not(test))]
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        pub extern "C" fn __SHUTTLE_Axum_load(
+            logs_fd: std::os::wasi::prelude::RawFd,
+            factory_fd: std::os::wasi::prelude::RawFd,
+        ) {
+            use shuttle_next::tracing_prelude::*;
+            use shuttle_next::{Logger, ResourceBuilder};
+            use std::os::wasi::io::FromRawFd;
+
+            let logs_fd = unsafe { std::fs::File::from_raw_fd(logs_fd) };
+
+            shuttle_next::tracing_registry()
+                .with(Logger::new(logs_fd))
+                .init();
+
+            // file descriptor for asking the runtime to provision resources
+            let factory_fd = unsafe { std::fs::File::from_raw_fd(factory_fd) };
+            let mut factory = shuttle_next::WasiFactory::new(factory_fd)
+                .expect("failed to get the service details from the runtime");
+
+            #(shuttle_next::block_on(shuttle_next::get_resource::<_, #types, _>(
+                #builders::new()#options,
+                &mut factory,
+            ))
+            .expect(concat!("failed to provision ", stringify!(#builders)));)*
+        }
+    ))
 }
 
 pub(crate) fn wasi_bindings(app: App) -> proc_macro2::TokenStream {
+    let load = load_binding(&app.resources);
+
     quote!(
         #app
 
+        #load
+
         #[cfg(not(test))]
         #[no_mangle]
         #[allow(non_snake_case)]
-        pub extern "C" fn __SHUTTLE_Axum_call(
+        pub extern "C" fn __SHUTTLE_Axum_init(
             logs_fd: std::os::wasi::prelude::RawFd,
-            parts_fd: std::os::wasi::prelude::RawFd,
-            body_fd: std::os::wasi::prelude::RawFd,
-        ) {
-            use shuttle_next::body::{Body, HttpBody};
+            resources_fd: std::os::wasi::prelude::RawFd,
+        ) -> i32 {
             use shuttle_next::tracing_prelude::*;
             use shuttle_next::Logger;
-            use std::io::{Read, Write};
             use std::os::wasi::io::FromRawFd;
 
-            // file descriptor 2 for writing logs to
+            // file descriptor for writing the logs of the instance, for every request it handles
             let logs_fd = unsafe { std::fs::File::from_raw_fd(logs_fd) };
 
             shuttle_next::tracing_registry()
                 .with(Logger::new(logs_fd))
                 .init(); // this sets the subscriber as the global default and also adds a compatibility layer for capturing `log::Record`s
 
+            // file descriptor for reading the outputs of the resources recorded at load
+            let resources_fd = unsafe { std::fs::File::from_raw_fd(resources_fd) };
+
+            shuttle_next::init_router(resources_fd, __app) as i32
+        }
+
+        #[cfg(not(test))]
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        pub extern "C" fn __SHUTTLE_Axum_call(
+            parts_fd: std::os::wasi::prelude::RawFd,
+            body_fd: std::os::wasi::prelude::RawFd,
+        ) {
+            use std::io::Write;
+            use std::os::wasi::io::FromRawFd;
+
             // file descriptor 3 for reading and writing http parts
             let mut parts_fd = unsafe { std::fs::File::from_raw_fd(parts_fd) };
 
@@ -362,23 +715,17 @@ pub(crate) fn wasi_bindings(app: App) -> proc_macro2::TokenStream {
             // deserialize request parts from rust messagepack
             let wrapper: shuttle_next::RequestWrapper = shuttle_next::from_read(reader).unwrap();
 
-            // file descriptor 4 for reading and writing http body
-            let mut body_stream = unsafe { std::fs::File::from_raw_fd(body_fd) };
-
-            let mut reader = std::io::BufReader::new(&mut body_stream);
-            let mut body_buf = Vec::new();
-            reader.read_to_end(&mut body_buf).unwrap();
-
-            let body = Body::from(body_buf);
+            // file descriptor 4 for streaming the http body in and out
+            let body_stream = std::sync::Arc::new(unsafe { std::fs::File::from_raw_fd(body_fd) });
 
             let request = wrapper
                 .into_request_builder()
-                .body(shuttle_next::body::boxed(body))
+                .body(shuttle_next::request_body(body_stream.clone()))
                 .unwrap();
 
-            let res = shuttle_next::block_on(__app(request));
+            let res = shuttle_next::call_router(request);
 
-            let (parts, mut body) = res.into_parts();
+            let (parts, body) = res.into_parts();
 
             // wrap and serialize response parts as rmp
             let response_parts = shuttle_next::ResponseWrapper::from(parts)
@@ -388,9 +735,10 @@ pub(crate) fn wasi_bindings(app: App) -> proc_macro2::TokenStream {
             // write response parts
             parts_fd.write_all(&response_parts).unwrap();
 
-            // write body if there is one
-            if let Some(body) = shuttle_next::block_on(body.data()) {
-                body_stream.write_all(body.unwrap().as_ref()).unwrap();
+            // write the body as the endpoint produces it, until the client goes away
+            match shuttle_next::write_body(body, &body_stream) {
+                Err(error) if error.kind() == std::io::ErrorKind::BrokenPipe => {}
+                result => result.unwrap(),
             }
         }
     )
@@ -404,7 +752,7 @@ mod tests {
 
     use crate::next::{App, Parameter};
 
-    use super::{Endpoint, Params};
+    use super::{Constructor, Endpoint, Params, Resource};
 
     #[test]
     fn endpoint_to_token() {
@@ -438,20 +786,22 @@ mod tests {
                             function: parse_quote!(goodbye),
                         },
                     ],
+                    resources: Vec::new(),
+                    state: None,
+                    layers: Vec::new(),
                 },
                 quote!(
                     async fn __app(
-                        request: shuttle_next::Request<shuttle_next::body::BoxBody>,
-                    ) -> shuttle_next::response::Response {
-                        use shuttle_next::Service;
-
-                        let mut router = shuttle_next::Router::new()
+                        _resources: Vec<shuttle_next::Value>,
+                    ) -> Result<
+                        shuttle_next::Router<(), shuttle_next::body::BoxBody>,
+                        shuttle_next::BoxError,
+                    > {
+                        let router = shuttle_next::Router::new()
                             .route("/goodbye", shuttle_next::routing::post(goodbye))
                             .route("/hello", shuttle_next::routing::get(hello));
 
-                        let response = router.call(request).await.unwrap();
-
-                        response
+                        Ok(router)
                     }
                 ),
             ),
@@ -474,23 +824,25 @@ mod tests {
                             function: parse_quote!(post_goodbye),
                         },
                     ],
+                    resources: Vec::new(),
+                    state: None,
+                    layers: Vec::new(),
                 },
                 quote!(
                     async fn __app(
-                        request: shuttle_next::Request<shuttle_next::body::BoxBody>,
-                    ) -> shuttle_next::response::Response {
-                        use shuttle_next::Service;
-
-                        let mut router = shuttle_next::Router::new()
+                        _resources: Vec<shuttle_next::Value>,
+                    ) -> Result<
+                        shuttle_next::Router<(), shuttle_next::body::BoxBody>,
+                        shuttle_next::BoxError,
+                    > {
+                        let router = shuttle_next::Router::new()
                             .route(
                                 "/goodbye",
                                 shuttle_next::routing::get(get_goodbye).post(post_goodbye)
                             )
                             .route("/hello", shuttle_next::routing::get(hello));
 
-                        let response = router.call(request).await.unwrap();
-
-                        response
+                        Ok(router)
                     }
                 ),
             ),
@@ -511,11 +863,11 @@ mod tests {
                 async fn hello() -> &'static str {
                     "Hello, World!"
                 }},
-                Some(Endpoint {
+                vec![Endpoint {
                     route: parse_quote!("/hello"),
                     method: parse_quote!(get),
                     function: parse_quote!(hello),
-                }),
+                }],
                 0,
             ),
             (
@@ -525,13 +877,34 @@ mod tests {
                 async fn hello() -> &'static str {
                     "Hello, World!"
                 }},
-                Some(Endpoint {
+                vec![Endpoint {
                     route: parse_quote!("/hello"),
                     method: parse_quote!(get),
                     function: parse_quote!(hello),
-                }),
+                }],
                 1,
             ),
+            (
+                parse_quote! {
+                #[shuttle_codegen::endpoint(method = get, route = "/hello")]
+                #[shuttle_codegen::endpoint(method = get, route = "/hello/:name")]
+                async fn hello() -> &'static str {
+                    "Hello, World!"
+                }},
+                vec![
+                    Endpoint {
+                        route: parse_quote!("/hello"),
+                        method: parse_quote!(get),
+                        function: parse_quote!(hello),
+                    },
+                    Endpoint {
+                        route: parse_quote!("/hello/:name"),
+                        method: parse_quote!(get),
+                        function: parse_quote!(hello),
+                    },
+                ],
+                0,
+            ),
             (
                 parse_quote! {
                     /// This attribute is not an endpoint so keep it
@@ -539,7 +912,7 @@ mod tests {
                         "Hello, World!"
                     }
                 },
-                None,
+                Vec::new(),
                 1,
             ),
         ];
@@ -628,11 +1001,228 @@ mod tests {
                     function: parse_quote!(goodbye),
                 },
             ],
+            resources: Vec::new(),
+            state: None,
+            layers: Vec::new(),
         };
 
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn parse_resources() {
+        let mut input = parse_quote! {
+            #[shuttle_codegen::endpoint(method = get, route = "/secret")]
+            async fn secret(
+                #[shuttle_secrets::Secrets(required = "API_KEY")] secrets: SecretStore,
+                body: String,
+            ) -> String {
+                secrets.get("API_KEY").unwrap()
+            }
+
+            #[shuttle_codegen::endpoint(method = get, route = "/also-secret")]
+            async fn also_secret(
+                #[shuttle_secrets::Secrets(required = "API_KEY")] secrets: SecretStore,
+            ) -> String {
+                secrets.get("API_KEY").unwrap()
+            }
+        };
+
+        let actual = App::from_file(&mut input);
+        let expected: Vec<Resource> = vec![Resource {
+            builder: parse_quote!(shuttle_secrets::Secrets),
+            options: Some(parse_quote!((required = "API_KEY"))),
+            ty: parse_quote!(SecretStore),
+        }];
+
+        // Both endpoints asked for the same resource, so it is only built once
+        assert_eq!(actual.resources, expected);
+
+        // And the endpoints extract it from the router instead
+        let expected: syn::File = parse_quote! {
+            async fn secret(
+                shuttle_next::Extension(secrets): shuttle_next::Extension<SecretStore>,
+                body: String,
+            ) -> String {
+                secrets.get("API_KEY").unwrap()
+            }
+
+            async fn also_secret(
+                shuttle_next::Extension(secrets): shuttle_next::Extension<SecretStore>,
+            ) -> String {
+                secrets.get("API_KEY").unwrap()
+            }
+        };
+
+        assert_eq!(quote!(#input).to_string(), quote!(#expected).to_string());
+    }
+
+    #[test]
+    #[rustfmt::skip::macros(quote)]
+    fn app_with_resources_to_token() {
+        let app = App {
+            endpoints: vec![Endpoint {
+                route: parse_quote!("/secret"),
+                method: parse_quote!(get),
+                function: parse_quote!(secret),
+            }],
+            resources: vec![Resource {
+                builder: parse_quote!(shuttle_secrets::Secrets),
+                options: None,
+                ty: parse_quote!(SecretStore),
+            }],
+            state: None,
+            layers: Vec::new(),
+        };
+
+        let actual = quote!(#app);
+        let expected = quote!(
+            async fn __app(
+                resources: Vec<shuttle_next::Value>,
+            ) -> Result<
+                shuttle_next::Router<(), shuttle_next::body::BoxBody>,
+                shuttle_next::BoxError,
+            > {
+                let mut resources = resources.into_iter();
+
+                let __resource_0 = shuttle_next::build_resource::<shuttle_secrets::Secrets, SecretStore>(
+                    resources.next()
+                )
+                .await?;
+
+                let router = shuttle_next::Router::new()
+                    .route("/secret", shuttle_next::routing::get(secret))
+                    .layer(shuttle_next::Extension(__resource_0));
+
+                Ok(router)
+            }
+        );
+
+        assert_eq!(actual.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn parse_state_and_layers() {
+        let mut input = parse_quote! {
+            #[shuttle_codegen::app_state]
+            fn state() -> AppState {
+                AppState::default()
+            }
+
+            #[shuttle_codegen::layer]
+            fn cors() -> CorsLayer {
+                CorsLayer::permissive()
+            }
+
+            /// Layers can be built asynchronously too
+            #[shuttle_codegen::layer]
+            async fn auth() -> RequireAuthorizationLayer {
+                RequireAuthorizationLayer::bearer("token")
+            }
+
+            #[shuttle_codegen::endpoint(method = get, route = "/hello")]
+            async fn hello(State(state): State<AppState>) -> String {
+                state.greeting.clone()
+            }
+        };
+
+        let actual = App::from_file(&mut input);
+        let expected = App {
+            endpoints: vec![Endpoint {
+                route: parse_quote!("/hello"),
+                method: parse_quote!(get),
+                function: parse_quote!(hello),
+            }],
+            resources: Vec::new(),
+            state: Some(Constructor {
+                function: parse_quote!(state),
+                is_async: false,
+            }),
+            layers: vec![
+                Constructor {
+                    function: parse_quote!(cors),
+                    is_async: false,
+                },
+                Constructor {
+                    function: parse_quote!(auth),
+                    is_async: true,
+                },
+            ],
+        };
+
+        assert_eq!(actual, expected);
+
+        // The functions are kept without their attributes
+        let expected: syn::File = parse_quote! {
+            fn state() -> AppState {
+                AppState::default()
+            }
+
+            fn cors() -> CorsLayer {
+                CorsLayer::permissive()
+            }
+
+            /// Layers can be built asynchronously too
+            async fn auth() -> RequireAuthorizationLayer {
+                RequireAuthorizationLayer::bearer("token")
+            }
+
+            async fn hello(State(state): State<AppState>) -> String {
+                state.greeting.clone()
+            }
+        };
+
+        assert_eq!(quote!(#input).to_string(), quote!(#expected).to_string());
+    }
+
+    #[test]
+    #[rustfmt::skip::macros(quote)]
+    fn app_with_state_and_layers_to_token() {
+        let app = App {
+            endpoints: vec![
+                Endpoint {
+                    route: parse_quote!("/hello"),
+                    method: parse_quote!(get),
+                    function: parse_quote!(hello),
+                },
+                Endpoint {
+                    route: parse_quote!("/hello/:name"),
+                    method: parse_quote!(get),
+                    function: parse_quote!(hello),
+                },
+            ],
+            resources: Vec::new(),
+            state: Some(Constructor {
+                function: parse_quote!(state),
+                is_async: true,
+            }),
+            layers: vec![Constructor {
+                function: parse_quote!(cors),
+                is_async: false,
+            }],
+        };
+
+        let actual = quote!(#app);
+        let expected = quote!(
+            async fn __app(
+                _resources: Vec<shuttle_next::Value>,
+            ) -> Result<
+                shuttle_next::Router<(), shuttle_next::body::BoxBody>,
+                shuttle_next::BoxError,
+            > {
+                let router = shuttle_next::Router::new()
+                    .route("/hello", shuttle_next::routing::get(hello))
+                    .route("/hello/:name", shuttle_next::routing::get(hello))
+                    .layer(cors())
+                    .with_state(state().await);
+
+                Ok(router)
+            }
+        );
+
+        assert_eq!(actual.to_string(), expected.to_string());
+    }
+
     #[test]
     fn ui() {
         let t = trybuild::TestCases::new();
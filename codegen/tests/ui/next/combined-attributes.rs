@@ -0,0 +1,19 @@
+shuttle_codegen::app! {
+    #[shuttle_codegen::app_state]
+    #[shuttle_codegen::endpoint(method = get, route = "/state")]
+    fn state() -> u32 {
+        1
+    }
+
+    #[shuttle_codegen::layer]
+    #[shuttle_codegen::endpoint(method = get, route = "/layer")]
+    async fn layer() -> u32 {
+        1
+    }
+
+    #[shuttle_codegen::app_state]
+    #[shuttle_codegen::layer]
+    fn both() -> u32 {
+        1
+    }
+}
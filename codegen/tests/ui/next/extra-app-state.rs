@@ -0,0 +1,11 @@
+shuttle_codegen::app! {
+    #[shuttle_codegen::app_state]
+    fn state() -> u32 {
+        1
+    }
+
+    #[shuttle_codegen::app_state]
+    fn other_state() -> u32 {
+        2
+    }
+}
@@ -0,0 +1,14 @@
+shuttle_codegen::app! {
+    #[shuttle_codegen::app_state(clone = true)]
+    fn with_arguments() -> u32 {
+        1
+    }
+
+    #[shuttle_codegen::app_state]
+    fn with_parameters(count: u32) -> u32 {
+        count
+    }
+
+    #[shuttle_codegen::app_state]
+    fn without_state() {}
+}
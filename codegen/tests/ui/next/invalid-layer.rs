@@ -0,0 +1,15 @@
+shuttle_codegen::app! {
+    #[shuttle_codegen::layer]
+    #[shuttle_codegen::layer]
+    fn twice() -> CorsLayer {
+        CorsLayer::permissive()
+    }
+
+    #[shuttle_codegen::layer]
+    fn with_parameters(origin: &str) -> CorsLayer {
+        CorsLayer::new().allow_origin(origin)
+    }
+
+    #[shuttle_codegen::layer]
+    async fn without_layer() {}
+}
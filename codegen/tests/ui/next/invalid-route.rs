@@ -0,0 +1,11 @@
+shuttle_codegen::app! {
+    #[shuttle_codegen::endpoint(method = get, route = "hello")]
+    async fn hello() -> &'static str {
+        "Hello, World!"
+    }
+
+    #[shuttle_codegen::endpoint(method = get, route = "/users/:")]
+    async fn user() -> &'static str {
+        "Hello, user!"
+    }
+}
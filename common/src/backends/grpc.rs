@@ -0,0 +1,85 @@
+use http::header::{HeaderValue, CONTENT_TYPE, HOST};
+use http::{Request, Version};
+
+/// Check if a request is a gRPC call. These need HTTP/2 all the way to the service, since their
+/// status is sent in the trailers of the response.
+pub fn is_grpc_request<B>(req: &Request<B>) -> bool {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |content_type| {
+            content_type.starts_with("application/grpc")
+        })
+}
+
+/// Get a request ready to be forwarded by a proxy. HTTP/2 requests carry their host in the URI
+/// rather than in a `Host` header, so the header is set from the URI for the next hop. Anything
+/// which is not a gRPC call is forwarded over HTTP/1.1 no matter what the client spoke.
+pub fn prepare_for_forwarding<B>(req: &mut Request<B>) {
+    if !req.headers().contains_key(HOST) {
+        if let Some(host) = req
+            .uri()
+            .authority()
+            .and_then(|authority| HeaderValue::from_str(authority.as_str()).ok())
+        {
+            req.headers_mut().insert(HOST, host);
+        }
+    }
+
+    if !is_grpc_request(req) {
+        *req.version_mut() = Version::HTTP_11;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::HOST;
+    use http::{Request, Version};
+
+    use super::{is_grpc_request, prepare_for_forwarding};
+
+    #[test]
+    fn grpc_request() {
+        let request = |content_type: &str| {
+            Request::post("/grpc.health.v1.Health/Check")
+                .header("content-type", content_type)
+                .body(())
+                .unwrap()
+        };
+
+        assert!(is_grpc_request(&request("application/grpc")));
+        assert!(is_grpc_request(&request("application/grpc+proto")));
+        assert!(!is_grpc_request(&request("application/json")));
+        assert!(!is_grpc_request(&Request::get("/").body(()).unwrap()));
+    }
+
+    #[test]
+    fn forwarding() {
+        let mut grpc = Request::post("https://matrix.shuttleapp.rs/grpc.health.v1.Health/Check")
+            .version(Version::HTTP_2)
+            .header("content-type", "application/grpc")
+            .body(())
+            .unwrap();
+        prepare_for_forwarding(&mut grpc);
+
+        assert_eq!(grpc.version(), Version::HTTP_2);
+        assert_eq!(grpc.headers()[HOST], "matrix.shuttleapp.rs");
+
+        let mut browser = Request::get("https://matrix.shuttleapp.rs/hello")
+            .version(Version::HTTP_2)
+            .body(())
+            .unwrap();
+        prepare_for_forwarding(&mut browser);
+
+        assert_eq!(browser.version(), Version::HTTP_11);
+        assert_eq!(browser.headers()[HOST], "matrix.shuttleapp.rs");
+
+        let mut http1 = Request::get("/hello")
+            .header("host", "zion.shuttleapp.rs")
+            .body(())
+            .unwrap();
+        prepare_for_forwarding(&mut http1);
+
+        assert_eq!(http1.headers()[HOST], "zion.shuttleapp.rs");
+    }
+}
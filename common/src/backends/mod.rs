@@ -1,6 +1,8 @@
 pub mod auth;
 pub mod cache;
 mod future;
+pub mod grpc;
 pub mod headers;
 pub mod metrics;
 pub mod tracing;
+pub mod upgrade;
@@ -0,0 +1,308 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use http::header::{HeaderName, CONNECTION, UPGRADE};
+use http::uri::{PathAndQuery, Uri};
+use http::{HeaderValue, Request, Response, StatusCode};
+use hyper::client::connect::Connect;
+use hyper::upgrade::OnUpgrade;
+use hyper::{Body, Client};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::trace;
+
+static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// Size of the buffers used to copy between connections
+const BUFFER_SIZE: usize = 8 * 1024;
+
+/// Check if a request asks to upgrade its connection, such as to a WebSocket
+pub fn is_upgrade_request<B>(req: &Request<B>) -> bool {
+    let headers = req.headers();
+
+    headers.contains_key(UPGRADE)
+        && headers.get_all(CONNECTION).iter().any(|value| {
+            value.to_str().map_or(false, |value| {
+                value
+                    .split(',')
+                    .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+            })
+        })
+}
+
+/// Forward an upgrade request from `client_ip` to the service at `target`. The response of the
+/// service is returned as is, along with an [Upgrade] when the service agreed to switch
+/// protocols. The [Upgrade] has to be piped for the upgraded connection to carry any data.
+pub async fn forward_upgrade<C>(
+    client: &Client<C>,
+    client_ip: IpAddr,
+    target: SocketAddr,
+    mut req: Request<Body>,
+) -> Result<(Response<Body>, Option<Upgrade>), hyper::Error>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    let downstream = hyper::upgrade::on(&mut req);
+    let (mut parts, body) = req.into_parts();
+
+    parts.uri = Uri::builder()
+        .scheme("http")
+        .authority(target.to_string())
+        .path_and_query(
+            parts
+                .uri
+                .path_and_query()
+                .cloned()
+                .unwrap_or_else(|| PathAndQuery::from_static("/")),
+        )
+        .build()
+        .expect("a socket address and a path to make a valid uri");
+
+    let forwarded_for = match parts
+        .headers
+        .get(&X_FORWARDED_FOR)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(forwarded_for) => format!("{forwarded_for}, {client_ip}"),
+        None => client_ip.to_string(),
+    };
+    if let Ok(forwarded_for) = HeaderValue::from_str(&forwarded_for) {
+        parts.headers.insert(X_FORWARDED_FOR.clone(), forwarded_for);
+    }
+
+    let mut response = client.request(Request::from_parts(parts, body)).await?;
+
+    let upgrade = (response.status() == StatusCode::SWITCHING_PROTOCOLS).then(|| Upgrade {
+        downstream,
+        upstream: hyper::upgrade::on(&mut response),
+    });
+
+    Ok((response, upgrade))
+}
+
+/// Both sides of a connection which is being upgraded
+pub struct Upgrade {
+    downstream: OnUpgrade,
+    upstream: OnUpgrade,
+}
+
+impl Upgrade {
+    /// Wait for both sides to be upgraded and then copy between them until either side closes,
+    /// or nothing was sent for `idle_timeout`. Returns how many bytes were sent upstream and
+    /// downstream.
+    pub async fn pipe(self, idle_timeout: Duration) -> io::Result<(u64, u64)> {
+        let (mut downstream, mut upstream) = tokio::try_join!(self.downstream, self.upstream)
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+
+        let result = copy_bidirectional(&mut downstream, &mut upstream, idle_timeout).await;
+
+        let _ = downstream.shutdown().await;
+        let _ = upstream.shutdown().await;
+
+        result
+    }
+}
+
+/// Copy between two connections, such as upgraded or raw TCP ones, until either side closes, or
+/// nothing was sent for `idle_timeout`. Returns how many bytes were sent upstream and downstream.
+pub async fn copy_bidirectional<D, U>(
+    downstream: &mut D,
+    upstream: &mut U,
+    idle_timeout: Duration,
+) -> io::Result<(u64, u64)>
+where
+    D: AsyncRead + AsyncWrite + Unpin,
+    U: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut downstream_buf = vec![0; BUFFER_SIZE];
+    let mut upstream_buf = vec![0; BUFFER_SIZE];
+    let mut sent_upstream = 0;
+    let mut sent_downstream = 0;
+
+    loop {
+        // Reads are cancel safe, so the read which loses the race does not lose any data
+        let read = tokio::time::timeout(idle_timeout, async {
+            tokio::select! {
+                read = downstream.read(&mut downstream_buf) => (read, true),
+                read = upstream.read(&mut upstream_buf) => (read, false),
+            }
+        })
+        .await;
+
+        let Ok((read, from_downstream)) = read else {
+            trace!(?idle_timeout, "connection is idle, closing it");
+            break;
+        };
+
+        let read = read?;
+
+        if read == 0 {
+            break;
+        }
+
+        if from_downstream {
+            upstream.write_all(&downstream_buf[..read]).await?;
+            upstream.flush().await?;
+            sent_upstream += read as u64;
+        } else {
+            downstream.write_all(&upstream_buf[..read]).await?;
+            downstream.flush().await?;
+            sent_downstream += read as u64;
+        }
+    }
+
+    Ok((sent_upstream, sent_downstream))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use axum::extract::ws::WebSocketUpgrade;
+    use axum::routing::get;
+    use axum::Router;
+    use futures::{SinkExt, StreamExt};
+    use hyper::server::conn::AddrStream;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Client, Request, Server};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::connect_async;
+    use tokio_tungstenite::tungstenite::Message;
+
+    use super::{copy_bidirectional, forward_upgrade, is_upgrade_request};
+
+    /// Start a WebSocket service which echoes every message back
+    fn spawn_echo() -> SocketAddr {
+        let router = Router::new().route(
+            "/ws",
+            get(|ws: WebSocketUpgrade| async {
+                ws.on_upgrade(|mut socket| async move {
+                    while let Some(Ok(message)) = socket.recv().await {
+                        if socket.send(message).await.is_err() {
+                            break;
+                        }
+                    }
+                })
+            }),
+        );
+
+        let server =
+            Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(router.into_make_service());
+        let address = server.local_addr();
+        tokio::spawn(server);
+
+        address
+    }
+
+    /// Start a proxy which forwards every upgrade to `target`
+    fn spawn_proxy(target: SocketAddr, idle_timeout: Duration) -> SocketAddr {
+        let make_service = make_service_fn(move |socket: &AddrStream| {
+            let client_ip = socket.remote_addr().ip();
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| async move {
+                    let (response, upgrade) =
+                        forward_upgrade(&Client::new(), client_ip, target, req).await?;
+
+                    if let Some(upgrade) = upgrade {
+                        tokio::spawn(upgrade.pipe(idle_timeout));
+                    }
+
+                    Ok::<_, hyper::Error>(response)
+                }))
+            }
+        });
+
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let address = server.local_addr();
+        tokio::spawn(server);
+
+        address
+    }
+
+    #[test]
+    fn upgrade_request() {
+        let request = |connection: &str| {
+            Request::get("/ws")
+                .header("connection", connection)
+                .header("upgrade", "websocket")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        assert!(is_upgrade_request(&request("Upgrade")));
+        assert!(is_upgrade_request(&request("keep-alive, upgrade")));
+        assert!(!is_upgrade_request(&request("keep-alive")));
+        assert!(!is_upgrade_request(
+            &Request::get("/ws").body(Body::empty()).unwrap()
+        ));
+    }
+
+    #[tokio::test]
+    async fn websocket_through_two_hops() {
+        let echo = spawn_echo();
+        let deployer = spawn_proxy(echo, Duration::from_secs(60));
+        let gateway = spawn_proxy(deployer, Duration::from_secs(60));
+
+        let (mut ws, _) = connect_async(format!("ws://{gateway}/ws")).await.unwrap();
+
+        for text in ["hello", "world"] {
+            ws.send(Message::Text(text.to_string())).await.unwrap();
+
+            assert_eq!(
+                ws.next().await.unwrap().unwrap(),
+                Message::Text(text.to_string())
+            );
+        }
+
+        ws.close(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn idle_timeout() {
+        let echo = spawn_echo();
+        let proxy = spawn_proxy(echo, Duration::from_millis(100));
+
+        let (mut ws, _) = connect_async(format!("ws://{proxy}/ws")).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        assert!(matches!(ws.next().await, None | Some(Err(_))));
+    }
+
+    #[tokio::test]
+    async fn raw_tcp() {
+        let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let echo_address = echo.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = echo.accept().await.unwrap();
+            let (mut read, mut write) = stream.split();
+            tokio::io::copy(&mut read, &mut write).await.unwrap();
+        });
+
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_address = proxy.local_addr().unwrap();
+        let piped = tokio::spawn(async move {
+            let (mut downstream, _) = proxy.accept().await.unwrap();
+            let mut upstream = TcpStream::connect(echo_address).await.unwrap();
+
+            copy_bidirectional(&mut downstream, &mut upstream, Duration::from_secs(60))
+                .await
+                .unwrap()
+        });
+
+        let mut client = TcpStream::connect(proxy_address).await.unwrap();
+        let mut buf = [0; 5];
+
+        client.write_all(b"hello").await.unwrap();
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        drop(client);
+
+        assert_eq!(piped.await.unwrap(), (5, 5));
+    }
+}
@@ -64,6 +64,21 @@ pub enum Scope {
     /// Add or update secrets of a project
     SecretWrite,
 
+    /// List the custom domains of a project
+    CustomDomain,
+
+    /// Add, renew or remove the custom domains of a project
+    CustomDomainWrite,
+
+    /// Read the traffic statistics of a project
+    Stats,
+
+    /// Get the public TCP port of a project
+    Tcp,
+
+    /// Open or close the public TCP port of a project
+    TcpWrite,
+
     /// Get list of users
     User,
 
@@ -105,6 +120,11 @@ impl ScopeBuilder {
             Scope::ResourcesWrite,
             Scope::Secret,
             Scope::SecretWrite,
+            Scope::CustomDomain,
+            Scope::CustomDomainWrite,
+            Scope::Stats,
+            Scope::Tcp,
+            Scope::TcpWrite,
         ])
     }
 
@@ -122,6 +142,11 @@ impl ScopeBuilder {
             Scope::ResourcesWrite,
             Scope::Secret,
             Scope::SecretWrite,
+            Scope::CustomDomain,
+            Scope::CustomDomainWrite,
+            Scope::Stats,
+            Scope::Tcp,
+            Scope::TcpWrite,
         ]);
         self
     }
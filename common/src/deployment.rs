@@ -17,11 +17,205 @@ pub enum State {
     Completed,
     Stopped,
     Crashed,
+    Restarting,
     Unknown,
 }
 
+/// Probe for the health of a deployed service, as declared in the `[health_check]` table of a
+/// `Shuttle.toml`:
+///
+/// ```toml
+/// [health_check]
+/// path = "/health"
+/// interval = 30
+/// grace_period = 10
+/// ```
+///
+/// Unknown keys are rejected, in particular the `restart` key this table used to have, since
+/// restarts are now declared by the `[restart]` table with a [RestartPolicy].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct HealthCheck {
+    /// Path which should answer with a successful status code
+    pub path: String,
+
+    /// Seconds between probes
+    #[serde(default = "default_health_check_interval")]
+    pub interval: u64,
+
+    /// Seconds a probe waits for an answer
+    #[serde(default = "default_health_check_timeout")]
+    pub timeout: u64,
+
+    /// Failed probes in a row after which the deployment is considered crashed, and restarted
+    /// according to its [RestartPolicy]
+    #[serde(default = "default_health_check_failure_threshold")]
+    pub failure_threshold: u32,
+
+    /// Seconds to wait after the deployment started before probing it, for services which take a
+    /// while to become healthy
+    #[serde(default = "default_health_check_grace_period")]
+    pub grace_period: u64,
+}
+
+fn default_health_check_interval() -> u64 {
+    30
+}
+
+fn default_health_check_timeout() -> u64 {
+    5
+}
+
+fn default_health_check_failure_threshold() -> u32 {
+    3
+}
+
+fn default_health_check_grace_period() -> u64 {
+    10
+}
+
+/// How a new deployment takes over the traffic of the deployments it replaces, as declared in the
+/// `[readiness]` table of a `Shuttle.toml`:
+///
+/// ```toml
+/// [readiness]
+/// path = "/ready"
+/// warm_up = 5
+/// ```
+///
+/// Requests keep going to the old deployments until the new one is ready. Without a `path`, the
+/// path of the health check is probed instead, or the new deployment is ready as soon as it accepts
+/// connections.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Readiness {
+    /// Path which should answer with a successful status code once the deployment is ready
+    pub path: Option<String>,
+
+    /// Seconds to wait after the deployment is ready before it gets any requests
+    #[serde(default)]
+    pub warm_up: u64,
+
+    /// Seconds the deployment has to become ready
+    #[serde(default = "default_readiness_timeout")]
+    pub timeout: u64,
+
+    /// Seconds the old deployments get to finish the requests they are handling before they are
+    /// stopped
+    #[serde(default = "default_readiness_drain_timeout")]
+    pub drain_timeout: u64,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self {
+            path: None,
+            warm_up: 0,
+            timeout: default_readiness_timeout(),
+            drain_timeout: default_readiness_drain_timeout(),
+        }
+    }
+}
+
+fn default_readiness_timeout() -> u64 {
+    60
+}
+
+fn default_readiness_drain_timeout() -> u64 {
+    10
+}
+
+/// When a deployment which stopped without being asked to is started again, as declared in the
+/// `[restart]` table of a `Shuttle.toml`:
+///
+/// ```toml
+/// [restart]
+/// policy = "on-failure"
+/// max_restarts = 5
+/// ```
+///
+/// The wait before every restart doubles, starting from a second.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "policy", rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Leave the deployment stopped
+    #[default]
+    Never,
+
+    /// Restart the deployment when it crashes, at most `max_restarts` times
+    OnFailure {
+        #[serde(default = "default_restart_policy_max_restarts")]
+        max_restarts: u32,
+    },
+
+    /// Restart the deployment whenever it stops, unless it was stopped by the user
+    Always,
+}
+
+fn default_restart_policy_max_restarts() -> u32 {
+    5
+}
+
+/// Raw TCP connections a deployed service accepts on the address it binds to, instead of HTTP
+/// requests, as declared in the `[tcp]` table of a `Shuttle.toml`:
+///
+/// ```toml
+/// [tcp]
+/// idle_timeout = 600
+/// ```
+///
+/// The connections come in on the public port the gateway allocated to the project with
+/// `cargo shuttle tcp open`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Tcp {
+    /// Seconds a connection can go without sending anything before it is closed
+    #[serde(default = "default_tcp_idle_timeout")]
+    pub idle_timeout: u64,
+}
+
+impl Default for Tcp {
+    fn default() -> Self {
+        Self {
+            idle_timeout: default_tcp_idle_timeout(),
+        }
+    }
+}
+
+fn default_tcp_idle_timeout() -> u64 {
+    5 * 60
+}
+
+/// How a shuttle-next deployment handles requests, as declared in the `[next]` table of a
+/// `Shuttle.toml`:
+///
+/// ```toml
+/// [next]
+/// body_limit = 1048576
+/// ```
+///
+/// Bodies are streamed to and from the service, so the limit only bounds how much a single request
+/// can upload rather than how much memory it takes.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Next {
+    /// Largest request body a service accepts, in bytes. Larger requests are answered with a 413.
+    #[serde(default = "default_next_body_limit")]
+    pub body_limit: u64,
+}
+
+impl Default for Next {
+    fn default() -> Self {
+        Self {
+            body_limit: default_next_body_limit(),
+        }
+    }
+}
+
+fn default_next_body_limit() -> u64 {
+    64 * 1024
+}
+
 /// This which environment is this deployment taking place
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Environment {
     Local,
     Production,
@@ -12,6 +12,7 @@ pub mod models;
 #[cfg(feature = "service")]
 pub mod project;
 pub mod resource;
+pub mod secrets;
 #[cfg(feature = "service")]
 pub mod storage_manager;
 #[cfg(feature = "tracing")]
@@ -20,6 +21,8 @@ pub mod tracing;
 pub mod wasm;
 
 use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "service")]
@@ -123,6 +126,21 @@ impl DatabaseReadyInfo {
     }
 }
 
+/// Holds the details to reach a bucket on an S3-compatible object storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BucketReadyInfo {
+    pub bucket_name: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Set when the credentials are temporary ones
+    pub session_token: Option<String>,
+    pub region: String,
+    /// Endpoint to reach the storage at from within the platform
+    pub endpoint_private: String,
+    /// Endpoint to reach the storage at from anywhere else
+    pub endpoint_public: String,
+}
+
 /// Store that holds all the secrets available to a deployment
 #[derive(Deserialize, Serialize, Clone)]
 pub struct SecretStore {
@@ -137,4 +155,25 @@ impl SecretStore {
     pub fn get(&self, key: &str) -> Option<String> {
         self.secrets.get(key).map(ToOwned::to_owned)
     }
+
+    /// Get a secret parsed as `T`, failing if it is not set or does not parse
+    pub fn get_parsed<T>(&self, key: &str) -> Result<T, secrets::ParseSecretError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self
+            .secrets
+            .get(key)
+            .ok_or_else(|| secrets::ParseSecretError::Missing {
+                key: key.to_string(),
+            })?;
+
+        value
+            .parse()
+            .map_err(|error: T::Err| secrets::ParseSecretError::Invalid {
+                key: key.to_string(),
+                reason: error.to_string(),
+            })
+    }
 }
@@ -5,6 +5,7 @@ use chrono::{DateTime, Utc};
 #[cfg(feature = "display")]
 use crossterm::style::{StyledContent, Stylize};
 use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
 #[cfg(feature = "openapi")]
 use utoipa::ToSchema;
 use uuid::Uuid;
@@ -85,8 +86,12 @@ impl std::fmt::Display for Item {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+/// Levels are ordered from least to most severe
+#[derive(
+    Clone, Debug, Deserialize, Display, EnumString, Serialize, Eq, PartialEq, Ord, PartialOrd,
+)]
 #[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
 #[cfg_attr(feature = "openapi", derive(ToSchema))]
 #[cfg_attr(feature = "openapi", schema(as = shuttle_common::log::Level))]
 pub enum Level {
@@ -110,6 +115,145 @@ impl Level {
     }
 }
 
+/// Filters to only get some of the logs of a deployment
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Query {
+    /// Only logs at this level or a more severe one
+    pub level: Option<Level>,
+    /// Only logs with a target starting with this
+    pub target: Option<String>,
+    /// Only logs from this moment onwards
+    pub since: Option<DateTime<Utc>>,
+    /// Only logs up to this moment
+    pub until: Option<DateTime<Utc>>,
+    /// Only logs with a message containing this
+    pub search: Option<String>,
+    /// Match `search` as a regular expression instead
+    #[serde(default)]
+    pub regex: bool,
+}
+
+/// Points at the last log of a page, so that the next page can continue right after it
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cursor {
+    pub id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    /// Orders logs with the same timestamp
+    pub position: i64,
+}
+
+impl std::fmt::Display for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}_{}_{}",
+            self.id,
+            self.timestamp
+                .to_rfc3339_opts(chrono::SecondsFormat::Nanos, true),
+            self.position
+        )
+    }
+}
+
+impl std::str::FromStr for Cursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid cursor {s:?}");
+        let mut parts = s.split('_');
+
+        let (Some(id), Some(timestamp), Some(position), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+
+        Ok(Self {
+            id: id.parse().map_err(|_| invalid())?,
+            timestamp: DateTime::parse_from_rfc3339(timestamp)
+                .map_err(|_| invalid())?
+                .with_timezone(&Utc),
+            position: position.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl TryFrom<String> for Cursor {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Cursor> for String {
+    fn from(cursor: Cursor) -> Self {
+        cursor.to_string()
+    }
+}
+
+/// Which page of the logs of a deployment to get
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PageQuery {
+    /// Continue after this cursor, or start from the oldest log when not set
+    pub cursor: Option<Cursor>,
+    /// Maximum number of logs to get
+    pub limit: Option<u32>,
+}
+
+/// One page of the logs of a deployment
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::log::Page))]
+pub struct Page {
+    pub logs: Vec<Item>,
+    /// Cursor for the next page, if there are more logs
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<String>))]
+    pub next: Option<Cursor>,
+}
+
+/// How long the logs of a service are kept
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::log::Retention))]
+pub struct Retention {
+    /// Delete logs older than this many hours
+    pub max_age_hours: Option<u32>,
+    /// Only keep this many of the most recent log lines
+    pub max_lines: Option<u32>,
+}
+
+/// An external collector the logs of a project are forwarded to
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::log::Drain))]
+pub struct Drain {
+    #[serde(flatten)]
+    pub target: DrainTarget,
+    /// Also forward the logs of deployments while they are built, and not only their runtime logs
+    #[serde(default)]
+    pub build_logs: bool,
+}
+
+/// Where the logs of a drain are sent to
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::log::DrainTarget))]
+pub enum DrainTarget {
+    /// OTLP over gRPC, like `https://collector.example.com:4317`. Logs sent to an `http://`
+    /// endpoint are not encrypted.
+    Otlp { endpoint: String },
+    /// RFC 5424 syslog over TCP, like `logs.example.com:6514` with `tls` or `logs.example.com:514`
+    /// without it. Logs are only encrypted with `tls`.
+    Syslog {
+        address: String,
+        #[serde(default)]
+        tls: bool,
+    },
+}
+
 impl From<&tracing::Level> for Level {
     fn from(level: &tracing::Level) -> Self {
         match *level {
@@ -121,3 +265,44 @@ impl From<&tracing::Level> for Level {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn cursor_round_trip() {
+        let cursor = Cursor {
+            id: Uuid::new_v4(),
+            timestamp: Utc.timestamp_opt(1682398000, 123456789).unwrap(),
+            position: 42,
+        };
+
+        assert_eq!(cursor.to_string().parse::<Cursor>().unwrap(), cursor);
+        assert!("not-a-cursor".parse::<Cursor>().is_err());
+        assert!(format!("{}_yesterday_42", cursor.id)
+            .parse::<Cursor>()
+            .is_err());
+        assert!(format!("{cursor}_42").parse::<Cursor>().is_err());
+    }
+
+    #[test]
+    fn drain_defaults() {
+        let drain: Drain =
+            serde_json::from_str(r#"{"type": "syslog", "address": "logs.example.com:514"}"#)
+                .unwrap();
+
+        assert_eq!(
+            drain,
+            Drain {
+                target: DrainTarget::Syslog {
+                    address: "logs.example.com:514".to_string(),
+                    tls: false,
+                },
+                build_logs: false,
+            }
+        );
+    }
+}
@@ -25,6 +25,14 @@ pub struct Response {
     pub state: State,
     #[cfg_attr(feature = "openapi", schema(value_type = KnownFormat::DateTime))]
     pub last_update: DateTime<Utc>,
+    /// When the last scheduled run of a cron service started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<KnownFormat::DateTime>))]
+    pub last_run: Option<DateTime<Utc>>,
+    /// When the next scheduled run of a cron service is due
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<KnownFormat::DateTime>))]
+    pub next_run: Option<DateTime<Utc>>,
 }
 
 impl Display for Response {
@@ -38,7 +46,17 @@ impl Display for Response {
                 .dim(),
             self.id,
             self.state.to_string().cyan()
-        )
+        )?;
+
+        if let Some(last_run) = self.last_run {
+            write!(f, "\n  last run: {}", last_run.format("%Y-%m-%dT%H:%M:%SZ"))?;
+        }
+
+        if let Some(next_run) = self.next_run {
+            write!(f, "\n  next run: {}", next_run.format("%Y-%m-%dT%H:%M:%SZ"))?;
+        }
+
+        Ok(())
     }
 }
 
@@ -49,7 +67,7 @@ impl State {
             State::Running => Color::Green,
             State::Completed | State::Stopped => Color::Blue,
             State::Crashed => Color::Red,
-            State::Unknown => Color::Yellow,
+            State::Restarting | State::Unknown => Color::Yellow,
         }
     }
 }
@@ -0,0 +1,56 @@
+use chrono::{DateTime, Utc};
+use comfy_table::{
+    modifiers::UTF8_ROUND_CORNERS, presets::UTF8_FULL, Attribute, Cell, CellAlignment,
+    ContentArrangement, Table,
+};
+use crossterm::style::Stylize;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::domain::Response))]
+pub struct Response {
+    pub fqdn: String,
+    pub project_name: String,
+    /// When the certificate of the domain stops being valid
+    #[cfg_attr(feature = "openapi", schema(value_type = KnownFormat::DateTime))]
+    pub expires: DateTime<Utc>,
+}
+
+pub fn get_table(domains: &Vec<Response>, project_name: &str) -> String {
+    if domains.is_empty() {
+        format!(
+            "{}\n",
+            "No custom domains are linked to this project".bold()
+        )
+    } else {
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .apply_modifier(UTF8_ROUND_CORNERS)
+            .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+            .set_header(vec![
+                Cell::new("Domain")
+                    .set_alignment(CellAlignment::Center)
+                    .add_attribute(Attribute::Bold),
+                Cell::new("Certificate expires")
+                    .set_alignment(CellAlignment::Center)
+                    .add_attribute(Attribute::Bold),
+            ]);
+
+        for domain in domains.iter() {
+            table.add_row(vec![
+                domain.fqdn.to_string(),
+                domain.expires.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            ]);
+        }
+
+        format!(
+            r#"These custom domains are linked to {project_name}
+{table}
+"#,
+        )
+    }
+}
@@ -48,7 +48,13 @@ pub enum ErrorKind {
     CustomDomainNotFound,
     InvalidCustomDomain,
     CustomDomainAlreadyExists,
+    CustomDomainMisconfigured,
     InvalidOperation,
+    RateLimited,
+    PayloadTooLarge,
+    TooManyConnections,
+    TcpPortNotFound,
+    TcpPortsExhausted,
     Internal,
     NotReady,
     ServiceUnavailable,
@@ -100,6 +106,30 @@ impl From<ErrorKind> for ApiError {
             ErrorKind::CustomDomainAlreadyExists => {
                 (StatusCode::BAD_REQUEST, "custom domain already in use")
             }
+            ErrorKind::CustomDomainMisconfigured => (
+                StatusCode::BAD_REQUEST,
+                "custom domain does not point to this project. Add a CNAME record for it with the shuttle address of the project as its target, and try again once it has propagated.",
+            ),
+            ErrorKind::RateLimited => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "too many requests, please slow down",
+            ),
+            ErrorKind::PayloadTooLarge => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "request body is larger than this project allows",
+            ),
+            ErrorKind::TooManyConnections => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "project has too many requests in flight, please try again in a little bit",
+            ),
+            ErrorKind::TcpPortNotFound => (
+                StatusCode::NOT_FOUND,
+                "project has no public TCP port. Run `cargo shuttle tcp open` to get one.",
+            ),
+            ErrorKind::TcpPortsExhausted => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "no public TCP ports are left, please try again later",
+            ),
             ErrorKind::Unauthorized => (StatusCode::UNAUTHORIZED, "unauthorized"),
             ErrorKind::Forbidden => (StatusCode::FORBIDDEN, "forbidden"),
             ErrorKind::NotReady => (StatusCode::INTERNAL_SERVER_ERROR, "service not ready"),
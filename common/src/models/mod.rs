@@ -1,10 +1,12 @@
 pub mod deployment;
+pub mod domain;
 pub mod error;
 pub mod project;
 pub mod resource;
 pub mod secret;
 pub mod service;
 pub mod stats;
+pub mod tcp;
 pub mod user;
 
 use anyhow::{Context, Result};
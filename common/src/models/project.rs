@@ -174,6 +174,25 @@ impl State {
 #[derive(Deserialize, Serialize)]
 pub struct Config {
     pub idle_minutes: u64,
+    #[serde(default)]
+    pub limits: Limits,
+}
+
+/// Limits on the traffic the gateway lets through to a project. Every limit is off when unset.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Limits {
+    /// Requests per second allowed from a single client IP, which can also burst up to this many.
+    /// Behind a load balancer, the client is taken from `X-Forwarded-For` when the gateway trusts
+    /// the balancer. Raw TCP connections are always limited by the address they come from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests_per_second: Option<u32>,
+    /// Largest request body allowed, in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_body_bytes: Option<u64>,
+    /// Client connections allowed to be open to the project at the same time. All the requests
+    /// sent over one HTTP connection count as a single connection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<u32>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -8,7 +8,7 @@ use crossterm::style::Stylize;
 
 use crate::{
     resource::{Response, Type},
-    DbOutput, SecretStore,
+    BucketReadyInfo, DbOutput, SecretStore,
 };
 
 pub fn get_resources_table(resources: &Vec<Response>, service_name: &str) -> String {
@@ -21,6 +21,7 @@ pub fn get_resources_table(resources: &Vec<Response>, service_name: &str) -> Str
                 Type::Secrets => "Secrets",
                 Type::StaticFolder => "Static Folder",
                 Type::Persist => "Persist",
+                Type::ObjectStorage => "Object Storage",
             };
 
             let elements = acc.entry(title).or_insert(Vec::new());
@@ -47,6 +48,10 @@ pub fn get_resources_table(resources: &Vec<Response>, service_name: &str) -> Str
             output.push(get_persist_table(persist, service_name));
         };
 
+        if let Some(buckets) = resource_groups.get("Object Storage") {
+            output.push(get_object_storage_table(buckets, service_name));
+        };
+
         output.join("\n")
     }
 }
@@ -158,3 +163,34 @@ fn get_persist_table(persist_instances: &[&Response], service_name: &str) -> Str
         service_name
     )
 }
+
+fn get_object_storage_table(buckets: &[&Response], service_name: &str) -> String {
+    let mut table = Table::new();
+
+    table
+        .load_preset(UTF8_FULL)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+        .set_header(vec![
+            Cell::new("Bucket")
+                .add_attribute(Attribute::Bold)
+                .set_alignment(CellAlignment::Center),
+            Cell::new("Endpoint")
+                .add_attribute(Attribute::Bold)
+                .set_alignment(CellAlignment::Center),
+        ]);
+
+    for bucket in buckets {
+        let info = serde_json::from_value::<BucketReadyInfo>(bucket.data.clone()).unwrap();
+
+        table.add_row(vec![info.bucket_name, info.endpoint_public]);
+    }
+
+    format!(
+        r#"These {} are linked to {}
+{table}
+"#,
+        "buckets".bold(),
+        service_name
+    )
+}
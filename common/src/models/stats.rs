@@ -1,3 +1,10 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use comfy_table::{
+    modifiers::UTF8_ROUND_CORNERS, presets::UTF8_FULL, Attribute, Cell, CellAlignment,
+    ContentArrangement, Table,
+};
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "openapi")]
 use utoipa::ToSchema;
@@ -17,3 +24,90 @@ pub struct LoadResponse {
     pub builds_count: usize,
     pub has_capacity: bool,
 }
+
+/// How far back to look at the traffic of a project
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::stats::TrafficPeriod))]
+pub enum TrafficPeriod {
+    #[default]
+    Hour,
+    Day,
+}
+
+impl Display for TrafficPeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrafficPeriod::Hour => write!(f, "hour"),
+            TrafficPeriod::Day => write!(f, "day"),
+        }
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct TrafficQuery {
+    #[serde(default)]
+    pub period: TrafficPeriod,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::stats::TrafficResponse))]
+pub struct TrafficResponse {
+    pub requests: u64,
+    /// Responses by the class of their status, such as `4xx`
+    pub statuses: BTreeMap<String, u64>,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// Latency percentiles in milliseconds. Each is the upper bound of the histogram bucket it
+    /// falls in, so the actual latency is at most this.
+    pub latency_p50_ms: Option<u64>,
+    pub latency_p95_ms: Option<u64>,
+    pub latency_p99_ms: Option<u64>,
+}
+
+pub fn get_traffic_table(
+    traffic: &TrafficResponse,
+    project_name: &str,
+    period: TrafficPeriod,
+) -> String {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_content_arrangement(ContentArrangement::DynamicFullWidth)
+        .set_header(vec![
+            Cell::new("Metric")
+                .set_alignment(CellAlignment::Center)
+                .add_attribute(Attribute::Bold),
+            Cell::new("Value")
+                .set_alignment(CellAlignment::Center)
+                .add_attribute(Attribute::Bold),
+        ]);
+
+    table.add_row(vec!["Requests".to_string(), traffic.requests.to_string()]);
+
+    for (class, count) in traffic.statuses.iter() {
+        table.add_row(vec![format!("{class} responses"), count.to_string()]);
+    }
+
+    table.add_row(vec!["Bytes in".to_string(), traffic.bytes_in.to_string()]);
+    table.add_row(vec!["Bytes out".to_string(), traffic.bytes_out.to_string()]);
+
+    for (name, latency) in [
+        ("p50", traffic.latency_p50_ms),
+        ("p95", traffic.latency_p95_ms),
+        ("p99", traffic.latency_p99_ms),
+    ] {
+        if let Some(latency) = latency {
+            table.add_row(vec![format!("Latency {name}"), format!("<= {latency} ms")]);
+        }
+    }
+
+    format!(
+        r#"Traffic of {project_name} over the last {period}
+{table}
+"#,
+    )
+}
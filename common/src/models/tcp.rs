@@ -0,0 +1,28 @@
+use std::fmt::{Display, Formatter};
+
+use crossterm::style::Stylize;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::tcp::Response))]
+pub struct Response {
+    pub project_name: String,
+    /// Public port the raw TCP connections to the project come in on
+    pub port: u16,
+    /// Address to connect to, such as `my-project.shuttleapp.rs:30000`
+    pub address: String,
+}
+
+impl Display for Response {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} accepts raw TCP connections on {}",
+            self.project_name,
+            self.address.clone().bold()
+        )
+    }
+}
@@ -0,0 +1,309 @@
+//! Schema for the secrets a service expects to have, as declared in the `[secrets]` table of a
+//! `Shuttle.toml`:
+//!
+//! ```toml
+//! [secrets]
+//! DISCORD_TOKEN = "string"
+//! PORT = { type = "integer", default = "8000" }
+//! GREETING = { optional = true }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// The type a secret value should parse as
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretType {
+    #[default]
+    String,
+    Integer,
+    Float,
+    Boolean,
+}
+
+impl SecretType {
+    pub fn accepts(&self, value: &str) -> bool {
+        match self {
+            Self::String => true,
+            Self::Integer => value.parse::<i64>().is_ok(),
+            Self::Float => value.parse::<f64>().is_ok(),
+            Self::Boolean => value.parse::<bool>().is_ok(),
+        }
+    }
+}
+
+impl fmt::Display for SecretType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String => write!(f, "string"),
+            Self::Integer => write!(f, "integer"),
+            Self::Float => write!(f, "float"),
+            Self::Boolean => write!(f, "boolean"),
+        }
+    }
+}
+
+/// Declaration of a single secret
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(from = "SecretSpecRepr")]
+pub struct SecretSpec {
+    #[serde(rename = "type")]
+    pub r#type: SecretType,
+
+    /// Value to use when the secret is not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+
+    /// Whether the service can start without this secret
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+}
+
+impl SecretSpec {
+    /// A secret is required when it has no default and is not marked as optional
+    pub fn is_required(&self) -> bool {
+        !self.optional && self.default.is_none()
+    }
+}
+
+/// Allows the short `KEY = "type"` form next to the full table form
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SecretSpecRepr {
+    Type(SecretType),
+    Full {
+        #[serde(default, rename = "type")]
+        r#type: SecretType,
+        #[serde(default)]
+        default: Option<String>,
+        #[serde(default)]
+        optional: bool,
+    },
+}
+
+impl From<SecretSpecRepr> for SecretSpec {
+    fn from(repr: SecretSpecRepr) -> Self {
+        match repr {
+            SecretSpecRepr::Type(r#type) => Self {
+                r#type,
+                ..Default::default()
+            },
+            SecretSpecRepr::Full {
+                r#type,
+                default,
+                optional,
+            } => Self {
+                r#type,
+                default,
+                optional,
+            },
+        }
+    }
+}
+
+/// All the secrets declared by a service
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct SecretSchema(pub BTreeMap<String, SecretSpec>);
+
+impl SecretSchema {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Get the declared keys which are required but not in `keys`
+    pub fn missing<'a>(&self, keys: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+        let keys: Vec<_> = keys.into_iter().collect();
+
+        self.0
+            .iter()
+            .filter(|(key, spec)| spec.is_required() && !keys.contains(&key.as_str()))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Get the declared keys whose value in `secrets` does not parse as the declared type
+    pub fn invalid(&self, secrets: &BTreeMap<String, String>) -> Vec<(String, SecretType)> {
+        self.0
+            .iter()
+            .filter_map(|(key, spec)| {
+                let value = secrets.get(key).or(spec.default.as_ref())?;
+
+                (!spec.r#type.accepts(value)).then(|| (key.clone(), spec.r#type))
+            })
+            .collect()
+    }
+
+    /// Check that `secrets` has all the required keys and that every declared value has the right type
+    pub fn validate(&self, secrets: &BTreeMap<String, String>) -> Result<(), ValidationError> {
+        ValidationError {
+            missing: self.missing(secrets.keys().map(String::as_str)),
+            invalid: self.invalid(secrets),
+        }
+        .into_result()
+    }
+
+    /// Get the defaults for the declared keys which are not in `keys`
+    pub fn defaults_for_missing<'a>(
+        &self,
+        keys: impl IntoIterator<Item = &'a str>,
+    ) -> BTreeMap<String, String> {
+        let keys: Vec<_> = keys.into_iter().collect();
+
+        self.0
+            .iter()
+            .filter(|(key, _)| !keys.contains(&key.as_str()))
+            .filter_map(|(key, spec)| Some((key.clone(), spec.default.clone()?)))
+            .collect()
+    }
+}
+
+/// The secrets of a service did not match its schema
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ValidationError {
+    pub missing: Vec<String>,
+    pub invalid: Vec<(String, SecretType)>,
+}
+
+impl ValidationError {
+    /// Turn into an error only if something failed validation
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.missing.is_empty() && self.invalid.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.missing.is_empty() {
+            write!(f, "missing required secrets: {}", self.missing.join(", "))?;
+        }
+
+        if !self.invalid.is_empty() {
+            if !self.missing.is_empty() {
+                write!(f, "; ")?;
+            }
+
+            let invalid: Vec<_> = self
+                .invalid
+                .iter()
+                .map(|(key, secret_type)| format!("{key} (expected {secret_type})"))
+                .collect();
+
+            write!(f, "invalid secrets: {}", invalid.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Errors from getting a typed value out of a [SecretStore][crate::SecretStore]
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseSecretError {
+    Missing { key: String },
+    Invalid { key: String, reason: String },
+}
+
+impl fmt::Display for ParseSecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing { key } => write!(f, "secret `{key}` is not set"),
+            Self::Invalid { key, reason } => write!(f, "secret `{key}` is invalid: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseSecretError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> SecretSchema {
+        serde_json::from_value(serde_json::json!({
+            "DISCORD_TOKEN": "string",
+            "PORT": { "type": "integer", "default": "8000" },
+            "DEBUG": { "type": "boolean", "optional": true },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_schema() {
+        let schema = schema();
+
+        assert_eq!(schema.0["DISCORD_TOKEN"], SecretSpec::default());
+        assert_eq!(
+            schema.0["PORT"],
+            SecretSpec {
+                r#type: SecretType::Integer,
+                default: Some("8000".to_string()),
+                optional: false,
+            }
+        );
+        assert!(schema.0["DEBUG"].optional);
+    }
+
+    #[test]
+    fn validate() {
+        let schema = schema();
+
+        assert_eq!(
+            schema.validate(&Default::default()),
+            Err(ValidationError {
+                missing: vec!["DISCORD_TOKEN".to_string()],
+                invalid: vec![],
+            })
+        );
+
+        let secrets = BTreeMap::from([
+            ("DEBUG".to_string(), "maybe".to_string()),
+            ("PORT".to_string(), "eighty".to_string()),
+        ]);
+        let error = schema.validate(&secrets).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "missing required secrets: DISCORD_TOKEN; invalid secrets: DEBUG (expected boolean), PORT (expected integer)"
+        );
+
+        let secrets = BTreeMap::from([("DISCORD_TOKEN".to_string(), "token".to_string())]);
+
+        assert!(schema.validate(&secrets).is_ok());
+        assert_eq!(
+            schema.defaults_for_missing(secrets.keys().map(String::as_str)),
+            BTreeMap::from([("PORT".to_string(), "8000".to_string())])
+        );
+    }
+
+    #[test]
+    fn get_parsed() {
+        let store = crate::SecretStore::new(BTreeMap::from([
+            ("PORT".to_string(), "8000".to_string()),
+            ("DEBUG".to_string(), "maybe".to_string()),
+        ]));
+
+        assert_eq!(store.get_parsed::<u16>("PORT"), Ok(8000));
+        assert_eq!(
+            store.get_parsed::<bool>("DEBUG"),
+            Err(ParseSecretError::Invalid {
+                key: "DEBUG".to_string(),
+                reason: "provided string was not `true` or `false`".to_string(),
+            })
+        );
+        assert_eq!(
+            store.get_parsed::<String>("DISCORD_TOKEN"),
+            Err(ParseSecretError::Missing {
+                key: "DISCORD_TOKEN".to_string(),
+            })
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeMap,
     io::Write,
     slice::IterMut,
     sync::{Arc, Mutex},
@@ -8,10 +9,13 @@ use chrono::{DateTime, NaiveDateTime, Utc};
 use http::{HeaderMap, Method, Request, Response, StatusCode, Uri, Version};
 use rmps::Serializer;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tracing::Subscriber;
 use tracing_subscriber::Layer;
 
+use crate::deployment::Environment;
 use crate::tracing::JsonVisitor;
+use crate::{database, resource, BucketReadyInfo, DatabaseReadyInfo};
 
 extern crate rmp_serde as rmps;
 
@@ -113,6 +117,61 @@ impl ResponseWrapper {
     }
 }
 
+/// Requests a shuttle-next module makes to the runtime while it is being loaded, so that the
+/// resources it needs can be provisioned on the host
+#[derive(Serialize, Deserialize, Debug)]
+pub enum FactoryRequest {
+    DbConnection(database::Type),
+    Bucket,
+    Secrets,
+    ServiceName,
+    Environment,
+    /// Get the output of a resource from a past deployment which had the same config
+    CachedOutput {
+        r#type: resource::Type,
+        config: Value,
+    },
+    /// Record the output of a resource for this deployment
+    RecordResource {
+        r#type: resource::Type,
+        config: Value,
+        output: Value,
+    },
+}
+
+/// The answer of the runtime to a [FactoryRequest]
+#[derive(Serialize, Deserialize, Debug)]
+pub enum FactoryResponse {
+    DbConnection(DatabaseReadyInfo),
+    Bucket(BucketReadyInfo),
+    Secrets(BTreeMap<String, String>),
+    ServiceName(String),
+    Environment(Environment),
+    CachedOutput(Option<Value>),
+    Recorded,
+    Error(String),
+}
+
+impl FactoryRequest {
+    /// Serialize a FactoryRequest to the Rust MessagePack data format
+    pub fn into_rmp(self) -> Result<Vec<u8>, rmps::encode::Error> {
+        let mut buf = Vec::new();
+        self.serialize(&mut Serializer::new(&mut buf))?;
+
+        Ok(buf)
+    }
+}
+
+impl FactoryResponse {
+    /// Serialize a FactoryResponse to the Rust MessagePack data format
+    pub fn into_rmp(self) -> Result<Vec<u8>, rmps::encode::Error> {
+        let mut buf = Vec::new();
+        self.serialize(&mut Serializer::new(&mut buf))?;
+
+        Ok(buf)
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Log {
     pub level: Level,
@@ -483,6 +542,52 @@ mod tests {
         assert_eq!(rx.get::<Log>(), None);
     }
 
+    #[test]
+    fn factory_messages_over_socket() {
+        let (mut tx, mut rx) = UnixStream::pair().unwrap();
+
+        tx.write_all(&FactoryRequest::Secrets.into_rmp().unwrap())
+            .unwrap();
+        tx.write_all(
+            &FactoryRequest::CachedOutput {
+                r#type: resource::Type::Secrets,
+                config: json!({"keys": ["API_KEY"]}),
+            }
+            .into_rmp()
+            .unwrap(),
+        )
+        .unwrap();
+        tx.write_all(
+            &FactoryResponse::CachedOutput(Some(json!({"API_KEY": "secret"})))
+                .into_rmp()
+                .unwrap(),
+        )
+        .unwrap();
+
+        // Messages follow each other on the stream, so each one should be read on its own
+        let actual: FactoryRequest = rmps::from_read(&mut rx).unwrap();
+        assert!(matches!(actual, FactoryRequest::Secrets));
+
+        let actual: FactoryRequest = rmps::from_read(&mut rx).unwrap();
+        assert!(matches!(
+            actual,
+            FactoryRequest::CachedOutput {
+                r#type: resource::Type::Secrets,
+                config,
+            } if config == json!({"keys": ["API_KEY"]})
+        ));
+
+        let actual: FactoryResponse = rmps::from_read(&mut rx).unwrap();
+        assert!(matches!(
+            actual,
+            FactoryResponse::CachedOutput(Some(output)) if output == json!({"API_KEY": "secret"})
+        ));
+
+        // A closed stream is an error rather than a message
+        drop(tx);
+        assert!(rmps::from_read::<_, FactoryRequest>(&mut rx).is_err());
+    }
+
     #[test]
     fn logging() {
         let (tx, rx) = UnixStream::pair().unwrap();
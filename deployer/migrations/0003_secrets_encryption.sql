@@ -0,0 +1,3 @@
+-- Data key the secret value is encrypted with, itself encrypted with the deployer's secrets key.
+-- Values without a data key are still in plaintext and are encrypted when the deployer starts.
+ALTER TABLE secrets ADD COLUMN data_key TEXT;
@@ -0,0 +1,6 @@
+-- How long the logs of a service are kept. Unset values use the defaults the deployer is started with.
+ALTER TABLE services ADD COLUMN log_retention_max_age_hours INTEGER;
+ALTER TABLE services ADD COLUMN log_retention_max_lines INTEGER;
+
+-- Retention and pagination both go through the timestamps of the logs.
+CREATE INDEX IF NOT EXISTS logs_timestamp ON logs (timestamp);
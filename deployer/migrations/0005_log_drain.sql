@@ -0,0 +1,5 @@
+-- A deployer only serves one project, so there is at most one drain.
+CREATE TABLE IF NOT EXISTS log_drain (
+    id INTEGER PRIMARY KEY CHECK (id = 0), -- Always 0.
+    config TEXT NOT NULL                   -- Where the runtime logs of the project are forwarded to, as JSON.
+);
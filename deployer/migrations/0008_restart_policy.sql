@@ -0,0 +1,2 @@
+-- The `[restart]` table of the Shuttle.toml of a deployment, as JSON, if it has one.
+ALTER TABLE deployments ADD COLUMN restart_policy TEXT;
@@ -0,0 +1,2 @@
+-- The `[tcp]` table of the Shuttle.toml of a deployment, as JSON, if it has one.
+ALTER TABLE deployments ADD COLUMN tcp TEXT;
@@ -0,0 +1,2 @@
+-- The `[next]` table of the Shuttle.toml of a deployment, as JSON, if it has one.
+ALTER TABLE deployments ADD COLUMN next TEXT;
@@ -0,0 +1,3 @@
+-- The `[secrets]` table of the Shuttle.toml of a deployment, as JSON, if it has one. Its defaults are
+-- applied when the deployment is loaded rather than stored with the secrets of the service.
+ALTER TABLE deployments ADD COLUMN secrets_schema TEXT;
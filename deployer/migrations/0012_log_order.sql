@@ -0,0 +1,22 @@
+-- Logs of a deployment can share a timestamp, so they are no longer keyed on it. Their rowid breaks the
+-- ties instead, which is what log pages are ordered and continued by.
+CREATE TABLE IF NOT EXISTS logs_ordered (
+    id TEXT,           -- The deployment that this log line pertains to.
+    timestamp INTEGER, -- Unix epoch timestamp.
+    state TEXT,        -- The state of the deployment at the time at which the log text was produced.
+    level TEXT,        -- The log level
+    file TEXT,         -- The file log took place in
+    line INTEGER,      -- The line log took place on
+    target TEXT,       -- The module log took place in
+    fields TEXT,       -- Log fields object.
+    FOREIGN KEY(id) REFERENCES deployments(id)
+);
+
+INSERT INTO logs_ordered (id, timestamp, state, level, file, line, target, fields)
+    SELECT id, timestamp, state, level, file, line, target, fields FROM logs ORDER BY timestamp;
+
+DROP TABLE logs;
+ALTER TABLE logs_ordered RENAME TO logs;
+
+CREATE INDEX IF NOT EXISTS logs_id_timestamp ON logs (id, timestamp);
+CREATE INDEX IF NOT EXISTS logs_timestamp ON logs (timestamp);
@@ -0,0 +1,3 @@
+-- Whether the deployment was added for another service in the workspace of a deployment, rather than
+-- deployed on its own. These are reused once they are done so they do not pile up on every deploy.
+ALTER TABLE deployments ADD COLUMN workspace BOOLEAN DEFAULT 0 NOT NULL;
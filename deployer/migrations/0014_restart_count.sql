@@ -0,0 +1,7 @@
+-- Times the deployment has been restarted by its restart policy since it last ran healthy, so that
+-- the count survives a restart of the deployer.
+ALTER TABLE deployments ADD COLUMN restart_count INTEGER DEFAULT 0 NOT NULL;
+
+-- The `restart` key of the `[health_check]` table was replaced by the `[restart]` table, and is now
+-- rejected.
+UPDATE deployments SET health_check = json_remove(health_check, '$.restart') WHERE health_check IS NOT NULL;
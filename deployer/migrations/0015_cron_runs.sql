@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS cron_runs (
+    id TEXT PRIMARY KEY, -- The cron deployment these runs are for.
+    last_run TEXT,       -- RFC 3339 time of when the last run started.
+    next_run TEXT,       -- RFC 3339 time of when the next run is due.
+    FOREIGN KEY(id) REFERENCES deployments(id)
+);
+
+-- The runs used to be read from the logs of shuttle-cron, which log retention deletes
+INSERT INTO cron_runs (id, last_run, next_run)
+    SELECT d.id,
+        (SELECT timestamp FROM logs AS l
+            WHERE l.id = d.id AND target = 'shuttle_cron' AND json_extract(fields, '$.cron') = 'started'
+            ORDER BY timestamp DESC LIMIT 1),
+        (SELECT json_extract(fields, '$.next_run') FROM logs AS l
+            WHERE l.id = d.id AND target = 'shuttle_cron' AND json_extract(fields, '$.cron') = 'scheduled'
+            ORDER BY timestamp DESC LIMIT 1)
+    FROM deployments AS d
+    WHERE EXISTS (SELECT 1 FROM logs AS l WHERE l.id = d.id AND target = 'shuttle_cron');
@@ -12,6 +12,7 @@ shuttle-service = { path = "/usr/src/shuttle/service" }
 shuttle-runtime = { path = "/usr/src/shuttle/runtime" }
 
 shuttle-aws-rds = { path = "/usr/src/shuttle/resources/aws-rds" }
+shuttle-object-store = { path = "/usr/src/shuttle/resources/object-store" }
 shuttle-persist = { path = "/usr/src/shuttle/resources/persist" }
 shuttle-shared-db = { path = "/usr/src/shuttle/resources/shared-db" }
 shuttle-secrets = { path = "/usr/src/shuttle/resources/secrets" }
@@ -27,6 +28,7 @@ shuttle-salvo = { path = "/usr/src/shuttle/services/shuttle-salvo" }
 shuttle-serenity = { path = "/usr/src/shuttle/services/shuttle-serenity" }
 shuttle-thruster = { path = "/usr/src/shuttle/services/shuttle-thruster" }
 shuttle-tide = { path = "/usr/src/shuttle/services/shuttle-tide" }
+shuttle-tonic = { path = "/usr/src/shuttle/services/shuttle-tonic" }
 shuttle-tower = { path = "/usr/src/shuttle/services/shuttle-tower" }
 shuttle-warp = { path = "/usr/src/shuttle/services/shuttle-warp" }' > $CARGO_HOME/config.toml
 
@@ -1,15 +1,19 @@
 use std::{net::SocketAddr, path::PathBuf};
 
-use clap::Parser;
+use anyhow::Context;
+use clap::{ArgGroup, Parser};
 use fqdn::FQDN;
 use hyper::Uri;
-use shuttle_common::project::ProjectName;
+use shuttle_common::{log, project::ProjectName};
 use tonic::transport::Endpoint;
 
+use crate::persistence::SecretCipher;
+
 /// Program to handle the deploys for a single project
 /// Handling includes, building, testing, and running each service
 #[derive(Debug, Parser)]
 #[clap(author, version, about)]
+#[clap(group(ArgGroup::new("secrets_key_source").required(true)))]
 pub struct Args {
     /// Uri to the `.sqlite` file used to store state
     #[clap(long, default_value = "./deployer.sqlite")]
@@ -31,6 +35,10 @@ pub struct Args {
     #[clap(long, default_value = "0.0.0.0:8000")]
     pub proxy_address: SocketAddr,
 
+    /// Address to bind the proxy for raw TCP connections to
+    #[clap(long, default_value = "0.0.0.0:8002")]
+    pub tcp_proxy_address: SocketAddr,
+
     /// Address to reach gateway's control plane at
     #[clap(long, default_value = "http://gateway:8001")]
     pub gateway_uri: Uri,
@@ -51,7 +59,50 @@ pub struct Args {
     #[clap(long, default_value = "/tmp")]
     pub artifacts_path: PathBuf,
 
+    /// Base64 encoded 256-bit key used to encrypt secrets at rest
+    #[clap(long, group = "secrets_key_source")]
+    pub secrets_key: Option<String>,
+
+    /// File containing the base64 encoded 256-bit key used to encrypt secrets at rest
+    #[clap(long, group = "secrets_key_source")]
+    pub secrets_key_file: Option<PathBuf>,
+
+    /// Delete the logs of a service older than this many hours, unless the service sets its own retention
+    #[clap(long, default_value_t = 24 * 30)]
+    pub log_retention_max_age_hours: u32,
+
+    /// Only keep this many of the most recent log lines of a service, unless the service sets its own retention
+    #[clap(long, default_value_t = 100_000)]
+    pub log_retention_max_lines: u32,
+
+    /// Close an upgraded connection, such as a WebSocket, once nothing was sent over it for this many seconds
+    #[clap(long, default_value_t = 5 * 60)]
+    pub upgrade_idle_timeout_secs: u64,
+
     /// Add an auth layer to deployer for local development
     #[arg(long)]
     pub local: bool,
 }
+
+impl Args {
+    /// Get the log retention for services which have not set their own
+    pub fn log_retention(&self) -> log::Retention {
+        log::Retention {
+            max_age_hours: Some(self.log_retention_max_age_hours),
+            max_lines: Some(self.log_retention_max_lines),
+        }
+    }
+
+    /// Get the cipher for secrets from whichever key source was passed in
+    pub fn secret_cipher(&self) -> anyhow::Result<SecretCipher> {
+        let key = match (&self.secrets_key, &self.secrets_key_file) {
+            (Some(key), _) => key.clone(),
+            (None, Some(path)) => {
+                std::fs::read_to_string(path).context("failed to read secrets key file")?
+            }
+            (None, None) => unreachable!("clap requires one of the secrets key arguments"),
+        };
+
+        Ok(SecretCipher::from_base64(&key)?)
+    }
+}
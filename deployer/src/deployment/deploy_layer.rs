@@ -30,6 +30,9 @@ use uuid::Uuid;
 
 use crate::persistence::{self, DeploymentState, LogLevel, State};
 
+/// Target of the logs shuttle-cron writes about the runs of its job
+pub const CRON_LOG_TARGET: &str = "shuttle_cron";
+
 /// Records logs for the deployment progress
 pub trait LogRecorder: Clone + Send + 'static {
     fn record(&self, log: Log);
@@ -116,6 +119,16 @@ impl TryFrom<runtime::LogItem> for Log {
     type Error = ParseError;
 
     fn try_from(log: runtime::LogItem) -> Result<Self, Self::Error> {
+        // The runs of a cron deployment are recorded from the logs of shuttle-cron, so other code
+        // cannot claim its target. It keeps the module it is from instead.
+        let target = if log.target == CRON_LOG_TARGET
+            && log.module_path.as_deref() != Some(CRON_LOG_TARGET)
+        {
+            log.module_path.unwrap_or_default()
+        } else {
+            log.target
+        };
+
         Ok(Self {
             id: Default::default(),
             state: State::Running,
@@ -125,7 +138,7 @@ impl TryFrom<runtime::LogItem> for Log {
             timestamp: DateTime::from(SystemTime::try_from(log.timestamp.unwrap_or_default())?),
             file: log.file,
             line: log.line,
-            target: log.target,
+            target,
             fields: serde_json::from_slice(&log.fields)?,
             r#type: LogType::Event,
         })
@@ -319,9 +332,15 @@ mod tests {
     use ctor::ctor;
     use flate2::{write::GzEncoder, Compression};
     use portpicker::pick_unused_port;
-    use shuttle_proto::provisioner::{
-        provisioner_server::{Provisioner, ProvisionerServer},
-        DatabaseDeletionResponse, DatabaseRequest, DatabaseResponse,
+    use shuttle_common::deployment::{HealthCheck, Next, Readiness, RestartPolicy, Tcp};
+    use shuttle_common::secrets::SecretSchema;
+    use shuttle_proto::{
+        provisioner::{
+            provisioner_server::{Provisioner, ProvisionerServer},
+            BucketRequest, BucketResponse, DatabaseDeletionResponse, DatabaseRequest,
+            DatabaseResponse,
+        },
+        runtime,
     };
     use tempfile::Builder;
     use tokio::{select, time::sleep};
@@ -334,10 +353,10 @@ mod tests {
             deploy_layer::LogType, gateway_client::BuildQueueClient, ActiveDeploymentsGetter,
             Built, DeploymentManager, Queued,
         },
-        persistence::{Secret, SecretGetter, SecretRecorder, State},
+        persistence::{self, Secret, SecretGetter, SecretRecorder, State},
     };
 
-    use super::{DeployLayer, Log, LogRecorder};
+    use super::{DeployLayer, Log, LogRecorder, CRON_LOG_TARGET};
 
     #[ctor]
     static RECORDER: Arc<Mutex<RecorderMock>> = {
@@ -451,6 +470,13 @@ mod tests {
         ) -> Result<tonic::Response<DatabaseDeletionResponse>, tonic::Status> {
             panic!("no deploy layer tests should request delete a db");
         }
+
+        async fn provision_bucket(
+            &self,
+            _request: tonic::Request<BucketRequest>,
+        ) -> Result<tonic::Response<BucketResponse>, tonic::Status> {
+            panic!("no deploy layer tests should request a bucket");
+        }
     }
 
     fn get_runtime_manager() -> Arc<tokio::sync::Mutex<RuntimeManager>> {
@@ -485,6 +511,10 @@ mod tests {
         ) -> Result<(), Self::Err> {
             panic!("no tests should set secrets")
         }
+
+        async fn remove_secret(&self, _service_id: &Uuid, _key: &str) -> Result<(), Self::Err> {
+            panic!("no tests should remove secrets")
+        }
     }
 
     impl<R: LogRecorder> LogRecorder for Arc<Mutex<R>> {
@@ -507,6 +537,62 @@ mod tests {
         async fn set_is_next(&self, _id: &Uuid, _is_next: bool) -> Result<(), Self::Err> {
             Ok(())
         }
+
+        async fn set_health_check(
+            &self,
+            _id: &Uuid,
+            _health_check: Option<&HealthCheck>,
+        ) -> Result<(), Self::Err> {
+            Ok(())
+        }
+
+        async fn set_readiness(
+            &self,
+            _id: &Uuid,
+            _readiness: Option<&Readiness>,
+        ) -> Result<(), Self::Err> {
+            Ok(())
+        }
+
+        async fn set_restart_policy(
+            &self,
+            _id: &Uuid,
+            _restart_policy: Option<&RestartPolicy>,
+        ) -> Result<(), Self::Err> {
+            Ok(())
+        }
+
+        async fn set_tcp(&self, _id: &Uuid, _tcp: Option<&Tcp>) -> Result<(), Self::Err> {
+            Ok(())
+        }
+
+        async fn set_next(&self, _id: &Uuid, _next: Option<&Next>) -> Result<(), Self::Err> {
+            Ok(())
+        }
+
+        async fn set_secrets_schema(
+            &self,
+            _id: &Uuid,
+            _secrets_schema: Option<&SecretSchema>,
+        ) -> Result<(), Self::Err> {
+            Ok(())
+        }
+
+        async fn set_restart_count(
+            &self,
+            _id: &Uuid,
+            _restart_count: u32,
+        ) -> Result<(), Self::Err> {
+            Ok(())
+        }
+
+        async fn add_built_deployment(
+            &self,
+            _service_name: &str,
+            _is_next: bool,
+        ) -> Result<(Uuid, Uuid), Self::Err> {
+            Ok((Uuid::new_v4(), Uuid::new_v4()))
+        }
     }
 
     #[derive(Clone)]
@@ -519,7 +605,7 @@ mod tests {
         async fn get_active_deployments(
             &self,
             _service_id: &Uuid,
-        ) -> std::result::Result<Vec<Uuid>, Self::Err> {
+        ) -> std::result::Result<Vec<persistence::Deployment>, Self::Err> {
             Ok(vec![])
         }
     }
@@ -813,6 +899,12 @@ mod tests {
                 tracing_context: Default::default(),
                 is_next: false,
                 claim: None,
+                health_check: None,
+                readiness: Default::default(),
+                restart_policy: Default::default(),
+                next: Default::default(),
+                secrets_schema: Default::default(),
+                restart_count: 0,
             })
             .await;
 
@@ -872,6 +964,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn cron_target_from_runtime() {
+        let log_item = |target: &str, module_path: &str| runtime::LogItem {
+            timestamp: None,
+            level: runtime::LogLevel::Info as i32,
+            file: None,
+            line: None,
+            target: target.to_string(),
+            fields: br#"{"cron": "started"}"#.to_vec(),
+            module_path: Some(module_path.to_string()),
+        };
+
+        let log = Log::try_from(log_item(CRON_LOG_TARGET, CRON_LOG_TARGET)).unwrap();
+        assert_eq!(log.target, CRON_LOG_TARGET);
+
+        let log = Log::try_from(log_item(CRON_LOG_TARGET, "my_cron::jobs")).unwrap();
+        assert_eq!(
+            log.target, "my_cron::jobs",
+            "only shuttle-cron should log as shuttle-cron"
+        );
+
+        let log = Log::try_from(log_item("my_cron", "my_cron::jobs")).unwrap();
+        assert_eq!(log.target, "my_cron");
+    }
+
     async fn get_deployment_manager() -> DeploymentManager {
         DeploymentManager::builder()
             .build_log_recorder(RECORDER.clone())
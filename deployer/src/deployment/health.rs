@@ -0,0 +1,322 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use axum::headers::HeaderMapExt;
+use fqdn::FQDN;
+use hyper::{
+    header::{HOST, USER_AGENT},
+    Body, Request,
+};
+use shuttle_common::{
+    backends::headers::XShuttleProject,
+    deployment::{HealthCheck, Readiness, Tcp},
+};
+use tokio::{
+    net::TcpStream,
+    time::{sleep, Instant, MissedTickBehavior},
+};
+use tracing::{debug, instrument, trace, warn};
+use uuid::Uuid;
+
+use crate::proxy::{self, AddressGetter};
+
+/// Time between the probes of a deployment which is not ready yet
+const READINESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Seconds a readiness probe waits for an answer
+const READINESS_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Domain probes are addressed to. It only has to match between the probes and the proxy since
+/// probes never leave the deployer.
+const PROBE_HOST: &str = "health-check.shuttle.internal";
+
+/// Wait for a new deployment to be ready for requests, followed by its warm-up.
+///
+/// A deployment with a readiness path which is not ready in time is an error. Without a path, the
+/// deployment only has to accept connections, and services which never do so (like bots) are
+/// considered ready once the time runs out.
+#[instrument(skip(readiness))]
+pub async fn wait_until_ready(
+    address: SocketAddr,
+    service_name: &str,
+    readiness: &Readiness,
+) -> Result<(), String> {
+    let timeout = Duration::from_secs(readiness.timeout);
+    let prober = Prober::new(address, service_name);
+
+    let ready = async {
+        loop {
+            let result = match &readiness.path {
+                Some(path) => prober.probe(path, READINESS_PROBE_TIMEOUT).await,
+                None => TcpStream::connect(address)
+                    .await
+                    .map(|_| ())
+                    .map_err(|error| error.to_string()),
+            };
+
+            match result {
+                Ok(()) => return,
+                Err(error) => trace!(error, "deployment is not ready yet"),
+            }
+
+            sleep(READINESS_INTERVAL).await;
+        }
+    };
+
+    match tokio::time::timeout(timeout, ready).await {
+        Ok(()) => {}
+        Err(_) if readiness.path.is_some() => {
+            return Err(format!("deployment was not ready within {timeout:?}"))
+        }
+        Err(_) => warn!(
+            "deployment did not accept connections within {timeout:?}, considering it ready anyway"
+        ),
+    }
+
+    if readiness.warm_up > 0 {
+        debug!(warm_up = readiness.warm_up, "warming up deployment");
+
+        sleep(Duration::from_secs(readiness.warm_up)).await;
+    }
+
+    Ok(())
+}
+
+/// Watches over the health of a running deployment
+pub struct HealthMonitor {
+    health_check: HealthCheck,
+    service_name: String,
+}
+
+impl HealthMonitor {
+    pub fn new(health_check: HealthCheck, service_name: String) -> Self {
+        Self {
+            health_check,
+            service_name,
+        }
+    }
+
+    /// Probe the deployment until it fails too many probes in a row. Returns why the last probe
+    /// failed, leaving it to the caller to stop the deployment.
+    #[instrument(skip(self))]
+    pub async fn watch(&self, id: &Uuid, address: SocketAddr) -> String {
+        let prober = Prober::new(address, &self.service_name);
+
+        probe_until_unhealthy(&prober, &self.health_check).await
+    }
+}
+
+/// Probe the health path of a deployment on every interval, starting once its grace period is over,
+/// until `failure_threshold` probes in a row have failed
+async fn probe_until_unhealthy(prober: &Prober, health_check: &HealthCheck) -> String {
+    let timeout = Duration::from_secs(health_check.timeout);
+    let mut interval = tokio::time::interval_at(
+        Instant::now() + Duration::from_secs(health_check.grace_period),
+        Duration::from_secs(health_check.interval.max(1)),
+    );
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut failures = 0;
+
+    loop {
+        interval.tick().await;
+
+        match prober.probe(&health_check.path, timeout).await {
+            Ok(()) => failures = 0,
+            Err(error) => {
+                failures += 1;
+
+                debug!(error, failures, "health check probe failed");
+
+                if failures >= health_check.failure_threshold {
+                    return error;
+                }
+            }
+        }
+    }
+}
+
+/// Sends probes to one deployment. They go through the same proxy as the requests of users do, but
+/// straight to the deployment being probed rather than to whichever deployment of its service is
+/// active, since a new deployment is probed before it takes over.
+#[derive(Clone)]
+struct Prober {
+    address: SocketAddr,
+    service_name: String,
+}
+
+impl Prober {
+    fn new(address: SocketAddr, service_name: &str) -> Self {
+        Self {
+            address,
+            service_name: service_name.to_string(),
+        }
+    }
+
+    async fn probe(&self, path: &str, timeout: Duration) -> Result<(), String> {
+        let mut request = Request::get(format!("/{}", path.trim_start_matches('/')))
+            .header(HOST, PROBE_HOST)
+            .header(USER_AGENT, "shuttle-health-check")
+            .body(Body::empty())
+            .map_err(|error| format!("invalid health check request: {error}"))?;
+
+        request
+            .headers_mut()
+            .typed_insert(XShuttleProject(self.service_name.clone()));
+
+        let fqdn: FQDN = PROBE_HOST.parse().expect("probe host to be a valid FQDN");
+        let response = proxy::handle(
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0),
+            fqdn,
+            request,
+            self.clone(),
+            timeout,
+        );
+
+        match tokio::time::timeout(timeout, response).await {
+            Ok(Ok(response)) if response.status().is_success() => Ok(()),
+            Ok(Ok(response)) => Err(format!("health check answered with {}", response.status())),
+            Ok(Err(infallible)) => match infallible {},
+            Err(_) => Err(format!("health check got no answer within {timeout:?}")),
+        }
+    }
+}
+
+#[async_trait]
+impl AddressGetter for Prober {
+    async fn get_address_for_service(
+        &self,
+        _service_name: &str,
+    ) -> crate::handlers::Result<Option<SocketAddr>> {
+        Ok(Some(self.address))
+    }
+
+    async fn get_tcp_address(&self) -> crate::handlers::Result<Option<(SocketAddr, Tcp)>> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use axum::{http::StatusCode, routing::get, Router};
+    use portpicker::pick_unused_port;
+    use shuttle_common::deployment::{HealthCheck, Readiness};
+
+    use super::{probe_until_unhealthy, wait_until_ready, Prober};
+
+    async fn serve(status: StatusCode) -> SocketAddr {
+        let address = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), pick_unused_port().unwrap());
+        let router = Router::new().route("/health", get(move || async move { status }));
+
+        tokio::spawn(axum::Server::bind(&address).serve(router.into_make_service()));
+
+        address
+    }
+
+    fn readiness(path: Option<&str>) -> Readiness {
+        Readiness {
+            path: path.map(ToString::to_string),
+            timeout: 2,
+            ..Default::default()
+        }
+    }
+
+    fn health_check(path: &str) -> HealthCheck {
+        HealthCheck {
+            path: path.to_string(),
+            interval: 1,
+            timeout: 1,
+            failure_threshold: 2,
+            grace_period: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn unhealthy() {
+        let prober = Prober::new(serve(StatusCode::SERVICE_UNAVAILABLE).await, "my-service");
+
+        assert_eq!(
+            probe_until_unhealthy(&prober, &health_check("/health")).await,
+            "health check answered with 503 Service Unavailable"
+        );
+
+        assert_eq!(
+            probe_until_unhealthy(&prober, &health_check("missing")).await,
+            "health check answered with 404 Not Found"
+        );
+    }
+
+    #[tokio::test]
+    async fn healthy() {
+        let prober = Prober::new(serve(StatusCode::OK).await, "my-service");
+
+        let result = tokio::time::timeout(
+            std::time::Duration::from_secs(3),
+            probe_until_unhealthy(&prober, &health_check("health")),
+        )
+        .await;
+
+        assert!(
+            result.is_err(),
+            "a healthy service should keep being probed"
+        );
+    }
+
+    #[tokio::test]
+    async fn grace_period() {
+        let prober = Prober::new(serve(StatusCode::SERVICE_UNAVAILABLE).await, "my-service");
+        let health_check = HealthCheck {
+            failure_threshold: 1,
+            grace_period: 2,
+            ..health_check("/health")
+        };
+
+        let result = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            probe_until_unhealthy(&prober, &health_check),
+        )
+        .await;
+
+        assert!(
+            result.is_err(),
+            "a service should not be probed during its grace period"
+        );
+    }
+
+    #[tokio::test]
+    async fn ready() {
+        let address = serve(StatusCode::OK).await;
+
+        assert_eq!(
+            wait_until_ready(address, "my-service", &readiness(Some("/health"))).await,
+            Ok(())
+        );
+        assert_eq!(
+            wait_until_ready(address, "my-service", &readiness(None)).await,
+            Ok(())
+        );
+    }
+
+    #[tokio::test]
+    async fn not_ready() {
+        let address = serve(StatusCode::SERVICE_UNAVAILABLE).await;
+
+        assert!(
+            wait_until_ready(address, "my-service", &readiness(Some("/health")))
+                .await
+                .is_err()
+        );
+
+        // Services which do not listen for connections are ready once the time runs out
+        let address = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), pick_unused_port().unwrap());
+
+        assert_eq!(
+            wait_until_ready(address, "my-service", &readiness(None)).await,
+            Ok(())
+        );
+    }
+}
@@ -1,11 +1,14 @@
 pub mod deploy_layer;
 pub mod gateway_client;
+mod health;
 mod queue;
+mod restart;
 mod run;
 
 use std::{path::PathBuf, sync::Arc};
 
 pub use queue::Queued;
+use restart::PendingRestarts;
 pub use run::{ActiveDeploymentsGetter, Built};
 use shuttle_common::storage_manager::ArtifactsStorageManager;
 use tracing::{instrument, Span};
@@ -125,6 +128,7 @@ where
         let storage_manager = ArtifactsStorageManager::new(artifacts_path);
 
         let run_send_clone = run_send.clone();
+        let pending_restarts = PendingRestarts::default();
 
         tokio::spawn(queue::task(
             queue_recv,
@@ -132,11 +136,14 @@ where
             deployment_updater.clone(),
             build_log_recorder,
             secret_recorder,
+            secret_getter.clone(),
             storage_manager.clone(),
             queue_client,
         ));
         tokio::spawn(run::task(
             run_recv,
+            run_send.clone(),
+            pending_restarts.clone(),
             runtime_manager.clone(),
             deployment_updater,
             active_deployment_getter,
@@ -148,6 +155,7 @@ where
         DeploymentManager {
             queue_send,
             run_send,
+            pending_restarts,
             runtime_manager,
             storage_manager,
         }
@@ -158,6 +166,7 @@ where
 pub struct DeploymentManager {
     queue_send: QueueSender,
     run_send: RunSender,
+    pending_restarts: PendingRestarts,
     runtime_manager: Arc<Mutex<RuntimeManager>>,
     storage_manager: ArtifactsStorageManager,
 }
@@ -174,7 +183,9 @@ pub struct DeploymentManager {
 ///       |
 ///       v
 ///    run task     tasks enter the State::Running state and begin
-///                 executing
+///                 executing. Tasks which stop on their own enter the
+///                 State::Restarting state and go back on the run channel
+///                 when the restart policy of their service asks for it
 /// ```
 impl DeploymentManager {
     /// Create a new deployment manager. Manages one or more 'pipelines' for
@@ -210,6 +221,7 @@ impl DeploymentManager {
     }
 
     pub async fn kill(&self, id: Uuid) {
+        self.pending_restarts.cancel(&id);
         self.runtime_manager.lock().await.kill(&id).await;
     }
 
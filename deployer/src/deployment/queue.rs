@@ -2,7 +2,7 @@ use super::deploy_layer::{Log, LogRecorder, LogType};
 use super::gateway_client::BuildQueueClient;
 use super::{Built, QueueReceiver, RunSender, State};
 use crate::error::{Error, Result, TestError};
-use crate::persistence::{DeploymentUpdater, LogLevel, SecretRecorder};
+use crate::persistence::{DeploymentUpdater, LogLevel, SecretGetter, SecretRecorder};
 use shuttle_common::storage_manager::{ArtifactsStorageManager, StorageManager};
 
 use cargo::util::interning::InternedString;
@@ -12,6 +12,8 @@ use crossbeam_channel::Sender;
 use opentelemetry::global;
 use serde_json::json;
 use shuttle_common::claims::Claim;
+use shuttle_common::deployment::{HealthCheck, Next, Readiness, RestartPolicy, Tcp};
+use shuttle_common::secrets::SecretSchema;
 use shuttle_service::builder::{build_workspace, get_config, BuiltService};
 use tokio::time::{sleep, timeout};
 use tracing::{debug, debug_span, error, info, instrument, trace, warn, Instrument, Span};
@@ -38,6 +40,7 @@ pub async fn task(
     deployment_updater: impl DeploymentUpdater,
     log_recorder: impl LogRecorder,
     secret_recorder: impl SecretRecorder,
+    secret_getter: impl SecretGetter,
     storage_manager: ArtifactsStorageManager,
     queue_client: impl BuildQueueClient,
 ) {
@@ -52,6 +55,7 @@ pub async fn task(
         let run_send_cloned = run_send.clone();
         let log_recorder = log_recorder.clone();
         let secret_recorder = secret_recorder.clone();
+        let secret_getter = secret_getter.clone();
         let storage_manager = storage_manager.clone();
         let queue_client = queue_client.clone();
 
@@ -79,12 +83,16 @@ pub async fn task(
                         deployment_updater,
                         log_recorder,
                         secret_recorder,
+                        secret_getter,
                     )
                     .await
                 {
                     Ok(built) => {
                         remove_from_queue(queue_client, id).await;
-                        promote_to_run(built, run_send_cloned).await
+
+                        for built in built {
+                            promote_to_run(built, run_send_cloned.clone()).await
+                        }
                     }
                     Err(err) => {
                         remove_from_queue(queue_client, id).await;
@@ -158,14 +166,15 @@ pub struct Queued {
 }
 
 impl Queued {
-    #[instrument(skip(self, storage_manager, deployment_updater, log_recorder, secret_recorder), fields(id = %self.id, state = %State::Building))]
+    #[instrument(skip(self, storage_manager, deployment_updater, log_recorder, secret_recorder, secret_getter), fields(id = %self.id, state = %State::Building))]
     async fn handle(
         self,
         storage_manager: ArtifactsStorageManager,
         deployment_updater: impl DeploymentUpdater,
         log_recorder: impl LogRecorder,
         secret_recorder: impl SecretRecorder,
-    ) -> Result<Built> {
+        secret_getter: impl SecretGetter,
+    ) -> Result<Vec<Built>> {
         info!("Extracting received data");
 
         let project_path = storage_manager.service_build_path(&self.service_name)?;
@@ -211,16 +220,27 @@ impl Queued {
 
         let project_path = project_path.canonicalize()?;
 
-        // Currently returns the first found shuttle service in a given workspace.
-        let runtime = build_deployment(&project_path, tx.clone()).await?;
+        // Fail before spending time on a build if the secrets don't match the ones declared in Shuttle.toml
+        let schema = get_secrets_schema(&project_path).await?;
+        check_secrets(
+            &schema,
+            &project_path,
+            &self.service_id,
+            secret_getter.clone(),
+        )
+        .await?;
+
+        let mut services = build_deployment(&project_path, tx.clone()).await?;
+
+        // This deployment goes to the shuttle service with the same name as the service being
+        // deployed, or to the first one found in the workspace when none of them have that name.
+        let primary = services.remove(primary_service_index(&services, &self.service_name));
 
         // Get the Secrets.toml from the shuttle service in the workspace.
-        let secrets = get_secrets(&runtime.working_directory).await?;
+        let secrets = get_secrets(&primary.working_directory).await?;
 
         // Set the secrets from the service, ignoring any Secrets.toml if it is in the root of the workspace.
-        // TODO: refactor this when we support starting multiple services. Do we want to set secrets in the
-        // workspace root?
-        set_secrets(secrets, &self.service_id, secret_recorder).await?;
+        set_secrets(secrets, &self.service_id, secret_recorder.clone()).await?;
 
         if self.will_run_tests {
             info!(
@@ -233,28 +253,254 @@ impl Queued {
 
         info!("Moving built executable");
 
-        store_executable(&storage_manager, runtime.executable_path.clone(), &self.id).await?;
+        store_executable(&storage_manager, primary.executable_path.clone(), &self.id).await?;
 
-        let is_next = runtime.is_wasm;
+        let is_next = primary.is_wasm;
 
         deployment_updater
             .set_is_next(&id, is_next)
             .await
             .map_err(|e| Error::Build(Box::new(e)))?;
 
-        let built = Built {
+        // The Shuttle.toml of the service takes precedence over the one in the root of the workspace
+        let run_config = RunConfig::read(&primary.working_directory)
+            .await?
+            .or(RunConfig::read(&project_path).await?);
+
+        run_config.store(&id, &deployment_updater).await?;
+
+        let mut built = vec![run_config.into_built(Built {
             id: self.id,
             service_name: self.service_name,
             service_id: self.service_id,
             tracing_context: Default::default(),
             is_next,
-            claim: self.claim,
-        };
+            claim: self.claim.clone(),
+            health_check: None,
+            readiness: Default::default(),
+            restart_policy: Default::default(),
+            next: Default::default(),
+            secrets_schema: Default::default(),
+            restart_count: 0,
+        })];
+
+        // Every other shuttle service in the workspace gets a deployment of its own
+        for service in services {
+            let workspace_built = add_workspace_service(
+                &service,
+                self.claim.clone(),
+                &storage_manager,
+                deployment_updater.clone(),
+                secret_recorder.clone(),
+                secret_getter.clone(),
+            )
+            .await;
+
+            match workspace_built {
+                Ok(workspace_built) => built.push(workspace_built),
+                Err(error) => {
+                    // None of the deployments of the workspace get to run, so the ones added so far
+                    // should not be left behind as built
+                    for workspace_built in &built[1..] {
+                        build_failed(
+                            &workspace_built.id,
+                            Error::Build(
+                                anyhow::anyhow!("another service in the workspace failed").into(),
+                            ),
+                        );
+                    }
+
+                    return Err(error);
+                }
+            }
+        }
 
         Ok(built)
     }
 }
 
+/// Position of the service which should take the deployment of `service_name`
+fn primary_service_index(services: &[BuiltService], service_name: &str) -> usize {
+    services
+        .iter()
+        .position(|service| {
+            service
+                .service_name()
+                .map_or(false, |name| name.to_string() == service_name)
+        })
+        .unwrap_or(0)
+}
+
+/// Add a deployment for a service which is not the one being deployed, but which was built from the
+/// same workspace
+async fn add_workspace_service(
+    service: &BuiltService,
+    claim: Option<Claim>,
+    storage_manager: &ArtifactsStorageManager,
+    deployment_updater: impl DeploymentUpdater,
+    secret_recorder: impl SecretRecorder,
+    secret_getter: impl SecretGetter,
+) -> Result<Built> {
+    let service_name = service
+        .service_name()
+        .map_err(|e| Error::Build(e.into()))?
+        .to_string();
+
+    info!(
+        service_name,
+        "Adding deployment for another service in the workspace"
+    );
+
+    let (service_id, id) = deployment_updater
+        .add_built_deployment(&service_name, service.is_wasm)
+        .await
+        .map_err(|e| Error::Build(Box::new(e)))?;
+
+    let run_config = match prepare_workspace_service(
+        service,
+        &service_id,
+        &id,
+        storage_manager,
+        deployment_updater,
+        secret_recorder,
+        secret_getter,
+    )
+    .await
+    {
+        Ok(run_config) => run_config,
+        Err(error) => {
+            build_failed(&id, error);
+
+            return Err(Error::Build(
+                anyhow::anyhow!("failed to prepare the {service_name} service").into(),
+            ));
+        }
+    };
+
+    Ok(run_config.into_built(Built {
+        id,
+        service_name,
+        service_id,
+        tracing_context: Default::default(),
+        is_next: service.is_wasm,
+        claim,
+        health_check: None,
+        readiness: Default::default(),
+        restart_policy: Default::default(),
+        next: Default::default(),
+        secrets_schema: Default::default(),
+        restart_count: 0,
+    }))
+}
+
+/// Set the secrets, store the run config and the executable of a service which is not the one being
+/// deployed, but which was built from the same workspace. Returns the run config of the service.
+async fn prepare_workspace_service(
+    service: &BuiltService,
+    service_id: &Uuid,
+    id: &Uuid,
+    storage_manager: &ArtifactsStorageManager,
+    deployment_updater: impl DeploymentUpdater,
+    secret_recorder: impl SecretRecorder,
+    secret_getter: impl SecretGetter,
+) -> Result<RunConfig> {
+    let schema = get_secrets_schema(&service.working_directory).await?;
+    check_secrets(
+        &schema,
+        &service.working_directory,
+        service_id,
+        secret_getter,
+    )
+    .await?;
+
+    let secrets = get_secrets(&service.working_directory).await?;
+
+    set_secrets(secrets, service_id, secret_recorder).await?;
+
+    let run_config = RunConfig::read(&service.working_directory).await?;
+
+    run_config.store(id, &deployment_updater).await?;
+
+    store_executable(storage_manager, service.executable_path.clone(), id).await?;
+
+    Ok(run_config)
+}
+
+/// Tables of the Shuttle.toml of a service which decide how its deployments are run
+#[derive(Default)]
+struct RunConfig {
+    health_check: Option<HealthCheck>,
+    readiness: Option<Readiness>,
+    restart_policy: Option<RestartPolicy>,
+    tcp: Option<Tcp>,
+    next: Option<Next>,
+    secrets_schema: Option<SecretSchema>,
+}
+
+impl RunConfig {
+    async fn read(project_path: &Path) -> Result<Self> {
+        Ok(Self {
+            health_check: get_health_check(project_path).await?,
+            readiness: get_readiness(project_path).await?,
+            restart_policy: get_restart_policy(project_path).await?,
+            tcp: get_tcp(project_path).await?,
+            next: get_next(project_path).await?,
+            secrets_schema: Some(get_secrets_schema(project_path).await?)
+                .filter(|secrets_schema| !secrets_schema.is_empty()),
+        })
+    }
+
+    /// Fall back to the tables of `other` for the ones which are missing
+    fn or(self, other: Self) -> Self {
+        Self {
+            health_check: self.health_check.or(other.health_check),
+            readiness: self.readiness.or(other.readiness),
+            restart_policy: self.restart_policy.or(other.restart_policy),
+            tcp: self.tcp.or(other.tcp),
+            next: self.next.or(other.next),
+            secrets_schema: self.secrets_schema.or(other.secrets_schema),
+        }
+    }
+
+    async fn store(&self, id: &Uuid, deployment_updater: &impl DeploymentUpdater) -> Result<()> {
+        deployment_updater
+            .set_health_check(id, self.health_check.as_ref())
+            .await
+            .map_err(|e| Error::Build(Box::new(e)))?;
+        deployment_updater
+            .set_readiness(id, self.readiness.as_ref())
+            .await
+            .map_err(|e| Error::Build(Box::new(e)))?;
+        deployment_updater
+            .set_restart_policy(id, self.restart_policy.as_ref())
+            .await
+            .map_err(|e| Error::Build(Box::new(e)))?;
+        deployment_updater
+            .set_tcp(id, self.tcp.as_ref())
+            .await
+            .map_err(|e| Error::Build(Box::new(e)))?;
+        deployment_updater
+            .set_next(id, self.next.as_ref())
+            .await
+            .map_err(|e| Error::Build(Box::new(e)))?;
+        deployment_updater
+            .set_secrets_schema(id, self.secrets_schema.as_ref())
+            .await
+            .map_err(|e| Error::Build(Box::new(e)))
+    }
+
+    fn into_built(self, built: Built) -> Built {
+        Built {
+            health_check: self.health_check,
+            readiness: self.readiness.unwrap_or_default(),
+            restart_policy: self.restart_policy.unwrap_or_default(),
+            next: self.next.unwrap_or_default(),
+            secrets_schema: self.secrets_schema.unwrap_or_default(),
+            ..built
+        }
+    }
+}
+
 impl fmt::Debug for Queued {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Queued")
@@ -268,21 +514,148 @@ impl fmt::Debug for Queued {
 
 #[instrument(skip(project_path))]
 async fn get_secrets(project_path: &Path) -> Result<BTreeMap<String, String>> {
+    let secrets = read_secrets(project_path).await?;
+
+    let secrets_file = project_path.join("Secrets.toml");
+    if secrets_file.is_file() {
+        remove_file(secrets_file)?;
+    }
+
+    Ok(secrets)
+}
+
+async fn read_secrets(project_path: &Path) -> Result<BTreeMap<String, String>> {
     let secrets_file = project_path.join("Secrets.toml");
 
     if secrets_file.exists() && secrets_file.is_file() {
-        let secrets_str = fs::read_to_string(secrets_file.clone()).await?;
+        let secrets_str = fs::read_to_string(secrets_file).await?;
 
         let secrets: BTreeMap<String, String> = secrets_str.parse::<toml::Value>()?.try_into()?;
 
-        remove_file(secrets_file)?;
-
         Ok(secrets)
     } else {
         Ok(Default::default())
     }
 }
 
+async fn read_shuttle_toml(project_path: &Path) -> Result<Option<toml::Value>> {
+    let shuttle_toml = project_path.join("Shuttle.toml");
+
+    if !shuttle_toml.is_file() {
+        return Ok(None);
+    }
+
+    let shuttle_toml = fs::read_to_string(shuttle_toml)
+        .await?
+        .parse::<toml::Value>()?;
+
+    Ok(Some(shuttle_toml))
+}
+
+/// Get the secrets declared in the `[secrets]` table of the Shuttle.toml, if there is one
+#[instrument(skip(project_path))]
+async fn get_secrets_schema(project_path: &Path) -> Result<SecretSchema> {
+    let shuttle_toml = read_shuttle_toml(project_path).await?;
+
+    match shuttle_toml.as_ref().and_then(|toml| toml.get("secrets")) {
+        Some(secrets) => Ok(secrets.clone().try_into()?),
+        None => Ok(Default::default()),
+    }
+}
+
+/// Get the `[health_check]` table of the Shuttle.toml, if there is one
+#[instrument(skip(project_path))]
+async fn get_health_check(project_path: &Path) -> Result<Option<HealthCheck>> {
+    let shuttle_toml = read_shuttle_toml(project_path).await?;
+
+    shuttle_toml
+        .as_ref()
+        .and_then(|toml| toml.get("health_check"))
+        .map(|health_check| health_check.clone().try_into())
+        .transpose()
+        .map_err(Error::HealthCheckParse)
+}
+
+/// Get the `[readiness]` table of the Shuttle.toml, if there is one
+#[instrument(skip(project_path))]
+async fn get_readiness(project_path: &Path) -> Result<Option<Readiness>> {
+    let shuttle_toml = read_shuttle_toml(project_path).await?;
+
+    shuttle_toml
+        .as_ref()
+        .and_then(|toml| toml.get("readiness"))
+        .map(|readiness| readiness.clone().try_into())
+        .transpose()
+        .map_err(Error::ReadinessParse)
+}
+
+/// Get the `[restart]` table of the Shuttle.toml, if there is one
+#[instrument(skip(project_path))]
+async fn get_restart_policy(project_path: &Path) -> Result<Option<RestartPolicy>> {
+    let shuttle_toml = read_shuttle_toml(project_path).await?;
+
+    shuttle_toml
+        .as_ref()
+        .and_then(|toml| toml.get("restart"))
+        .map(|restart_policy| restart_policy.clone().try_into())
+        .transpose()
+        .map_err(Error::RestartPolicyParse)
+}
+
+/// Get the `[tcp]` table of the Shuttle.toml, if there is one
+#[instrument(skip(project_path))]
+async fn get_tcp(project_path: &Path) -> Result<Option<Tcp>> {
+    let shuttle_toml = read_shuttle_toml(project_path).await?;
+
+    shuttle_toml
+        .as_ref()
+        .and_then(|toml| toml.get("tcp"))
+        .map(|tcp| tcp.clone().try_into())
+        .transpose()
+        .map_err(Error::TcpParse)
+}
+
+/// Get the `[next]` table of the Shuttle.toml, if there is one
+#[instrument(skip(project_path))]
+async fn get_next(project_path: &Path) -> Result<Option<Next>> {
+    let shuttle_toml = read_shuttle_toml(project_path).await?;
+
+    shuttle_toml
+        .as_ref()
+        .and_then(|toml| toml.get("next"))
+        .map(|next| next.clone().try_into())
+        .transpose()
+        .map_err(Error::NextParse)
+}
+
+/// Check the secrets already set for the service together with the ones in its Secrets.toml against
+/// the schema. The defaults of the schema are not stored with the secrets, but applied when the
+/// deployment is loaded.
+#[instrument(skip(schema, project_path, service_id, secret_getter))]
+async fn check_secrets(
+    schema: &SecretSchema,
+    project_path: &Path,
+    service_id: &Uuid,
+    secret_getter: impl SecretGetter,
+) -> Result<()> {
+    if schema.is_empty() {
+        return Ok(());
+    }
+
+    let mut secrets: BTreeMap<_, _> = secret_getter
+        .get_secrets(service_id)
+        .await
+        .map_err(|e| Error::SecretsGet(Box::new(e)))?
+        .into_iter()
+        .map(|secret| (secret.key, secret.value))
+        .collect();
+    secrets.extend(read_secrets(project_path).await?);
+
+    schema.validate(&secrets)?;
+
+    Ok(())
+}
+
 #[instrument(skip(secrets, service_id, secret_recorder))]
 async fn set_secrets(
     secrets: BTreeMap<String, String>,
@@ -338,12 +711,18 @@ async fn extract_tar_gz_data(data: impl Read, dest: impl AsRef<Path>) -> Result<
 async fn build_deployment(
     project_path: &Path,
     tx: crossbeam_channel::Sender<Message>,
-) -> Result<BuiltService> {
-    let runtimes = build_workspace(project_path, true, tx)
+) -> Result<Vec<BuiltService>> {
+    let services = build_workspace(project_path, true, tx)
         .await
         .map_err(|e| Error::Build(e.into()))?;
 
-    Ok(runtimes[0].clone())
+    if services.is_empty() {
+        return Err(Error::Build(
+            anyhow::anyhow!("no shuttle services were found in the workspace").into(),
+        ));
+    }
+
+    Ok(services)
 }
 
 #[instrument(skip(project_path, tx))]
@@ -421,12 +800,37 @@ async fn store_executable(
 mod tests {
     use std::{collections::BTreeMap, fs::File, io::Write, path::Path};
 
-    use shuttle_common::storage_manager::ArtifactsStorageManager;
+    use async_trait::async_trait;
+    use chrono::Utc;
+    use shuttle_common::{
+        deployment::{HealthCheck, Next, Readiness, RestartPolicy, Tcp},
+        storage_manager::ArtifactsStorageManager,
+    };
     use tempfile::Builder;
     use tokio::fs;
     use uuid::Uuid;
 
-    use crate::error::TestError;
+    use crate::{
+        error::{Error, TestError},
+        persistence::{Secret, SecretGetter},
+    };
+
+    #[derive(Clone)]
+    struct StubSecretGetter;
+
+    #[async_trait]
+    impl SecretGetter for StubSecretGetter {
+        type Err = std::io::Error;
+
+        async fn get_secrets(&self, service_id: &Uuid) -> Result<Vec<Secret>, Self::Err> {
+            Ok(vec![Secret {
+                service_id: *service_id,
+                key: "DATABASE_URL".to_string(),
+                value: "postgres://localhost".to_string(),
+                last_update: Utc::now(),
+            }])
+        }
+    }
 
     #[tokio::test]
     async fn extract_tar_gz_data() {
@@ -598,4 +1002,260 @@ ff0e55bda1ff01000000000000000000e0079c01ff12a55500280000",
 
         assert!(!secret_p.exists(), "the secrets file should be deleted");
     }
+
+    #[tokio::test]
+    async fn check_secrets() {
+        let temp = Builder::new().prefix("secrets").tempdir().unwrap();
+        let temp_p = temp.path();
+
+        fs::write(
+            temp_p.join("Shuttle.toml"),
+            r#"
+name = "my-project"
+
+[secrets]
+DATABASE_URL = "string"
+DISCORD_TOKEN = "string"
+PORT = { type = "integer", default = "8000" }
+"#,
+        )
+        .await
+        .unwrap();
+
+        let schema = super::get_secrets_schema(temp_p).await.unwrap();
+        let service_id = Uuid::new_v4();
+
+        let error = super::check_secrets(&schema, temp_p, &service_id, StubSecretGetter)
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(error, Error::SecretsValidation(ref error) if error.missing == vec!["DISCORD_TOKEN".to_string()]),
+            "only the key not set anywhere should be missing, got {error}"
+        );
+
+        fs::write(temp_p.join("Secrets.toml"), b"DISCORD_TOKEN = 'token'")
+            .await
+            .unwrap();
+
+        super::check_secrets(&schema, temp_p, &service_id, StubSecretGetter)
+            .await
+            .unwrap();
+
+        assert!(
+            temp_p.join("Secrets.toml").exists(),
+            "checking should not consume the secrets file"
+        );
+
+        // The defaults are kept with the deployment, to be applied when it is loaded
+        let run_config = super::RunConfig::read(temp_p).await.unwrap();
+
+        assert_eq!(
+            run_config
+                .secrets_schema
+                .unwrap()
+                .defaults_for_missing(["DISCORD_TOKEN"]),
+            BTreeMap::from([("PORT".to_string(), "8000".to_string())])
+        );
+    }
+
+    #[tokio::test]
+    async fn get_health_check() {
+        let temp = Builder::new().prefix("health_check").tempdir().unwrap();
+        let temp_p = temp.path();
+
+        assert_eq!(super::get_health_check(temp_p).await.unwrap(), None);
+
+        fs::write(
+            temp_p.join("Shuttle.toml"),
+            r#"
+name = "my-project"
+
+[health_check]
+path = "/health"
+failure_threshold = 5
+"#,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            super::get_health_check(temp_p).await.unwrap(),
+            Some(HealthCheck {
+                path: "/health".to_string(),
+                interval: 30,
+                timeout: 5,
+                failure_threshold: 5,
+                grace_period: 10,
+            })
+        );
+
+        fs::write(
+            temp_p.join("Shuttle.toml"),
+            b"[health_check]\ninterval = 10",
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(
+            super::get_health_check(temp_p).await,
+            Err(Error::HealthCheckParse(_))
+        ));
+
+        // Restarts moved to the `[restart]` table, so the old key should not be ignored silently
+        fs::write(
+            temp_p.join("Shuttle.toml"),
+            b"[health_check]\npath = \"/health\"\nrestart = true",
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(
+            super::get_health_check(temp_p).await,
+            Err(Error::HealthCheckParse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn get_readiness() {
+        let temp = Builder::new().prefix("readiness").tempdir().unwrap();
+        let temp_p = temp.path();
+
+        fs::write(
+            temp_p.join("Shuttle.toml"),
+            r#"
+name = "my-project"
+
+[readiness]
+path = "/ready"
+drain_timeout = 30
+"#,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            super::get_readiness(temp_p).await.unwrap(),
+            Some(Readiness {
+                path: Some("/ready".to_string()),
+                warm_up: 0,
+                timeout: 60,
+                drain_timeout: 30,
+            })
+        );
+
+        fs::write(temp_p.join("Shuttle.toml"), b"[readiness]\nwarm_up = -1")
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            super::get_readiness(temp_p).await,
+            Err(Error::ReadinessParse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn get_restart_policy() {
+        let temp = Builder::new().prefix("restart_policy").tempdir().unwrap();
+        let temp_p = temp.path();
+
+        fs::write(
+            temp_p.join("Shuttle.toml"),
+            b"[restart]\npolicy = 'on-failure'\nmax_restarts = 3",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            super::get_restart_policy(temp_p).await.unwrap(),
+            Some(RestartPolicy::OnFailure { max_restarts: 3 })
+        );
+
+        fs::write(temp_p.join("Shuttle.toml"), b"[restart]\npolicy = 'always'")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            super::get_restart_policy(temp_p).await.unwrap(),
+            Some(RestartPolicy::Always)
+        );
+
+        fs::write(
+            temp_p.join("Shuttle.toml"),
+            b"[restart]\npolicy = 'sometimes'",
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(
+            super::get_restart_policy(temp_p).await,
+            Err(Error::RestartPolicyParse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn get_tcp() {
+        let temp = Builder::new().prefix("tcp").tempdir().unwrap();
+        let temp_p = temp.path();
+
+        assert_eq!(super::get_tcp(temp_p).await.unwrap(), None);
+
+        fs::write(temp_p.join("Shuttle.toml"), b"[tcp]")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            super::get_tcp(temp_p).await.unwrap(),
+            Some(Tcp { idle_timeout: 300 })
+        );
+
+        fs::write(
+            temp_p.join("Shuttle.toml"),
+            b"[tcp]\nidle_timeout = 'never'",
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(
+            super::get_tcp(temp_p).await,
+            Err(Error::TcpParse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn get_next() {
+        let temp = Builder::new().prefix("next").tempdir().unwrap();
+        let temp_p = temp.path();
+
+        assert_eq!(super::get_next(temp_p).await.unwrap(), None);
+
+        fs::write(temp_p.join("Shuttle.toml"), b"[next]")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            super::get_next(temp_p).await.unwrap(),
+            Some(Next { body_limit: 65536 })
+        );
+
+        fs::write(temp_p.join("Shuttle.toml"), b"[next]\nbody_limit = 1048576")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            super::get_next(temp_p).await.unwrap(),
+            Some(Next {
+                body_limit: 1048576
+            })
+        );
+
+        fs::write(temp_p.join("Shuttle.toml"), b"[next]\nbody_limit = -1")
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            super::get_next(temp_p).await,
+            Err(Error::NextParse(_))
+        ));
+    }
 }
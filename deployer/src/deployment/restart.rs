@@ -0,0 +1,264 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use shuttle_common::deployment::RestartPolicy;
+use shuttle_proto::runtime::StopReason;
+use tokio::{task::JoinHandle, time::sleep};
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use super::{Built, RunSender, State};
+
+/// Bounds for the wait before a restart, which doubles with every restart of a deployment
+const RESTART_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(5 * 60);
+
+/// Time after which a running deployment is healthy again, and starts counting its restarts from zero
+pub const RESTART_COUNT_RESET: Duration = Duration::from_secs(10 * 60);
+
+/// Restarts which are waiting for their backoff to pass, by the id of the service being restarted
+#[derive(Clone, Default)]
+pub struct PendingRestarts {
+    restarts: Arc<Mutex<HashMap<Uuid, PendingRestart>>>,
+}
+
+struct PendingRestart {
+    id: Uuid,
+    task: JoinHandle<()>,
+}
+
+impl PendingRestarts {
+    /// Put a deployment which stopped for `reason` after running for `ran_for` back on the run
+    /// queue once its backoff has passed, if its restart policy asks for it
+    pub fn apply(&self, built: &Built, reason: StopReason, ran_for: Duration, run_send: RunSender) {
+        let restart_count = if ran_for >= RESTART_COUNT_RESET {
+            0
+        } else {
+            built.restart_count
+        };
+
+        if !should_restart(&built.restart_policy, reason, restart_count) {
+            return;
+        }
+
+        let delay = backoff(restart_count);
+        let built = Built {
+            tracing_context: Default::default(),
+            // This will cause us to read the resource info from past provisions
+            claim: None,
+            restart_count: restart_count + 1,
+            ..built.clone()
+        };
+
+        restarting(&built.id, built.restart_count, delay);
+
+        let mut restarts = self.restarts.lock().unwrap();
+        let pending_restarts = self.clone();
+        let service_id = built.service_id;
+        let id = built.id;
+
+        let task = tokio::spawn(async move {
+            sleep(delay).await;
+
+            if pending_restarts.take(&service_id, &id) && run_send.send(built).await.is_err() {
+                warn!(%id, "failed to restart deployment");
+            }
+        });
+
+        if let Some(previous) = restarts.insert(service_id, PendingRestart { id, task }) {
+            previous.task.abort();
+            restart_cancelled(&previous.id);
+        }
+    }
+
+    /// Cancel the restart of a deployment, as it was stopped by the user
+    pub fn cancel(&self, id: &Uuid) {
+        let mut restarts = self.restarts.lock().unwrap();
+
+        restarts.retain(|_, pending| {
+            if &pending.id == id {
+                pending.task.abort();
+                restart_cancelled(id);
+
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Cancel the restart of an older deployment of the service which `built` replaces
+    pub fn supersede(&self, built: &Built) {
+        let mut restarts = self.restarts.lock().unwrap();
+
+        if restarts
+            .get(&built.service_id)
+            .map_or(false, |pending| pending.id != built.id)
+        {
+            let previous = restarts
+                .remove(&built.service_id)
+                .expect("pending restart to exist");
+
+            previous.task.abort();
+            restart_cancelled(&previous.id);
+        }
+    }
+
+    /// Remove the pending restart of a service when it is still the one for the deployment `id`
+    fn take(&self, service_id: &Uuid, id: &Uuid) -> bool {
+        let mut restarts = self.restarts.lock().unwrap();
+
+        if restarts
+            .get(service_id)
+            .map_or(false, |pending| &pending.id == id)
+        {
+            restarts.remove(service_id);
+
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn should_restart(policy: &RestartPolicy, reason: StopReason, restart_count: u32) -> bool {
+    match (policy, reason) {
+        (_, StopReason::Request) => false,
+        (RestartPolicy::Never, _) => false,
+        (RestartPolicy::OnFailure { max_restarts }, StopReason::Crash) => {
+            restart_count < *max_restarts
+        }
+        (RestartPolicy::OnFailure { .. }, StopReason::End) => false,
+        (RestartPolicy::Always, _) => true,
+    }
+}
+
+fn backoff(restart_count: u32) -> Duration {
+    RESTART_BACKOFF_MIN
+        .saturating_mul(2u32.saturating_pow(restart_count))
+        .min(RESTART_BACKOFF_MAX)
+}
+
+#[instrument(skip(_id), fields(id = %_id, state = %State::Restarting))]
+fn restarting(_id: &Uuid, restart_count: u32, delay: Duration) {
+    info!("service will be restarted in {delay:?}");
+}
+
+#[instrument(skip(_id), fields(id = %_id, state = %State::Stopped))]
+fn restart_cancelled(_id: &Uuid) {
+    info!("restart of the service was cancelled");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use shuttle_common::deployment::RestartPolicy;
+    use shuttle_proto::runtime::StopReason;
+    use tokio::sync::mpsc;
+    use uuid::Uuid;
+
+    use super::{backoff, should_restart, PendingRestarts, RESTART_COUNT_RESET};
+    use crate::deployment::Built;
+
+    #[test]
+    fn policy() {
+        let on_failure = RestartPolicy::OnFailure { max_restarts: 2 };
+
+        assert!(!should_restart(&RestartPolicy::Never, StopReason::Crash, 0));
+        assert!(should_restart(&on_failure, StopReason::Crash, 1));
+        assert!(!should_restart(&on_failure, StopReason::Crash, 2));
+        assert!(!should_restart(&on_failure, StopReason::End, 0));
+        assert!(should_restart(&RestartPolicy::Always, StopReason::End, 10));
+        assert!(!should_restart(
+            &RestartPolicy::Always,
+            StopReason::Request,
+            0
+        ));
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        assert_eq!(backoff(0), Duration::from_secs(1));
+        assert_eq!(backoff(3), Duration::from_secs(8));
+        assert_eq!(backoff(40), Duration::from_secs(5 * 60));
+    }
+
+    fn built(service_id: Uuid) -> Built {
+        Built {
+            id: Uuid::new_v4(),
+            service_name: "restart-test".to_string(),
+            service_id,
+            tracing_context: Default::default(),
+            is_next: false,
+            claim: None,
+            health_check: None,
+            readiness: Default::default(),
+            restart_policy: RestartPolicy::Always,
+            next: Default::default(),
+            secrets_schema: Default::default(),
+            restart_count: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn restart() {
+        let (run_send, mut run_recv) = mpsc::channel(1);
+        let pending_restarts = PendingRestarts::default();
+        let built = built(Uuid::new_v4());
+
+        pending_restarts.apply(&built, StopReason::Crash, Duration::ZERO, run_send);
+
+        let restarted = run_recv.recv().await.unwrap();
+        assert_eq!(restarted.id, built.id);
+        assert_eq!(restarted.restart_count, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restart_count_reset() {
+        let (run_send, mut run_recv) = mpsc::channel(1);
+        let pending_restarts = PendingRestarts::default();
+        let built = Built {
+            restart_policy: RestartPolicy::OnFailure { max_restarts: 2 },
+            restart_count: 2,
+            ..built(Uuid::new_v4())
+        };
+
+        // Out of restarts when it crashes soon after the last one
+        pending_restarts.apply(
+            &built,
+            StopReason::Crash,
+            Duration::from_secs(60),
+            run_send.clone(),
+        );
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert!(run_recv.try_recv().is_err());
+
+        // But healthy again when it ran for a while before crashing
+        pending_restarts.apply(&built, StopReason::Crash, RESTART_COUNT_RESET, run_send);
+
+        let restarted = run_recv.recv().await.unwrap();
+        assert_eq!(restarted.restart_count, 1);
+    }
+
+    #[tokio::test]
+    async fn cancelled_restart() {
+        let (run_send, mut run_recv) = mpsc::channel(1);
+        let pending_restarts = PendingRestarts::default();
+        let service_id = Uuid::new_v4();
+        let stopped = built(service_id);
+        let replaced = built(service_id);
+
+        pending_restarts.apply(&stopped, StopReason::End, Duration::ZERO, run_send.clone());
+        pending_restarts.cancel(&stopped.id);
+
+        pending_restarts.apply(&replaced, StopReason::Crash, Duration::ZERO, run_send);
+        pending_restarts.supersede(&built(service_id));
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(run_recv.try_recv().is_err());
+    }
+}
@@ -3,6 +3,7 @@ use std::{
     net::{Ipv4Addr, SocketAddr},
     path::PathBuf,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
@@ -10,7 +11,9 @@ use opentelemetry::global;
 use portpicker::pick_unused_port;
 use shuttle_common::{
     claims::{Claim, ClaimService, InjectPropagation},
+    deployment::{HealthCheck, Next, Readiness, RestartPolicy},
     resource,
+    secrets::SecretSchema,
     storage_manager::ArtifactsStorageManager,
 };
 
@@ -18,23 +21,36 @@ use shuttle_proto::runtime::{
     runtime_client::RuntimeClient, LoadRequest, StartRequest, StopReason, SubscribeStopRequest,
     SubscribeStopResponse,
 };
-use tokio::sync::Mutex;
+use tokio::{
+    sync::Mutex,
+    time::{sleep, timeout},
+};
 use tonic::{transport::Channel, Code};
 use tracing::{debug, debug_span, error, info, instrument, trace, warn, Instrument};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 use uuid::Uuid;
 
-use super::{RunReceiver, State};
+use super::{
+    health::{wait_until_ready, HealthMonitor},
+    restart::{PendingRestarts, RESTART_COUNT_RESET},
+    RunReceiver, RunSender, State,
+};
 use crate::{
     error::{Error, Result},
-    persistence::{DeploymentUpdater, Resource, ResourceManager, SecretGetter},
-    RuntimeManager,
+    persistence::{
+        deployment::DeploymentRunnable, Deployment, DeploymentUpdater, Resource, ResourceManager,
+        ResourceType, SecretGetter,
+    },
+    proxy, RuntimeManager,
 };
 
 /// Run a task which takes runnable deploys from a channel and starts them up on our runtime
-/// A deploy is killed when it receives a signal from the kill channel
+/// A deploy is killed when it receives a signal from the kill channel, and is put back on the
+/// channel when its restart policy asks for it
 pub async fn task(
     mut recv: RunReceiver,
+    run_send: RunSender,
+    pending_restarts: PendingRestarts,
     runtime_manager: Arc<Mutex<RuntimeManager>>,
     deployment_updater: impl DeploymentUpdater,
     active_deployment_getter: impl ActiveDeploymentsGetter,
@@ -49,6 +65,8 @@ pub async fn task(
 
         info!("Built deployment at the front of run queue: {id}");
 
+        pending_restarts.supersede(&built);
+
         let deployment_updater = deployment_updater.clone();
         let secret_getter = secret_getter.clone();
         let resource_manager = resource_manager.clone();
@@ -59,12 +77,24 @@ pub async fn task(
             id,
             active_deployment_getter.clone(),
             runtime_manager.clone(),
+            Duration::from_secs(built.readiness.drain_timeout),
         );
+        let restart = {
+            let built = built.clone();
+            let run_send = run_send.clone();
+            let pending_restarts = pending_restarts.clone();
+            let started = Instant::now();
+
+            move |reason| pending_restarts.apply(&built, reason, started.elapsed(), run_send)
+        };
+        let restart_crashed_start = restart.clone();
         let cleanup = move |response: Option<SubscribeStopResponse>| {
             debug!(response = ?response,  "stop client response: ");
 
-            if let Some(response) = response {
-                match StopReason::from_i32(response.reason).unwrap_or_default() {
+            let reason = if let Some(response) = response {
+                let reason = StopReason::from_i32(response.reason).unwrap_or_default();
+
+                match reason {
                     StopReason::Request => stopped_cleanup(&id),
                     StopReason::End => completed_cleanup(&id),
                     StopReason::Crash => crashed_cleanup(
@@ -72,14 +102,20 @@ pub async fn task(
                         Error::Run(anyhow::Error::msg(response.message).into()),
                     ),
                 }
+
+                reason
             } else {
                 crashed_cleanup(
                     &id,
                     Error::Runtime(anyhow::anyhow!(
                         "stop subscribe channel stopped unexpectedly"
                     )),
-                )
-            }
+                );
+
+                StopReason::Crash
+            };
+
+            restart(reason);
         };
         let runtime_manager = runtime_manager.clone();
 
@@ -103,7 +139,8 @@ pub async fn task(
                     )
                     .await
                 {
-                    start_crashed_cleanup(&id, err)
+                    start_crashed_cleanup(&id, err);
+                    restart_crashed_start(StopReason::Crash);
                 }
 
                 info!("deployment done");
@@ -114,23 +151,46 @@ pub async fn task(
     }
 }
 
+/// Stop the other deployments of a service once they are done with the requests and connections
+/// the proxy sent them, or once they had `drain_timeout` to finish them. This should only happen once
+/// the new deployment gets the requests.
 #[instrument(skip(active_deployment_getter, runtime_manager))]
 async fn kill_old_deployments(
     service_id: Uuid,
     deployment_id: Uuid,
     active_deployment_getter: impl ActiveDeploymentsGetter,
     runtime_manager: Arc<Mutex<RuntimeManager>>,
+    drain_timeout: Duration,
 ) -> Result<()> {
-    let mut guard = runtime_manager.lock().await;
-
-    for old_id in active_deployment_getter
+    let old_deployments: Vec<_> = active_deployment_getter
         .clone()
         .get_active_deployments(&service_id)
         .await
         .map_err(|e| Error::OldCleanup(Box::new(e)))?
         .into_iter()
-        .filter(|old_id| old_id != &deployment_id)
+        .filter(|old| old.id != deployment_id)
+        .collect();
+
+    if old_deployments.is_empty() {
+        return Ok(());
+    }
+
+    let addresses: Vec<_> = old_deployments
+        .iter()
+        .filter_map(|old| old.address)
+        .collect();
+
+    trace!(?drain_timeout, "draining old deployments");
+    if timeout(drain_timeout, proxy::wait_until_idle(&addresses))
+        .await
+        .is_err()
     {
+        debug!("old deployments still had requests in flight after the drain timeout");
+    }
+
+    let mut guard = runtime_manager.lock().await;
+
+    for old_id in old_deployments.into_iter().map(|old| old.id) {
         trace!(%old_id, "stopping old deployment");
 
         if !guard.kill(&old_id).await {
@@ -174,7 +234,7 @@ pub trait ActiveDeploymentsGetter: Clone + Send + Sync + 'static {
     async fn get_active_deployments(
         &self,
         service_id: &Uuid,
-    ) -> std::result::Result<Vec<Uuid>, Self::Err>;
+    ) -> std::result::Result<Vec<Deployment>, Self::Err>;
 }
 
 #[derive(Clone, Debug)]
@@ -185,6 +245,41 @@ pub struct Built {
     pub tracing_context: HashMap<String, String>,
     pub is_next: bool,
     pub claim: Option<Claim>,
+    pub health_check: Option<HealthCheck>,
+    pub readiness: Readiness,
+    pub restart_policy: RestartPolicy,
+    pub next: Next,
+    pub secrets_schema: SecretSchema,
+    /// Times this deployment has been restarted by its restart policy
+    pub restart_count: u32,
+}
+
+impl From<DeploymentRunnable> for Built {
+    fn from(runnable: DeploymentRunnable) -> Self {
+        Self {
+            id: runnable.id,
+            service_name: runnable.service_name,
+            service_id: runnable.service_id,
+            tracing_context: Default::default(),
+            is_next: runnable.is_next,
+            claim: None, // This will cause us to read the resource info from past provisions
+            health_check: runnable.health_check.map(|health_check| health_check.0),
+            readiness: runnable
+                .readiness
+                .map(|readiness| readiness.0)
+                .unwrap_or_default(),
+            restart_policy: runnable
+                .restart_policy
+                .map(|restart_policy| restart_policy.0)
+                .unwrap_or_default(),
+            next: runnable.next.map(|next| next.0).unwrap_or_default(),
+            secrets_schema: runnable
+                .secrets_schema
+                .map(|secrets_schema| secrets_schema.0)
+                .unwrap_or_default(),
+            restart_count: runnable.restart_count,
+        }
+    }
 }
 
 impl Built {
@@ -197,7 +292,7 @@ impl Built {
         resource_manager: impl ResourceManager,
         runtime_manager: Arc<Mutex<RuntimeManager>>,
         deployment_updater: impl DeploymentUpdater,
-        kill_old_deployments: impl futures::Future<Output = Result<()>>,
+        kill_old_deployments: impl futures::Future<Output = Result<()>> + Send + 'static,
         cleanup: impl FnOnce(Option<SubscribeStopResponse>) + Send + 'static,
     ) -> Result<()> {
         // For alpha this is the path to the users project with an embedded runtime.
@@ -216,6 +311,12 @@ impl Built {
 
         let address = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port);
 
+        // Kept so that a restart of the deployer does not give the deployment its restarts back
+        deployment_updater
+            .set_restart_count(&self.id, self.restart_count)
+            .await
+            .map_err(|e| Error::PrepareRun(format!("could not set restart count: {e}")))?;
+
         let alpha_runtime_path = if self.is_next {
             // The runtime client for next is the installed shuttle-next bin
             None
@@ -230,13 +331,17 @@ impl Built {
             .await
             .map_err(Error::Runtime)?;
 
-        kill_old_deployments.await?;
+        let health_monitor = self
+            .health_check
+            .clone()
+            .map(|health_check| HealthMonitor::new(health_check, self.service_name.clone()));
 
         // Execute loaded service
         load(
             self.service_name.clone(),
             self.service_id,
             executable_path.clone(),
+            &self.secrets_schema,
             secret_getter,
             resource_manager,
             runtime_client.clone(),
@@ -244,12 +349,27 @@ impl Built {
         )
         .await?;
 
+        // The health check doubles as the readiness probe when no readiness path is declared
+        let readiness = Readiness {
+            path: self.readiness.path.clone().or_else(|| {
+                self.health_check
+                    .as_ref()
+                    .map(|health_check| health_check.path.clone())
+            }),
+            ..self.readiness
+        };
+
         tokio::spawn(run(
             self.id,
             self.service_name,
             runtime_client,
             address,
+            self.next.body_limit,
             deployment_updater,
+            runtime_manager,
+            readiness,
+            kill_old_deployments,
+            health_monitor,
             cleanup,
         ));
 
@@ -257,10 +377,12 @@ impl Built {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn load(
     service_name: String,
     service_id: Uuid,
     executable_path: PathBuf,
+    secrets_schema: &SecretSchema,
     secret_getter: impl SecretGetter,
     resource_manager: impl ResourceManager,
     mut runtime_client: RuntimeClient<ClaimService<InjectPropagation<Channel>>>,
@@ -275,19 +397,18 @@ async fn load(
             .unwrap_or_default()
     );
 
-    // Get resources from cache when a claim is not set (ie an idl project is started)
-    let resources = if claim.is_none() {
-        resource_manager
-            .get_resources(&service_id)
-            .await
-            .unwrap()
-            .into_iter()
-            .map(resource::Response::from)
-            .map(resource::Response::into_bytes)
-            .collect()
-    } else {
-        Default::default()
-    };
+    // Get resources from cache when a claim is not set (ie an idl project is started). A bucket is
+    // always taken from the cache once it has been provisioned: provisioning it again hands out a new
+    // key, and revokes older keys which deployments still running can be using.
+    let resources = resource_manager
+        .get_resources(&service_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|resource| claim.is_none() || resource.r#type == ResourceType::ObjectStorage)
+        .map(resource::Response::from)
+        .map(resource::Response::into_bytes)
+        .collect();
 
     let secrets = secret_getter
         .get_secrets(&service_id)
@@ -295,7 +416,12 @@ async fn load(
         .map_err(|e| Error::SecretsGet(Box::new(e)))?
         .into_iter()
         .map(|secret| (secret.key, secret.value));
-    let secrets = HashMap::from_iter(secrets);
+    let mut secrets = HashMap::from_iter(secrets);
+
+    // Defaults are applied for every load rather than stored, so that they follow the Shuttle.toml
+    // of the deployment
+    let defaults = secrets_schema.defaults_for_missing(secrets.keys().map(String::as_str));
+    secrets.extend(defaults);
 
     let mut load_request = tonic::Request::new(LoadRequest {
         path: executable_path
@@ -350,22 +476,24 @@ async fn load(
     }
 }
 
-#[instrument(skip(runtime_client, deployment_updater, cleanup), fields(state = %State::Running))]
+#[instrument(skip(runtime_client, deployment_updater, runtime_manager, readiness, kill_old_deployments, health_monitor, cleanup), fields(state = %State::Running))]
+#[allow(clippy::too_many_arguments)]
 async fn run(
     id: Uuid,
     service_name: String,
     mut runtime_client: RuntimeClient<ClaimService<InjectPropagation<Channel>>>,
     address: SocketAddr,
+    body_limit: u64,
     deployment_updater: impl DeploymentUpdater,
+    runtime_manager: Arc<Mutex<RuntimeManager>>,
+    readiness: Readiness,
+    kill_old_deployments: impl futures::Future<Output = Result<()>> + Send + 'static,
+    health_monitor: Option<HealthMonitor>,
     cleanup: impl FnOnce(Option<SubscribeStopResponse>) + Send + 'static,
 ) {
-    deployment_updater
-        .set_address(&id, &address)
-        .await
-        .expect("to set deployment address");
-
     let start_request = tonic::Request::new(StartRequest {
         ip: address.to_string(),
+        body_limit,
     });
 
     // Subscribe to stop before starting to catch immediate errors
@@ -382,8 +510,86 @@ async fn run(
         Ok(response) => {
             info!(response = ?response.into_inner(),  "start client response: ");
 
-            // Wait for stop reason
-            let reason = stream.message().await.expect("message from tonic stream");
+            // The old deployments keep getting the requests until this one is ready
+            let ready = tokio::select! {
+                reason = stream.message() => {
+                    cleanup(reason.expect("message from tonic stream"));
+                    return;
+                }
+                ready = wait_until_ready(address, &service_name, &readiness) => ready,
+            };
+
+            if let Err(message) = ready {
+                if !runtime_manager.lock().await.kill(&id).await {
+                    warn!("failed to stop deployment which is not ready");
+                }
+
+                cleanup(Some(SubscribeStopResponse {
+                    reason: StopReason::Crash as i32,
+                    message,
+                }));
+                return;
+            }
+
+            deployment_updater
+                .set_address(&id, &address)
+                .await
+                .expect("to set deployment address");
+
+            tokio::spawn(
+                async move {
+                    if let Err(error) = kill_old_deployments.await {
+                        error!(
+                            error = &error as &dyn std::error::Error,
+                            "failed to stop old deployments"
+                        );
+                    }
+                }
+                .in_current_span(),
+            );
+
+            let stopped = async {
+                let Some(health_monitor) = health_monitor else {
+                    // Wait for stop reason
+                    return stream.message().await.expect("message from tonic stream");
+                };
+
+                // The monitor only stops the deployment once this select is over, so the stop it
+                // causes cannot be taken for a stop requested by the user
+                let message = tokio::select! {
+                    biased;
+                    message = health_monitor.watch(&id, address) => message,
+                    reason = stream.message() => return reason.expect("message from tonic stream"),
+                };
+
+                warn!(message, "deployment failed its health check, stopping it");
+
+                if !runtime_manager.lock().await.kill(&id).await {
+                    warn!("failed to stop unhealthy deployment");
+                }
+
+                Some(SubscribeStopResponse {
+                    reason: StopReason::Crash as i32,
+                    message,
+                })
+            };
+            tokio::pin!(stopped);
+
+            // A deployment which keeps running for a while is healthy again, so it starts counting
+            // its restarts from zero
+            let reason = tokio::select! {
+                reason = &mut stopped => reason,
+                _ = sleep(RESTART_COUNT_RESET) => {
+                    if let Err(error) = deployment_updater.set_restart_count(&id, 0).await {
+                        error!(
+                            error = &error as &dyn std::error::Error,
+                            "failed to reset restart count"
+                        );
+                    }
+
+                    stopped.await
+                }
+            };
 
             cleanup(reason);
         }
@@ -416,28 +622,45 @@ mod tests {
 
     use async_trait::async_trait;
     use portpicker::pick_unused_port;
-    use shuttle_common::storage_manager::ArtifactsStorageManager;
+    use serde_json::{json, Value};
+    use shuttle_common::{
+        claims::{Claim, ClaimLayer, InjectPropagationLayer},
+        deployment::{HealthCheck, Next, Readiness, RestartPolicy, Tcp},
+        resource,
+        secrets::SecretSchema,
+        storage_manager::ArtifactsStorageManager,
+    };
     use shuttle_proto::{
         provisioner::{
             provisioner_server::{Provisioner, ProvisionerServer},
-            DatabaseDeletionResponse, DatabaseRequest, DatabaseResponse,
+            BucketRequest, BucketResponse, DatabaseDeletionResponse, DatabaseRequest,
+            DatabaseResponse,
+        },
+        runtime::{
+            runtime_client::RuntimeClient,
+            runtime_server::{Runtime, RuntimeServer},
+            LoadRequest, LoadResponse, LogItem, StartRequest, StartResponse, StopReason,
+            StopRequest, StopResponse, SubscribeLogsRequest, SubscribeStopRequest,
+            SubscribeStopResponse,
         },
-        runtime::{StopReason, SubscribeStopResponse},
     };
     use tempfile::Builder;
     use tokio::{
-        sync::{oneshot, Mutex},
+        sync::{mpsc, oneshot, Mutex},
         time::sleep,
     };
-    use tonic::transport::Server;
+    use tonic::transport::{Endpoint, Server};
+    use tower::ServiceBuilder;
     use uuid::Uuid;
 
     use crate::{
-        persistence::{DeploymentUpdater, Resource, ResourceManager, Secret, SecretGetter},
+        persistence::{
+            DeploymentUpdater, Resource, ResourceManager, ResourceType, Secret, SecretGetter,
+        },
         RuntimeManager,
     };
 
-    use super::Built;
+    use super::{Built, PendingRestarts};
 
     const RESOURCES_PATH: &str = "tests/resources";
 
@@ -469,6 +692,13 @@ mod tests {
         ) -> Result<tonic::Response<DatabaseDeletionResponse>, tonic::Status> {
             panic!("no run tests should delete a db");
         }
+
+        async fn provision_bucket(
+            &self,
+            _request: tonic::Request<BucketRequest>,
+        ) -> Result<tonic::Response<BucketResponse>, tonic::Status> {
+            panic!("no run tests should request a bucket");
+        }
     }
 
     fn get_runtime_manager() -> Arc<Mutex<RuntimeManager>> {
@@ -524,6 +754,114 @@ mod tests {
         }
     }
 
+    /// Keeps the resources it is given, like the persistence does
+    #[derive(Clone, Default)]
+    struct RecordingResourceManager(Arc<std::sync::Mutex<Vec<Resource>>>);
+
+    #[async_trait]
+    impl ResourceManager for RecordingResourceManager {
+        type Err = std::io::Error;
+
+        async fn insert_resource(&self, resource: &Resource) -> Result<(), Self::Err> {
+            let mut resources = self.0.lock().unwrap();
+
+            resources.retain(|existing| existing.r#type != resource.r#type);
+            resources.push(Resource {
+                service_id: resource.service_id,
+                r#type: resource.r#type,
+                data: resource.data.clone(),
+                config: resource.config.clone(),
+            });
+
+            Ok(())
+        }
+        async fn get_resources(&self, _service_id: &Uuid) -> Result<Vec<Resource>, Self::Err> {
+            let resources = self.0.lock().unwrap();
+
+            Ok(resources
+                .iter()
+                .map(|resource| Resource {
+                    service_id: resource.service_id,
+                    r#type: resource.r#type,
+                    data: resource.data.clone(),
+                    config: resource.config.clone(),
+                })
+                .collect())
+        }
+    }
+
+    /// Runtime whose service asks for a bucket. Like a real runtime, it only provisions the bucket
+    /// when the load does not come with a past one, and every provision hands out a new key.
+    #[derive(Clone, Default)]
+    struct BucketRuntime {
+        provisions: Arc<std::sync::Mutex<u32>>,
+    }
+
+    #[async_trait]
+    impl Runtime for BucketRuntime {
+        async fn load(
+            &self,
+            request: tonic::Request<LoadRequest>,
+        ) -> Result<tonic::Response<LoadResponse>, tonic::Status> {
+            let past_bucket = request
+                .into_inner()
+                .resources
+                .into_iter()
+                .map(resource::Response::from_bytes)
+                .find(|resource| resource.r#type == resource::Type::ObjectStorage);
+
+            let bucket = past_bucket.unwrap_or_else(|| {
+                let mut provisions = self.provisions.lock().unwrap();
+                *provisions += 1;
+
+                resource::Response {
+                    r#type: resource::Type::ObjectStorage,
+                    config: Value::Null,
+                    data: json!({ "access_key_id": format!("key-{provisions}") }),
+                }
+            });
+
+            Ok(tonic::Response::new(LoadResponse {
+                success: true,
+                message: String::new(),
+                resources: vec![bucket.into_bytes()],
+            }))
+        }
+
+        async fn start(
+            &self,
+            _request: tonic::Request<StartRequest>,
+        ) -> Result<tonic::Response<StartResponse>, tonic::Status> {
+            unimplemented!()
+        }
+
+        async fn stop(
+            &self,
+            _request: tonic::Request<StopRequest>,
+        ) -> Result<tonic::Response<StopResponse>, tonic::Status> {
+            unimplemented!()
+        }
+
+        type SubscribeStopStream =
+            futures::stream::Empty<Result<SubscribeStopResponse, tonic::Status>>;
+
+        async fn subscribe_stop(
+            &self,
+            _request: tonic::Request<SubscribeStopRequest>,
+        ) -> Result<tonic::Response<Self::SubscribeStopStream>, tonic::Status> {
+            unimplemented!()
+        }
+
+        type SubscribeLogsStream = futures::stream::Empty<Result<LogItem, tonic::Status>>;
+
+        async fn subscribe_logs(
+            &self,
+            _request: tonic::Request<SubscribeLogsRequest>,
+        ) -> Result<tonic::Response<Self::SubscribeLogsStream>, tonic::Status> {
+            unimplemented!()
+        }
+    }
+
     #[derive(Clone)]
     struct StubDeploymentUpdater;
 
@@ -538,6 +876,115 @@ mod tests {
         async fn set_is_next(&self, _id: &Uuid, _is_next: bool) -> Result<(), Self::Err> {
             Ok(())
         }
+
+        async fn set_health_check(
+            &self,
+            _id: &Uuid,
+            _health_check: Option<&HealthCheck>,
+        ) -> Result<(), Self::Err> {
+            Ok(())
+        }
+
+        async fn set_readiness(
+            &self,
+            _id: &Uuid,
+            _readiness: Option<&Readiness>,
+        ) -> Result<(), Self::Err> {
+            Ok(())
+        }
+
+        async fn set_restart_policy(
+            &self,
+            _id: &Uuid,
+            _restart_policy: Option<&RestartPolicy>,
+        ) -> Result<(), Self::Err> {
+            Ok(())
+        }
+
+        async fn set_tcp(&self, _id: &Uuid, _tcp: Option<&Tcp>) -> Result<(), Self::Err> {
+            Ok(())
+        }
+
+        async fn set_next(&self, _id: &Uuid, _next: Option<&Next>) -> Result<(), Self::Err> {
+            Ok(())
+        }
+
+        async fn set_secrets_schema(
+            &self,
+            _id: &Uuid,
+            _secrets_schema: Option<&SecretSchema>,
+        ) -> Result<(), Self::Err> {
+            Ok(())
+        }
+
+        async fn set_restart_count(
+            &self,
+            _id: &Uuid,
+            _restart_count: u32,
+        ) -> Result<(), Self::Err> {
+            Ok(())
+        }
+
+        async fn add_built_deployment(
+            &self,
+            _service_name: &str,
+            _is_next: bool,
+        ) -> Result<(Uuid, Uuid), Self::Err> {
+            Ok((Uuid::new_v4(), Uuid::new_v4()))
+        }
+    }
+
+    // A new deployment keeps the bucket key of the one it replaces, which is still running with it
+    #[tokio::test]
+    async fn bucket_is_provisioned_once() {
+        let runtime = BucketRuntime::default();
+        let address = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), pick_unused_port().unwrap());
+
+        tokio::spawn(
+            Server::builder()
+                .add_service(RuntimeServer::new(runtime.clone()))
+                .serve(address),
+        );
+
+        // Give the runtime some time to start up
+        sleep(Duration::from_millis(500)).await;
+
+        let channel = Endpoint::new(format!("http://{address}"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let runtime_client = RuntimeClient::new(
+            ServiceBuilder::new()
+                .layer(ClaimLayer)
+                .layer(InjectPropagationLayer)
+                .service(channel),
+        );
+        let resource_manager = RecordingResourceManager::default();
+        let service_id = Uuid::new_v4();
+
+        // Both deployments come with a claim, so neither is a restart of the other
+        for _ in 0..2 {
+            super::load(
+                "bucket".to_string(),
+                service_id,
+                PathBuf::new(),
+                &Default::default(),
+                StubSecretGetter,
+                resource_manager.clone(),
+                runtime_client.clone(),
+                Some(Claim::new("bucket-test".to_string(), Vec::new())),
+            )
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(*runtime.provisions.lock().unwrap(), 1);
+
+        let resources = resource_manager.0.lock().unwrap();
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0].r#type, ResourceType::ObjectStorage);
+        assert_eq!(resources[0].data, json!({ "access_key_id": "key-1" }));
     }
 
     // This test uses the kill signal to make sure a service does stop when asked to
@@ -624,6 +1071,77 @@ mod tests {
         drop(runtime_manager);
     }
 
+    // A service which fails its health check is stopped as crashed, and so restarted on failure
+    #[tokio::test]
+    async fn unhealthy() {
+        let (built, storage_manager) = make_and_built("unhealthy");
+        let built = Built {
+            health_check: Some(HealthCheck {
+                path: "/health".to_string(),
+                interval: 1,
+                timeout: 1,
+                failure_threshold: 2,
+                grace_period: 0,
+            }),
+            readiness: Readiness {
+                path: Some("/ready".to_string()),
+                ..Default::default()
+            },
+            restart_policy: RestartPolicy::OnFailure { max_restarts: 1 },
+            ..built
+        };
+        let runtime_manager = get_runtime_manager();
+        let pending_restarts = PendingRestarts::default();
+        let (run_send, mut run_recv) = mpsc::channel(1);
+        let (cleanup_send, cleanup_recv) = oneshot::channel();
+
+        let handle_cleanup = {
+            let built = built.clone();
+
+            move |response: Option<SubscribeStopResponse>| {
+                let response = response.unwrap();
+                let reason = StopReason::from_i32(response.reason).unwrap();
+
+                pending_restarts.apply(&built, reason, Duration::ZERO, run_send);
+                cleanup_send.send((reason, response.message)).unwrap();
+            }
+        };
+
+        built
+            .clone()
+            .handle(
+                storage_manager,
+                StubSecretGetter,
+                StubResourceManager,
+                runtime_manager.clone(),
+                StubDeploymentUpdater,
+                kill_old_deployments(),
+                handle_cleanup,
+            )
+            .await
+            .unwrap();
+
+        let (reason, message) = tokio::select! {
+            _ = sleep(Duration::from_secs(10)) => panic!("cleanup should have been called as service is unhealthy"),
+            Ok(stopped) = cleanup_recv => stopped,
+        };
+        assert_eq!(reason, StopReason::Crash);
+        assert_eq!(
+            message,
+            "health check answered with 503 Service Unavailable"
+        );
+
+        let restarted = tokio::time::timeout(Duration::from_secs(5), run_recv.recv())
+            .await
+            .expect("unhealthy service to be restarted")
+            .unwrap();
+        assert_eq!(restarted.id, built.id);
+        assert_eq!(restarted.restart_count, 1);
+
+        // Prevent the runtime manager from dropping earlier, which will kill the processes it manages
+        drop(runtime_manager);
+    }
+
     // Test for panics in Service::bind
     #[tokio::test]
     async fn panic_in_bind() {
@@ -720,6 +1238,12 @@ mod tests {
                 tracing_context: Default::default(),
                 is_next: false,
                 claim: None,
+                health_check: None,
+                readiness: Default::default(),
+                restart_policy: Default::default(),
+                next: Default::default(),
+                secrets_schema: Default::default(),
+                restart_count: 0,
             },
             storage_manager,
         )
@@ -26,6 +26,18 @@ pub enum Error {
     SecretsSet(#[source] Box<dyn StdError + Send>),
     #[error("Failed to get secrets: {0}")]
     SecretsGet(#[source] Box<dyn StdError + Send>),
+    #[error("Secrets do not match the ones declared in Shuttle.toml: {0}")]
+    SecretsValidation(#[from] shuttle_common::secrets::ValidationError),
+    #[error("Failed to parse the health check in Shuttle.toml: {0}")]
+    HealthCheckParse(#[source] toml::de::Error),
+    #[error("Failed to parse the readiness in Shuttle.toml: {0}")]
+    ReadinessParse(#[source] toml::de::Error),
+    #[error("Failed to parse the restart policy in Shuttle.toml: {0}")]
+    RestartPolicyParse(#[source] toml::de::Error),
+    #[error("Failed to parse the tcp table in Shuttle.toml: {0}")]
+    TcpParse(#[source] toml::de::Error),
+    #[error("Failed to parse the next table in Shuttle.toml: {0}")]
+    NextParse(#[source] toml::de::Error),
     #[error("Failed to cleanup old deployments: {0}")]
     OldCleanup(#[source] Box<dyn StdError + Send>),
     #[error("Gateway client error: {0}")]
@@ -23,10 +23,22 @@ pub enum Error {
     },
     #[error("{0}, try running `cargo shuttle deploy`")]
     NotFound(String),
+    #[error("{0}")]
+    BadRequest(String),
     #[error("Custom error: {0}")]
     Custom(#[from] anyhow::Error),
 }
 
+impl From<crate::log_drain::Error> for Error {
+    fn from(error: crate::log_drain::Error) -> Self {
+        match error {
+            crate::log_drain::Error::Persistence(error) => Self::Persistence(error),
+            error @ crate::log_drain::Error::InvalidDrain(_) => Self::BadRequest(error.to_string()),
+            error => Self::Custom(error.into()),
+        }
+    }
+}
+
 impl Serialize for Error {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
@@ -46,6 +58,7 @@ impl IntoResponse for Error {
 
         let code = match self {
             Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::BadRequest(_) => StatusCode::BAD_REQUEST,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
@@ -5,7 +5,7 @@ use axum::extract::{Extension, Path, Query};
 use axum::handler::Handler;
 use axum::headers::HeaderMapExt;
 use axum::middleware::{self, from_extractor};
-use axum::routing::{get, post, Router};
+use axum::routing::{delete, get, post, Router};
 use axum::{extract::BodyStream, Json};
 use bytes::BufMut;
 use chrono::{TimeZone, Utc};
@@ -18,6 +18,7 @@ use shuttle_common::backends::auth::{
 use shuttle_common::backends::headers::XShuttleAccountName;
 use shuttle_common::backends::metrics::{Metrics, TraceLayer};
 use shuttle_common::claims::{Claim, Scope};
+use shuttle_common::log;
 use shuttle_common::models::secret;
 use shuttle_common::project::ProjectName;
 use shuttle_common::storage_manager::StorageManager;
@@ -30,15 +31,25 @@ use utoipa_swagger_ui::SwaggerUi;
 use uuid::Uuid;
 
 use crate::deployment::{DeploymentManager, Queued};
-use crate::persistence::{Deployment, Log, Persistence, ResourceManager, SecretGetter, State};
+use crate::log_drain::LogDrainManager;
+use crate::persistence::{
+    Deployment, Log, LogFilter, LogPosition, Persistence, ResourceManager, SecretGetter,
+    SecretRecorder, State,
+};
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 pub use {self::error::Error, self::error::Result, self::local::set_jwt_bearer};
 
 mod local;
 mod project;
 
+/// Number of logs in a page when the request does not set a limit
+const LOGS_PAGE_LIMIT_DEFAULT: u32 = 1000;
+
+/// Upper bound on the number of logs in a page
+const LOGS_PAGE_LIMIT_MAX: u32 = 10_000;
+
 #[derive(OpenApi)]
 #[openapi(
     paths(
@@ -50,9 +61,18 @@ mod project;
         get_deployments,
         get_deployment,
         delete_deployment,
+        rollback_deployment,
         get_logs_subscribe,
         get_logs,
+        get_logs_page,
+        get_log_retention,
+        set_log_retention,
+        get_log_drain,
+        set_log_drain,
+        delete_log_drain,
         get_secrets,
+        set_secrets,
+        delete_secret,
         clean_project
     ),
     components(schemas(
@@ -66,6 +86,10 @@ mod project;
         shuttle_common::models::secret::Response,
         shuttle_common::models::deployment::Response,
         shuttle_common::log::Item,
+        shuttle_common::log::Page,
+        shuttle_common::log::Retention,
+        shuttle_common::log::Drain,
+        shuttle_common::log::DrainTarget,
         shuttle_common::models::secret::Response,
         shuttle_common::log::Level,
         shuttle_common::deployment::State
@@ -84,6 +108,7 @@ impl RouterBuilder {
     pub fn new(
         persistence: Persistence,
         deployment_manager: DeploymentManager,
+        log_drain_manager: LogDrainManager,
         proxy_fqdn: FQDN,
         project_name: ProjectName,
         auth_uri: Uri,
@@ -109,6 +134,17 @@ impl RouterBuilder {
                 "/projects/:project_name/services/:service_name/resources",
                 get(get_service_resources).layer(ScopedLayer::new(vec![Scope::Resources])),
             )
+            .route(
+                "/projects/:project_name/services/:service_name/logs/retention",
+                get(get_log_retention.layer(ScopedLayer::new(vec![Scope::Logs])))
+                    .post(set_log_retention.layer(ScopedLayer::new(vec![Scope::ServiceCreate]))),
+            )
+            .route(
+                "/projects/:project_name/logs/drain",
+                get(get_log_drain.layer(ScopedLayer::new(vec![Scope::Logs])))
+                    .post(set_log_drain.layer(ScopedLayer::new(vec![Scope::ServiceCreate])))
+                    .delete(delete_log_drain.layer(ScopedLayer::new(vec![Scope::ServiceCreate]))),
+            )
             .route(
                 "/projects/:project_name/deployments",
                 get(get_deployments).layer(ScopedLayer::new(vec![Scope::Service])),
@@ -118,6 +154,10 @@ impl RouterBuilder {
                 get(get_deployment.layer(ScopedLayer::new(vec![Scope::Deployment])))
                     .delete(delete_deployment.layer(ScopedLayer::new(vec![Scope::DeploymentPush]))),
             )
+            .route(
+                "/projects/:project_name/deployments/:deployment_id/rollback",
+                post(rollback_deployment.layer(ScopedLayer::new(vec![Scope::DeploymentPush]))),
+            )
             .route(
                 "/projects/:project_name/ws/deployments/:deployment_id/logs",
                 get(get_logs_subscribe.layer(ScopedLayer::new(vec![Scope::Logs]))),
@@ -126,9 +166,18 @@ impl RouterBuilder {
                 "/projects/:project_name/deployments/:deployment_id/logs",
                 get(get_logs.layer(ScopedLayer::new(vec![Scope::Logs]))),
             )
+            .route(
+                "/projects/:project_name/deployments/:deployment_id/logs/page",
+                get(get_logs_page.layer(ScopedLayer::new(vec![Scope::Logs]))),
+            )
             .route(
                 "/projects/:project_name/secrets/:service_name",
-                get(get_secrets.layer(ScopedLayer::new(vec![Scope::Secret]))),
+                get(get_secrets.layer(ScopedLayer::new(vec![Scope::Secret])))
+                    .post(set_secrets.layer(ScopedLayer::new(vec![Scope::SecretWrite]))),
+            )
+            .route(
+                "/projects/:project_name/secrets/:service_name/:key",
+                delete(delete_secret.layer(ScopedLayer::new(vec![Scope::SecretWrite]))),
             )
             .route(
                 "/projects/:project_name/clean",
@@ -136,6 +185,7 @@ impl RouterBuilder {
             )
             .layer(Extension(persistence))
             .layer(Extension(deployment_manager))
+            .layer(Extension(log_drain_manager))
             .layer(Extension(proxy_fqdn))
             .layer(JwtAuthenticationLayer::new(AuthPublicKey::new(
                 auth_uri.clone(),
@@ -244,7 +294,7 @@ pub async fn get_service(
             .map(Into::into);
 
         let response = shuttle_common::models::service::Summary {
-            uri: format!("https://{proxy_fqdn}"),
+            uri: service_uri(&proxy_fqdn, &project_name, &service.name),
             name: service.name,
             deployment,
         };
@@ -376,9 +426,9 @@ pub async fn stop_service(
         }
 
         let response = shuttle_common::models::service::Summary {
+            uri: service_uri(&proxy_fqdn, &project_name, &service.name),
             name: service.name,
             deployment: running_deployment.map(Into::into),
-            uri: format!("https://{proxy_fqdn}"),
         };
 
         Ok(Json(response))
@@ -438,7 +488,13 @@ pub async fn get_deployment(
     Path((project_name, deployment_id)): Path<(String, Uuid)>,
 ) -> Result<Json<shuttle_common::models::deployment::Response>> {
     if let Some(deployment) = persistence.get_deployment(&deployment_id).await? {
-        Ok(Json(deployment.into()))
+        let (last_run, next_run) = persistence.get_cron_runs(&deployment_id).await?;
+
+        Ok(Json(shuttle_common::models::deployment::Response {
+            last_run,
+            next_run,
+            ..deployment.into()
+        }))
     } else {
         Err(Error::NotFound("deployment not found".to_string()))
     }
@@ -472,6 +528,54 @@ pub async fn delete_deployment(
     }
 }
 
+#[instrument(skip_all, fields(%project_name, %deployment_id))]
+#[utoipa::path(
+    post,
+    path = "/projects/{project_name}/deployments/{deployment_id}/rollback",
+    responses(
+        (status = 200, description = "Restarts a previous deployment from its stored executable.", body = shuttle_common::models::deployment::Response),
+        (status = 500, description = "Database or streaming error.", body = String),
+        (status = 404, description = "Record could not be found.", body = String),
+    ),
+    params(
+        ("project_name" = String, Path, description = "Name of the project that owns the deployment."),
+        ("deployment_id" = String, Path, description = "The deployment id in uuid format.")
+    )
+)]
+pub async fn rollback_deployment(
+    Extension(deployment_manager): Extension<DeploymentManager>,
+    Extension(persistence): Extension<Persistence>,
+    Path((project_name, deployment_id)): Path<(String, Uuid)>,
+) -> Result<Json<shuttle_common::models::deployment::Response>> {
+    let Some(deployment) = persistence.get_deployment(&deployment_id).await? else {
+        return Err(Error::NotFound("deployment not found".to_string()));
+    };
+
+    // Nothing to do if this deployment is already the one serving requests
+    if deployment.state == State::Running {
+        return Ok(Json(deployment.into()));
+    }
+
+    let executable_path = deployment_manager
+        .storage_manager()
+        .deployment_executable_path(&deployment.id)
+        .map_err(anyhow::Error::new)?;
+
+    if !executable_path.exists() {
+        return Err(Error::NotFound(
+            "no stored executable found for deployment".to_string(),
+        ));
+    }
+
+    let Some(runnable) = persistence.get_runnable_deployment(&deployment.id).await? else {
+        return Err(Error::NotFound("service not found".to_string()));
+    };
+
+    deployment_manager.run_push(runnable.into()).await;
+
+    Ok(Json(deployment.into()))
+}
+
 #[instrument(skip_all, fields(%project_name, %deployment_id))]
 #[utoipa::path(
     get,
@@ -480,20 +584,30 @@ pub async fn delete_deployment(
         (status = 200, description = "Gets the logs a specific deployment.", body = [shuttle_common::log::Item]),
         (status = 500, description = "Database or streaming error.", body = String),
         (status = 404, description = "Record could not be found.", body = String),
+        (status = 400, description = "Invalid search pattern.", body = String),
     ),
     params(
         ("project_name" = String, Path, description = "Name of the project that owns the deployment."),
-        ("deployment_id" = String, Path, description = "The deployment id in uuid format.")
+        ("deployment_id" = String, Path, description = "The deployment id in uuid format."),
+        ("level" = Option<String>, Query, description = "Only logs at this level or a more severe one."),
+        ("target" = Option<String>, Query, description = "Only logs with a target starting with this."),
+        ("since" = Option<String>, Query, description = "Only logs from this RFC 3339 timestamp onwards."),
+        ("until" = Option<String>, Query, description = "Only logs up to this RFC 3339 timestamp."),
+        ("search" = Option<String>, Query, description = "Only logs with a message containing this."),
+        ("regex" = Option<bool>, Query, description = "Match `search` as a regular expression."),
     )
 )]
 pub async fn get_logs(
     Extension(persistence): Extension<Persistence>,
     Path((project_name, deployment_id)): Path<(String, Uuid)>,
+    Query(query): Query<log::Query>,
 ) -> Result<Json<Vec<LogItem>>> {
+    let filter = log_filter(query)?;
+
     if let Some(deployment) = persistence.get_deployment(&deployment_id).await? {
         Ok(Json(
             persistence
-                .get_deployment_logs(&deployment.id)
+                .get_deployment_logs(&deployment.id, &filter)
                 .await?
                 .into_iter()
                 .filter_map(Into::into)
@@ -504,28 +618,246 @@ pub async fn get_logs(
     }
 }
 
+#[instrument(skip_all, fields(%project_name, %deployment_id))]
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/deployments/{deployment_id}/logs/page",
+    responses(
+        (status = 200, description = "Gets a page of the logs of a specific deployment.", body = shuttle_common::log::Page),
+        (status = 500, description = "Database or streaming error.", body = String),
+        (status = 404, description = "Record could not be found.", body = String),
+        (status = 400, description = "Invalid search pattern or cursor.", body = String),
+    ),
+    params(
+        ("project_name" = String, Path, description = "Name of the project that owns the deployment."),
+        ("deployment_id" = String, Path, description = "The deployment id in uuid format."),
+        ("level" = Option<String>, Query, description = "Only logs at this level or a more severe one."),
+        ("target" = Option<String>, Query, description = "Only logs with a target starting with this."),
+        ("since" = Option<String>, Query, description = "Only logs from this RFC 3339 timestamp onwards."),
+        ("until" = Option<String>, Query, description = "Only logs up to this RFC 3339 timestamp."),
+        ("search" = Option<String>, Query, description = "Only logs with a message containing this."),
+        ("regex" = Option<bool>, Query, description = "Match `search` as a regular expression."),
+        ("cursor" = Option<String>, Query, description = "Continue from the `next` cursor of the previous page."),
+        ("limit" = Option<u32>, Query, description = "Maximum number of logs in the page."),
+    )
+)]
+pub async fn get_logs_page(
+    Extension(persistence): Extension<Persistence>,
+    Path((project_name, deployment_id)): Path<(String, Uuid)>,
+    Query(query): Query<log::Query>,
+    Query(page): Query<log::PageQuery>,
+) -> Result<Json<log::Page>> {
+    let filter = log_filter(query)?;
+
+    let after = match page.cursor {
+        Some(cursor) if cursor.id != deployment_id => {
+            return Err(Error::BadRequest(
+                "the cursor is for a different deployment".to_string(),
+            ))
+        }
+        Some(cursor) => Some(LogPosition {
+            timestamp: cursor.timestamp,
+            rowid: cursor.position,
+        }),
+        None => None,
+    };
+    let limit = page
+        .limit
+        .unwrap_or(LOGS_PAGE_LIMIT_DEFAULT)
+        .clamp(1, LOGS_PAGE_LIMIT_MAX);
+
+    if let Some(deployment) = persistence.get_deployment(&deployment_id).await? {
+        let (logs, next) = persistence
+            .get_deployment_logs_page(&deployment.id, &filter, after, limit)
+            .await?;
+
+        Ok(Json(log::Page {
+            logs: logs.into_iter().filter_map(Into::into).collect(),
+            next: next.map(|position| log::Cursor {
+                id: deployment.id,
+                timestamp: position.timestamp,
+                position: position.rowid,
+            }),
+        }))
+    } else {
+        Err(Error::NotFound("deployment not found".to_string()))
+    }
+}
+
 #[utoipa::path(
     get,
     path = "/projects/{project_name}/deployments/{deployment_id}/logs",
     responses(
-        (status = 200, description = "Subscribes to a specific deployment logs.")
+        (status = 200, description = "Subscribes to a specific deployment logs."),
+        (status = 400, description = "Invalid search pattern.", body = String),
     ),
     params(
         ("project_name" = String, Path, description = "Name of the project that owns the deployment."),
-        ("deployment_id" = String, Path, description = "The deployment id in uuid format.")
+        ("deployment_id" = String, Path, description = "The deployment id in uuid format."),
+        ("level" = Option<String>, Query, description = "Only logs at this level or a more severe one."),
+        ("target" = Option<String>, Query, description = "Only logs with a target starting with this."),
+        ("since" = Option<String>, Query, description = "Only logs from this RFC 3339 timestamp onwards."),
+        ("until" = Option<String>, Query, description = "Only logs up to this RFC 3339 timestamp."),
+        ("search" = Option<String>, Query, description = "Only logs with a message containing this."),
+        ("regex" = Option<bool>, Query, description = "Match `search` as a regular expression."),
     )
 )]
 pub async fn get_logs_subscribe(
     Extension(persistence): Extension<Persistence>,
     Path((_project_name, deployment_id)): Path<(String, Uuid)>,
+    Query(query): Query<log::Query>,
     ws_upgrade: ws::WebSocketUpgrade,
-) -> axum::response::Response {
-    ws_upgrade.on_upgrade(move |s| logs_websocket_handler(s, persistence, deployment_id))
+) -> Result<axum::response::Response> {
+    let filter = log_filter(query)?;
+
+    Ok(ws_upgrade
+        .on_upgrade(move |s| logs_websocket_handler(s, persistence, deployment_id, filter)))
 }
 
-async fn logs_websocket_handler(mut s: WebSocket, persistence: Persistence, id: Uuid) {
+#[instrument(skip_all, fields(%project_name, %service_name))]
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/services/{service_name}/logs/retention",
+    responses(
+        (status = 200, description = "Gets the log retention of a specific service. Unset values use the deployer defaults.", body = shuttle_common::log::Retention),
+        (status = 500, description = "Database error.", body = String),
+        (status = 404, description = "Record could not be found.", body = String),
+    ),
+    params(
+        ("project_name" = String, Path, description = "Name of the project that owns the service."),
+        ("service_name" = String, Path, description = "Name of the service.")
+    )
+)]
+pub async fn get_log_retention(
+    Extension(persistence): Extension<Persistence>,
+    Path((project_name, service_name)): Path<(String, String)>,
+) -> Result<Json<log::Retention>> {
+    if let Some(service) = persistence.get_service_by_name(&service_name).await? {
+        Ok(Json(persistence.get_log_retention(&service.id).await?))
+    } else {
+        Err(Error::NotFound("service not found".to_string()))
+    }
+}
+
+#[instrument(skip_all, fields(%project_name, %service_name))]
+#[utoipa::path(
+    post,
+    path = "/projects/{project_name}/services/{service_name}/logs/retention",
+    request_body = shuttle_common::log::Retention,
+    responses(
+        (status = 200, description = "Sets the log retention of a specific service.", body = shuttle_common::log::Retention),
+        (status = 500, description = "Database error.", body = String),
+        (status = 404, description = "Record could not be found.", body = String),
+    ),
+    params(
+        ("project_name" = String, Path, description = "Name of the project that owns the service."),
+        ("service_name" = String, Path, description = "Name of the service.")
+    )
+)]
+pub async fn set_log_retention(
+    Extension(persistence): Extension<Persistence>,
+    Path((project_name, service_name)): Path<(String, String)>,
+    Json(retention): Json<log::Retention>,
+) -> Result<Json<log::Retention>> {
+    if let Some(service) = persistence.get_service_by_name(&service_name).await? {
+        persistence
+            .set_log_retention(&service.id, &retention)
+            .await?;
+
+        Ok(Json(retention))
+    } else {
+        Err(Error::NotFound("service not found".to_string()))
+    }
+}
+
+#[instrument(skip_all, fields(%project_name))]
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/logs/drain",
+    responses(
+        (status = 200, description = "Gets the drain the logs of the project are forwarded to, if any.", body = shuttle_common::log::Drain),
+        (status = 500, description = "Database error.", body = String),
+    ),
+    params(
+        ("project_name" = String, Path, description = "Name of the project.")
+    )
+)]
+pub async fn get_log_drain(
+    Extension(log_drain_manager): Extension<LogDrainManager>,
+    Path(project_name): Path<String>,
+) -> Result<Json<Option<log::Drain>>> {
+    Ok(Json(log_drain_manager.get().await?))
+}
+
+#[instrument(skip_all, fields(%project_name))]
+#[utoipa::path(
+    post,
+    path = "/projects/{project_name}/logs/drain",
+    request_body = shuttle_common::log::Drain,
+    responses(
+        (status = 200, description = "Forwards the runtime logs, and optionally the build logs, of the project to a drain, replacing any previous drain.", body = shuttle_common::log::Drain),
+        (status = 500, description = "Database error.", body = String),
+        (status = 400, description = "Invalid drain endpoint or address.", body = String),
+    ),
+    params(
+        ("project_name" = String, Path, description = "Name of the project.")
+    )
+)]
+pub async fn set_log_drain(
+    Extension(log_drain_manager): Extension<LogDrainManager>,
+    Path(project_name): Path<String>,
+    Json(drain): Json<log::Drain>,
+) -> Result<Json<log::Drain>> {
+    log_drain_manager.set(Some(&drain)).await?;
+
+    Ok(Json(drain))
+}
+
+#[instrument(skip_all, fields(%project_name))]
+#[utoipa::path(
+    delete,
+    path = "/projects/{project_name}/logs/drain",
+    responses(
+        (status = 200, description = "Stops forwarding the runtime logs of the project."),
+        (status = 500, description = "Database error.", body = String),
+    ),
+    params(
+        ("project_name" = String, Path, description = "Name of the project.")
+    )
+)]
+pub async fn delete_log_drain(
+    Extension(log_drain_manager): Extension<LogDrainManager>,
+    Path(project_name): Path<String>,
+) -> Result<()> {
+    log_drain_manager.set(None).await?;
+
+    Ok(())
+}
+
+/// The service named like the project is served at the root, and every other service of the
+/// project under its own path
+fn service_uri(proxy_fqdn: &FQDN, project_name: &str, service_name: &str) -> String {
+    if project_name == service_name {
+        format!("https://{proxy_fqdn}")
+    } else {
+        format!("https://{proxy_fqdn}/_services/{service_name}")
+    }
+}
+
+fn log_filter(query: log::Query) -> Result<LogFilter> {
+    query.try_into().map_err(|error: regex::Error| {
+        Error::BadRequest(format!("invalid search pattern: {error}"))
+    })
+}
+
+async fn logs_websocket_handler(
+    mut s: WebSocket,
+    persistence: Persistence,
+    id: Uuid,
+    filter: LogFilter,
+) {
     let mut log_recv = persistence.get_log_subscriber();
-    let backlog = match persistence.get_deployment_logs(&id).await {
+    let backlog = match persistence.get_deployment_logs(&id, &filter).await {
         Ok(backlog) => backlog,
         Err(error) => {
             error!(
@@ -561,7 +893,13 @@ async fn logs_websocket_handler(mut s: WebSocket, persistence: Persistence, id:
         trace!(?log, "received log from broadcast channel");
 
         if log.id == id && log.timestamp > last_timestamp {
-            if let Some(log_item) = Option::<LogItem>::from(Log::from(log)) {
+            let log = Log::from(log);
+
+            if !filter.matches(&log) {
+                continue;
+            }
+
+            if let Some(log_item) = Option::<LogItem>::from(log) {
                 let msg = serde_json::to_string(&log_item).expect("to convert log item to json");
                 let sent = s.send(ws::Message::Text(msg)).await;
 
@@ -608,6 +946,121 @@ pub async fn get_secrets(
     }
 }
 
+#[instrument(skip_all, fields(%project_name, %service_name))]
+#[utoipa::path(
+    post,
+    path = "/projects/{project_name}/secrets/{service_name}",
+    request_body = BTreeMap<String, String>,
+    responses(
+        (status = 200, description = "Adds or updates secrets of a specific service.", body = [shuttle_common::models::secret::Response]),
+        (status = 500, description = "Database or streaming error.", body = String),
+        (status = 404, description = "Record could not be found.", body = String),
+    ),
+    params(
+        ("project_name" = String, Path, description = "Name of the project that owns the service."),
+        ("service_name" = String, Path, description = "Name of the service."),
+        ("restart" = Option<String>, Query, description = "Restart the active deployment so it picks up the new secrets.")
+    )
+)]
+pub async fn set_secrets(
+    Extension(persistence): Extension<Persistence>,
+    Extension(deployment_manager): Extension<DeploymentManager>,
+    Path((project_name, service_name)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    Json(secrets): Json<BTreeMap<String, String>>,
+) -> Result<Json<Vec<secret::Response>>> {
+    if let Some(service) = persistence.get_service_by_name(&service_name).await? {
+        for (key, value) in secrets.iter() {
+            debug!(key, "setting secret");
+
+            persistence.insert_secret(&service.id, key, value).await?;
+        }
+
+        if params.contains_key("restart") {
+            restart_active_deployment(&persistence, &deployment_manager, &service.id).await?;
+        }
+
+        let keys = persistence
+            .get_secrets(&service.id)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        Ok(Json(keys))
+    } else {
+        Err(Error::NotFound("service not found".to_string()))
+    }
+}
+
+#[instrument(skip_all, fields(%project_name, %service_name, %key))]
+#[utoipa::path(
+    delete,
+    path = "/projects/{project_name}/secrets/{service_name}/{key}",
+    responses(
+        (status = 200, description = "Removes a secret from a specific service.", body = [shuttle_common::models::secret::Response]),
+        (status = 500, description = "Database or streaming error.", body = String),
+        (status = 404, description = "Record could not be found.", body = String),
+    ),
+    params(
+        ("project_name" = String, Path, description = "Name of the project that owns the service."),
+        ("service_name" = String, Path, description = "Name of the service."),
+        ("key" = String, Path, description = "Key of the secret to remove."),
+        ("restart" = Option<String>, Query, description = "Restart the active deployment so it picks up the new secrets.")
+    )
+)]
+pub async fn delete_secret(
+    Extension(persistence): Extension<Persistence>,
+    Extension(deployment_manager): Extension<DeploymentManager>,
+    Path((project_name, service_name, key)): Path<(String, String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<secret::Response>>> {
+    if let Some(service) = persistence.get_service_by_name(&service_name).await? {
+        let secrets = persistence.get_secrets(&service.id).await?;
+
+        if !secrets.iter().any(|secret| secret.key == key) {
+            return Err(Error::NotFound("secret not found".to_string()));
+        }
+
+        persistence.remove_secret(&service.id, &key).await?;
+
+        if params.contains_key("restart") {
+            restart_active_deployment(&persistence, &deployment_manager, &service.id).await?;
+        }
+
+        let keys = persistence
+            .get_secrets(&service.id)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        Ok(Json(keys))
+    } else {
+        Err(Error::NotFound("service not found".to_string()))
+    }
+}
+
+/// Stop the running deployment of a service and start it again from its stored executable so that
+/// it is loaded with the latest secrets
+async fn restart_active_deployment(
+    persistence: &Persistence,
+    deployment_manager: &DeploymentManager,
+    service_id: &Uuid,
+) -> Result<()> {
+    let Some(deployment) = persistence.get_active_deployment(service_id).await? else {
+        debug!("no running deployment to restart");
+        return Ok(());
+    };
+
+    if let Some(runnable) = persistence.get_runnable_deployment(&deployment.id).await? {
+        deployment_manager.kill(deployment.id).await;
+        deployment_manager.run_push(runnable.into()).await;
+    }
+
+    Ok(())
+}
+
 #[utoipa::path(
     post,
     path = "/projects/{project_name}/clean",
@@ -1,4 +1,4 @@
-use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+use std::{convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
 
 pub use args::Args;
 pub use deployment::deploy_layer::DeployLayer;
@@ -8,11 +8,12 @@ use hyper::{
     server::conn::AddrStream,
     service::{make_service_fn, service_fn},
 };
+use log_drain::LogDrainManager;
 pub use persistence::Persistence;
-use proxy::AddressGetter;
+pub use proxy::AddressGetter;
 pub use runtime_manager::RuntimeManager;
-use tokio::sync::Mutex;
-use tracing::{error, info};
+use tokio::{net::TcpListener, sync::Mutex};
+use tracing::{debug, error, info};
 
 use crate::deployment::gateway_client::GatewayClient;
 
@@ -20,15 +21,21 @@ mod args;
 mod deployment;
 mod error;
 pub mod handlers;
+mod log_drain;
 mod persistence;
 mod proxy;
 mod runtime_manager;
 
+/// How often the log retention of every service is enforced
+const LOG_RETENTION_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
 pub async fn start(
     persistence: Persistence,
     runtime_manager: Arc<Mutex<RuntimeManager>>,
     args: Args,
 ) {
+    let log_retention = args.log_retention();
+
     let deployment_manager = DeploymentManager::builder()
         .build_log_recorder(persistence.clone())
         .secret_recorder(persistence.clone())
@@ -46,20 +53,43 @@ pub async fn start(
     let runnable_deployments = persistence.get_all_runnable_deployments().await.unwrap();
     info!(count = %runnable_deployments.len(), "enqueuing runnable deployments");
     for existing_deployment in runnable_deployments {
-        let built = Built {
-            id: existing_deployment.id,
-            service_name: existing_deployment.service_name,
-            service_id: existing_deployment.service_id,
-            tracing_context: Default::default(),
-            is_next: existing_deployment.is_next,
-            claim: None, // This will cause us to read the resource info from past provisions
-        };
-        deployment_manager.run_push(built).await;
+        deployment_manager
+            .run_push(Built::from(existing_deployment))
+            .await;
+    }
+
+    let retention_persistence = persistence.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(LOG_RETENTION_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match retention_persistence
+                .enforce_log_retention(&log_retention)
+                .await
+            {
+                Ok(deleted) => debug!(deleted, "enforced log retention"),
+                Err(error) => error!(
+                    error = &error as &dyn std::error::Error,
+                    "failed to enforce log retention"
+                ),
+            }
+        }
+    });
+
+    let log_drain_manager = LogDrainManager::new(persistence.clone(), args.project.to_string());
+    if let Err(error) = log_drain_manager.start().await {
+        error!(
+            error = &error as &dyn std::error::Error,
+            "failed to start forwarding logs to the log drain"
+        );
     }
 
     let mut builder = handlers::RouterBuilder::new(
         persistence,
         deployment_manager,
+        log_drain_manager,
         args.proxy_fqdn,
         args.project,
         args.auth_uri,
@@ -86,6 +116,7 @@ pub async fn start_proxy(
     proxy_address: SocketAddr,
     fqdn: FQDN,
     address_getter: impl AddressGetter,
+    upgrade_idle_timeout: Duration,
 ) {
     let make_service = make_service_fn(move |socket: &AddrStream| {
         let remote_address = socket.remote_addr();
@@ -94,7 +125,13 @@ pub async fn start_proxy(
 
         async move {
             Ok::<_, Infallible>(service_fn(move |req| {
-                proxy::handle(remote_address, fqdn.clone(), req, address_getter.clone())
+                proxy::handle(
+                    remote_address,
+                    fqdn.clone(),
+                    req,
+                    address_getter.clone(),
+                    upgrade_idle_timeout,
+                )
             }))
         }
     });
@@ -108,3 +145,30 @@ pub async fn start_proxy(
         std::process::exit(1);
     }
 }
+
+/// Accept the raw TCP connections the gateway forwards to this project, and pass them on to the
+/// deployment which accepts them
+pub async fn start_tcp_proxy(tcp_proxy_address: SocketAddr, address_getter: impl AddressGetter) {
+    let listener = match TcpListener::bind(tcp_proxy_address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(error = %e, "tcp proxy failed to bind, killing process...");
+            std::process::exit(1);
+        }
+    };
+
+    info!("Starting tcp proxy server on: {}", tcp_proxy_address);
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, remote_address)) => {
+                tokio::spawn(proxy::handle_tcp(
+                    remote_address,
+                    stream,
+                    address_getter.clone(),
+                ));
+            }
+            Err(e) => error!(error = %e, "tcp proxy failed to accept connection"),
+        }
+    }
+}
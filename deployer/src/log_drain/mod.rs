@@ -0,0 +1,339 @@
+//! Forwarding of the logs of a project to an external collector.
+//!
+//! Every runtime log which goes through [Persistence] is also sent to the drain of the project, if
+//! it has one, and so are build logs when the drain asks for them. Logs are sent in batches, and
+//! they are held on to in a bounded buffer while the collector cannot be reached so that they can
+//! be retried later.
+
+mod otlp;
+mod syslog;
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use shuttle_common::log::{Drain, DrainTarget};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::deployment::deploy_layer;
+use crate::persistence::{Log, Persistence, PersistenceError, State};
+
+/// Most logs sent to the collector in one request
+const BATCH_SIZE: usize = 512;
+
+/// How long logs wait for a batch to fill up before they are sent anyway
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Most logs held on to while the collector cannot be reached. The oldest logs are dropped first.
+const BUFFER_CAPACITY: usize = 10_000;
+
+/// Bounds for the wait between retries, which doubles after every failed attempt
+const RETRY_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const RETRY_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Persistence failure: {0}")]
+    Persistence(#[from] PersistenceError),
+    #[error("Invalid log drain: {0}")]
+    InvalidDrain(String),
+    #[error("Failed to export logs: {0}")]
+    Export(String),
+}
+
+/// Sends batches of logs to a collector
+#[async_trait]
+trait Exporter: Send {
+    async fn export(&mut self, logs: &[Log]) -> Result<(), Error>;
+}
+
+fn exporter(drain: &Drain, project_name: &str) -> Result<Box<dyn Exporter>, Error> {
+    match &drain.target {
+        DrainTarget::Otlp { endpoint } => Ok(Box::new(otlp::Otlp::new(endpoint, project_name)?)),
+        DrainTarget::Syslog { address, tls } => {
+            Ok(Box::new(syslog::Syslog::new(address, *tls, project_name)?))
+        }
+    }
+}
+
+/// Keeps the task forwarding the logs in line with the drain configured for the project
+#[derive(Clone)]
+pub struct LogDrainManager {
+    persistence: Persistence,
+    project_name: String,
+    task: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl LogDrainManager {
+    pub fn new(persistence: Persistence, project_name: String) -> Self {
+        Self {
+            persistence,
+            project_name,
+            task: Default::default(),
+        }
+    }
+
+    /// Start forwarding to the drain stored in persistence, if there is one
+    pub async fn start(&self) -> Result<(), Error> {
+        let drain = self.persistence.get_log_drain().await?;
+        let exporter = drain
+            .as_ref()
+            .map(|drain| Ok((exporter(drain, &self.project_name)?, drain.build_logs)))
+            .transpose()?;
+
+        self.replace(exporter).await;
+
+        Ok(())
+    }
+
+    pub async fn get(&self) -> Result<Option<Drain>, Error> {
+        Ok(self.persistence.get_log_drain().await?)
+    }
+
+    /// Store a new drain and forward every log to it from now on, or stop forwarding when `drain`
+    /// is `None`
+    pub async fn set(&self, drain: Option<&Drain>) -> Result<(), Error> {
+        // Make sure the drain is valid before storing it
+        let exporter = drain
+            .map(|drain| Ok((exporter(drain, &self.project_name)?, drain.build_logs)))
+            .transpose()?;
+
+        self.persistence.set_log_drain(drain).await?;
+        self.replace(exporter).await;
+
+        Ok(())
+    }
+
+    /// Replace the forwarding task with one for `exporter`, which also gets build logs when its flag
+    /// is set
+    async fn replace(&self, exporter: Option<(Box<dyn Exporter>, bool)>) {
+        let mut task = self.task.lock().await;
+
+        if let Some(task) = task.take() {
+            task.abort();
+        }
+
+        *task = exporter.map(|(exporter, build_logs)| {
+            tokio::spawn(forward(
+                exporter,
+                self.persistence.get_log_subscriber(),
+                build_logs,
+            ))
+        });
+    }
+}
+
+/// Whether a log was written while its deployment was being built rather than while it ran
+fn is_build_log(log: &deploy_layer::Log) -> bool {
+    matches!(log.state, State::Queued | State::Building | State::Built)
+}
+
+async fn forward(
+    mut exporter: Box<dyn Exporter>,
+    mut logs: Receiver<deploy_layer::Log>,
+    build_logs: bool,
+) {
+    let mut buffer = Buffer::new(BUFFER_CAPACITY);
+    let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+    let mut backoff = RETRY_BACKOFF_MIN;
+    let mut retry_at = Instant::now();
+
+    loop {
+        let due = tokio::select! {
+            log = logs.recv() => match log {
+                Ok(log) if !build_logs && is_build_log(&log) => false,
+                Ok(log) => {
+                    buffer.push(log.into());
+
+                    buffer.len() >= BATCH_SIZE
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "log drain fell behind, some logs will not be forwarded");
+
+                    false
+                }
+                Err(RecvError::Closed) => return,
+            },
+            _ = flush.tick() => !buffer.logs.is_empty(),
+        };
+
+        if !due || Instant::now() < retry_at {
+            continue;
+        }
+
+        match buffer.flush(exporter.as_mut()).await {
+            Ok(()) => backoff = RETRY_BACKOFF_MIN,
+            Err(error) => {
+                warn!(
+                    error = &error as &dyn std::error::Error,
+                    buffered = buffer.len(),
+                    retry_in = ?backoff,
+                    "failed to forward logs to the log drain"
+                );
+
+                retry_at = Instant::now() + backoff;
+                backoff = (backoff * 2).min(RETRY_BACKOFF_MAX);
+            }
+        }
+    }
+}
+
+/// Logs waiting to be exported
+struct Buffer {
+    logs: VecDeque<Log>,
+    capacity: usize,
+    dropped: usize,
+}
+
+impl Buffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            logs: VecDeque::new(),
+            capacity,
+            dropped: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.logs.len()
+    }
+
+    /// Add a log, making room for it by dropping the oldest log when the buffer is full
+    fn push(&mut self, log: Log) {
+        if self.logs.len() >= self.capacity {
+            self.logs.pop_front();
+            self.dropped += 1;
+        }
+
+        self.logs.push_back(log);
+    }
+
+    /// Export all the logs in batches. Logs are only removed once their batch has been exported.
+    async fn flush(&mut self, exporter: &mut dyn Exporter) -> Result<(), Error> {
+        if self.dropped > 0 {
+            warn!(
+                dropped = self.dropped,
+                "log drain buffer was full, the oldest logs were dropped"
+            );
+            self.dropped = 0;
+        }
+
+        while !self.logs.is_empty() {
+            let len = self.logs.len().min(BATCH_SIZE);
+
+            exporter.export(&self.logs.make_contiguous()[..len]).await?;
+            self.logs.drain(..len);
+
+            debug!(count = len, "forwarded logs to the log drain");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use chrono::Utc;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{is_build_log, Buffer, Error, Exporter, BATCH_SIZE};
+    use crate::deployment::deploy_layer;
+    use crate::persistence::{Log, LogLevel, State};
+
+    /// Fails the first `failures` exports and records the rest
+    struct StubExporter {
+        failures: usize,
+        batches: Vec<Vec<Log>>,
+    }
+
+    #[async_trait]
+    impl Exporter for StubExporter {
+        async fn export(&mut self, logs: &[Log]) -> Result<(), Error> {
+            if self.failures > 0 {
+                self.failures -= 1;
+
+                return Err(Error::Export("collector is down".to_string()));
+            }
+
+            self.batches.push(logs.to_vec());
+
+            Ok(())
+        }
+    }
+
+    fn log(message: usize) -> Log {
+        Log {
+            id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            state: State::Running,
+            level: LogLevel::Info,
+            file: None,
+            line: None,
+            target: String::new(),
+            fields: json!({ "message": message.to_string() }),
+        }
+    }
+
+    #[test]
+    fn build_logs() {
+        let log = |state| deploy_layer::Log {
+            id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            state,
+            level: LogLevel::Info,
+            file: None,
+            line: None,
+            target: String::new(),
+            fields: json!({ "message": "compiling" }),
+            r#type: deploy_layer::LogType::Event,
+        };
+
+        assert!(is_build_log(&log(State::Building)));
+        assert!(is_build_log(&log(State::Built)));
+        assert!(!is_build_log(&log(State::Loading)));
+        assert!(!is_build_log(&log(State::Running)));
+    }
+
+    #[test]
+    fn buffer_is_bounded() {
+        let mut buffer = Buffer::new(3);
+
+        for message in 0..5 {
+            buffer.push(log(message));
+        }
+
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.dropped, 2);
+        assert_eq!(buffer.logs[0].message(), "2");
+    }
+
+    #[tokio::test]
+    async fn buffer_is_kept_until_exported() {
+        let mut buffer = Buffer::new(BATCH_SIZE * 3);
+        let mut exporter = StubExporter {
+            failures: 1,
+            batches: Vec::new(),
+        };
+
+        for message in 0..BATCH_SIZE + 1 {
+            buffer.push(log(message));
+        }
+
+        assert!(buffer.flush(&mut exporter).await.is_err());
+        assert_eq!(buffer.len(), BATCH_SIZE + 1);
+
+        buffer.flush(&mut exporter).await.unwrap();
+        assert_eq!(buffer.len(), 0);
+
+        let sizes: Vec<_> = exporter.batches.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![BATCH_SIZE, 1]);
+        assert_eq!(exporter.batches[0][0].message(), "0");
+    }
+}
@@ -0,0 +1,146 @@
+use async_trait::async_trait;
+use opentelemetry_proto::tonic::collector::logs::v1::{
+    logs_service_client::LogsServiceClient, ExportLogsServiceRequest,
+};
+use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, InstrumentationScope, KeyValue};
+use opentelemetry_proto::tonic::logs::v1::{LogRecord, ResourceLogs, ScopeLogs, SeverityNumber};
+use opentelemetry_proto::tonic::resource::v1::Resource;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+
+use super::{Error, Exporter};
+use crate::persistence::{Log, LogLevel};
+
+/// Sends logs to an OpenTelemetry collector with the OTLP logs service over gRPC, with TLS for
+/// `https://` endpoints
+pub struct Otlp {
+    client: LogsServiceClient<Channel>,
+    resource: Resource,
+}
+
+impl Otlp {
+    pub fn new(endpoint: &str, project_name: &str) -> Result<Self, Error> {
+        let invalid = |error: &dyn std::fmt::Display| {
+            Error::InvalidDrain(format!("OTLP endpoint `{endpoint}` is invalid: {error}"))
+        };
+
+        let mut endpoint =
+            Endpoint::from_shared(endpoint.to_string()).map_err(|error| invalid(&error))?;
+
+        match endpoint.uri().scheme_str() {
+            Some("https") => {
+                // Verified against the root certificates of the system
+                endpoint = endpoint
+                    .tls_config(ClientTlsConfig::new())
+                    .map_err(|error| invalid(&error))?;
+            }
+            Some("http") => {}
+            _ => {
+                return Err(Error::InvalidDrain(format!(
+                    "OTLP endpoint `{}` should be an `https://` or `http://` URI",
+                    endpoint.uri()
+                )))
+            }
+        }
+
+        // Only connect once the first logs are exported so that a collector which is down does
+        // not stop the drain from being configured
+        let client = LogsServiceClient::new(endpoint.connect_lazy());
+
+        Ok(Self {
+            client,
+            resource: Resource {
+                attributes: vec![string_attribute("service.name", project_name)],
+                ..Default::default()
+            },
+        })
+    }
+}
+
+#[async_trait]
+impl Exporter for Otlp {
+    async fn export(&mut self, logs: &[Log]) -> Result<(), Error> {
+        let request = ExportLogsServiceRequest {
+            resource_logs: vec![ResourceLogs {
+                resource: Some(self.resource.clone()),
+                scope_logs: vec![ScopeLogs {
+                    scope: Some(InstrumentationScope {
+                        name: env!("CARGO_PKG_NAME").to_string(),
+                        ..Default::default()
+                    }),
+                    log_records: logs.iter().map(log_record).collect(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        };
+
+        self.client
+            .export(request)
+            .await
+            .map_err(|status| Error::Export(status.to_string()))?;
+
+        Ok(())
+    }
+}
+
+fn log_record(log: &Log) -> LogRecord {
+    let (severity_number, severity_text) = match log.level {
+        LogLevel::Trace => (SeverityNumber::Trace, "TRACE"),
+        LogLevel::Debug => (SeverityNumber::Debug, "DEBUG"),
+        LogLevel::Info => (SeverityNumber::Info, "INFO"),
+        LogLevel::Warn => (SeverityNumber::Warn, "WARN"),
+        LogLevel::Error => (SeverityNumber::Error, "ERROR"),
+    };
+
+    let mut attributes = vec![
+        string_attribute("deployment.id", &log.id.to_string()),
+        string_attribute("deployment.state", &log.state.to_string()),
+        string_attribute("code.namespace", &log.target),
+    ];
+
+    if let Some(file) = &log.file {
+        attributes.push(string_attribute("code.filepath", file));
+    }
+
+    if let Some(line) = log.line {
+        attributes.push(KeyValue {
+            key: "code.lineno".to_string(),
+            value: Some(AnyValue {
+                value: Some(any_value::Value::IntValue(line.into())),
+            }),
+        });
+    }
+
+    LogRecord {
+        time_unix_nano: log.timestamp.timestamp_nanos() as u64,
+        severity_number: severity_number as i32,
+        severity_text: severity_text.to_string(),
+        body: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(log.message())),
+        }),
+        attributes,
+        ..Default::default()
+    }
+}
+
+fn string_attribute(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.to_string())),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Otlp;
+
+    #[tokio::test]
+    async fn endpoint() {
+        assert!(Otlp::new("https://collector.example.com:4317", "my-project").is_ok());
+        assert!(Otlp::new("http://collector:4317", "my-project").is_ok());
+        assert!(Otlp::new("grpc://collector:4317", "my-project").is_err());
+        assert!(Otlp::new("not a uri", "my-project").is_err());
+    }
+}
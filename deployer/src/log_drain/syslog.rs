@@ -0,0 +1,188 @@
+use async_trait::async_trait;
+use chrono::SecondsFormat;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_native_tls::{native_tls, TlsConnector};
+
+use super::{Error, Exporter};
+use crate::persistence::{Log, LogLevel};
+
+/// The `user-level messages` facility
+const FACILITY_USER: u8 = 1;
+
+/// Sends logs as RFC 5424 syslog messages over TCP, framed with octet counting as described in
+/// RFC 6587. The connection is wrapped in TLS as described in RFC 5425 when a connector is set.
+pub struct Syslog {
+    address: String,
+    host: String,
+    hostname: String,
+    tls: Option<TlsConnector>,
+    stream: Option<Box<dyn AsyncWrite + Send + Unpin>>,
+}
+
+impl Syslog {
+    pub fn new(address: &str, tls: bool, project_name: &str) -> Result<Self, Error> {
+        let host = match address.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => host,
+            _ => {
+                return Err(Error::InvalidDrain(format!(
+                    "syslog address `{address}` should look like `host:port`"
+                )))
+            }
+        };
+
+        let tls = if tls {
+            let connector = native_tls::TlsConnector::new().map_err(|error| {
+                Error::InvalidDrain(format!("failed to set up TLS for syslog: {error}"))
+            })?;
+
+            Some(connector.into())
+        } else {
+            None
+        };
+
+        Ok(Self {
+            address: address.to_string(),
+            host: host.to_string(),
+            hostname: project_name.to_string(),
+            tls,
+            stream: None,
+        })
+    }
+}
+
+/// Connect to the collector, over TLS when there is a connector
+async fn connect(
+    address: &str,
+    host: &str,
+    tls: Option<&TlsConnector>,
+) -> Result<Box<dyn AsyncWrite + Send + Unpin>, Error> {
+    let stream = TcpStream::connect(address)
+        .await
+        .map_err(|error| Error::Export(error.to_string()))?;
+
+    match tls {
+        Some(connector) => {
+            let stream = connector
+                .connect(host, stream)
+                .await
+                .map_err(|error| Error::Export(error.to_string()))?;
+
+            Ok(Box::new(stream))
+        }
+        None => Ok(Box::new(stream)),
+    }
+}
+
+#[async_trait]
+impl Exporter for Syslog {
+    async fn export(&mut self, logs: &[Log]) -> Result<(), Error> {
+        let mut frames = Vec::new();
+
+        for log in logs {
+            let message = format_message(log, &self.hostname);
+
+            frames.extend(format!("{} {message}", message.len()).into_bytes());
+        }
+
+        if self.stream.is_none() {
+            self.stream = Some(connect(&self.address, &self.host, self.tls.as_ref()).await?);
+        }
+
+        let stream = self.stream.as_mut().expect("stream to be connected");
+
+        if let Err(error) = stream.write_all(&frames).await {
+            // Reconnect on the next attempt, the collector might have restarted
+            self.stream = None;
+
+            return Err(Error::Export(error.to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Format a log as `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG`, where the
+/// hostname is the project, the app name is the log target, the process id is the deployment and
+/// the message id is the state of the deployment
+fn format_message(log: &Log, hostname: &str) -> String {
+    let severity = match log.level {
+        LogLevel::Error => 3,
+        LogLevel::Warn => 4,
+        LogLevel::Info => 6,
+        LogLevel::Debug | LogLevel::Trace => 7,
+    };
+
+    format!(
+        "<{}>1 {} {} {} {} {} - {}",
+        FACILITY_USER * 8 + severity,
+        log.timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+        header_field(hostname, 255),
+        header_field(&log.target, 48),
+        log.id,
+        header_field(&log.state.to_string(), 32),
+        log.message(),
+    )
+}
+
+/// Header fields can only hold printable ASCII without spaces, and use `-` when they have no value
+fn header_field(value: &str, max_len: usize) -> String {
+    let value: String = value
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(max_len)
+        .collect();
+
+    if value.is_empty() {
+        "-".to_string()
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{format_message, Syslog};
+    use crate::persistence::{Log, LogLevel, State};
+
+    #[test]
+    fn message() {
+        let log = Log {
+            id: Uuid::nil(),
+            timestamp: Utc.with_ymd_and_hms(2023, 4, 17, 8, 30, 0).unwrap(),
+            state: State::Running,
+            level: LogLevel::Warn,
+            file: None,
+            line: None,
+            target: "my_app::handlers".to_string(),
+            fields: json!({ "message": "slow request" }),
+        };
+
+        assert_eq!(
+            format_message(&log, "my-project"),
+            "<12>1 2023-04-17T08:30:00.000000Z my-project my_app::handlers 00000000-0000-0000-0000-000000000000 Running - slow request"
+        );
+
+        let log = Log {
+            target: String::new(),
+            level: LogLevel::Debug,
+            ..log
+        };
+
+        assert!(format_message(&log, "my project").starts_with(
+            "<15>1 2023-04-17T08:30:00.000000Z myproject - 00000000-0000-0000-0000-000000000000 Running"
+        ));
+    }
+
+    #[test]
+    fn address() {
+        assert!(Syslog::new("logs.example.com:6514", true, "my-project").is_ok());
+        assert!(Syslog::new("10.0.0.1:514", false, "my-project").is_ok());
+        assert!(Syslog::new("logs.example.com", false, "my-project").is_err());
+        assert!(Syslog::new(":514", false, "my-project").is_err());
+    }
+}
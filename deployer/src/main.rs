@@ -1,8 +1,10 @@
-use std::process::exit;
+use std::{process::exit, time::Duration};
 
 use clap::Parser;
 use shuttle_common::backends::tracing::setup_tracing;
-use shuttle_deployer::{start, start_proxy, Args, DeployLayer, Persistence, RuntimeManager};
+use shuttle_deployer::{
+    start, start_proxy, start_tcp_proxy, Args, DeployLayer, Persistence, RuntimeManager,
+};
 use tokio::select;
 use tracing::{error, trace};
 use tracing_subscriber::prelude::*;
@@ -15,7 +17,10 @@ async fn main() {
 
     trace!(args = ?args, "parsed args");
 
-    let (persistence, _) = Persistence::new(&args.state).await;
+    let secret_cipher = args
+        .secret_cipher()
+        .expect("a valid key to encrypt secrets with");
+    let (persistence, _) = Persistence::new(&args.state, secret_cipher).await;
     setup_tracing(
         tracing_subscriber::registry().with(DeployLayer::new(persistence.clone())),
         "deployer",
@@ -29,9 +34,17 @@ async fn main() {
     );
 
     select! {
-        _ = start_proxy(args.proxy_address, args.proxy_fqdn.clone(), persistence.clone()) => {
+        _ = start_proxy(
+            args.proxy_address,
+            args.proxy_fqdn.clone(),
+            persistence.clone(),
+            Duration::from_secs(args.upgrade_idle_timeout_secs),
+        ) => {
             error!("Proxy stopped.")
         },
+        _ = start_tcp_proxy(args.tcp_proxy_address, persistence.clone()) => {
+            error!("TCP proxy stopped.")
+        },
         _ = start(persistence, runtime_manager, args) => {
             error!("Deployment service stopped.")
         },
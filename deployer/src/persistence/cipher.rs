@@ -0,0 +1,162 @@
+use std::sync::Arc;
+
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("the secrets key should be a base64 encoded 256-bit key")]
+    InvalidKey,
+    #[error("failed to decode sealed data: {0}")]
+    Decode(#[from] base64::DecodeError),
+    #[error("failed to seal secret")]
+    Seal,
+    #[error("failed to open sealed secret, was it sealed with a different key?")]
+    Open,
+}
+
+/// A secret value as it is stored at rest
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sealed {
+    /// The data key the value was sealed with, itself sealed with the master key
+    pub data_key: String,
+
+    /// The sealed value
+    pub value: String,
+}
+
+/// Envelope encryption for the secrets stored in the state database.
+///
+/// Each value is sealed with a freshly generated data key, and only a copy of that data key sealed
+/// with the master key is stored next to it. The master key never touches the database.
+#[derive(Clone)]
+pub struct SecretCipher {
+    master_key: Arc<LessSafeKey>,
+    rng: SystemRandom,
+}
+
+impl SecretCipher {
+    pub fn new(key: &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            master_key: Arc::new(make_key(key)?),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Create a cipher from a base64 encoded master key
+    pub fn from_base64(encoded: &str) -> Result<Self, Error> {
+        let key = base64::decode(encoded.trim()).map_err(|_| Error::InvalidKey)?;
+
+        Self::new(&key)
+    }
+
+    pub fn seal(&self, plaintext: &str) -> Result<Sealed, Error> {
+        let mut data_key = [0; 32];
+        self.rng.fill(&mut data_key).map_err(|_| Error::Seal)?;
+
+        let value = self.seal_with(&make_key(&data_key)?, plaintext.as_bytes())?;
+        let data_key = self.seal_with(&self.master_key, &data_key)?;
+
+        Ok(Sealed { data_key, value })
+    }
+
+    pub fn open(&self, sealed: &Sealed) -> Result<String, Error> {
+        let data_key = open_with(&self.master_key, &sealed.data_key)?;
+        let value = open_with(&make_key(&data_key)?, &sealed.value)?;
+
+        String::from_utf8(value).map_err(|_| Error::Open)
+    }
+
+    /// Encrypt with a random nonce and return the base64 encoding of the nonce followed by the
+    /// ciphertext and tag
+    fn seal_with(&self, key: &LessSafeKey, plaintext: &[u8]) -> Result<String, Error> {
+        let mut nonce = [0; NONCE_LEN];
+        self.rng.fill(&mut nonce).map_err(|_| Error::Seal)?;
+
+        let mut in_out = plaintext.to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut in_out,
+        )
+        .map_err(|_| Error::Seal)?;
+
+        let mut out = nonce.to_vec();
+        out.extend(in_out);
+
+        Ok(base64::encode(out))
+    }
+}
+
+fn open_with(key: &LessSafeKey, encoded: &str) -> Result<Vec<u8>, Error> {
+    let sealed = base64::decode(encoded)?;
+
+    if sealed.len() < NONCE_LEN {
+        return Err(Error::Open);
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| Error::Open)?;
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = key
+        .open_in_place(nonce, Aad::empty(), &mut in_out)
+        .map_err(|_| Error::Open)?;
+
+    Ok(plaintext.to_vec())
+}
+
+fn make_key(key: &[u8]) -> Result<LessSafeKey, Error> {
+    let key = UnboundKey::new(&AES_256_GCM, key).map_err(|_| Error::InvalidKey)?;
+
+    Ok(LessSafeKey::new(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let cipher = SecretCipher::new(&[7; 32]).unwrap();
+
+        let sealed = cipher.seal("the contents of my API key").unwrap();
+
+        assert!(!sealed.value.contains("API key"));
+        assert_eq!(cipher.open(&sealed).unwrap(), "the contents of my API key");
+    }
+
+    #[test]
+    fn unique_data_keys() {
+        let cipher = SecretCipher::new(&[7; 32]).unwrap();
+
+        let first = cipher.seal("value").unwrap();
+        let second = cipher.seal("value").unwrap();
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn wrong_master_key() {
+        let cipher = SecretCipher::new(&[7; 32]).unwrap();
+        let other = SecretCipher::new(&[8; 32]).unwrap();
+
+        let sealed = cipher.seal("value").unwrap();
+
+        assert!(matches!(other.open(&sealed), Err(Error::Open)));
+    }
+
+    #[test]
+    fn invalid_master_key() {
+        assert!(matches!(
+            SecretCipher::new(&[7; 16]),
+            Err(Error::InvalidKey)
+        ));
+        assert!(matches!(
+            SecretCipher::from_base64("not base64!"),
+            Err(Error::InvalidKey)
+        ));
+        assert!(SecretCipher::from_base64(&base64::encode([1; 32])).is_ok());
+    }
+}
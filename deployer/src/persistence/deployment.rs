@@ -2,6 +2,9 @@ use std::{net::SocketAddr, str::FromStr};
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use shuttle_common::deployment::{HealthCheck, Next, Readiness, RestartPolicy, Tcp};
+use shuttle_common::secrets::SecretSchema;
+use sqlx::types::Json;
 use sqlx::{sqlite::SqliteRow, FromRow, Row};
 use tracing::error;
 use utoipa::ToSchema;
@@ -51,6 +54,8 @@ impl From<Deployment> for shuttle_common::models::deployment::Response {
             service_id: deployment.service_id,
             state: deployment.state.into(),
             last_update: deployment.last_update,
+            last_run: None,
+            next_run: None,
         }
     }
 }
@@ -60,11 +65,57 @@ impl From<Deployment> for shuttle_common::models::deployment::Response {
 pub trait DeploymentUpdater: Clone + Send + Sync + 'static {
     type Err: std::error::Error + Send;
 
-    /// Set the address for a deployment
+    /// Set the address for a deployment, which is when it starts getting requests
     async fn set_address(&self, id: &Uuid, address: &SocketAddr) -> Result<(), Self::Err>;
 
     /// Set if a deployment is build on shuttle-next
     async fn set_is_next(&self, id: &Uuid, is_next: bool) -> Result<(), Self::Err>;
+
+    /// Set the health check a deployment is probed with while it runs
+    async fn set_health_check(
+        &self,
+        id: &Uuid,
+        health_check: Option<&HealthCheck>,
+    ) -> Result<(), Self::Err>;
+
+    /// Set how a deployment takes over from the deployments it replaces
+    async fn set_readiness(
+        &self,
+        id: &Uuid,
+        readiness: Option<&Readiness>,
+    ) -> Result<(), Self::Err>;
+
+    /// Set when a deployment is started again after it stops
+    async fn set_restart_policy(
+        &self,
+        id: &Uuid,
+        restart_policy: Option<&RestartPolicy>,
+    ) -> Result<(), Self::Err>;
+
+    /// Set the raw TCP connections a deployment accepts instead of HTTP requests
+    async fn set_tcp(&self, id: &Uuid, tcp: Option<&Tcp>) -> Result<(), Self::Err>;
+
+    /// Set how a shuttle-next deployment handles requests
+    async fn set_next(&self, id: &Uuid, next: Option<&Next>) -> Result<(), Self::Err>;
+
+    /// Set the secrets a deployment declares, whose defaults it is loaded with
+    async fn set_secrets_schema(
+        &self,
+        id: &Uuid,
+        secrets_schema: Option<&SecretSchema>,
+    ) -> Result<(), Self::Err>;
+
+    /// Set the times a deployment has been restarted by its restart policy since it last ran healthy
+    async fn set_restart_count(&self, id: &Uuid, restart_count: u32) -> Result<(), Self::Err>;
+
+    /// Add a deployment which has already been built for the service with the given name, creating
+    /// the service when it does not exist yet. A deployment added like this before which is done is
+    /// reused rather than adding a new one. Returns the ids of the service and of the deployment.
+    async fn add_built_deployment(
+        &self,
+        service_name: &str,
+        is_next: bool,
+    ) -> Result<(Uuid, Uuid), Self::Err>;
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -80,4 +131,10 @@ pub struct DeploymentRunnable {
     pub service_name: String,
     pub service_id: Uuid,
     pub is_next: bool,
+    pub health_check: Option<Json<HealthCheck>>,
+    pub readiness: Option<Json<Readiness>>,
+    pub restart_policy: Option<Json<RestartPolicy>>,
+    pub next: Option<Json<Next>>,
+    pub secrets_schema: Option<Json<SecretSchema>>,
+    pub restart_count: u32,
 }
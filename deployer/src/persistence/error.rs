@@ -2,6 +2,8 @@
 pub enum Error {
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
+    #[error("Secret encryption error: {0}")]
+    Cipher(#[from] super::cipher::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
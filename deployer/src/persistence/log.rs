@@ -1,4 +1,5 @@
 use chrono::{DateTime, Utc};
+use regex::Regex;
 use serde_json::{json, Value};
 use shuttle_common::STATE_MESSAGE;
 use uuid::Uuid;
@@ -17,7 +18,36 @@ pub struct Log {
     pub fields: serde_json::Value,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, sqlx::Type)]
+impl Log {
+    /// The human readable message of this log, falling back to all its fields
+    pub fn message(&self) -> String {
+        match &self.fields {
+            Value::String(message) => message.clone(),
+            fields => match fields.get("message") {
+                Some(Value::String(message)) => message.clone(),
+                _ => extract_message(fields).unwrap_or_else(|| fields.to_string()),
+            },
+        }
+    }
+}
+
+/// Where a page of logs stopped. The rowid breaks ties between logs with the same timestamp.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LogPosition {
+    pub timestamp: DateTime<Utc>,
+    pub rowid: i64,
+}
+
+/// A log along with its rowid, to know where a page of logs stopped
+#[derive(sqlx::FromRow)]
+pub(super) struct PagedLog {
+    pub rowid: i64,
+    #[sqlx(flatten)]
+    pub log: Log,
+}
+
+/// Levels are ordered from least to most severe
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, sqlx::Type)]
 pub enum Level {
     Trace,
     Debug,
@@ -26,6 +56,88 @@ pub enum Level {
     Error,
 }
 
+impl Level {
+    /// This level and all the levels more severe than it
+    pub fn and_above(&self) -> impl Iterator<Item = Level> + '_ {
+        [
+            Self::Trace,
+            Self::Debug,
+            Self::Info,
+            Self::Warn,
+            Self::Error,
+        ]
+        .into_iter()
+        .filter(move |level| level >= self)
+    }
+}
+
+/// Filter for the logs of a deployment, built from a [shuttle_common::log::Query]
+#[derive(Clone, Debug, Default)]
+pub struct LogFilter {
+    pub(super) level: Option<Level>,
+    pub(super) target: Option<String>,
+    pub(super) since: Option<DateTime<Utc>>,
+    pub(super) until: Option<DateTime<Utc>>,
+    search: Option<Search>,
+}
+
+#[derive(Clone, Debug)]
+enum Search {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl TryFrom<shuttle_common::log::Query> for LogFilter {
+    type Error = regex::Error;
+
+    fn try_from(query: shuttle_common::log::Query) -> Result<Self, Self::Error> {
+        let search = match query.search {
+            Some(search) if query.regex => Some(Search::Regex(Regex::new(&search)?)),
+            Some(search) => Some(Search::Substring(search)),
+            None => None,
+        };
+
+        Ok(Self {
+            level: query.level.map(Into::into),
+            target: query.target,
+            since: query.since,
+            until: query.until,
+            search,
+        })
+    }
+}
+
+impl LogFilter {
+    /// Check the log against every part of the filter
+    pub fn matches(&self, log: &Log) -> bool {
+        self.level
+            .as_ref()
+            .map_or(true, |level| &log.level >= level)
+            && self
+                .target
+                .as_ref()
+                .map_or(true, |target| log.target.starts_with(target))
+            && self.since.map_or(true, |since| log.timestamp >= since)
+            && self.until.map_or(true, |until| log.timestamp <= until)
+            && self.matches_search(log)
+    }
+
+    /// Check only the message search, which cannot be done by the database
+    pub(super) fn matches_search(&self, log: &Log) -> bool {
+        let search = match &self.search {
+            Some(search) => search,
+            None => return true,
+        };
+
+        let message = log.message();
+
+        match search {
+            Search::Substring(substring) => message.contains(substring),
+            Search::Regex(regex) => regex.is_match(&message),
+        }
+    }
+}
+
 impl From<Log> for Option<shuttle_common::LogItem> {
     fn from(log: Log) -> Self {
         if log.state == State::Building {
@@ -1,3 +1,4 @@
+mod cipher;
 pub mod deployment;
 mod error;
 pub mod log;
@@ -7,7 +8,7 @@ pub mod service;
 mod state;
 mod user;
 
-use crate::deployment::deploy_layer::{self, LogRecorder, LogType};
+use crate::deployment::deploy_layer::{self, LogRecorder, LogType, CRON_LOG_TARGET};
 use crate::deployment::ActiveDeploymentsGetter;
 use crate::proxy::AddressGetter;
 use error::{Error, Result};
@@ -16,21 +17,29 @@ use std::net::SocketAddr;
 use std::path::Path;
 use std::str::FromStr;
 
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 use serde_json::json;
+use shuttle_common::deployment::{HealthCheck, Next, Readiness, RestartPolicy, Tcp};
+use shuttle_common::log::{Drain, Retention};
+use shuttle_common::secrets::SecretSchema;
 use shuttle_common::STATE_MESSAGE;
 use sqlx::migrate::{MigrateDatabase, Migrator};
 use sqlx::sqlite::{Sqlite, SqliteConnectOptions, SqliteJournalMode, SqlitePool};
+use sqlx::types::Json;
+use sqlx::QueryBuilder;
 use tokio::sync::broadcast::{self, Receiver, Sender};
 use tokio::task::JoinHandle;
 use tracing::{error, info, instrument, trace};
 use uuid::Uuid;
 
+pub use self::cipher::{Error as CipherError, SecretCipher};
 use self::deployment::DeploymentRunnable;
 pub use self::deployment::{Deployment, DeploymentState, DeploymentUpdater};
 pub use self::error::Error as PersistenceError;
-pub use self::log::{Level as LogLevel, Log};
+use self::log::PagedLog;
+pub use self::log::{Level as LogLevel, Log, LogFilter, LogPosition};
 pub use self::resource::{Resource, ResourceManager, Type as ResourceType};
+use self::secret::SealedSecret;
 pub use self::secret::{Secret, SecretGetter, SecretRecorder};
 pub use self::service::Service;
 pub use self::state::State;
@@ -38,11 +47,16 @@ pub use self::user::User;
 
 pub static MIGRATIONS: Migrator = sqlx::migrate!("./migrations");
 
+/// How many logs a subscriber can fall behind before it starts missing some. The log drain
+/// forwards in batches, so it needs more room than a websocket.
+const LOG_STREAM_CAPACITY: usize = 1024;
+
 #[derive(Clone)]
 pub struct Persistence {
     pool: SqlitePool,
     log_send: crossbeam_channel::Sender<deploy_layer::Log>,
     stream_log_send: Sender<deploy_layer::Log>,
+    secret_cipher: SecretCipher,
 }
 
 impl Persistence {
@@ -50,7 +64,7 @@ impl Persistence {
     /// function creates all necessary tables and sets up a database connection
     /// pool - new connections should be made by cloning [`Persistence`] rather
     /// than repeatedly calling [`Persistence::new`].
-    pub async fn new(path: &str) -> (Self, JoinHandle<()>) {
+    pub async fn new(path: &str, secret_cipher: SecretCipher) -> (Self, JoinHandle<()>) {
         if !Path::new(path).exists() {
             Sqlite::create_database(path).await.unwrap();
         }
@@ -74,22 +88,28 @@ impl Persistence {
 
         let pool = SqlitePool::connect_with(sqlite_options).await.unwrap();
 
-        Self::from_pool(pool).await
+        Self::from_pool(pool, secret_cipher).await
     }
 
     #[allow(dead_code)]
     async fn new_in_memory() -> (Self, JoinHandle<()>) {
         let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
-        Self::from_pool(pool).await
+        let secret_cipher = SecretCipher::new(&[42; 32]).unwrap();
+
+        Self::from_pool(pool, secret_cipher).await
     }
 
-    async fn from_pool(pool: SqlitePool) -> (Self, JoinHandle<()>) {
+    async fn from_pool(pool: SqlitePool, secret_cipher: SecretCipher) -> (Self, JoinHandle<()>) {
         MIGRATIONS.run(&pool).await.unwrap();
 
+        encrypt_plaintext_secrets(&pool, &secret_cipher)
+            .await
+            .expect("to encrypt the secrets that are still in plaintext");
+
         let (log_send, log_recv): (crossbeam_channel::Sender<deploy_layer::Log>, _) =
             crossbeam_channel::bounded(0);
 
-        let (stream_log_send, _) = broadcast::channel(1);
+        let (stream_log_send, _) = broadcast::channel(LOG_STREAM_CAPACITY);
         let stream_log_send_clone = stream_log_send.clone();
 
         let pool_cloned = pool.clone();
@@ -109,6 +129,17 @@ impl Persistence {
                                     "failed to insert event log"
                                 )
                             });
+
+                        if log.target == CRON_LOG_TARGET {
+                            record_cron_run(&pool_cloned, &log)
+                                .await
+                                .unwrap_or_else(|error| {
+                                    error!(
+                                        error = &error as &dyn std::error::Error,
+                                        "failed to record cron run"
+                                    )
+                                });
+                        }
                     }
                     LogType::State => {
                         insert_log(
@@ -162,6 +193,7 @@ impl Persistence {
             pool,
             log_send,
             stream_log_send,
+            secret_cipher,
         };
 
         (persistence, handle)
@@ -263,23 +295,178 @@ impl Persistence {
             .map_err(Error::from)
     }
 
+    /// Get the deployments which should be running, including the ones which were waiting to be
+    /// restarted
     pub async fn get_all_runnable_deployments(&self) -> Result<Vec<DeploymentRunnable>> {
         sqlx::query_as(
-            r#"SELECT d.id, service_id, s.name AS service_name, d.is_next
+            r#"SELECT d.id, service_id, s.name AS service_name, d.is_next, d.health_check, d.readiness,
+                    d.restart_policy, d.next, d.secrets_schema, d.restart_count
                 FROM deployments AS d
                 JOIN services AS s ON s.id = d.service_id
-                WHERE state = ?
+                WHERE state IN (?, ?)
                 ORDER BY last_update"#,
         )
         .bind(State::Running)
+        .bind(State::Restarting)
         .fetch_all(&self.pool)
         .await
         .map_err(Error::from)
     }
 
-    pub(crate) async fn get_deployment_logs(&self, id: &Uuid) -> Result<Vec<Log>> {
+    pub async fn get_runnable_deployment(&self, id: &Uuid) -> Result<Option<DeploymentRunnable>> {
+        sqlx::query_as(
+            r#"SELECT d.id, service_id, s.name AS service_name, d.is_next, d.health_check, d.readiness,
+                    d.restart_policy, d.next, d.secrets_schema, d.restart_count
+                FROM deployments AS d
+                JOIN services AS s ON s.id = d.service_id
+                WHERE d.id = ?"#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(Error::from)
+    }
+
+    pub(crate) async fn get_deployment_logs(
+        &self,
+        id: &Uuid,
+        filter: &LogFilter,
+    ) -> Result<Vec<Log>> {
         // TODO: stress this a bit
-        get_deployment_logs(&self.pool, id).await
+        get_deployment_logs(&self.pool, id, filter).await
+    }
+
+    /// Get at most `limit` logs of a deployment after the `after` position, along with the position
+    /// to continue from for the next page if there is one
+    pub(crate) async fn get_deployment_logs_page(
+        &self,
+        id: &Uuid,
+        filter: &LogFilter,
+        after: Option<LogPosition>,
+        limit: u32,
+    ) -> Result<(Vec<Log>, Option<LogPosition>)> {
+        get_deployment_logs_page(&self.pool, id, filter, after, limit).await
+    }
+
+    /// Get when the last run of a cron deployment started and when its next run is due, as they
+    /// were recorded from the logs of the shuttle-cron service
+    pub async fn get_cron_runs(
+        &self,
+        id: &Uuid,
+    ) -> Result<(Option<DateTime<Utc>>, Option<DateTime<Utc>>)> {
+        let runs: Option<(Option<DateTime<Utc>>, Option<DateTime<Utc>>, State)> = sqlx::query_as(
+            "SELECT c.last_run, c.next_run, d.state FROM cron_runs AS c INNER JOIN deployments AS d ON c.id = d.id WHERE c.id = ?",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(match runs {
+            // A deployment which is not running anymore has no next run, even if one was scheduled
+            Some((last_run, next_run, state)) => {
+                (last_run, next_run.filter(|_| state == State::Running))
+            }
+            None => (None, None),
+        })
+    }
+
+    pub async fn get_log_retention(&self, service_id: &Uuid) -> Result<Retention> {
+        let (max_age_hours, max_lines) = sqlx::query_as(
+            "SELECT log_retention_max_age_hours, log_retention_max_lines FROM services WHERE id = ?",
+        )
+        .bind(service_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(Retention {
+            max_age_hours,
+            max_lines,
+        })
+    }
+
+    pub async fn set_log_retention(&self, service_id: &Uuid, retention: &Retention) -> Result<()> {
+        sqlx::query(
+            "UPDATE services SET log_retention_max_age_hours = ?, log_retention_max_lines = ? WHERE id = ?",
+        )
+        .bind(retention.max_age_hours)
+        .bind(retention.max_lines)
+        .bind(service_id)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .map_err(Error::from)
+    }
+
+    /// Delete the logs of every service which fall outside of its retention. Services use the
+    /// `default` for any part of the retention they have not set. Returns the number of deleted logs.
+    pub async fn enforce_log_retention(&self, default: &Retention) -> Result<u64> {
+        let services: Vec<(Uuid, Option<u32>, Option<u32>)> = sqlx::query_as(
+            "SELECT id, log_retention_max_age_hours, log_retention_max_lines FROM services",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut deleted = 0;
+
+        for (service_id, max_age_hours, max_lines) in services {
+            if let Some(max_age_hours) = max_age_hours.or(default.max_age_hours) {
+                let cutoff = Utc::now() - Duration::hours(max_age_hours.into());
+
+                deleted += sqlx::query(
+                    "DELETE FROM logs WHERE id IN (SELECT id FROM deployments WHERE service_id = ?) AND timestamp < ?",
+                )
+                .bind(service_id)
+                .bind(cutoff)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+            }
+
+            if let Some(max_lines) = max_lines.or(default.max_lines) {
+                deleted += sqlx::query(
+                    r#"DELETE FROM logs WHERE rowid IN (
+                        SELECT logs.rowid FROM logs
+                        JOIN deployments ON logs.id = deployments.id
+                        WHERE deployments.service_id = ?
+                        ORDER BY logs.timestamp DESC
+                        LIMIT -1 OFFSET ?
+                    )"#,
+                )
+                .bind(service_id)
+                .bind(max_lines)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    pub async fn get_log_drain(&self) -> Result<Option<Drain>> {
+        let drain: Option<(Json<Drain>,)> =
+            sqlx::query_as("SELECT config FROM log_drain WHERE id = 0")
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(drain.map(|(Json(drain),)| drain))
+    }
+
+    /// Store the drain the logs are forwarded to, or remove it when `drain` is `None`
+    pub async fn set_log_drain(&self, drain: Option<&Drain>) -> Result<()> {
+        let query = match drain {
+            Some(drain) => {
+                sqlx::query("INSERT OR REPLACE INTO log_drain (id, config) VALUES (0, ?)")
+                    .bind(Json(drain))
+            }
+            None => sqlx::query("DELETE FROM log_drain"),
+        };
+
+        query
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
     }
 
     /// Get a broadcast channel for listening to logs that are being stored into persistence
@@ -296,9 +483,12 @@ impl Persistence {
 async fn update_deployment(pool: &SqlitePool, state: impl Into<DeploymentState>) -> Result<()> {
     let state = state.into();
 
-    sqlx::query("UPDATE deployments SET state = ?, last_update = ? WHERE id = ?")
+    // A deployment which is loaded again only gets requests once its new runtime is ready, so
+    // forget the address of its last runtime
+    sqlx::query("UPDATE deployments SET state = ?, last_update = ?, address = CASE WHEN ? THEN NULL ELSE address END WHERE id = ?")
         .bind(state.state)
         .bind(state.last_update)
+        .bind(state.state == State::Loading)
         .bind(state.id)
         .execute(pool)
         .await
@@ -332,12 +522,167 @@ async fn insert_log(pool: &SqlitePool, log: impl Into<Log>) -> Result<()> {
         .map_err(Error::from)
 }
 
-async fn get_deployment_logs(pool: &SqlitePool, id: &Uuid) -> Result<Vec<Log>> {
-    sqlx::query_as("SELECT * FROM logs WHERE id = ? ORDER BY timestamp")
-        .bind(id)
+/// Record a run of a cron deployment from a log of shuttle-cron. These are kept apart from the logs
+/// so that log retention does not lose them.
+async fn record_cron_run(pool: &SqlitePool, log: &deploy_layer::Log) -> Result<()> {
+    match log.fields.get("cron").and_then(|cron| cron.as_str()) {
+        Some("started") => sqlx::query(
+            "INSERT INTO cron_runs (id, last_run) VALUES (?, ?) ON CONFLICT (id) DO UPDATE SET last_run = excluded.last_run",
+        )
+        .bind(log.id)
+        .bind(log.timestamp),
+        Some("scheduled") => {
+            let next_run = log
+                .fields
+                .get("next_run")
+                .and_then(|next_run| next_run.as_str())
+                .and_then(|next_run| DateTime::parse_from_rfc3339(next_run).ok())
+                .map(|next_run| next_run.with_timezone(&Utc));
+
+            let Some(next_run) = next_run else {
+                error!(fields = %log.fields, "cron log has no valid next run");
+                return Ok(());
+            };
+
+            sqlx::query(
+                "INSERT INTO cron_runs (id, next_run) VALUES (?, ?) ON CONFLICT (id) DO UPDATE SET next_run = excluded.next_run",
+            )
+            .bind(log.id)
+            .bind(next_run)
+        }
+        Some("exhausted") => sqlx::query(
+            "INSERT INTO cron_runs (id, next_run) VALUES (?, NULL) ON CONFLICT (id) DO UPDATE SET next_run = NULL",
+        )
+        .bind(log.id),
+        _ => return Ok(()),
+    }
+    .execute(pool)
+    .await
+    .map(|_| ())
+    .map_err(Error::from)
+}
+
+/// Encrypt any secrets that were stored before secrets were encrypted at rest
+async fn encrypt_plaintext_secrets(pool: &SqlitePool, secret_cipher: &SecretCipher) -> Result<()> {
+    let plaintext: Vec<SealedSecret> =
+        sqlx::query_as("SELECT * FROM secrets WHERE data_key IS NULL")
+            .fetch_all(pool)
+            .await?;
+
+    if !plaintext.is_empty() {
+        info!(count = plaintext.len(), "encrypting plaintext secrets");
+    }
+
+    for secret in plaintext {
+        let sealed = secret_cipher.seal(&secret.value)?;
+
+        sqlx::query("UPDATE secrets SET value = ?, data_key = ? WHERE service_id = ? AND key = ?")
+            .bind(sealed.value)
+            .bind(sealed.data_key)
+            .bind(secret.service_id)
+            .bind(secret.key)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn get_deployment_logs(pool: &SqlitePool, id: &Uuid, filter: &LogFilter) -> Result<Vec<Log>> {
+    let mut query = deployment_logs_query(id, filter);
+    query.push(" ORDER BY timestamp");
+
+    // SQLite has no regex support, so matching on the message is done here
+    let logs = query
+        .build_query_as()
         .fetch_all(pool)
-        .await
-        .map_err(Error::from)
+        .await?
+        .into_iter()
+        .filter(|log| filter.matches_search(log))
+        .collect();
+
+    Ok(logs)
+}
+
+async fn get_deployment_logs_page(
+    pool: &SqlitePool,
+    id: &Uuid,
+    filter: &LogFilter,
+    after: Option<LogPosition>,
+    limit: u32,
+) -> Result<(Vec<Log>, Option<LogPosition>)> {
+    let mut query = deployment_logs_query(id, filter);
+
+    // Logs can share a timestamp, so their rowid is needed to know where exactly the last page stopped
+    if let Some(LogPosition { timestamp, rowid }) = after {
+        query
+            .push(" AND (timestamp > ")
+            .push_bind(timestamp)
+            .push(" OR (timestamp = ")
+            .push_bind(timestamp)
+            .push(" AND rowid > ")
+            .push_bind(rowid)
+            .push("))");
+    }
+
+    // Get one extra log to know if there is a next page
+    query
+        .push(" ORDER BY timestamp, rowid LIMIT ")
+        .push_bind(i64::from(limit) + 1);
+
+    let mut logs: Vec<PagedLog> = query.build_query_as().fetch_all(pool).await?;
+
+    // The cursor points at the last log read from the database, even if the search drops it
+    let next = if logs.len() > limit as usize {
+        logs.truncate(limit as usize);
+        logs.last().map(|paged| LogPosition {
+            timestamp: paged.log.timestamp,
+            rowid: paged.rowid,
+        })
+    } else {
+        None
+    };
+
+    let logs = logs
+        .into_iter()
+        .map(|paged| paged.log)
+        .filter(|log| filter.matches_search(log))
+        .collect();
+
+    Ok((logs, next))
+}
+
+/// Start a query for the logs of a deployment with all the parts of the filter the database can handle
+fn deployment_logs_query<'a>(id: &'a Uuid, filter: &'a LogFilter) -> QueryBuilder<'a, Sqlite> {
+    let mut query = QueryBuilder::new("SELECT rowid, * FROM logs WHERE id = ");
+    query.push_bind(id);
+
+    if let Some(level) = &filter.level {
+        query.push(" AND level IN (");
+
+        let mut levels = query.separated(", ");
+        for level in level.and_above() {
+            levels.push_bind(level);
+        }
+        levels.push_unseparated(")");
+    }
+
+    if let Some(target) = &filter.target {
+        query
+            .push(" AND instr(target, ")
+            .push_bind(target)
+            .push(") = 1");
+    }
+
+    if let Some(since) = filter.since {
+        query.push(" AND timestamp >= ").push_bind(since);
+    }
+
+    if let Some(until) = filter.until {
+        query.push(" AND timestamp <= ").push_bind(until);
+    }
+
+    query
 }
 
 impl LogRecorder for Persistence {
@@ -380,18 +725,31 @@ impl SecretRecorder for Persistence {
     type Err = Error;
 
     async fn insert_secret(&self, service_id: &Uuid, key: &str, value: &str) -> Result<()> {
+        let sealed = self.secret_cipher.seal(value)?;
+
         sqlx::query(
-            "INSERT OR REPLACE INTO secrets (service_id, key, value, last_update) VALUES (?, ?, ?, ?)",
+            "INSERT OR REPLACE INTO secrets (service_id, key, value, data_key, last_update) VALUES (?, ?, ?, ?, ?)",
         )
         .bind(service_id)
         .bind(key)
-        .bind(value)
+        .bind(sealed.value)
+        .bind(sealed.data_key)
         .bind(Utc::now())
         .execute(&self.pool)
         .await
         .map(|_| ())
         .map_err(Error::from)
     }
+
+    async fn remove_secret(&self, service_id: &Uuid, key: &str) -> Result<()> {
+        sqlx::query("DELETE FROM secrets WHERE service_id = ? AND key = ?")
+            .bind(service_id)
+            .bind(key)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
 }
 
 #[async_trait::async_trait]
@@ -399,11 +757,16 @@ impl SecretGetter for Persistence {
     type Err = Error;
 
     async fn get_secrets(&self, service_id: &Uuid) -> Result<Vec<Secret>> {
-        sqlx::query_as("SELECT * FROM secrets WHERE service_id = ? ORDER BY key")
-            .bind(service_id)
-            .fetch_all(&self.pool)
-            .await
-            .map_err(Error::from)
+        let sealed: Vec<SealedSecret> =
+            sqlx::query_as("SELECT * FROM secrets WHERE service_id = ? ORDER BY key")
+                .bind(service_id)
+                .fetch_all(&self.pool)
+                .await?;
+
+        sealed
+            .into_iter()
+            .map(|secret| secret.open(&self.secret_cipher).map_err(Error::from))
+            .collect()
     }
 }
 
@@ -418,8 +781,8 @@ impl AddressGetter for Persistence {
             r#"SELECT d.address
                 FROM deployments AS d
                 JOIN services AS s ON d.service_id = s.id
-                WHERE s.name = ? AND d.state = ?
-                ORDER BY d.last_update"#,
+                WHERE s.name = ? AND d.state = ? AND d.address IS NOT NULL
+                ORDER BY d.last_update DESC"#,
         )
         .bind(service_name)
         .bind(State::Running)
@@ -440,6 +803,33 @@ impl AddressGetter for Persistence {
             Ok(None)
         }
     }
+
+    #[instrument(skip(self))]
+    async fn get_tcp_address(&self) -> crate::handlers::Result<Option<(SocketAddr, Tcp)>> {
+        let tcp = sqlx::query_as::<_, (String, Json<Tcp>)>(
+            r#"SELECT address, tcp
+                FROM deployments
+                WHERE state = ? AND address IS NOT NULL AND tcp IS NOT NULL
+                ORDER BY last_update DESC"#,
+        )
+        .bind(State::Running)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(Error::from)
+        .map_err(crate::handlers::Error::Persistence)?;
+
+        if let Some((address_str, Json(tcp))) = tcp {
+            SocketAddr::from_str(&address_str)
+                .map(|address| Some((address, tcp)))
+                .map_err(|err| crate::handlers::Error::Convert {
+                    from: "String".to_string(),
+                    to: "SocketAddr".to_string(),
+                    message: err.to_string(),
+                })
+        } else {
+            Ok(None)
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -465,6 +855,152 @@ impl DeploymentUpdater for Persistence {
             .map(|_| ())
             .map_err(Error::from)
     }
+
+    async fn set_health_check(&self, id: &Uuid, health_check: Option<&HealthCheck>) -> Result<()> {
+        sqlx::query("UPDATE deployments SET health_check = ? WHERE id = ?")
+            .bind(health_check.map(Json))
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    async fn set_readiness(&self, id: &Uuid, readiness: Option<&Readiness>) -> Result<()> {
+        sqlx::query("UPDATE deployments SET readiness = ? WHERE id = ?")
+            .bind(readiness.map(Json))
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    async fn set_restart_policy(
+        &self,
+        id: &Uuid,
+        restart_policy: Option<&RestartPolicy>,
+    ) -> Result<()> {
+        sqlx::query("UPDATE deployments SET restart_policy = ? WHERE id = ?")
+            .bind(restart_policy.map(Json))
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    async fn set_tcp(&self, id: &Uuid, tcp: Option<&Tcp>) -> Result<()> {
+        sqlx::query("UPDATE deployments SET tcp = ? WHERE id = ?")
+            .bind(tcp.map(Json))
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    async fn set_next(&self, id: &Uuid, next: Option<&Next>) -> Result<()> {
+        sqlx::query("UPDATE deployments SET next = ? WHERE id = ?")
+            .bind(next.map(Json))
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    async fn set_secrets_schema(
+        &self,
+        id: &Uuid,
+        secrets_schema: Option<&SecretSchema>,
+    ) -> Result<()> {
+        sqlx::query("UPDATE deployments SET secrets_schema = ? WHERE id = ?")
+            .bind(secrets_schema.map(Json))
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    async fn set_restart_count(&self, id: &Uuid, restart_count: u32) -> Result<()> {
+        sqlx::query("UPDATE deployments SET restart_count = ? WHERE id = ?")
+            .bind(restart_count)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    async fn add_built_deployment(
+        &self,
+        service_name: &str,
+        is_next: bool,
+    ) -> Result<(Uuid, Uuid)> {
+        let service = self.get_or_create_service(service_name).await?;
+        let mut transaction = self.pool.begin().await?;
+
+        // Take over a workspace deployment of the service which is done, along with its id. Those
+        // which are still active are left alone since the new deployment replaces them once it runs.
+        let reusable: Option<Uuid> = sqlx::query_scalar(
+            "SELECT id FROM deployments WHERE service_id = ? AND workspace = 1 AND state IN (?, ?, ?, ?) ORDER BY last_update LIMIT 1",
+        )
+        .bind(service.id)
+        .bind(State::Stopped)
+        .bind(State::Completed)
+        .bind(State::Crashed)
+        .bind(State::Unknown)
+        .fetch_optional(&mut transaction)
+        .await?;
+
+        let id = match reusable {
+            Some(id) => {
+                sqlx::query(
+                    "UPDATE deployments SET state = ?, last_update = ?, address = NULL, is_next = ?, health_check = NULL, readiness = NULL, restart_policy = NULL, tcp = NULL, next = NULL, secrets_schema = NULL, restart_count = 0 WHERE id = ?",
+                )
+                .bind(State::Built)
+                .bind(Utc::now())
+                .bind(is_next)
+                .bind(id)
+                .execute(&mut transaction)
+                .await?;
+
+                // The logs of the previous run would be mixed up with those of this one otherwise
+                sqlx::query("DELETE FROM logs WHERE id = ?")
+                    .bind(id)
+                    .execute(&mut transaction)
+                    .await?;
+                sqlx::query("DELETE FROM cron_runs WHERE id = ?")
+                    .bind(id)
+                    .execute(&mut transaction)
+                    .await?;
+
+                id
+            }
+            None => {
+                let id = Uuid::new_v4();
+
+                sqlx::query(
+                    "INSERT INTO deployments (id, service_id, state, last_update, address, is_next, workspace) VALUES (?, ?, ?, ?, NULL, ?, 1)",
+                )
+                .bind(id)
+                .bind(service.id)
+                .bind(State::Built)
+                .bind(Utc::now())
+                .bind(is_next)
+                .execute(&mut transaction)
+                .await?;
+
+                id
+            }
+        };
+
+        transaction.commit().await?;
+
+        Ok((service.id, id))
+    }
 }
 
 #[async_trait::async_trait]
@@ -474,20 +1010,13 @@ impl ActiveDeploymentsGetter for Persistence {
     async fn get_active_deployments(
         &self,
         service_id: &Uuid,
-    ) -> std::result::Result<Vec<Uuid>, Self::Err> {
-        let ids: Vec<_> = sqlx::query_as::<_, Deployment>(
-            "SELECT * FROM deployments WHERE service_id = ? AND state = ?",
-        )
-        .bind(service_id)
-        .bind(State::Running)
-        .fetch_all(&self.pool)
-        .await
-        .map_err(Error::from)?
-        .into_iter()
-        .map(|deployment| deployment.id)
-        .collect();
-
-        Ok(ids)
+    ) -> std::result::Result<Vec<Deployment>, Self::Err> {
+        sqlx::query_as("SELECT * FROM deployments WHERE service_id = ? AND state = ?")
+            .bind(service_id)
+            .bind(State::Running)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(Error::from)
     }
 }
 
@@ -498,6 +1027,8 @@ mod tests {
     use chrono::{Duration, TimeZone, Utc};
     use rand::Rng;
     use serde_json::json;
+    use shuttle_common::log::DrainTarget;
+    use sqlx::Executor;
 
     use super::*;
     use crate::persistence::{
@@ -770,12 +1301,14 @@ mod tests {
 
         let bar_id = add_service_named(&p.pool, "bar").await.unwrap();
         let foo_id = add_service_named(&p.pool, "foo").await.unwrap();
+        let baz_id = add_service_named(&p.pool, "baz").await.unwrap();
         let service_id = add_service(&p.pool).await.unwrap();
         let service_id2 = add_service(&p.pool).await.unwrap();
 
         let id_1 = Uuid::new_v4();
         let id_2 = Uuid::new_v4();
         let id_3 = Uuid::new_v4();
+        let id_4 = Uuid::new_v4();
 
         for deployment in [
             Deployment {
@@ -818,6 +1351,14 @@ mod tests {
                 address: None,
                 is_next: false,
             },
+            Deployment {
+                id: id_4,
+                service_id: baz_id,
+                state: State::Restarting,
+                last_update: Utc.with_ymd_and_hms(2022, 4, 25, 4, 45, 10).unwrap(),
+                address: None,
+                is_next: false,
+            },
         ] {
             p.insert_deployment(deployment).await.unwrap();
         }
@@ -831,42 +1372,115 @@ mod tests {
                     service_name: "foo".to_string(),
                     service_id: foo_id,
                     is_next: false,
+                    health_check: None,
+                    readiness: None,
+                    restart_policy: None,
+                    next: None,
+                    secrets_schema: None,
+                    restart_count: 0,
                 },
                 DeploymentRunnable {
                     id: id_2,
                     service_name: "bar".to_string(),
                     service_id: bar_id,
                     is_next: true,
+                    health_check: None,
+                    readiness: None,
+                    restart_policy: None,
+                    next: None,
+                    secrets_schema: None,
+                    restart_count: 0,
                 },
                 DeploymentRunnable {
                     id: id_3,
                     service_name: "foo".to_string(),
                     service_id: foo_id,
                     is_next: false,
+                    health_check: None,
+                    readiness: None,
+                    restart_policy: None,
+                    next: None,
+                    secrets_schema: None,
+                    restart_count: 0,
+                },
+                DeploymentRunnable {
+                    id: id_4,
+                    service_name: "baz".to_string(),
+                    service_id: baz_id,
+                    is_next: false,
+                    health_check: None,
+                    readiness: None,
+                    restart_policy: None,
+                    next: None,
+                    secrets_schema: None,
+                    restart_count: 0,
                 },
             ]
         );
     }
 
     #[tokio::test(flavor = "multi_thread")]
-    async fn log_insert() {
+    async fn fetching_runnable_deployment() {
         let (p, _) = Persistence::new_in_memory().await;
-        let deployment_id = add_deployment(&p.pool).await.unwrap();
 
-        let log = Log {
-            id: deployment_id,
-            timestamp: Utc::now(),
-            state: State::Queued,
-            level: Level::Info,
-            file: Some("queue.rs".to_string()),
-            line: Some(12),
+        let foo_id = add_service_named(&p.pool, "foo").await.unwrap();
+        let id = Uuid::new_v4();
+
+        p.insert_deployment(Deployment {
+            id,
+            service_id: foo_id,
+            state: State::Stopped,
+            last_update: Utc.with_ymd_and_hms(2022, 4, 25, 4, 29, 44).unwrap(),
+            address: None,
+            is_next: true,
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            p.get_runnable_deployment(&id).await.unwrap(),
+            Some(DeploymentRunnable {
+                id,
+                service_name: "foo".to_string(),
+                service_id: foo_id,
+                is_next: true,
+                health_check: None,
+                readiness: None,
+                restart_policy: None,
+                next: None,
+                secrets_schema: None,
+                restart_count: 0,
+            })
+        );
+        assert!(p
+            .get_runnable_deployment(&Uuid::new_v4())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn log_insert() {
+        let (p, _) = Persistence::new_in_memory().await;
+        let deployment_id = add_deployment(&p.pool).await.unwrap();
+
+        let log = Log {
+            id: deployment_id,
+            timestamp: Utc::now(),
+            state: State::Queued,
+            level: Level::Info,
+            file: Some("queue.rs".to_string()),
+            line: Some(12),
             target: "tests::log_insert".to_string(),
             fields: json!({"message": "job queued"}),
         };
 
         insert_log(&p.pool, log.clone()).await.unwrap();
 
-        let logs = p.get_deployment_logs(&deployment_id).await.unwrap();
+        let logs = p
+            .get_deployment_logs(&deployment_id, &Default::default())
+            .await
+            .unwrap();
         assert!(!logs.is_empty(), "there should be one log");
 
         assert_eq!(logs.first().unwrap(), &log);
@@ -913,12 +1527,517 @@ mod tests {
             insert_log(&p.pool, log).await.unwrap();
         }
 
-        let logs = p.get_deployment_logs(&deployment_a).await.unwrap();
+        let logs = p
+            .get_deployment_logs(&deployment_a, &Default::default())
+            .await
+            .unwrap();
         assert!(!logs.is_empty(), "there should be two logs");
 
         assert_eq!(logs, vec![log_a1, log_a2]);
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn logs_filtered() {
+        let (p, _) = Persistence::new_in_memory().await;
+        let id = add_deployment(&p.pool).await.unwrap();
+        let now = Utc::now();
+
+        let log = |minutes_ago, level, target: &str, message: &str| Log {
+            id,
+            timestamp: now - Duration::minutes(minutes_ago),
+            state: State::Running,
+            level,
+            file: None,
+            line: None,
+            target: target.to_string(),
+            fields: json!({ "message": message }),
+        };
+
+        let old_info = log(90, Level::Info, "bot::commands", "ping from user 42");
+        let warn = log(30, Level::Warn, "serenity::gateway", "heartbeat ack late");
+        let error = log(
+            10,
+            Level::Error,
+            "bot::commands",
+            "failed to reply to user 7",
+        );
+
+        for log in [old_info.clone(), warn.clone(), error.clone()] {
+            insert_log(&p.pool, log).await.unwrap();
+        }
+
+        let get = |query: shuttle_common::log::Query| {
+            let p = p.clone();
+
+            async move {
+                p.get_deployment_logs(&id, &query.try_into().unwrap())
+                    .await
+                    .unwrap()
+            }
+        };
+
+        assert_eq!(
+            get(shuttle_common::log::Query {
+                level: Some(shuttle_common::log::Level::Warn),
+                ..Default::default()
+            })
+            .await,
+            vec![warn.clone(), error.clone()]
+        );
+        assert_eq!(
+            get(shuttle_common::log::Query {
+                target: Some("bot::".to_string()),
+                ..Default::default()
+            })
+            .await,
+            vec![old_info.clone(), error.clone()]
+        );
+        assert_eq!(
+            get(shuttle_common::log::Query {
+                since: Some(now - Duration::hours(1)),
+                until: Some(now - Duration::minutes(20)),
+                ..Default::default()
+            })
+            .await,
+            vec![warn.clone()]
+        );
+        assert_eq!(
+            get(shuttle_common::log::Query {
+                search: Some("user".to_string()),
+                ..Default::default()
+            })
+            .await,
+            vec![old_info.clone(), error.clone()]
+        );
+        assert_eq!(
+            get(shuttle_common::log::Query {
+                search: Some(r"user \d{2,}".to_string()),
+                regex: true,
+                ..Default::default()
+            })
+            .await,
+            vec![old_info.clone()]
+        );
+
+        let invalid: std::result::Result<LogFilter, _> = shuttle_common::log::Query {
+            search: Some("(unclosed".to_string()),
+            regex: true,
+            ..Default::default()
+        }
+        .try_into();
+        assert!(invalid.is_err(), "invalid regexes should be rejected");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn logs_paged() {
+        let (p, _) = Persistence::new_in_memory().await;
+        let id = add_deployment(&p.pool).await.unwrap();
+        let start = Utc::now();
+
+        // The second and third logs share a timestamp, and a page ends between them
+        let logs: Vec<_> = [0, 1, 1, 2, 3]
+            .into_iter()
+            .enumerate()
+            .map(|(i, seconds)| Log {
+                id,
+                timestamp: start + Duration::seconds(seconds),
+                state: State::Running,
+                level: Level::Info,
+                file: None,
+                line: None,
+                target: String::new(),
+                fields: json!({ "message": format!("line {i}") }),
+            })
+            .collect();
+
+        for log in logs.iter().cloned() {
+            insert_log(&p.pool, log).await.unwrap();
+        }
+
+        let filter = LogFilter::default();
+
+        let (page, next) = p
+            .get_deployment_logs_page(&id, &filter, None, 2)
+            .await
+            .unwrap();
+        assert_eq!(page, logs[0..2]);
+        assert_eq!(next.unwrap().timestamp, logs[1].timestamp);
+
+        let (page, next) = p
+            .get_deployment_logs_page(&id, &filter, next, 2)
+            .await
+            .unwrap();
+        assert_eq!(page, logs[2..4]);
+
+        let (page, next) = p
+            .get_deployment_logs_page(&id, &filter, next, 2)
+            .await
+            .unwrap();
+        assert_eq!(page, logs[4..]);
+        assert_eq!(next, None, "there should be no page after the last one");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn cron_runs() {
+        let (p, _) = Persistence::new_in_memory().await;
+        let id = add_deployment(&p.pool).await.unwrap();
+        let start = Utc.with_ymd_and_hms(2023, 4, 1, 9, 0, 0).unwrap();
+
+        assert_eq!(p.get_cron_runs(&id).await.unwrap(), (None, None));
+
+        let log = |minutes, fields| deploy_layer::Log {
+            id,
+            state: State::Running,
+            level: Level::Info,
+            timestamp: start + Duration::minutes(minutes),
+            file: None,
+            line: None,
+            target: CRON_LOG_TARGET.to_string(),
+            fields,
+            r#type: LogType::Event,
+        };
+
+        for log in [
+            log(0, json!({"cron": "started", "message": "run started"})),
+            log(
+                1,
+                json!({"cron": "scheduled", "next_run": "2023-04-01T10:00:00Z"}),
+            ),
+            log(60, json!({"cron": "started", "message": "run started"})),
+            log(
+                61,
+                json!({"cron": "scheduled", "next_run": "2023-04-01T11:00:00Z"}),
+            ),
+            log(62, json!({"cron": "finished", "outcome": "success"})),
+            log(63, json!({"cron": "scheduled", "next_run": "not a time"})),
+        ] {
+            insert_log(&p.pool, log.clone()).await.unwrap();
+            record_cron_run(&p.pool, &log).await.unwrap();
+        }
+
+        let runs = (
+            Some(start + Duration::minutes(60)),
+            Some(Utc.with_ymd_and_hms(2023, 4, 1, 11, 0, 0).unwrap()),
+        );
+        assert_eq!(p.get_cron_runs(&id).await.unwrap(), runs);
+
+        // Log retention does not make the runs go away
+        p.enforce_log_retention(&Retention {
+            max_age_hours: Some(1),
+            max_lines: None,
+        })
+        .await
+        .unwrap();
+        assert!(p
+            .get_deployment_logs(&id, &Default::default())
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(p.get_cron_runs(&id).await.unwrap(), runs);
+
+        sqlx::query("UPDATE deployments SET state = ? WHERE id = ?")
+            .bind(State::Stopped)
+            .bind(id)
+            .execute(&p.pool)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            p.get_cron_runs(&id).await.unwrap(),
+            (Some(start + Duration::minutes(60)), None),
+            "a stopped deployment has no next run"
+        );
+
+        record_cron_run(&p.pool, &log(120, json!({"cron": "exhausted"})))
+            .await
+            .unwrap();
+        let (_, next_run): (Option<DateTime<Utc>>, Option<DateTime<Utc>>) =
+            sqlx::query_as("SELECT last_run, next_run FROM cron_runs WHERE id = ?")
+                .bind(id)
+                .fetch_one(&p.pool)
+                .await
+                .unwrap();
+        assert_eq!(next_run, None, "an exhausted schedule has no next run");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn cron_runs_backfill() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        let (before, backfill): (Vec<_>, Vec<_>) = MIGRATIONS
+            .iter()
+            .partition(|migration| migration.version < 15);
+
+        for migration in before {
+            pool.execute(&*migration.sql).await.unwrap();
+        }
+
+        let id = add_deployment(&pool).await.unwrap();
+        let started = Utc.with_ymd_and_hms(2023, 4, 1, 9, 0, 0).unwrap();
+
+        for (timestamp, fields) in [
+            (
+                started,
+                json!({"cron": "started", "message": "run started"}),
+            ),
+            (
+                started + Duration::minutes(1),
+                json!({"cron": "scheduled", "next_run": "2023-04-01T10:00:00Z"}),
+            ),
+        ] {
+            let log = deploy_layer::Log {
+                id,
+                state: State::Running,
+                level: Level::Info,
+                timestamp,
+                file: None,
+                line: None,
+                target: CRON_LOG_TARGET.to_string(),
+                fields,
+                r#type: LogType::Event,
+            };
+            insert_log(&pool, log).await.unwrap();
+        }
+
+        for migration in backfill {
+            pool.execute(&*migration.sql).await.unwrap();
+        }
+
+        let runs: (Option<DateTime<Utc>>, Option<DateTime<Utc>>) =
+            sqlx::query_as("SELECT last_run, next_run FROM cron_runs WHERE id = ?")
+                .bind(id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            runs,
+            (
+                Some(started),
+                Some(Utc.with_ymd_and_hms(2023, 4, 1, 10, 0, 0).unwrap())
+            )
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn log_retention() {
+        let (p, _) = Persistence::new_in_memory().await;
+        let id_a = add_deployment(&p.pool).await.unwrap();
+        let id_b = add_deployment(&p.pool).await.unwrap();
+        let now = Utc::now();
+
+        let log = |id, hours_ago| Log {
+            id,
+            timestamp: now - Duration::hours(hours_ago),
+            state: State::Running,
+            level: Level::Info,
+            file: None,
+            line: None,
+            target: String::new(),
+            fields: json!({ "message": format!("{hours_ago} hours ago") }),
+        };
+
+        for hours_ago in [48, 12, 3, 1] {
+            insert_log(&p.pool, log(id_a, hours_ago)).await.unwrap();
+            insert_log(&p.pool, log(id_b, hours_ago)).await.unwrap();
+        }
+
+        let (service_b,): (Uuid,) =
+            sqlx::query_as("SELECT service_id FROM deployments WHERE id = ?")
+                .bind(id_b)
+                .fetch_one(&p.pool)
+                .await
+                .unwrap();
+        let retention_b = Retention {
+            max_age_hours: None,
+            max_lines: Some(1),
+        };
+        p.set_log_retention(&service_b, &retention_b).await.unwrap();
+        assert_eq!(p.get_log_retention(&service_b).await.unwrap(), retention_b);
+
+        let deleted = p
+            .enforce_log_retention(&Retention {
+                max_age_hours: Some(24),
+                max_lines: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(deleted, 4);
+
+        let logs_a = p
+            .get_deployment_logs(&id_a, &Default::default())
+            .await
+            .unwrap();
+        assert_eq!(logs_a, vec![log(id_a, 12), log(id_a, 3), log(id_a, 1)]);
+
+        let logs_b = p
+            .get_deployment_logs(&id_b, &Default::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            logs_b,
+            vec![log(id_b, 1)],
+            "the service's own line limit should apply on top of the default age"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn log_drain() {
+        let (p, _) = Persistence::new_in_memory().await;
+
+        assert_eq!(p.get_log_drain().await.unwrap(), None);
+
+        let otlp = Drain {
+            target: DrainTarget::Otlp {
+                endpoint: "http://collector:4317".to_string(),
+            },
+            build_logs: true,
+        };
+        p.set_log_drain(Some(&otlp)).await.unwrap();
+        assert_eq!(p.get_log_drain().await.unwrap(), Some(otlp));
+
+        let syslog = Drain {
+            target: DrainTarget::Syslog {
+                address: "logs.example.com:6514".to_string(),
+                tls: true,
+            },
+            build_logs: false,
+        };
+        p.set_log_drain(Some(&syslog)).await.unwrap();
+        assert_eq!(p.get_log_drain().await.unwrap(), Some(syslog));
+
+        p.set_log_drain(None).await.unwrap();
+        assert_eq!(p.get_log_drain().await.unwrap(), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn add_built_deployment() {
+        let (p, _) = Persistence::new_in_memory().await;
+
+        let (service_id, id) = p.add_built_deployment("bot", true).await.unwrap();
+        let (same_service_id, other_id) = p.add_built_deployment("bot", false).await.unwrap();
+
+        assert_eq!(service_id, same_service_id);
+        assert_ne!(id, other_id);
+        assert_eq!(
+            p.get_service_by_name("bot").await.unwrap().unwrap().id,
+            service_id
+        );
+
+        let deployment = p.get_deployment(&id).await.unwrap().unwrap();
+        assert_eq!(deployment.service_id, service_id);
+        assert_eq!(deployment.state, State::Built);
+        assert!(deployment.is_next);
+        assert_eq!(p.get_deployments(&service_id).await.unwrap().len(), 2);
+
+        let health_check: HealthCheck =
+            serde_json::from_value(json!({ "path": "/health", "timeout": 10 })).unwrap();
+        p.set_health_check(&id, Some(&health_check)).await.unwrap();
+
+        let runnable = p.get_runnable_deployment(&id).await.unwrap().unwrap();
+        assert_eq!(runnable.health_check, Some(Json(health_check)));
+        assert_eq!(runnable.health_check.unwrap().interval, 30);
+        assert_eq!(runnable.readiness, None);
+        assert_eq!(runnable.restart_policy, None);
+
+        let readiness: Readiness = serde_json::from_value(json!({ "warm_up": 5 })).unwrap();
+        p.set_readiness(&id, Some(&readiness)).await.unwrap();
+
+        let runnable = p.get_runnable_deployment(&id).await.unwrap().unwrap();
+        assert_eq!(
+            runnable.readiness,
+            Some(Json(Readiness {
+                warm_up: 5,
+                ..Default::default()
+            }))
+        );
+
+        let restart_policy: RestartPolicy =
+            serde_json::from_value(json!({ "policy": "on-failure" })).unwrap();
+        p.set_restart_policy(&id, Some(&restart_policy))
+            .await
+            .unwrap();
+
+        let runnable = p.get_runnable_deployment(&id).await.unwrap().unwrap();
+        assert_eq!(
+            runnable.restart_policy,
+            Some(Json(RestartPolicy::OnFailure { max_restarts: 5 }))
+        );
+        assert_eq!(runnable.next, None);
+
+        let next: Next = serde_json::from_value(json!({ "body_limit": 1024 })).unwrap();
+        p.set_next(&id, Some(&next)).await.unwrap();
+
+        let runnable = p.get_runnable_deployment(&id).await.unwrap().unwrap();
+        assert_eq!(runnable.next, Some(Json(Next { body_limit: 1024 })));
+        assert_eq!(runnable.restart_count, 0);
+
+        p.set_restart_count(&id, 3).await.unwrap();
+
+        let runnable = p.get_runnable_deployment(&id).await.unwrap().unwrap();
+        assert_eq!(runnable.restart_count, 3);
+
+        // Once it is done, the deployment is reused by the next deploy of the workspace
+        update_deployment(
+            &p.pool,
+            DeploymentState {
+                id,
+                state: State::Stopped,
+                last_update: Utc::now(),
+            },
+        )
+        .await
+        .unwrap();
+        insert_log(
+            &p.pool,
+            Log {
+                id,
+                timestamp: Utc::now(),
+                state: State::Stopped,
+                level: Level::Info,
+                file: None,
+                line: None,
+                target: String::new(),
+                fields: json!({ "message": "stopped" }),
+            },
+        )
+        .await
+        .unwrap();
+
+        let (_, reused_id) = p.add_built_deployment("bot", false).await.unwrap();
+        assert_eq!(reused_id, id);
+        assert_eq!(p.get_deployments(&service_id).await.unwrap().len(), 2);
+
+        let deployment = p.get_deployment(&id).await.unwrap().unwrap();
+        assert_eq!(deployment.state, State::Built);
+        assert!(!deployment.is_next);
+
+        let runnable = p.get_runnable_deployment(&id).await.unwrap().unwrap();
+        assert_eq!(runnable.health_check, None);
+        assert_eq!(runnable.next, None);
+        assert_eq!(runnable.restart_count, 0);
+        assert!(p
+            .get_deployment_logs(&id, &Default::default())
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Deployments which were deployed on their own are never reused
+        let own_service_id = add_service_named(&p.pool, "worker").await.unwrap();
+        let own_id = Uuid::new_v4();
+        p.insert_deployment(Deployment {
+            id: own_id,
+            service_id: own_service_id,
+            state: State::Stopped,
+            last_update: Utc::now(),
+            address: None,
+            is_next: false,
+        })
+        .await
+        .unwrap();
+
+        let (_, new_id) = p.add_built_deployment("worker", false).await.unwrap();
+        assert_ne!(new_id, own_id);
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn log_recorder_event() {
         let (p, handle) = Persistence::new_in_memory().await;
@@ -942,7 +2061,9 @@ mod tests {
         drop(p.log_send);
         assert!(handle.await.is_ok());
 
-        let logs = get_deployment_logs(&p.pool, &deployment_id).await.unwrap();
+        let logs = get_deployment_logs(&p.pool, &deployment_id, &Default::default())
+            .await
+            .unwrap();
 
         assert!(!logs.is_empty(), "there should be one log");
 
@@ -990,7 +2111,9 @@ mod tests {
         drop(p.log_send);
         assert!(handle.await.is_ok());
 
-        let logs = get_deployment_logs(&p.pool, &id).await.unwrap();
+        let logs = get_deployment_logs(&p.pool, &id, &Default::default())
+            .await
+            .unwrap();
 
         assert!(!logs.is_empty(), "state change should be logged");
 
@@ -1111,6 +2234,100 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn secrets_encrypted_at_rest() {
+        let (p, _) = Persistence::new_in_memory().await;
+
+        let service_id = add_service(&p.pool).await.unwrap();
+
+        p.insert_secret(&service_id, "key1", "value1")
+            .await
+            .unwrap();
+
+        let (value, data_key) = sqlx::query_as::<_, (String, Option<String>)>(
+            "SELECT value, data_key FROM secrets WHERE service_id = ?",
+        )
+        .bind(service_id)
+        .fetch_one(&p.pool)
+        .await
+        .unwrap();
+
+        assert_ne!(value, "value1");
+        assert!(data_key.is_some());
+        assert_eq!(p.get_secrets(&service_id).await.unwrap()[0].value, "value1");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn plaintext_secrets_migration() {
+        let (p, _) = Persistence::new_in_memory().await;
+
+        let service_id = add_service(&p.pool).await.unwrap();
+
+        // Insert the way secrets were stored before they were encrypted
+        sqlx::query(
+            "INSERT INTO secrets (service_id, key, value, last_update) VALUES (?, ?, ?, ?)",
+        )
+        .bind(service_id)
+        .bind("key1")
+        .bind("value1")
+        .bind(Utc::now())
+        .execute(&p.pool)
+        .await
+        .unwrap();
+
+        encrypt_plaintext_secrets(&p.pool, &p.secret_cipher)
+            .await
+            .unwrap();
+
+        let (value,) =
+            sqlx::query_as::<_, (String,)>("SELECT value FROM secrets WHERE service_id = ?")
+                .bind(service_id)
+                .fetch_one(&p.pool)
+                .await
+                .unwrap();
+
+        assert_ne!(value, "value1");
+        assert_eq!(p.get_secrets(&service_id).await.unwrap()[0].value, "value1");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn secret_removal() {
+        let (p, _) = Persistence::new_in_memory().await;
+
+        let service_id = add_service(&p.pool).await.unwrap();
+        let service_id2 = add_service(&p.pool).await.unwrap();
+
+        p.insert_secret(&service_id, "key1", "value1")
+            .await
+            .unwrap();
+        p.insert_secret(&service_id, "key2", "value2")
+            .await
+            .unwrap();
+        p.insert_secret(&service_id2, "key1", "value1")
+            .await
+            .unwrap();
+
+        p.remove_secret(&service_id, "key1").await.unwrap();
+
+        // Removing a key that does not exist is not an error
+        p.remove_secret(&service_id, "missing").await.unwrap();
+
+        let keys: Vec<_> = p
+            .get_secrets(&service_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|secret| secret.key)
+            .collect();
+        assert_eq!(keys, vec!["key2".to_string()]);
+
+        assert_eq!(
+            p.get_secrets(&service_id2).await.unwrap().len(),
+            1,
+            "other services should keep their secrets"
+        );
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn service() {
         let (p, _) = Persistence::new_in_memory().await;
@@ -1175,6 +2392,101 @@ mod tests {
         );
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tcp_address_getter() {
+        let (p, _) = Persistence::new_in_memory().await;
+        let service_id = add_service_named(&p.pool, "service-name").await.unwrap();
+        let http_id = Uuid::new_v4();
+        let tcp_id = Uuid::new_v4();
+
+        sqlx::query(
+            "INSERT INTO deployments (id, service_id, state, last_update, address) VALUES (?, ?, ?, ?, ?), (?, ?, ?, ?, ?)",
+        )
+        .bind(http_id)
+        .bind(service_id)
+        .bind(State::Running)
+        .bind(Utc::now())
+        .bind("10.0.0.5:12356")
+        .bind(tcp_id)
+        .bind(service_id)
+        .bind(State::Running)
+        .bind(Utc::now() - Duration::minutes(5))
+        .bind("10.0.0.5:9876")
+        .execute(&p.pool)
+        .await
+        .unwrap();
+
+        assert_eq!(p.get_tcp_address().await.unwrap(), None);
+
+        let tcp = Tcp { idle_timeout: 60 };
+        p.set_tcp(&tcp_id, Some(&tcp)).await.unwrap();
+
+        assert_eq!(
+            p.get_tcp_address().await.unwrap(),
+            Some((SocketAddr::from(([10, 0, 0, 5], 9876)), tcp)),
+            "only deployments with a tcp table should get raw TCP connections"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn address_getter_during_rollout() {
+        let (p, _) = Persistence::new_in_memory().await;
+        let service_id = add_service_named(&p.pool, "service-name").await.unwrap();
+        let old_id = Uuid::new_v4();
+        let new_id = Uuid::new_v4();
+
+        sqlx::query(
+            "INSERT INTO deployments (id, service_id, state, last_update, address) VALUES (?, ?, ?, ?, ?), (?, ?, ?, ?, ?)",
+        )
+        .bind(old_id)
+        .bind(service_id)
+        .bind(State::Running)
+        .bind(Utc::now() - Duration::minutes(5))
+        .bind("10.0.0.5:12356")
+        // The new deployment is still waiting to be ready
+        .bind(new_id)
+        .bind(service_id)
+        .bind(State::Running)
+        .bind(Utc::now())
+        .bind(None::<String>)
+        .execute(&p.pool)
+        .await
+        .unwrap();
+
+        assert_eq!(
+            p.get_address_for_service("service-name").await.unwrap(),
+            Some(SocketAddr::from(([10, 0, 0, 5], 12356))),
+            "old deployment should keep getting requests until the new one is ready"
+        );
+
+        p.set_address(&new_id, &SocketAddr::from(([10, 0, 0, 5], 9876)))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            p.get_address_for_service("service-name").await.unwrap(),
+            Some(SocketAddr::from(([10, 0, 0, 5], 9876))),
+            "new deployment should get the requests once it is ready"
+        );
+
+        update_deployment(
+            &p.pool,
+            DeploymentState {
+                id: old_id,
+                state: State::Loading,
+                last_update: Utc::now(),
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            p.get_deployment(&old_id).await.unwrap().unwrap().address,
+            None,
+            "a deployment which is loaded again should forget its old address"
+        );
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn active_deployment_getter() {
         let (p, _) = Persistence::new_in_memory().await;
@@ -1227,7 +2539,13 @@ mod tests {
             p.insert_deployment(deployment).await.unwrap();
         }
 
-        let actual = p.get_active_deployments(&service_id).await.unwrap();
+        let actual: Vec<_> = p
+            .get_active_deployments(&service_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|deployment| deployment.id)
+            .collect();
 
         assert_eq!(actual, vec![id_1, id_2]);
     }
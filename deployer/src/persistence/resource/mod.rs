@@ -42,6 +42,7 @@ pub enum Type {
     Secrets,
     StaticFolder,
     Persist,
+    ObjectStorage,
 }
 
 impl From<Type> for shuttle_common::resource::Type {
@@ -51,6 +52,7 @@ impl From<Type> for shuttle_common::resource::Type {
             Type::Secrets => Self::Secrets,
             Type::StaticFolder => Self::StaticFolder,
             Type::Persist => Self::Persist,
+            Type::ObjectStorage => Self::ObjectStorage,
         }
     }
 }
@@ -62,6 +64,7 @@ impl From<shuttle_common::resource::Type> for Type {
             shuttle_common::resource::Type::Secrets => Self::Secrets,
             shuttle_common::resource::Type::StaticFolder => Self::StaticFolder,
             shuttle_common::resource::Type::Persist => Self::Persist,
+            shuttle_common::resource::Type::ObjectStorage => Self::ObjectStorage,
         }
     }
 }
@@ -73,6 +76,7 @@ impl Display for Type {
             Type::Secrets => write!(f, "secrets"),
             Type::StaticFolder => write!(f, "static_folder"),
             Type::Persist => write!(f, "persist"),
+            Type::ObjectStorage => write!(f, "object_storage"),
         }
     }
 }
@@ -91,6 +95,7 @@ impl FromStr for Type {
                 "secrets" => Ok(Self::Secrets),
                 "static_folder" => Ok(Self::StaticFolder),
                 "persist" => Ok(Self::Persist),
+                "object_storage" => Ok(Self::ObjectStorage),
                 _ => Err(format!("'{s}' is an unknown resource type")),
             }
         }
@@ -139,6 +144,7 @@ mod tests {
             Type::Secrets,
             Type::StaticFolder,
             Type::Persist,
+            Type::ObjectStorage,
         ];
 
         for input in inputs {
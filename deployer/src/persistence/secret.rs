@@ -1,6 +1,8 @@
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
+use super::cipher::{self, Sealed, SecretCipher};
+
 #[async_trait::async_trait]
 /// Record a secret value for a service with name
 pub trait SecretRecorder: Clone + Send + Sync + 'static {
@@ -12,6 +14,8 @@ pub trait SecretRecorder: Clone + Send + Sync + 'static {
         key: &str,
         value: &str,
     ) -> Result<(), Self::Err>;
+
+    async fn remove_secret(&self, service_id: &Uuid, key: &str) -> Result<(), Self::Err>;
 }
 
 #[async_trait::async_trait]
@@ -30,6 +34,36 @@ pub struct Secret {
     pub last_update: DateTime<Utc>,
 }
 
+/// A secret as it is stored in the database
+#[derive(sqlx::FromRow, Debug)]
+pub(super) struct SealedSecret {
+    pub service_id: Uuid,
+    pub key: String,
+    pub value: String,
+    pub data_key: Option<String>,
+    pub last_update: DateTime<Utc>,
+}
+
+impl SealedSecret {
+    pub fn open(self, secret_cipher: &SecretCipher) -> Result<Secret, cipher::Error> {
+        let value = match self.data_key {
+            Some(data_key) => secret_cipher.open(&Sealed {
+                data_key,
+                value: self.value,
+            })?,
+            // Only possible for secrets stored before encryption that have not been migrated yet
+            None => self.value,
+        };
+
+        Ok(Secret {
+            service_id: self.service_id,
+            key: self.key,
+            value,
+            last_update: self.last_update,
+        })
+    }
+}
+
 impl From<Secret> for shuttle_common::models::secret::Response {
     fn from(secret: Secret) -> Self {
         Self {
@@ -1,41 +1,66 @@
 use std::{
+    collections::HashMap,
     convert::Infallible,
     net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll},
+    time::Duration,
 };
 
 use async_trait::async_trait;
 use axum::headers::HeaderMapExt;
+use bytes::Bytes;
 use fqdn::FQDN;
 use hyper::{
+    body::HttpBody,
     client::{connect::dns::GaiResolver, HttpConnector},
-    header::{HeaderValue, HOST, SERVER},
+    header::{HeaderMap, HeaderValue, HOST, SERVER},
     Body, Client, Request, Response, StatusCode,
 };
 use hyper_reverse_proxy::{ProxyError, ReverseProxy};
 use once_cell::sync::Lazy;
 use opentelemetry::global;
 use opentelemetry_http::HeaderExtractor;
-use shuttle_common::backends::headers::XShuttleProject;
-use tracing::{error, field, instrument, trace, Span};
+use shuttle_common::{
+    backends::{
+        grpc::{is_grpc_request, prepare_for_forwarding},
+        headers::XShuttleProject,
+        upgrade::{copy_bidirectional, forward_upgrade, is_upgrade_request},
+    },
+    deployment::Tcp,
+};
+use tokio::{net::TcpStream, sync::Notify};
+use tracing::{debug, error, field, instrument, trace, Span};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 static PROXY_CLIENT: Lazy<ReverseProxy<HttpConnector<GaiResolver>>> =
     Lazy::new(|| ReverseProxy::new(Client::new()));
+static GRPC_PROXY_CLIENT: Lazy<ReverseProxy<HttpConnector<GaiResolver>>> =
+    Lazy::new(|| ReverseProxy::new(Client::builder().http2_only(true).build_http()));
+static UPGRADE_CLIENT: Lazy<Client<HttpConnector<GaiResolver>>> = Lazy::new(Client::new);
 static SERVER_HEADER: Lazy<HeaderValue> = Lazy::new(|| "shuttle.rs".parse().unwrap());
+static IN_FLIGHT: Lazy<InFlight> = Lazy::new(Default::default);
+
+/// Path prefix to reach a service which is not the one named like the project
+const SERVICES_PATH_PREFIX: &str = "/_services/";
 
 #[instrument(name = "proxy_request", skip(address_getter), fields(http.method = %req.method(), http.uri = %req.uri(), http.status_code = field::Empty, service = field::Empty))]
 pub async fn handle(
     remote_address: SocketAddr,
     fqdn: FQDN,
-    req: Request<Body>,
+    mut req: Request<Body>,
     address_getter: impl AddressGetter,
-) -> Result<Response<Body>, Infallible> {
+    upgrade_idle_timeout: Duration,
+) -> Result<Response<ResponseBody>, Infallible> {
     let span = Span::current();
     let parent_context = global::get_text_map_propagator(|propagator| {
         propagator.extract(&HeaderExtractor(req.headers()))
     });
     span.set_parent(parent_context);
 
+    prepare_for_forwarding(&mut req);
+
     let host: FQDN = match req.headers().get(HOST) {
         Some(host) => host
             .to_str()
@@ -48,35 +73,53 @@ pub async fn handle(
             return Ok(Response::builder()
                 .status(StatusCode::BAD_REQUEST)
                 .body(Body::empty())
-                .unwrap());
+                .unwrap()
+                .map(Into::into));
         }
     };
 
-    if host != fqdn {
-        trace!(?host, "proxy won't serve foreign domain");
-        return Ok(Response::builder()
-            .status(StatusCode::BAD_REQUEST)
-            .body(Body::from("this domain is not served by proxy"))
-            .unwrap());
-    }
-
-    // We only have one service per project, and its name coincides
-    // with that of the project
-    let service = match req.headers().typed_get::<XShuttleProject>() {
+    // The service named like the project is served at the root of the project domain. Any other
+    // service from the same workspace is served under `/_services/<name>`. Paths under the prefix
+    // which name no service of the workspace are still left to the service named like the project.
+    let project = match req.headers().typed_get::<XShuttleProject>() {
         Some(project) => project.0,
         None => {
             trace!("proxy request has no X-Shuttle-Project header");
             return Ok(Response::builder()
                 .status(StatusCode::BAD_REQUEST)
                 .body(Body::from("request has no X-Shuttle-Project header"))
-                .unwrap());
+                .unwrap()
+                .map(Into::into));
         }
     };
 
+    if host != fqdn {
+        trace!(?host, "proxy won't serve foreign domain");
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from("this domain is not served by proxy"))
+            .unwrap()
+            .map(Into::into));
+    }
+
+    let original_uri = req.uri().clone();
+    let mut service = strip_service_path(&mut req).unwrap_or_else(|| project.clone());
+    let mut address = address_getter.get_address_for_service(&service).await;
+
+    if matches!(address, Ok(None)) && service != project {
+        trace!(
+            service,
+            "no service by this name, leaving the path to the project service"
+        );
+        *req.uri_mut() = original_uri;
+        service = project;
+        address = address_getter.get_address_for_service(&service).await;
+    }
+
     // Record current service for tracing purposes
     span.record("service", &service);
 
-    let proxy_address = match address_getter.get_address_for_service(&service).await {
+    let proxy_address = match address {
         Ok(Some(address)) => address,
         Ok(None) => {
             trace!(?host, service, "service not found on this server");
@@ -84,7 +127,8 @@ pub async fn handle(
             return Ok(Response::builder()
                 .status(StatusCode::NOT_FOUND)
                 .body(response_body.into())
-                .unwrap());
+                .unwrap()
+                .map(Into::into));
         }
         Err(err) => {
             error!(error = %err, service, "proxy failed to find address for host");
@@ -93,14 +137,34 @@ pub async fn handle(
             return Ok(Response::builder()
                 .status(StatusCode::INTERNAL_SERVER_ERROR)
                 .body(response_body.into())
-                .unwrap());
+                .unwrap()
+                .map(Into::into));
         }
     };
 
+    let in_flight = IN_FLIGHT.start(proxy_address);
+
+    if is_upgrade_request(&req) {
+        return Ok(upgrade_proxy(
+            remote_address.ip(),
+            proxy_address,
+            req,
+            upgrade_idle_timeout,
+            in_flight,
+        )
+        .await
+        .map(Into::into));
+    }
+
     match reverse_proxy(remote_address.ip(), &proxy_address.to_string(), req).await {
         Ok(response) => {
             Span::current().record("http.status_code", response.status().as_u16());
-            Ok(response)
+
+            // The request stays in flight until its whole response has been sent on
+            Ok(response.map(|body| ResponseBody {
+                inner: body,
+                _in_flight: Some(in_flight),
+            }))
         }
         Err(error) => {
             match error {
@@ -120,17 +184,87 @@ pub async fn handle(
             Ok(Response::builder()
                 .status(StatusCode::INTERNAL_SERVER_ERROR)
                 .body(Body::empty())
-                .unwrap())
+                .unwrap()
+                .map(Into::into))
         }
     }
 }
 
+/// Take the service out of a `/_services/<name>/rest` path, leaving only `/rest` for the service
+fn strip_service_path(req: &mut Request<Body>) -> Option<String> {
+    let path = req.uri().path().strip_prefix(SERVICES_PATH_PREFIX)?;
+    let (service, rest) = match path.find('/') {
+        Some(index) => path.split_at(index),
+        None => (path, "/"),
+    };
+
+    if service.is_empty() {
+        return None;
+    }
+
+    let path_and_query = match req.uri().query() {
+        Some(query) => format!("{rest}?{query}"),
+        None => rest.to_string(),
+    };
+    let service = service.to_string();
+
+    *req.uri_mut() = path_and_query.parse().ok()?;
+
+    Some(service)
+}
+
 #[async_trait]
 pub trait AddressGetter: Clone + Send + Sync + 'static {
     async fn get_address_for_service(
         &self,
         service_name: &str,
     ) -> crate::handlers::Result<Option<SocketAddr>>;
+
+    /// Get the address of the running deployment which accepts raw TCP connections, along with its
+    /// `[tcp]` table. Only one service of a project can accept them.
+    async fn get_tcp_address(&self) -> crate::handlers::Result<Option<(SocketAddr, Tcp)>>;
+}
+
+/// Forward a raw TCP connection to the deployment which accepts them, until either side closes it
+/// or it is idle for longer than the deployment allows
+#[instrument(name = "proxy_tcp", skip(stream, address_getter))]
+pub async fn handle_tcp(
+    remote_address: SocketAddr,
+    mut stream: TcpStream,
+    address_getter: impl AddressGetter,
+) {
+    let (address, Tcp { idle_timeout }) = match address_getter.get_tcp_address().await {
+        Ok(Some(tcp)) => tcp,
+        Ok(None) => {
+            trace!("no running deployment accepts raw TCP connections");
+            return;
+        }
+        Err(err) => {
+            error!(error = %err, "proxy failed to find address for raw TCP connection");
+            return;
+        }
+    };
+
+    let _in_flight = IN_FLIGHT.start(address);
+
+    let mut upstream = match TcpStream::connect(address).await {
+        Ok(upstream) => upstream,
+        Err(error) => {
+            error!(%error, %address, "failed to connect to deployment for raw TCP connection");
+            return;
+        }
+    };
+
+    match copy_bidirectional(
+        &mut stream,
+        &mut upstream,
+        Duration::from_secs(idle_timeout),
+    )
+    .await
+    {
+        Ok((sent, received)) => debug!(sent, received, "raw TCP connection closed"),
+        Err(error) => debug!(%error, "raw TCP connection failed"),
+    }
 }
 
 #[instrument(skip(req))]
@@ -140,9 +274,314 @@ async fn reverse_proxy(
     req: Request<Body>,
 ) -> Result<Response<Body>, ProxyError> {
     let forward_uri = format!("http://{service_address}");
-    let mut response = PROXY_CLIENT.call(remote_ip, &forward_uri, req).await?;
+
+    // gRPC services only speak HTTP/2, and the trailers of their responses are passed on as is
+    let client = if is_grpc_request(&req) {
+        &GRPC_PROXY_CLIENT
+    } else {
+        &PROXY_CLIENT
+    };
+    let mut response = client.call(remote_ip, &forward_uri, req).await?;
 
     response.headers_mut().insert(SERVER, SERVER_HEADER.clone());
 
     Ok(response)
 }
+
+/// Forward a request to upgrade its connection, such as to a WebSocket, and keep piping the
+/// upgraded connection in the background
+#[instrument(skip(req))]
+async fn upgrade_proxy(
+    remote_ip: IpAddr,
+    service_address: SocketAddr,
+    req: Request<Body>,
+    idle_timeout: Duration,
+    in_flight: InFlightGuard,
+) -> Response<Body> {
+    match forward_upgrade(&UPGRADE_CLIENT, remote_ip, service_address, req).await {
+        Ok((mut response, upgrade)) => {
+            Span::current().record("http.status_code", response.status().as_u16());
+            response.headers_mut().insert(SERVER, SERVER_HEADER.clone());
+
+            if let Some(upgrade) = upgrade {
+                tokio::spawn(async move {
+                    // The upgraded connection is in flight for as long as it is open
+                    let _in_flight = in_flight;
+
+                    match upgrade.pipe(idle_timeout).await {
+                        Ok((sent, received)) => {
+                            debug!(sent, received, "upgraded connection closed")
+                        }
+                        Err(error) => error!(%error, "upgraded connection failed"),
+                    }
+                });
+            }
+
+            response
+        }
+        Err(error) => {
+            error!(%error, "error while handling request needing upgrade in reverse proxy");
+
+            Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .body(Body::empty())
+                .unwrap()
+        }
+    }
+}
+
+/// Wait until the proxy is not forwarding anything to any of the `addresses` anymore
+pub async fn wait_until_idle(addresses: &[SocketAddr]) {
+    IN_FLIGHT.wait_until_idle(addresses).await
+}
+
+/// Counts the requests and connections the proxy is forwarding to each deployment address, so that
+/// an old deployment can be stopped as soon as it is done with everything it was sent
+#[derive(Default)]
+struct InFlight {
+    counts: Mutex<HashMap<SocketAddr, usize>>,
+    idle: Notify,
+}
+
+impl InFlight {
+    fn start(&'static self, address: SocketAddr) -> InFlightGuard {
+        *self.counts.lock().unwrap().entry(address).or_default() += 1;
+
+        InFlightGuard {
+            in_flight: self,
+            address,
+        }
+    }
+
+    fn is_idle(&self, addresses: &[SocketAddr]) -> bool {
+        let counts = self.counts.lock().unwrap();
+
+        addresses
+            .iter()
+            .all(|address| !counts.contains_key(address))
+    }
+
+    async fn wait_until_idle(&self, addresses: &[SocketAddr]) {
+        loop {
+            // Listen before checking so that a request finishing in between is not missed
+            let idle = self.idle.notified();
+
+            if self.is_idle(addresses) {
+                return;
+            }
+
+            idle.await;
+        }
+    }
+}
+
+/// A request or connection which is in flight until this is dropped
+struct InFlightGuard {
+    in_flight: &'static InFlight,
+    address: SocketAddr,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut counts = self.in_flight.counts.lock().unwrap();
+
+        if let Some(count) = counts.get_mut(&self.address) {
+            *count -= 1;
+
+            if *count == 0 {
+                counts.remove(&self.address);
+                drop(counts);
+
+                self.in_flight.idle.notify_waiters();
+            }
+        }
+    }
+}
+
+/// Body of a response from the proxy, which keeps the request it answers in flight until it is done
+pub struct ResponseBody {
+    inner: Body,
+    _in_flight: Option<InFlightGuard>,
+}
+
+impl From<Body> for ResponseBody {
+    fn from(inner: Body) -> Self {
+        Self {
+            inner,
+            _in_flight: None,
+        }
+    }
+}
+
+impl HttpBody for ResponseBody {
+    type Data = Bytes;
+    type Error = hyper::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Pin::new(&mut self.get_mut().inner).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        convert::Infallible,
+        net::{Ipv4Addr, SocketAddr},
+        sync::Arc,
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use fqdn::FQDN;
+    use hyper::{
+        header::HOST,
+        service::{make_service_fn, service_fn},
+        Body, Request, Response, Server,
+    };
+    use shuttle_common::deployment::Tcp;
+
+    use super::{handle, strip_service_path, AddressGetter, InFlight};
+
+    /// Services of a workspace, all answering with the path they were asked for
+    #[derive(Clone)]
+    struct Services(Arc<HashMap<String, SocketAddr>>);
+
+    #[async_trait]
+    impl AddressGetter for Services {
+        async fn get_address_for_service(
+            &self,
+            service_name: &str,
+        ) -> crate::handlers::Result<Option<SocketAddr>> {
+            Ok(self.0.get(service_name).copied())
+        }
+
+        async fn get_tcp_address(&self) -> crate::handlers::Result<Option<(SocketAddr, Tcp)>> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn service_path() {
+        let strip = |uri: &str| {
+            let mut req = Request::get(uri).body(Body::empty()).unwrap();
+            let service = strip_service_path(&mut req);
+
+            (service, req.uri().to_string())
+        };
+
+        assert_eq!(
+            strip("/_services/bot/commands?page=2"),
+            (Some("bot".to_string()), "/commands?page=2".to_string())
+        );
+        assert_eq!(
+            strip("/_services/bot"),
+            (Some("bot".to_string()), "/".to_string())
+        );
+        assert_eq!(strip("/_services/"), (None, "/_services/".to_string()));
+        assert_eq!(strip("/users/1"), (None, "/users/1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn service_routing() {
+        let echo = |name: &'static str| {
+            let server = Server::bind(&SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).serve(
+                make_service_fn(move |_| async move {
+                    Ok::<_, Infallible>(service_fn(move |req: Request<Body>| async move {
+                        let body = format!("{name} {}", req.uri());
+                        Ok::<_, Infallible>(Response::new(Body::from(body)))
+                    }))
+                }),
+            );
+            let address = server.local_addr();
+            tokio::spawn(server);
+
+            address
+        };
+        let services = Services(Arc::new(HashMap::from([
+            ("matrix".to_string(), echo("matrix")),
+            ("bot".to_string(), echo("bot")),
+        ])));
+        let fqdn: FQDN = "matrix.shuttleapp.rs".parse().unwrap();
+
+        let get = |uri: &'static str| {
+            let services = services.clone();
+            let fqdn = fqdn.clone();
+
+            async move {
+                let req = Request::get(uri)
+                    .header(HOST, "matrix.shuttleapp.rs")
+                    .header("X-Shuttle-Project", "matrix")
+                    .body(Body::empty())
+                    .unwrap();
+                let remote_address = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 9000);
+                let res = handle(remote_address, fqdn, req, services, Duration::from_secs(1))
+                    .await
+                    .unwrap();
+                let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+
+        assert_eq!(get("/users/1").await, "matrix /users/1");
+        assert_eq!(
+            get("/_services/bot/commands?page=2").await,
+            "bot /commands?page=2"
+        );
+        // No service of the workspace is called `assets`, so the path is the project service's own
+        assert_eq!(
+            get("/_services/assets/logo.png").await,
+            "matrix /_services/assets/logo.png"
+        );
+    }
+
+    #[tokio::test]
+    async fn in_flight() {
+        let in_flight: &'static InFlight = Box::leak(Default::default());
+        let old = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 8001);
+        let new = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 8002);
+
+        // Nothing was sent to the old deployment, so there is no need to wait for it
+        tokio::time::timeout(
+            Duration::from_millis(100),
+            in_flight.wait_until_idle(&[old]),
+        )
+        .await
+        .expect("an address without requests to be idle");
+
+        let first = in_flight.start(old);
+        let second = in_flight.start(old);
+        let _other = in_flight.start(new);
+
+        let wait = tokio::spawn(async move { in_flight.wait_until_idle(&[old]).await });
+
+        drop(first);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!wait.is_finished(), "one request is still in flight");
+
+        drop(second);
+        tokio::time::timeout(Duration::from_millis(100), wait)
+            .await
+            .expect("the old deployment to be idle once its requests are done")
+            .unwrap();
+    }
+}
@@ -61,7 +61,6 @@ impl RuntimeManager {
         trace!("making new client");
 
         let port = portpicker::pick_unused_port().context("failed to find available port")?;
-        let is_next = alpha_runtime_path.is_none();
 
         let get_runtime_executable = || {
             if let Some(alpha_runtime) = alpha_runtime_path {
@@ -108,7 +107,6 @@ impl RuntimeManager {
         };
 
         let (process, runtime_client) = runtime::start(
-            is_next,
             runtime::StorageManagerType::Artifacts(self.artifacts_path.clone()),
             &self.provisioner_address,
             self.auth_uri.as_ref(),
@@ -0,0 +1,36 @@
+use shuttle_runtime::Service;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+struct UnhealthyService;
+
+#[shuttle_runtime::main]
+async fn unhealthy() -> Result<UnhealthyService, shuttle_runtime::Error> {
+    Ok(UnhealthyService)
+}
+
+#[shuttle_runtime::async_trait]
+impl Service for UnhealthyService {
+    /// Answers that it is ready, but fails every health check
+    async fn bind(self, addr: std::net::SocketAddr) -> Result<(), shuttle_runtime::Error> {
+        let listener = TcpListener::bind(addr).await?;
+
+        loop {
+            let (mut stream, _) = listener.accept().await?;
+            let mut request = [0; 1024];
+            let read = stream.read(&mut request).await?;
+
+            let status = if request[..read].starts_with(b"GET /ready ") {
+                "200 OK"
+            } else {
+                "503 Service Unavailable"
+            };
+            let response =
+                format!("HTTP/1.1 {status}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n");
+
+            stream.write_all(response.as_bytes()).await?;
+        }
+    }
+}
@@ -42,6 +42,7 @@ shuttle-service = { path = "{}" }
 shuttle-runtime = { path = "{}" }
 
 shuttle-aws-rds = { path = "{}" }
+shuttle-object-store = { path = "{}" }
 shuttle-persist = { path = "{}" }
 shuttle-shared-db = { path = "{}" }
 shuttle-secrets = { path = "{}" }
@@ -57,11 +58,16 @@ shuttle-salvo = { path = "{}" }
 shuttle-serenity = { path = "{}" }
 shuttle-thruster = { path = "{}" }
 shuttle-tide = { path = "{}" }
+shuttle-tonic = { path = "{}" }
 shuttle-tower = { path = "{}" }
 shuttle-warp = { path = "{}" }"#,
                     WORKSPACE_ROOT.join("service").display(),
                     WORKSPACE_ROOT.join("runtime").display(),
                     WORKSPACE_ROOT.join("resources").join("aws-rds").display(),
+                    WORKSPACE_ROOT
+                        .join("resources")
+                        .join("object-store")
+                        .display(),
                     WORKSPACE_ROOT.join("resources").join("persist").display(),
                     WORKSPACE_ROOT.join("resources").join("shared-db").display(),
                     WORKSPACE_ROOT.join("resources").join("secrets").display(),
@@ -109,6 +115,10 @@ shuttle-warp = { path = "{}" }"#,
                         .join("services")
                         .join("shuttle-tide")
                         .display(),
+                    WORKSPACE_ROOT
+                        .join("services")
+                        .join("shuttle-tonic")
+                        .display(),
                     WORKSPACE_ROOT
                         .join("services")
                         .join("shuttle-tower")
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS tcp_ports (
+  project_name TEXT PRIMARY KEY REFERENCES projects (project_name),
+  port INTEGER UNIQUE NOT NULL
+);
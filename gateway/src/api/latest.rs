@@ -1,28 +1,28 @@
 use std::io::Cursor;
 use std::net::SocketAddr;
-use std::ops::Sub;
 use std::sync::Arc;
 use std::time::Duration;
 
 use axum::body::Body;
-use axum::extract::{Extension, Path, State};
+use axum::extract::{Extension, Path, Query, State};
 use axum::handler::Handler;
 use axum::http::Request;
 use axum::middleware::from_extractor;
-use axum::response::Response;
+use axum::response::{IntoResponse, Response};
 use axum::routing::{any, get, post};
 use axum::{Json as AxumJson, Router};
-use fqdn::FQDN;
+use fqdn::{Fqdn, FQDN};
 use futures::Future;
+use http::header::CONTENT_TYPE;
 use http::{StatusCode, Uri};
-use instant_acme::{AccountCredentials, ChallengeType};
+use instant_acme::AccountCredentials;
 use serde::{Deserialize, Serialize};
 use shuttle_common::backends::auth::{AuthPublicKey, JwtAuthenticationLayer, ScopedLayer};
 use shuttle_common::backends::cache::CacheManager;
 use shuttle_common::backends::metrics::{Metrics, TraceLayer};
 use shuttle_common::claims::{Scope, EXP_MINUTES};
 use shuttle_common::models::error::ErrorKind;
-use shuttle_common::models::{project, stats};
+use shuttle_common::models::{domain, project, stats, tcp};
 use shuttle_common::request_span;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::{Mutex, MutexGuard};
@@ -33,17 +33,15 @@ use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
 use utoipa::{Modify, OpenApi};
 use utoipa_swagger_ui::SwaggerUi;
 use uuid::Uuid;
-use x509_parser::nom::AsBytes;
-use x509_parser::parse_x509_certificate;
-use x509_parser::pem::parse_x509_pem;
-use x509_parser::time::ASN1Time;
 
 use crate::acme::{AcmeClient, CustomDomain};
 use crate::auth::{ScopedUser, User};
 use crate::project::{ContainerInspectResponseExt, Project, ProjectCreating};
+use crate::renewal::RenewalWindow;
 use crate::service::GatewayService;
 use crate::task::{self, BoxedTask, TaskResult};
-use crate::tls::{GatewayCertResolver, RENEWAL_VALIDITY_THRESHOLD_IN_DAYS};
+use crate::tcp::TcpProxy;
+use crate::tls::{certificate_expiry, remaining_validity, GatewayCertResolver};
 use crate::worker::WORKER_QUEUE_SIZE;
 use crate::{Error, ProjectName};
 
@@ -62,26 +60,37 @@ pub enum GatewayStatus {
 #[derive(Serialize, Deserialize)]
 pub struct StatusResponse {
     status: GatewayStatus,
+    /// Domains whose certificates failed to renew on the last attempt
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    certificate_renewal_failures: Vec<String>,
 }
 
 impl StatusResponse {
     pub fn healthy() -> Self {
         Self {
             status: GatewayStatus::Healthy,
+            certificate_renewal_failures: Vec::new(),
         }
     }
 
     pub fn degraded() -> Self {
         Self {
             status: GatewayStatus::Degraded,
+            certificate_renewal_failures: Vec::new(),
         }
     }
 
     pub fn unhealthy() -> Self {
         Self {
             status: GatewayStatus::Unhealthy,
+            certificate_renewal_failures: Vec::new(),
         }
     }
+
+    pub fn with_certificate_renewal_failures(mut self, domains: Vec<String>) -> Self {
+        self.certificate_renewal_failures = domains;
+        self
+    }
 }
 
 #[instrument(skip(service))]
@@ -156,7 +165,13 @@ async fn create_project(
     let is_admin = claim.scopes.contains(&Scope::Admin);
 
     let state = service
-        .create_project(project.clone(), name.clone(), is_admin, config.idle_minutes)
+        .create_project(
+            project.clone(),
+            name.clone(),
+            is_admin,
+            config.idle_minutes,
+            config.limits,
+        )
         .await?;
 
     service
@@ -239,17 +254,26 @@ async fn route_project(
         (status = 500, description = "Server internal error.")
     )
 )]
-async fn get_status(State(RouterState { sender, .. }): State<RouterState>) -> Response<Body> {
+async fn get_status(
+    State(RouterState {
+        sender, service, ..
+    }): State<RouterState>,
+) -> Response<Body> {
+    let renewal_failures = service.renewal_failures().domains();
+
     let (status, body) = if sender.is_closed() || sender.capacity() == 0 {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             StatusResponse::unhealthy(),
         )
-    } else if sender.capacity() < WORKER_QUEUE_SIZE - SVC_DEGRADED_THRESHOLD {
+    } else if sender.capacity() < WORKER_QUEUE_SIZE - SVC_DEGRADED_THRESHOLD
+        || !renewal_failures.is_empty()
+    {
         (StatusCode::OK, StatusResponse::degraded())
     } else {
         (StatusCode::OK, StatusResponse::healthy())
     };
+    let body = body.with_certificate_renewal_failures(renewal_failures);
 
     let body = serde_json::to_vec(&body).unwrap();
     Response::builder()
@@ -258,6 +282,123 @@ async fn get_status(State(RouterState { sender, .. }): State<RouterState>) -> Re
         .unwrap()
 }
 
+#[instrument(skip_all)]
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Successfully rendered the proxy metrics in the Prometheus text format.", body = String, content_type = "text/plain"),
+        (status = 500, description = "Server internal error.")
+    )
+)]
+async fn get_metrics(State(RouterState { service, .. }): State<RouterState>) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        service.proxy_metrics().render(),
+    )
+}
+
+#[instrument(skip_all, fields(%project_name))]
+#[utoipa::path(
+    get,
+    path = "/traffic/{project_name}",
+    responses(
+        (status = 200, description = "Successfully got the traffic of the project.", body = shuttle_common::models::stats::TrafficResponse),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+        ("period" = Option<shuttle_common::models::stats::TrafficPeriod>, Query, description = "How far back to look, either `hour` (the default) or `day`.")
+    )
+)]
+async fn get_project_traffic(
+    State(RouterState { service, .. }): State<RouterState>,
+    ScopedUser {
+        scope: project_name,
+        ..
+    }: ScopedUser,
+    Query(stats::TrafficQuery { period }): Query<stats::TrafficQuery>,
+) -> Result<AxumJson<stats::TrafficResponse>, Error> {
+    // Make sure the project exists, so its traffic is not mistaken for being empty
+    service.find_project(&project_name).await?;
+
+    Ok(AxumJson(
+        service.proxy_metrics().traffic(&project_name, period),
+    ))
+}
+
+#[instrument(skip_all, fields(%project_name))]
+#[utoipa::path(
+    get,
+    path = "/tcp/{project_name}",
+    responses(
+        (status = 200, description = "Successfully got the public TCP port of the project.", body = shuttle_common::models::tcp::Response),
+        (status = 404, description = "The project has no public TCP port."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn get_tcp_port(
+    Extension(tcp_proxy): Extension<TcpProxy>,
+    ScopedUser {
+        scope: project_name,
+        ..
+    }: ScopedUser,
+) -> Result<AxumJson<tcp::Response>, Error> {
+    Ok(AxumJson(tcp_proxy.get(&project_name).await?))
+}
+
+#[instrument(skip_all, fields(%project_name))]
+#[utoipa::path(
+    post,
+    path = "/tcp/{project_name}",
+    responses(
+        (status = 200, description = "Successfully allocated a public TCP port to the project, or got the one it already has.", body = shuttle_common::models::tcp::Response),
+        (status = 503, description = "No public TCP ports are left."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn open_tcp_port(
+    State(RouterState { service, .. }): State<RouterState>,
+    Extension(tcp_proxy): Extension<TcpProxy>,
+    ScopedUser {
+        scope: project_name,
+        ..
+    }: ScopedUser,
+) -> Result<AxumJson<tcp::Response>, Error> {
+    service.find_project(&project_name).await?;
+
+    Ok(AxumJson(tcp_proxy.open(&project_name).await?))
+}
+
+#[instrument(skip_all, fields(%project_name))]
+#[utoipa::path(
+    delete,
+    path = "/tcp/{project_name}",
+    responses(
+        (status = 200, description = "Successfully closed the public TCP port of the project.", body = shuttle_common::models::tcp::Response),
+        (status = 404, description = "The project has no public TCP port."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn close_tcp_port(
+    Extension(tcp_proxy): Extension<TcpProxy>,
+    ScopedUser {
+        scope: project_name,
+        ..
+    }: ScopedUser,
+) -> Result<AxumJson<tcp::Response>, Error> {
+    Ok(AxumJson(tcp_proxy.close(&project_name).await?))
+}
+
 #[instrument(skip_all)]
 #[utoipa::path(
     post,
@@ -451,38 +592,17 @@ async fn request_custom_domain_acme_certificate(
         .create_custom_domain_certificate(&fqdn, &acme_client, &project_name, credentials)
         .await?;
 
-    let project = service.find_project(&project_name).await?;
-    let idle_minutes = project.container().unwrap().idle_minutes();
-
-    // Destroy and recreate the project with the new domain.
-    service
-        .new_task()
-        .project(project_name.clone())
-        .and_then(task::destroy())
-        .and_then(task::run_until_done())
-        .and_then(task::run({
-            let fqdn = fqdn.to_string();
-            move |ctx| {
-                let fqdn = fqdn.clone();
-                async move {
-                    let creating = ProjectCreating::new_with_random_initial_key(
-                        ctx.project_name,
-                        idle_minutes,
-                    )
-                    .with_fqdn(fqdn);
-                    TaskResult::Done(Project::Creating(creating))
-                }
-            }
-        }))
-        .send(&sender)
-        .await?;
+    attach_custom_domain(
+        &service,
+        &sender,
+        &resolver,
+        &project_name,
+        &fqdn,
+        &certs,
+        &private_key,
+    )
+    .await?;
 
-    let mut buf = Vec::new();
-    buf.extend(certs.as_bytes());
-    buf.extend(private_key.as_bytes());
-    resolver
-        .serve_pem(&fqdn.to_string(), Cursor::new(buf))
-        .await?;
     Ok(format!(
         r#""New certificate created for {} project.""#,
         project_name
@@ -506,65 +626,24 @@ async fn renew_custom_domain_acme_certificate(
     State(RouterState { service, .. }): State<RouterState>,
     Extension(acme_client): Extension<AcmeClient>,
     Extension(resolver): Extension<Arc<GatewayCertResolver>>,
+    Extension(renewal_window): Extension<RenewalWindow>,
     Path((project_name, fqdn)): Path<(ProjectName, String)>,
     AxumJson(credentials): AxumJson<AccountCredentials<'_>>,
 ) -> Result<String, Error> {
     let fqdn: FQDN = fqdn
         .parse()
         .map_err(|_err| Error::from(ErrorKind::InvalidCustomDomain))?;
-    // Try retrieve the current certificate if any.
-    match service.project_details_for_custom_domain(&fqdn).await {
-        Ok(CustomDomain { certificate, .. }) => {
-            let (_, pem) = parse_x509_pem(certificate.as_bytes()).unwrap_or_else(|_| {
-                panic!(
-                    "Malformed existing PEM certificate for {} project.",
-                    project_name
-                )
-            });
-            let (_, x509_cert_chain) = parse_x509_certificate(pem.contents.as_bytes())
-                .unwrap_or_else(|_| {
-                    panic!(
-                        "Malformed existing X509 certificate for {} project.",
-                        project_name
-                    )
-                });
-            let diff = x509_cert_chain
-                .validity()
-                .not_after
-                .sub(ASN1Time::now())
-                .unwrap();
-
-            // If current certificate validity less_or_eq than 30 days, attempt renewal.
-            if diff.whole_days() <= RENEWAL_VALIDITY_THRESHOLD_IN_DAYS {
-                return match acme_client
-                    .create_certificate(&fqdn.to_string(), ChallengeType::Http01, credentials)
-                    .await
-                {
-                    // If successfuly created, save the certificate in memory to be
-                    // served in the future.
-                    Ok((certs, private_key)) => {
-                        let mut buf = Vec::new();
-                        buf.extend(certs.as_bytes());
-                        buf.extend(private_key.as_bytes());
-                        resolver
-                            .serve_pem(&fqdn.to_string(), Cursor::new(buf))
-                            .await?;
-                        Ok(format!(
-                            r#""Certificate renewed for {} project.""#,
-                            project_name
-                        ))
-                    }
-                    Err(err) => Err(err.into()),
-                };
-            } else {
-                Ok(format!(
-                    r#""Certificate renewal skipped, {} project certificate still valid for {} days.""#,
-                    project_name, diff
-                ))
-            }
-        }
-        Err(err) => Err(err),
-    }
+
+    renew_custom_domain_certificate(
+        &service,
+        &acme_client,
+        &resolver,
+        renewal_window,
+        &project_name,
+        &fqdn,
+        credentials,
+    )
+    .await
 }
 
 #[instrument(skip_all)]
@@ -580,14 +659,331 @@ async fn renew_gateway_acme_certificate(
     State(RouterState { service, .. }): State<RouterState>,
     Extension(acme_client): Extension<AcmeClient>,
     Extension(resolver): Extension<Arc<GatewayCertResolver>>,
+    Extension(renewal_window): Extension<RenewalWindow>,
     AxumJson(credentials): AxumJson<AccountCredentials<'_>>,
 ) -> Result<String, Error> {
     service
-        .renew_certificate(&acme_client, resolver, credentials)
-        .await;
+        .renew_certificate(&acme_client, resolver, renewal_window, credentials)
+        .await?;
     Ok(r#""Renewed the gateway certificate.""#.to_string())
 }
 
+#[instrument(skip_all, fields(%project_name))]
+#[utoipa::path(
+    get,
+    path = "/domains/{project_name}",
+    responses(
+        (status = 200, description = "Successfully got the custom domains of the project.", body = [shuttle_common::models::domain::Response]),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn get_custom_domains(
+    State(RouterState { service, .. }): State<RouterState>,
+    ScopedUser {
+        scope: project_name,
+        ..
+    }: ScopedUser,
+) -> Result<AxumJson<Vec<domain::Response>>, Error> {
+    let domains = match service.find_custom_domain_for_project(&project_name).await {
+        Ok(custom_domain) => vec![custom_domain_response(custom_domain)?],
+        Err(err) if err.kind() == ErrorKind::CustomDomainNotFound => Vec::new(),
+        Err(err) => return Err(err),
+    };
+
+    Ok(AxumJson(domains))
+}
+
+#[instrument(skip_all, fields(%project_name, %fqdn))]
+#[utoipa::path(
+    post,
+    path = "/domains/{project_name}/{fqdn}",
+    responses(
+        (status = 200, description = "Successfully added the custom domain to the project.", body = shuttle_common::models::domain::Response),
+        (status = 400, description = "The custom domain is invalid, in use or does not point to the project."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+        ("fqdn" = String, Path, description = "The fqdn of the custom domain."),
+    )
+)]
+async fn add_custom_domain(
+    State(RouterState {
+        service, sender, ..
+    }): State<RouterState>,
+    Extension(acme_client): Extension<AcmeClient>,
+    Extension(resolver): Extension<Arc<GatewayCertResolver>>,
+    ScopedUser {
+        scope: project_name,
+        ..
+    }: ScopedUser,
+    Path((_, fqdn)): Path<(String, String)>,
+) -> Result<AxumJson<domain::Response>, Error> {
+    let fqdn: FQDN = fqdn
+        .parse()
+        .map_err(|_err| Error::from(ErrorKind::InvalidCustomDomain))?;
+
+    match service.project_details_for_custom_domain(&fqdn).await {
+        Ok(custom_domain) if custom_domain.project_name == project_name => {
+            return Ok(AxumJson(custom_domain_response(custom_domain)?));
+        }
+        Ok(_) => return Err(Error::from(ErrorKind::CustomDomainAlreadyExists)),
+        Err(err) if err.kind() == ErrorKind::CustomDomainNotFound => {}
+        Err(err) => return Err(err),
+    }
+
+    // A project can only be reached on one custom domain at a time
+    match service.find_custom_domain_for_project(&project_name).await {
+        Ok(CustomDomain { fqdn: existing, .. }) => {
+            return Err(Error::custom(
+                ErrorKind::InvalidOperation,
+                format!("project is already using the custom domain {existing}"),
+            ));
+        }
+        Err(err) if err.kind() == ErrorKind::CustomDomainNotFound => {}
+        Err(err) => return Err(err),
+    }
+
+    service
+        .verify_custom_domain_target(&project_name, &fqdn)
+        .await?;
+
+    let (certs, private_key) = service
+        .create_custom_domain_certificate(
+            &fqdn,
+            &acme_client,
+            &project_name,
+            service.try_credentials()?,
+        )
+        .await?;
+
+    attach_custom_domain(
+        &service,
+        &sender,
+        &resolver,
+        &project_name,
+        &fqdn,
+        &certs,
+        &private_key,
+    )
+    .await?;
+
+    Ok(AxumJson(domain::Response {
+        fqdn: fqdn.to_string(),
+        project_name: project_name.to_string(),
+        expires: certificate_expiry(&certs)?,
+    }))
+}
+
+#[instrument(skip_all, fields(%project_name, %fqdn))]
+#[utoipa::path(
+    delete,
+    path = "/domains/{project_name}/{fqdn}",
+    responses(
+        (status = 200, description = "Successfully removed the custom domain from the project.", body = shuttle_common::models::domain::Response),
+        (status = 404, description = "The project does not have this custom domain."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+        ("fqdn" = String, Path, description = "The fqdn of the custom domain."),
+    )
+)]
+async fn remove_custom_domain(
+    State(RouterState {
+        service, sender, ..
+    }): State<RouterState>,
+    Extension(resolver): Extension<Arc<GatewayCertResolver>>,
+    ScopedUser {
+        scope: project_name,
+        ..
+    }: ScopedUser,
+    Path((_, fqdn)): Path<(String, String)>,
+) -> Result<AxumJson<domain::Response>, Error> {
+    let fqdn: FQDN = fqdn
+        .parse()
+        .map_err(|_err| Error::from(ErrorKind::InvalidCustomDomain))?;
+
+    let custom_domain = service.project_details_for_custom_domain(&fqdn).await?;
+    if custom_domain.project_name != project_name {
+        return Err(Error::from(ErrorKind::CustomDomainNotFound));
+    }
+
+    let response = custom_domain_response(custom_domain)?;
+
+    service.delete_custom_domain(&fqdn).await?;
+    recreate_project(&service, &sender, &project_name, None).await?;
+    resolver.stop_serving(&fqdn.to_string()).await;
+
+    Ok(AxumJson(response))
+}
+
+#[instrument(skip_all, fields(%project_name, %fqdn))]
+#[utoipa::path(
+    post,
+    path = "/domains/{project_name}/{fqdn}/renew",
+    responses(
+        (status = 200, description = "Successfully renewed the certificate of the custom domain, or skipped it while it is still valid for long enough."),
+        (status = 404, description = "The project does not have this custom domain."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+        ("fqdn" = String, Path, description = "The fqdn of the custom domain."),
+    )
+)]
+async fn renew_custom_domain(
+    State(RouterState { service, .. }): State<RouterState>,
+    Extension(acme_client): Extension<AcmeClient>,
+    Extension(resolver): Extension<Arc<GatewayCertResolver>>,
+    Extension(renewal_window): Extension<RenewalWindow>,
+    ScopedUser {
+        scope: project_name,
+        ..
+    }: ScopedUser,
+    Path((_, fqdn)): Path<(String, String)>,
+) -> Result<String, Error> {
+    let fqdn: FQDN = fqdn
+        .parse()
+        .map_err(|_err| Error::from(ErrorKind::InvalidCustomDomain))?;
+
+    renew_custom_domain_certificate(
+        &service,
+        &acme_client,
+        &resolver,
+        renewal_window,
+        &project_name,
+        &fqdn,
+        service.try_credentials()?,
+    )
+    .await
+}
+
+/// Recreate a project so that it can be reached on its custom domain, and start serving the
+/// certificate of the domain
+async fn attach_custom_domain(
+    service: &Arc<GatewayService>,
+    sender: &Sender<BoxedTask>,
+    resolver: &GatewayCertResolver,
+    project_name: &ProjectName,
+    fqdn: &Fqdn,
+    certs: &str,
+    private_key: &str,
+) -> Result<(), Error> {
+    recreate_project(service, sender, project_name, Some(fqdn.to_string())).await?;
+
+    let mut buf = Vec::new();
+    buf.extend(certs.as_bytes());
+    buf.extend(private_key.as_bytes());
+    resolver
+        .serve_pem(&fqdn.to_string(), Cursor::new(buf))
+        .await?;
+
+    Ok(())
+}
+
+/// Destroy and recreate a project, so that it picks up a change to its custom domain
+async fn recreate_project(
+    service: &Arc<GatewayService>,
+    sender: &Sender<BoxedTask>,
+    project_name: &ProjectName,
+    fqdn: Option<String>,
+) -> Result<(), Error> {
+    let project = service.find_project(project_name).await?;
+    // The settings of the project are kept on its container, which a project does not have while
+    // it is being created or once it is destroyed
+    let container = project
+        .container()
+        .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotReady))?;
+    let idle_minutes = container.idle_minutes();
+    let limits = container.limits();
+
+    service
+        .new_task()
+        .project(project_name.clone())
+        .and_then(task::destroy())
+        .and_then(task::run_until_done())
+        .and_then(task::run(move |ctx| {
+            let fqdn = fqdn.clone();
+            async move {
+                let mut creating =
+                    ProjectCreating::new_with_random_initial_key(ctx.project_name, idle_minutes)
+                        .with_limits(limits);
+                if let Some(fqdn) = fqdn {
+                    creating = creating.with_fqdn(fqdn);
+                }
+                TaskResult::Done(Project::Creating(creating))
+            }
+        }))
+        .send(sender)
+        .await?;
+
+    Ok(())
+}
+
+/// Renew the certificate of a custom domain of a project if it expires within the
+/// `renewal_window`
+async fn renew_custom_domain_certificate(
+    service: &GatewayService,
+    acme_client: &AcmeClient,
+    resolver: &GatewayCertResolver,
+    renewal_window: RenewalWindow,
+    project_name: &ProjectName,
+    fqdn: &Fqdn,
+    credentials: AccountCredentials<'_>,
+) -> Result<String, Error> {
+    if let Some(remaining) =
+        custom_domain_remaining_validity(service, renewal_window, project_name, fqdn).await?
+    {
+        return Ok(format!(
+            r#""Certificate renewal skipped, {} project certificate still valid for {} days.""#,
+            project_name,
+            remaining.num_days()
+        ));
+    }
+
+    service
+        .renew_custom_domain_certificate(project_name, fqdn, acme_client, resolver, credentials)
+        .await?;
+
+    Ok(format!(
+        r#""Certificate renewed for {} project.""#,
+        project_name
+    ))
+}
+
+/// Get how long the certificate of a custom domain of a project stays valid, or `None` when it
+/// should be renewed
+async fn custom_domain_remaining_validity(
+    service: &GatewayService,
+    renewal_window: RenewalWindow,
+    project_name: &ProjectName,
+    fqdn: &Fqdn,
+) -> Result<Option<chrono::Duration>, Error> {
+    let CustomDomain {
+        certificate,
+        project_name: owner,
+        ..
+    } = service.project_details_for_custom_domain(fqdn).await?;
+
+    if &owner != project_name {
+        return Err(Error::from(ErrorKind::CustomDomainNotFound));
+    }
+
+    remaining_validity(&certificate, renewal_window.0)
+}
+
+fn custom_domain_response(custom_domain: CustomDomain) -> Result<domain::Response, Error> {
+    Ok(domain::Response {
+        expires: certificate_expiry(&custom_domain.certificate)?,
+        fqdn: custom_domain.fqdn.to_string(),
+        project_name: custom_domain.project_name.to_string(),
+    })
+}
+
 #[utoipa::path(
     post,
     path = "/admin/projects",
@@ -628,11 +1024,20 @@ impl Modify for SecurityAddon {
         request_custom_domain_acme_certificate,
         renew_custom_domain_acme_certificate,
         renew_gateway_acme_certificate,
+        get_custom_domains,
+        add_custom_domain,
+        remove_custom_domain,
+        renew_custom_domain,
         get_status,
         get_projects_list,
         get_project,
         destroy_project,
         create_project,
+        get_metrics,
+        get_project_traffic,
+        get_tcp_port,
+        open_tcp_port,
+        close_tcp_port,
         post_load,
         delete_load,
         get_projects,
@@ -647,7 +1052,11 @@ impl Modify for SecurityAddon {
         shuttle_common::models::stats::LoadResponse,
         shuttle_common::models::project::AdminResponse,
         shuttle_common::models::stats::LoadResponse,
-        shuttle_common::models::project::State
+        shuttle_common::models::project::State,
+        shuttle_common::models::domain::Response,
+        shuttle_common::models::stats::TrafficPeriod,
+        shuttle_common::models::stats::TrafficResponse,
+        shuttle_common::models::tcp::Response
     ))
 )]
 pub struct ApiDoc;
@@ -682,7 +1091,12 @@ impl ApiBuilder {
         }
     }
 
-    pub fn with_acme(mut self, acme: AcmeClient, resolver: Arc<GatewayCertResolver>) -> Self {
+    pub fn with_acme(
+        mut self,
+        acme: AcmeClient,
+        resolver: Arc<GatewayCertResolver>,
+        renewal_window: RenewalWindow,
+    ) -> Self {
         self.router = self
             .router
             .route(
@@ -710,8 +1124,38 @@ impl ApiBuilder {
                         .layer(ScopedLayer::new(vec![Scope::GatewayCertificateRenew])),
                 ),
             )
+            .route(
+                "/domains/:project_name",
+                get(get_custom_domains.layer(ScopedLayer::new(vec![Scope::CustomDomain]))),
+            )
+            .route(
+                "/domains/:project_name/:fqdn",
+                post(add_custom_domain.layer(ScopedLayer::new(vec![Scope::CustomDomainWrite])))
+                    .delete(
+                        remove_custom_domain
+                            .layer(ScopedLayer::new(vec![Scope::CustomDomainWrite])),
+                    ),
+            )
+            .route(
+                "/domains/:project_name/:fqdn/renew",
+                post(renew_custom_domain.layer(ScopedLayer::new(vec![Scope::CustomDomainWrite]))),
+            )
             .layer(Extension(acme))
-            .layer(Extension(resolver));
+            .layer(Extension(resolver))
+            .layer(Extension(renewal_window));
+        self
+    }
+
+    pub fn with_tcp_proxy(mut self, tcp_proxy: TcpProxy) -> Self {
+        self.router = self
+            .router
+            .route(
+                "/tcp/:project_name",
+                get(get_tcp_port.layer(ScopedLayer::new(vec![Scope::Tcp])))
+                    .post(open_tcp_port.layer(ScopedLayer::new(vec![Scope::TcpWrite])))
+                    .delete(close_tcp_port.layer(ScopedLayer::new(vec![Scope::TcpWrite]))),
+            )
+            .layer(Extension(tcp_proxy));
         self
     }
 
@@ -771,7 +1215,15 @@ impl ApiBuilder {
                     .post(create_project.layer(ScopedLayer::new(vec![Scope::ProjectCreate]))),
             )
             .route("/projects/:project_name/*any", any(route_project))
+            .route(
+                "/traffic/:project_name",
+                get(get_project_traffic.layer(ScopedLayer::new(vec![Scope::Stats]))),
+            )
             .route("/stats/load", post(post_load).delete(delete_load))
+            .route(
+                "/metrics",
+                get(get_metrics.layer(ScopedLayer::new(vec![Scope::Admin]))),
+            )
             .nest("/admin", admin_routes);
 
         self
@@ -834,7 +1286,8 @@ pub mod tests {
 
     use super::*;
     use crate::service::GatewayService;
-    use crate::tests::{RequestBuilderExt, World};
+    use crate::tests::{certificate, RequestBuilderExt, World};
+    use crate::AccountName;
 
     #[tokio::test]
     async fn api_create_get_delete_projects() -> anyhow::Result<()> {
@@ -1085,4 +1538,80 @@ pub mod tests {
         let resp = router.call(get_status()).await.unwrap();
         assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
     }
+
+    #[tokio::test]
+    async fn recreate_project_without_container() {
+        let world = World::new().await;
+        let service = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+        let (sender, _receiver) = channel::<BoxedTask>(1);
+
+        let project_name: ProjectName = "matrix".parse().unwrap();
+        service
+            .create_project(
+                project_name.clone(),
+                "neo".parse().unwrap(),
+                false,
+                0,
+                Default::default(),
+            )
+            .await
+            .unwrap();
+
+        // The project is still being created, so it has no container yet
+        let error = super::recreate_project(&service, &sender, &project_name, None)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ProjectNotReady);
+    }
+
+    #[tokio::test]
+    async fn custom_domain_renewal_due() {
+        let world = World::new().await;
+        let service = GatewayService::init(world.args(), world.pool(), "".into()).await;
+        let window = RenewalWindow::days(30);
+
+        let account: AccountName = "neo".parse().unwrap();
+        let project_name: ProjectName = "matrix".parse().unwrap();
+        service
+            .create_project(project_name.clone(), account, false, 0, Default::default())
+            .await
+            .unwrap();
+
+        let fqdn: FQDN = "neo.the.matrix".parse().unwrap();
+        let remaining_validity = |certificate: String| {
+            let service = &service;
+            let project_name = &project_name;
+            let fqdn = &fqdn;
+
+            async move {
+                service
+                    .create_custom_domain(project_name, fqdn, &certificate, "dummy private key")
+                    .await
+                    .unwrap();
+
+                super::custom_domain_remaining_validity(service, window, project_name, fqdn).await
+            }
+        };
+
+        let remaining = remaining_validity(certificate("neo.the.matrix", 4000))
+            .await
+            .unwrap();
+        assert!(
+            remaining.unwrap() > window.0,
+            "a certificate far from expiring should not be renewed"
+        );
+
+        let remaining = remaining_validity(certificate("neo.the.matrix", 2000))
+            .await
+            .unwrap();
+        assert!(
+            remaining.is_none(),
+            "an expired certificate should be renewed right away"
+        );
+
+        let error = remaining_validity("not a certificate".to_string())
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Internal);
+    }
 }
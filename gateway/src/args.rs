@@ -1,4 +1,7 @@
-use std::{net::SocketAddr, path::PathBuf};
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+};
 
 use clap::{Parser, Subcommand, ValueEnum};
 use fqdn::FQDN;
@@ -39,6 +42,27 @@ pub struct StartArgs {
     /// Allows to disable the use of TLS in the user proxy service (DANGEROUS)
     #[arg(long, default_value = "enable")]
     pub use_tls: UseTls,
+    /// Renew the certificates of custom domains once they expire within this many days
+    #[arg(long, default_value = "30")]
+    pub certificate_renewal_window: u32,
+    /// Close an upgraded connection, such as a WebSocket, once nothing was sent over it for this many seconds
+    #[arg(long, default_value = "300")]
+    pub upgrade_idle_timeout_secs: u64,
+    /// Peers, such as load balancers, trusted to name the client of a request in its X-Forwarded-For header
+    #[arg(long, value_delimiter = ',')]
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Address to bind the public ports for raw TCP connections to
+    #[arg(long, default_value = "127.0.0.1")]
+    pub tcp_ip: IpAddr,
+    /// First public port to allocate to projects for raw TCP connections
+    #[arg(long, default_value = "30000")]
+    pub tcp_port_start: u16,
+    /// Last public port to allocate to projects for raw TCP connections
+    #[arg(long, default_value = "30999")]
+    pub tcp_port_end: u16,
+    /// Close a raw TCP connection once nothing was sent over it for this many seconds, even when its service allows longer
+    #[arg(long, default_value = "3600")]
+    pub tcp_idle_timeout_secs: u64,
     #[command(flatten)]
     pub context: ContextArgs,
 }
@@ -68,4 +92,8 @@ pub struct ContextArgs {
     /// The path to the docker daemon socket
     #[arg(long, default_value = "/var/run/docker.sock")]
     pub docker_host: String,
+    /// File containing the base64 encoded 256-bit master key, from which the key each deployer
+    /// encrypts secrets at rest with is derived
+    #[arg(long)]
+    pub deployer_secrets_key_file: PathBuf,
 }
@@ -23,10 +23,14 @@ pub mod acme;
 pub mod api;
 pub mod args;
 pub mod auth;
+pub mod limits;
+pub mod metrics;
 pub mod project;
 pub mod proxy;
+pub mod renewal;
 pub mod service;
 pub mod task;
+pub mod tcp;
 pub mod tls;
 pub mod worker;
 
@@ -220,6 +224,12 @@ pub trait DockerContext: Send + Sync {
     fn docker(&self) -> &Docker;
 
     fn container_settings(&self) -> &ContainerSettings;
+
+    /// Whether a project still has connections open through the user proxy, such as WebSockets or
+    /// raw TCP ones, which keep it from being idle
+    fn has_open_connections(&self, _project_name: &ProjectName) -> bool {
+        false
+    }
 }
 
 #[async_trait]
@@ -323,12 +333,13 @@ pub trait Refresh<Ctx>: Sized {
 pub mod tests {
     use std::collections::HashMap;
     use std::env;
-    use std::net::SocketAddr;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
     use std::str::FromStr;
     use std::sync::{Arc, Mutex};
     use std::time::Duration;
 
     use anyhow::{anyhow, Context as AnyhowContext};
+    use async_trait::async_trait;
     use axum::headers::authorization::Bearer;
     use axum::headers::Authorization;
     use axum::routing::get;
@@ -345,17 +356,21 @@ pub mod tests {
     use ring::signature::{self, Ed25519KeyPair, KeyPair};
     use shuttle_common::backends::auth::ConvertResponse;
     use shuttle_common::claims::{Claim, Scope};
+    use shuttle_common::deployment::Tcp;
     use shuttle_common::models::project;
+    use shuttle_deployer::AddressGetter;
     use sqlx::SqlitePool;
+    use tokio::net::TcpStream;
     use tokio::sync::mpsc::channel;
 
     use crate::acme::AcmeClient;
     use crate::api::latest::ApiBuilder;
     use crate::args::{ContextArgs, StartArgs, UseTls};
+    use crate::project::Project;
     use crate::proxy::UserServiceBuilder;
     use crate::service::{ContainerSettings, GatewayService, MIGRATIONS};
     use crate::worker::Worker;
-    use crate::DockerContext;
+    use crate::{DockerContext, ProjectName};
 
     macro_rules! value_block_helper {
         ($next:ident, $block:block) => {
@@ -542,6 +557,130 @@ pub mod tests {
         }
     }
 
+    /// A self-signed PEM certificate for `domain` which expires at the start of `expiry_year`
+    pub fn certificate(domain: &str, expiry_year: i32) -> String {
+        let mut params = rcgen::CertificateParams::new(vec![domain.to_string()]);
+        params.not_after = rcgen::date_time_ymd(expiry_year, 1, 1);
+
+        rcgen::Certificate::from_params(params)
+            .unwrap()
+            .serialize_pem()
+            .unwrap()
+    }
+
+    /// A project which is ready, with its deployer listening at `target` rather than in a container
+    pub fn ready_project(
+        project_name: &ProjectName,
+        target: IpAddr,
+        limits: project::Limits,
+    ) -> Project {
+        serde_json::from_value(serde_json::json!({
+            "ready": {
+                "container": {
+                    "Config": {
+                        "Labels": {
+                            "shuttle.project": project_name.to_string(),
+                            "shuttle.limits": serde_json::to_string(&limits).unwrap(),
+                        }
+                    }
+                },
+                "service": {
+                    "name": project_name.to_string(),
+                    "target": target,
+                    "last_check": null,
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    /// A loopback IP of its own, so the deployer proxies of a test can listen at the same ports they
+    /// use in their containers. Linux routes all of `127.0.0.0/8` to the loopback interface.
+    pub fn loopback_ip() -> IpAddr {
+        let octet = Uniform::from(1..=254);
+        let mut rng = rand::thread_rng();
+
+        Ipv4Addr::new(
+            127,
+            octet.sample(&mut rng),
+            octet.sample(&mut rng),
+            octet.sample(&mut rng),
+        )
+        .into()
+    }
+
+    /// Wait until something listens at `address`
+    pub async fn wait_for_listener(address: SocketAddr) {
+        while TcpStream::connect(address).await.is_err() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Where the service of a deployment listens, as the deployer proxies look it up
+    #[derive(Clone)]
+    pub struct Deployment {
+        pub http: SocketAddr,
+        pub tcp: Option<(SocketAddr, Tcp)>,
+    }
+
+    #[async_trait]
+    impl AddressGetter for Deployment {
+        async fn get_address_for_service(
+            &self,
+            _service_name: &str,
+        ) -> shuttle_deployer::handlers::Result<Option<SocketAddr>> {
+            Ok(Some(self.http))
+        }
+
+        async fn get_tcp_address(
+            &self,
+        ) -> shuttle_deployer::handlers::Result<Option<(SocketAddr, Tcp)>> {
+            Ok(self.tcp.clone())
+        }
+    }
+
+    /// Start the proxies of the deployer of `project_name` at `target`, forwarding to `deployment`
+    pub async fn spawn_deployer(
+        target: IpAddr,
+        project_name: &ProjectName,
+        public: &FQDN,
+        deployment: Deployment,
+    ) {
+        let http = SocketAddr::new(target, 8000);
+        let tcp = SocketAddr::new(target, 8002);
+        let fqdn = format!("{project_name}.{public}").parse().unwrap();
+
+        tokio::spawn(shuttle_deployer::start_proxy(
+            http,
+            fqdn,
+            deployment.clone(),
+            Duration::from_secs(60),
+        ));
+        tokio::spawn(shuttle_deployer::start_tcp_proxy(tcp, deployment));
+
+        wait_for_listener(http).await;
+        wait_for_listener(tcp).await;
+    }
+
+    /// Start the user proxy of `service`, returning the address it listens at
+    pub async fn spawn_user_proxy(service: Arc<GatewayService>, public: FQDN) -> SocketAddr {
+        let address = SocketAddr::from(([127, 0, 0, 1], portpicker::pick_unused_port().unwrap()));
+        let (sender, _) = channel(256);
+
+        tokio::spawn(
+            UserServiceBuilder::new()
+                .with_service(service)
+                .with_task_sender(sender)
+                .with_public(public)
+                .with_user_proxy_binding_to(address)
+                .serve(),
+        );
+
+        wait_for_listener(address).await;
+
+        address
+    }
+
     pub struct World {
         docker: Docker,
         settings: ContainerSettings,
@@ -598,11 +737,25 @@ pub mod tests {
 
             let docker_host = "/var/run/docker.sock".to_string();
 
+            let deployer_secrets_key_file = tempfile::NamedTempFile::new()
+                .unwrap()
+                .into_temp_path()
+                .keep()
+                .unwrap();
+            std::fs::write(&deployer_secrets_key_file, base64::encode([7; 32])).unwrap();
+
             let args = StartArgs {
                 control,
                 user,
                 bouncer,
                 use_tls: UseTls::Disable,
+                certificate_renewal_window: 30,
+                upgrade_idle_timeout_secs: 300,
+                trusted_proxies: Vec::new(),
+                tcp_ip: Ipv4Addr::LOCALHOST.into(),
+                tcp_port_start: 30000,
+                tcp_port_end: 30999,
+                tcp_idle_timeout_secs: 3600,
                 context: ContextArgs {
                     docker_host,
                     image,
@@ -611,6 +764,7 @@ pub mod tests {
                     auth_uri: auth_uri.clone(),
                     network_name,
                     proxy_fqdn: FQDN::from_str("test.shuttleapp.rs").unwrap(),
+                    deployer_secrets_key_file,
                 },
             };
 
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::ProjectName;
+
+/// How often token buckets which have filled up again are dropped
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Allows `rate` requests per second, with bursts of up to `rate` requests
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn full(rate: u32, now: Instant) -> Self {
+        Self {
+            tokens: rate.into(),
+            updated: now,
+        }
+    }
+
+    fn try_take(&mut self, rate: u32, now: Instant) -> bool {
+        let rate = f64::from(rate);
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// A bucket refills completely within a second, after which it is the same as a new one
+    fn is_full(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.updated) >= Duration::from_secs(1)
+    }
+}
+
+struct Buckets {
+    buckets: HashMap<(ProjectName, IpAddr), TokenBucket>,
+    last_pruned: Instant,
+}
+
+/// Enforces the rate and connection limits of projects in the user proxy
+#[derive(Clone)]
+pub struct ProxyLimiter {
+    buckets: Arc<Mutex<Buckets>>,
+    connections: Arc<Mutex<HashMap<ProjectName, u32>>>,
+}
+
+impl Default for ProxyLimiter {
+    fn default() -> Self {
+        Self {
+            buckets: Arc::new(Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                last_pruned: Instant::now(),
+            })),
+            connections: Default::default(),
+        }
+    }
+}
+
+impl ProxyLimiter {
+    /// Take a token for a request from `client` to a project which allows `rate` requests per
+    /// second. Returns `false` when the client has run out of tokens.
+    pub fn try_request(&self, project_name: &ProjectName, client: IpAddr, rate: u32) -> bool {
+        self.try_request_at(project_name, client, rate, Instant::now())
+    }
+
+    fn try_request_at(
+        &self,
+        project_name: &ProjectName,
+        client: IpAddr,
+        rate: u32,
+        now: Instant,
+    ) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if now.saturating_duration_since(buckets.last_pruned) >= PRUNE_INTERVAL {
+            buckets.buckets.retain(|_, bucket| !bucket.is_full(now));
+            buckets.last_pruned = now;
+        }
+
+        buckets
+            .buckets
+            .entry((project_name.clone(), client))
+            .or_insert_with(|| TokenBucket::full(rate, now))
+            .try_take(rate, now)
+    }
+
+    /// Open a connection to a project which allows `max` of them at the same time. The
+    /// connection stays open until the returned guard is dropped.
+    pub fn try_connect(&self, project_name: &ProjectName, max: u32) -> Option<ConnectionGuard> {
+        let mut connections = self.connections.lock().unwrap();
+        let open = connections.entry(project_name.clone()).or_default();
+
+        if *open >= max {
+            return None;
+        }
+
+        *open += 1;
+
+        Some(ConnectionGuard {
+            connections: self.connections.clone(),
+            project_name: project_name.clone(),
+        })
+    }
+}
+
+/// Closes its connection to a project when dropped
+pub struct ConnectionGuard {
+    connections: Arc<Mutex<HashMap<ProjectName, u32>>>,
+    project_name: ProjectName,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut connections = self.connections.lock().unwrap();
+
+        if let Some(open) = connections.get_mut(&self.project_name) {
+            *open -= 1;
+
+            if *open == 0 {
+                connections.remove(&self.project_name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::{Duration, Instant};
+
+    use super::ProxyLimiter;
+
+    #[test]
+    fn rate_limit() {
+        let limiter = ProxyLimiter::default();
+        let matrix = "matrix".parse().unwrap();
+        let neo = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let trinity = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        let now = Instant::now();
+
+        // Bursts of up to the rate are allowed
+        for _ in 0..5 {
+            assert!(limiter.try_request_at(&matrix, neo, 5, now));
+        }
+        assert!(!limiter.try_request_at(&matrix, neo, 5, now));
+
+        // Every client has its own bucket
+        assert!(limiter.try_request_at(&matrix, trinity, 5, now));
+
+        // And tokens come back over time
+        let later = now + Duration::from_millis(250);
+        assert!(limiter.try_request_at(&matrix, neo, 5, later));
+        assert!(!limiter.try_request_at(&matrix, neo, 5, later));
+    }
+
+    #[test]
+    fn connection_limit() {
+        let limiter = ProxyLimiter::default();
+        let matrix = "matrix".parse().unwrap();
+
+        let first = limiter.try_connect(&matrix, 2).unwrap();
+        let _second = limiter.try_connect(&matrix, 2).unwrap();
+        assert!(limiter.try_connect(&matrix, 2).is_none());
+
+        drop(first);
+        assert!(limiter.try_connect(&matrix, 2).is_some());
+
+        // The guard above was dropped straight away, so only one connection is still open
+        assert_eq!(
+            *limiter.connections.lock().unwrap().get(&matrix).unwrap(),
+            1
+        );
+    }
+}
@@ -7,8 +7,10 @@ use shuttle_gateway::api::latest::{ApiBuilder, SVC_DEGRADED_THRESHOLD};
 use shuttle_gateway::args::StartArgs;
 use shuttle_gateway::args::{Args, Commands, UseTls};
 use shuttle_gateway::proxy::UserServiceBuilder;
+use shuttle_gateway::renewal::{CertificateRenewer, RenewalWindow};
 use shuttle_gateway::service::{GatewayService, MIGRATIONS};
 use shuttle_gateway::task;
+use shuttle_gateway::tcp::TcpProxy;
 use shuttle_gateway::tls::make_tls_acceptor;
 use shuttle_gateway::worker::{Worker, WORKER_QUEUE_SIZE};
 use sqlx::migrate::MigrateDatabase;
@@ -136,11 +138,26 @@ async fn start(db: SqlitePool, fs: PathBuf, args: StartArgs) -> io::Result<()> {
         }
     });
 
+    let tcp_proxy = TcpProxy::new(
+        Arc::clone(&gateway),
+        sender.clone(),
+        args.tcp_ip,
+        args.tcp_port_start..=args.tcp_port_end,
+        args.context.proxy_fqdn.clone(),
+        Duration::from_secs(args.tcp_idle_timeout_secs),
+    );
+
+    tcp_proxy
+        .start()
+        .await
+        .expect("could not list the TCP ports of projects");
+
     let acme_client = AcmeClient::new();
 
     let mut api_builder = ApiBuilder::new()
         .with_service(Arc::clone(&gateway))
         .with_sender(sender.clone())
+        .with_tcp_proxy(tcp_proxy)
         .binding_to(args.control);
 
     let mut user_builder = UserServiceBuilder::new()
@@ -148,6 +165,8 @@ async fn start(db: SqlitePool, fs: PathBuf, args: StartArgs) -> io::Result<()> {
         .with_task_sender(sender)
         .with_public(args.context.proxy_fqdn.clone())
         .with_user_proxy_binding_to(args.user)
+        .with_upgrade_idle_timeout(Duration::from_secs(args.upgrade_idle_timeout_secs))
+        .with_trusted_proxies(args.trusted_proxies.clone())
         .with_bouncer(args.bouncer);
 
     if let UseTls::Enable = args.use_tls {
@@ -157,7 +176,11 @@ async fn start(db: SqlitePool, fs: PathBuf, args: StartArgs) -> io::Result<()> {
             .with_acme(acme_client.clone())
             .with_tls(tls_acceptor);
 
-        api_builder = api_builder.with_acme(acme_client.clone(), resolver.clone());
+        api_builder = api_builder.with_acme(
+            acme_client.clone(),
+            resolver.clone(),
+            RenewalWindow::days(args.certificate_renewal_window),
+        );
 
         for CustomDomain {
             fqdn,
@@ -175,6 +198,16 @@ async fn start(db: SqlitePool, fs: PathBuf, args: StartArgs) -> io::Result<()> {
                 .unwrap();
         }
 
+        tokio::spawn(
+            CertificateRenewer::new(
+                gateway.clone(),
+                acme_client.clone(),
+                resolver.clone(),
+                RenewalWindow::days(args.certificate_renewal_window),
+            )
+            .start(),
+        );
+
         tokio::spawn(async move {
             // Make sure we have a certificate for ourselves.
             let certs = gateway
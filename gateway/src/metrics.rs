@@ -0,0 +1,457 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use shuttle_common::models::stats::{TrafficPeriod, TrafficResponse};
+
+use crate::ProjectName;
+
+/// Upper bounds of the latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Width of the windows recent traffic is grouped in, in seconds
+const WINDOW_SECONDS: i64 = 5 * 60;
+
+/// How long recent traffic is kept for, in seconds
+const RETENTION_SECONDS: i64 = 24 * 60 * 60;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Counters {
+    requests: u64,
+    /// Responses by the class of their status, starting with 1xx
+    status_classes: [u64; 5],
+    /// Requests by latency bucket, with a last bucket for those slower than all the others
+    latency_buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    latency_sum: f64,
+    bytes_in: u64,
+    bytes_out: u64,
+}
+
+impl Counters {
+    fn add_request(&mut self, status: StatusCode, latency: Duration) {
+        let latency = latency.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| latency <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+
+        self.requests += 1;
+        self.status_classes[(status.as_u16() / 100).clamp(1, 5) as usize - 1] += 1;
+        self.latency_buckets[bucket] += 1;
+        self.latency_sum += latency;
+    }
+
+    fn merge(&mut self, other: &Counters) {
+        self.requests += other.requests;
+        for (class, count) in self.status_classes.iter_mut().zip(other.status_classes) {
+            *class += count;
+        }
+        for (bucket, count) in self.latency_buckets.iter_mut().zip(other.latency_buckets) {
+            *bucket += count;
+        }
+        self.latency_sum += other.latency_sum;
+        self.bytes_in += other.bytes_in;
+        self.bytes_out += other.bytes_out;
+    }
+
+    /// Upper bound of the latency bucket the `quantile` falls in, in milliseconds
+    fn latency_quantile(&self, quantile: f64) -> Option<u64> {
+        let count: u64 = self.latency_buckets.iter().sum();
+
+        if count == 0 {
+            return None;
+        }
+
+        let rank = (quantile * count as f64).ceil() as u64;
+        let mut seen = 0;
+
+        self.latency_buckets
+            .iter()
+            .position(|bucket| {
+                seen += bucket;
+                seen >= rank
+            })
+            .map(|bucket| {
+                let bound = LATENCY_BUCKETS
+                    .get(bucket)
+                    .unwrap_or(&LATENCY_BUCKETS[LATENCY_BUCKETS.len() - 1]);
+
+                (bound * 1000.0) as u64
+            })
+    }
+}
+
+impl From<Counters> for TrafficResponse {
+    fn from(counters: Counters) -> Self {
+        Self {
+            requests: counters.requests,
+            statuses: counters
+                .status_classes
+                .iter()
+                .enumerate()
+                .filter(|(_, count)| **count > 0)
+                .map(|(class, count)| (format!("{}xx", class + 1), *count))
+                .collect(),
+            bytes_in: counters.bytes_in,
+            bytes_out: counters.bytes_out,
+            latency_p50_ms: counters.latency_quantile(0.5),
+            latency_p95_ms: counters.latency_quantile(0.95),
+            latency_p99_ms: counters.latency_quantile(0.99),
+        }
+    }
+}
+
+#[derive(Default)]
+struct ProjectCounters {
+    /// All the traffic since the gateway started
+    total: Counters,
+    /// Recent traffic, by the start of its window in seconds since the epoch
+    windows: VecDeque<(i64, Counters)>,
+    /// Connections, such as upgraded or raw TCP ones, which are still being piped
+    open_connections: u64,
+}
+
+impl ProjectCounters {
+    fn update(&mut self, now: DateTime<Utc>, f: impl Fn(&mut Counters)) {
+        let now = now.timestamp();
+        let start = now - now.rem_euclid(WINDOW_SECONDS);
+
+        f(&mut self.total);
+
+        match self.windows.back_mut() {
+            Some((window_start, counters)) if *window_start == start => f(counters),
+            _ => {
+                let mut counters = Counters::default();
+                f(&mut counters);
+                self.windows.push_back((start, counters));
+            }
+        }
+
+        while self.windows.front().map_or(false, |(window_start, _)| {
+            *window_start <= now - RETENTION_SECONDS
+        }) {
+            self.windows.pop_front();
+        }
+    }
+
+    /// The traffic in the windows which overlap with the last `period`
+    fn since(&self, now: DateTime<Utc>, period: chrono::Duration) -> Counters {
+        let since = (now - period).timestamp();
+
+        self.windows
+            .iter()
+            .filter(|(window_start, _)| window_start + WINDOW_SECONDS > since)
+            .fold(Counters::default(), |mut total, (_, counters)| {
+                total.merge(counters);
+                total
+            })
+    }
+}
+
+/// Traffic passing through the user proxy, by project
+#[derive(Clone, Default)]
+pub struct ProxyMetrics(Arc<Mutex<HashMap<ProjectName, ProjectCounters>>>);
+
+impl ProxyMetrics {
+    pub fn record_request(
+        &self,
+        project_name: &ProjectName,
+        status: StatusCode,
+        latency: Duration,
+    ) {
+        self.update(project_name, |counters| {
+            counters.add_request(status, latency)
+        });
+    }
+
+    pub fn record_bytes_in(&self, project_name: &ProjectName, bytes: u64) {
+        self.update(project_name, |counters| counters.bytes_in += bytes);
+    }
+
+    pub fn record_bytes_out(&self, project_name: &ProjectName, bytes: u64) {
+        self.update(project_name, |counters| counters.bytes_out += bytes);
+    }
+
+    fn update(&self, project_name: &ProjectName, f: impl Fn(&mut Counters)) {
+        let mut projects = self.0.lock().unwrap();
+
+        match projects.get_mut(project_name) {
+            Some(project) => project.update(Utc::now(), f),
+            None => {
+                let mut project = ProjectCounters::default();
+                project.update(Utc::now(), f);
+                projects.insert(project_name.clone(), project);
+            }
+        }
+    }
+
+    /// Count a connection to a project, such as an upgraded or a raw TCP one, as open until the
+    /// returned guard is dropped
+    pub fn open_connection(&self, project_name: &ProjectName) -> OpenConnection {
+        self.0
+            .lock()
+            .unwrap()
+            .entry(project_name.clone())
+            .or_default()
+            .open_connections += 1;
+
+        OpenConnection {
+            metrics: self.clone(),
+            project_name: project_name.clone(),
+        }
+    }
+
+    pub fn open_connections(&self, project_name: &ProjectName) -> u64 {
+        self.0
+            .lock()
+            .unwrap()
+            .get(project_name)
+            .map_or(0, |project| project.open_connections)
+    }
+
+    /// The traffic of a project over the last `period`
+    pub fn traffic(&self, project_name: &ProjectName, period: TrafficPeriod) -> TrafficResponse {
+        let period = match period {
+            TrafficPeriod::Hour => chrono::Duration::hours(1),
+            TrafficPeriod::Day => chrono::Duration::days(1),
+        };
+
+        self.0
+            .lock()
+            .unwrap()
+            .get(project_name)
+            .map(|project| project.since(Utc::now(), period))
+            .unwrap_or_default()
+            .into()
+    }
+
+    /// All the traffic since the gateway started, in the Prometheus text format
+    pub fn render(&self) -> String {
+        let projects = self.0.lock().unwrap();
+        let mut projects: Vec<_> = projects.iter().collect();
+        projects.sort_by_key(|(project_name, _)| project_name.to_string());
+
+        let mut out = String::new();
+
+        writeln!(out, "# HELP shuttle_proxy_requests_total Requests proxied to a project, by the class of their response status").unwrap();
+        writeln!(out, "# TYPE shuttle_proxy_requests_total counter").unwrap();
+        for (project_name, ProjectCounters { total, .. }) in projects.iter() {
+            for (class, count) in total.status_classes.iter().enumerate() {
+                writeln!(
+                    out,
+                    "shuttle_proxy_requests_total{{project=\"{project_name}\",status=\"{}xx\"}} {count}",
+                    class + 1
+                )
+                .unwrap();
+            }
+        }
+
+        writeln!(out, "# HELP shuttle_proxy_request_duration_seconds Time until the response headers of a project were received").unwrap();
+        writeln!(
+            out,
+            "# TYPE shuttle_proxy_request_duration_seconds histogram"
+        )
+        .unwrap();
+        for (project_name, ProjectCounters { total, .. }) in projects.iter() {
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(total.latency_buckets) {
+                cumulative += count;
+                writeln!(
+                    out,
+                    "shuttle_proxy_request_duration_seconds_bucket{{project=\"{project_name}\",le=\"{bound}\"}} {cumulative}"
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "shuttle_proxy_request_duration_seconds_bucket{{project=\"{project_name}\",le=\"+Inf\"}} {}",
+                total.requests
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "shuttle_proxy_request_duration_seconds_sum{{project=\"{project_name}\"}} {}",
+                total.latency_sum
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "shuttle_proxy_request_duration_seconds_count{{project=\"{project_name}\"}} {}",
+                total.requests
+            )
+            .unwrap();
+        }
+
+        for (name, help, bytes) in [
+            (
+                "shuttle_proxy_request_bytes_total",
+                "Bytes of request bodies proxied to a project",
+                (|counters: &Counters| counters.bytes_in) as fn(&Counters) -> u64,
+            ),
+            (
+                "shuttle_proxy_response_bytes_total",
+                "Bytes of response bodies proxied from a project",
+                |counters: &Counters| counters.bytes_out,
+            ),
+        ] {
+            writeln!(out, "# HELP {name} {help}").unwrap();
+            writeln!(out, "# TYPE {name} counter").unwrap();
+            for (project_name, ProjectCounters { total, .. }) in projects.iter() {
+                writeln!(out, "{name}{{project=\"{project_name}\"}} {}", bytes(total)).unwrap();
+            }
+        }
+
+        writeln!(out, "# HELP shuttle_proxy_open_connections Upgraded and raw TCP connections to a project which are still open").unwrap();
+        writeln!(out, "# TYPE shuttle_proxy_open_connections gauge").unwrap();
+        for (project_name, project) in projects.iter() {
+            writeln!(
+                out,
+                "shuttle_proxy_open_connections{{project=\"{project_name}\"}} {}",
+                project.open_connections
+            )
+            .unwrap();
+        }
+
+        out
+    }
+}
+
+/// Counts a connection to a project as open until it is dropped
+pub struct OpenConnection {
+    metrics: ProxyMetrics,
+    project_name: ProjectName,
+}
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        if let Some(project) = self.metrics.0.lock().unwrap().get_mut(&self.project_name) {
+            project.open_connections -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use http::StatusCode;
+    use shuttle_common::models::stats::{TrafficPeriod, TrafficResponse};
+
+    use super::{ProjectCounters, ProxyMetrics};
+
+    #[test]
+    fn traffic() {
+        let metrics = ProxyMetrics::default();
+        let matrix = "matrix".parse().unwrap();
+
+        for _ in 0..98 {
+            metrics.record_request(&matrix, StatusCode::OK, Duration::from_millis(20));
+        }
+        metrics.record_request(&matrix, StatusCode::NOT_FOUND, Duration::from_millis(200));
+        metrics.record_request(&matrix, StatusCode::BAD_GATEWAY, Duration::from_secs(20));
+        metrics.record_bytes_in(&matrix, 10);
+        metrics.record_bytes_out(&matrix, 100);
+        metrics.record_bytes_out(&matrix, 20);
+
+        assert_eq!(
+            metrics.traffic(&matrix, TrafficPeriod::Hour),
+            TrafficResponse {
+                requests: 100,
+                statuses: [
+                    ("2xx".to_string(), 98),
+                    ("4xx".to_string(), 1),
+                    ("5xx".to_string(), 1)
+                ]
+                .into_iter()
+                .collect(),
+                bytes_in: 10,
+                bytes_out: 120,
+                latency_p50_ms: Some(25),
+                latency_p95_ms: Some(25),
+                latency_p99_ms: Some(250),
+            }
+        );
+
+        assert_eq!(
+            metrics.traffic(&"zion".parse().unwrap(), TrafficPeriod::Day),
+            TrafficResponse::default()
+        );
+    }
+
+    #[test]
+    fn windows() {
+        let mut project = ProjectCounters::default();
+        let start = Utc.with_ymd_and_hms(2023, 5, 1, 0, 0, 0).unwrap();
+
+        project.update(start, |counters| counters.bytes_in += 1);
+        project.update(start + chrono::Duration::minutes(2), |counters| {
+            counters.bytes_in += 2
+        });
+        project.update(start + chrono::Duration::hours(2), |counters| {
+            counters.bytes_in += 4
+        });
+
+        assert_eq!(project.windows.len(), 2);
+
+        let now = start + chrono::Duration::hours(2);
+        assert_eq!(project.since(now, chrono::Duration::hours(1)).bytes_in, 4);
+        assert_eq!(project.since(now, chrono::Duration::days(1)).bytes_in, 7);
+
+        // Windows older than a day are dropped, but still count towards the total
+        project.update(start + chrono::Duration::days(1), |counters| {
+            counters.bytes_in += 8
+        });
+
+        assert_eq!(project.windows.len(), 2);
+        assert_eq!(project.total.bytes_in, 15);
+    }
+
+    #[test]
+    fn render() {
+        let metrics = ProxyMetrics::default();
+        let matrix = "matrix".parse().unwrap();
+
+        metrics.record_request(&matrix, StatusCode::OK, Duration::from_millis(20));
+        metrics.record_request(&matrix, StatusCode::OK, Duration::from_secs(20));
+        metrics.record_bytes_out(&matrix, 42);
+        let _connection = metrics.open_connection(&matrix);
+
+        let rendered = metrics.render();
+
+        for line in [
+            "shuttle_proxy_requests_total{project=\"matrix\",status=\"2xx\"} 2",
+            "shuttle_proxy_requests_total{project=\"matrix\",status=\"5xx\"} 0",
+            "shuttle_proxy_request_duration_seconds_bucket{project=\"matrix\",le=\"0.01\"} 0",
+            "shuttle_proxy_request_duration_seconds_bucket{project=\"matrix\",le=\"0.025\"} 1",
+            "shuttle_proxy_request_duration_seconds_bucket{project=\"matrix\",le=\"10\"} 1",
+            "shuttle_proxy_request_duration_seconds_bucket{project=\"matrix\",le=\"+Inf\"} 2",
+            "shuttle_proxy_request_duration_seconds_count{project=\"matrix\"} 2",
+            "shuttle_proxy_request_bytes_total{project=\"matrix\"} 0",
+            "shuttle_proxy_response_bytes_total{project=\"matrix\"} 42",
+            "shuttle_proxy_open_connections{project=\"matrix\"} 1",
+        ] {
+            assert!(rendered.lines().any(|l| l == line), "missing {line}");
+        }
+    }
+
+    #[test]
+    fn open_connections() {
+        let metrics = ProxyMetrics::default();
+        let matrix = "matrix".parse().unwrap();
+
+        assert_eq!(metrics.open_connections(&matrix), 0);
+
+        let first = metrics.open_connection(&matrix);
+        let _second = metrics.open_connection(&matrix);
+        assert_eq!(metrics.open_connections(&matrix), 2);
+
+        drop(first);
+        assert_eq!(metrics.open_connections(&matrix), 1);
+    }
+}
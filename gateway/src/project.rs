@@ -5,7 +5,7 @@ use std::time::Duration;
 
 use bollard::container::{
     Config, CreateContainerOptions, KillContainerOptions, RemoveContainerOptions, Stats,
-    StatsOptions, StopContainerOptions,
+    StatsOptions, StopContainerOptions, UploadToContainerOptions,
 };
 use bollard::errors::Error as DockerError;
 use bollard::models::{ContainerInspectResponse, ContainerStateStatusEnum};
@@ -20,7 +20,7 @@ use hyper::Client;
 use once_cell::sync::Lazy;
 use rand::distributions::{Alphanumeric, DistString};
 use serde::{Deserialize, Serialize};
-use shuttle_common::models::project::{idle_minutes, IDLE_MINUTES};
+use shuttle_common::models::project::{idle_minutes, Limits, IDLE_MINUTES};
 use tokio::time::{sleep, timeout};
 use tracing::{debug, error, info, instrument};
 
@@ -65,10 +65,17 @@ macro_rules! impl_from_variant {
 }
 
 const RUNTIME_API_PORT: u16 = 8001;
+const RUNTIME_TCP_PORT: u16 = 8002;
 const MAX_RECREATES: usize = 5;
 const MAX_RESTARTS: usize = 5;
 const MAX_REBOOTS: usize = 3;
 
+/// Where the deployer finds the key it encrypts secrets at rest with. The key is copied into the
+/// container when it is created, rather than passed on its command line for anyone able to
+/// inspect the container to read.
+const DEPLOYER_SECRETS_KEY_DIR: &str = "/opt/shuttle";
+const DEPLOYER_SECRETS_KEY_FILE: &str = "secrets.key";
+
 // Client used for health checks
 static CLIENT: Lazy<Client<HttpConnector>> = Lazy::new(Client::new);
 // Health check must succeed within 10 seconds
@@ -113,6 +120,18 @@ pub trait ContainerInspectResponseExt {
         IDLE_MINUTES
     }
 
+    fn limits(&self) -> Limits {
+        let container = self.container();
+
+        container
+            .config
+            .as_ref()
+            .and_then(|config| config.labels.as_ref())
+            .and_then(|labels| labels.get("shuttle.limits"))
+            .and_then(|limits| serde_json::from_str(limits).ok())
+            .unwrap_or_default()
+    }
+
     fn find_arg_and_then<'s, F, O>(&'s self, find: &str, and_then: F) -> Result<O, ProjectError>
     where
         F: FnOnce(&'s str) -> O,
@@ -259,6 +278,13 @@ impl Project {
             .map(|target_ip| SocketAddr::new(target_ip, RUNTIME_API_PORT)))
     }
 
+    /// Address the deployer of the project accepts raw TCP connections at
+    pub fn target_tcp_addr(&self) -> Result<Option<SocketAddr>, Error> {
+        Ok(self
+            .target_ip()?
+            .map(|target_ip| SocketAddr::new(target_ip, RUNTIME_TCP_PORT)))
+    }
+
     pub fn state(&self) -> String {
         match self {
             Self::Started(_) => "started".to_string(),
@@ -584,6 +610,9 @@ pub struct ProjectCreating {
     /// Label set on container as to how many minutes to wait before a project is considered idle
     #[serde(default = "idle_minutes")]
     idle_minutes: u64,
+    /// Label set on container as to which limits the proxy enforces on traffic to the project
+    #[serde(default)]
+    limits: Limits,
 }
 
 impl ProjectCreating {
@@ -596,6 +625,7 @@ impl ProjectCreating {
             from: None,
             recreate_count: 0,
             idle_minutes,
+            limits: Default::default(),
         }
     }
 
@@ -605,6 +635,7 @@ impl ProjectCreating {
     ) -> Result<Self, ProjectError> {
         let project_name = container.project_name()?;
         let idle_minutes = container.idle_minutes();
+        let limits = container.limits();
         let initial_key = container.initial_key()?;
 
         Ok(Self {
@@ -615,6 +646,7 @@ impl ProjectCreating {
             from: Some(container),
             recreate_count,
             idle_minutes,
+            limits,
         })
     }
 
@@ -628,6 +660,11 @@ impl ProjectCreating {
         self
     }
 
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
     pub fn new_with_random_initial_key(project_name: ProjectName, idle_minutes: u64) -> Self {
         let initial_key = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
         Self::new(project_name, initial_key, idle_minutes)
@@ -677,6 +714,7 @@ impl ProjectCreating {
             fqdn,
             image,
             idle_minutes,
+            limits,
             ..
         } = &self;
 
@@ -697,6 +735,7 @@ impl ProjectCreating {
                         "shuttle.prefix": prefix,
                         "shuttle.project": project_name,
                         "shuttle.idle_minutes": format!("{idle_minutes}"),
+                        "shuttle.limits": serde_json::to_string(limits).unwrap(),
                     },
                     "Cmd": [
                         "--admin-secret",
@@ -709,6 +748,8 @@ impl ProjectCreating {
                         format!("http://{provisioner_host}:8000"),
                         "--proxy-address",
                         "0.0.0.0:8000",
+                        "--tcp-proxy-address",
+                        format!("0.0.0.0:{RUNTIME_TCP_PORT}"),
                         "--proxy-fqdn",
                         fqdn.clone().unwrap_or(format!("{project_name}.{public}")),
                         "--artifacts-path",
@@ -717,6 +758,8 @@ impl ProjectCreating {
                         "/opt/shuttle/deployer.sqlite",
                         "--auth-uri",
                         auth_uri,
+                        "--secrets-key-file",
+                        format!("{DEPLOYER_SECRETS_KEY_DIR}/{DEPLOYER_SECRETS_KEY_FILE}"),
                     ],
                     "Env": [
                         "RUST_LOG=debug,shuttle=trace,h2=warn",
@@ -726,6 +769,20 @@ impl ProjectCreating {
 
         let mut config = Config::<String>::from(container_config);
 
+        // Containers created before deployers encrypted secrets are recreated from a command
+        // without the key, which deployers no longer start without. The key file itself is
+        // uploaded to every container once it is created.
+        let cmd = config.cmd.get_or_insert_with(Vec::new);
+        if !cmd
+            .iter()
+            .any(|arg| arg == "--secrets-key-file" || arg == "--secrets-key")
+        {
+            cmd.push("--secrets-key-file".to_string());
+            cmd.push(format!(
+                "{DEPLOYER_SECRETS_KEY_DIR}/{DEPLOYER_SECRETS_KEY_FILE}"
+            ));
+        }
+
         config.host_config = deserialize_json!({
             "Mounts": [{
                 "Target": "/opt/shuttle",
@@ -749,6 +806,27 @@ Config: {config:#?}
 
         (create_container_options, config)
     }
+
+    /// An archive holding the key the deployer of this project encrypts secrets with
+    fn secrets_key_archive<C: DockerContext>(&self, ctx: &C) -> Vec<u8> {
+        let key = ctx
+            .container_settings()
+            .secrets_key
+            .project_key(&self.project_name);
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(key.len() as u64);
+        header.set_mode(0o400);
+
+        let mut archive = tar::Builder::new(Vec::new());
+        archive
+            .append_data(&mut header, DEPLOYER_SECRETS_KEY_FILE, key.as_bytes())
+            .expect("writing an archive to memory to succeed");
+
+        archive
+            .into_inner()
+            .expect("writing an archive to memory to succeed")
+    }
 }
 
 #[async_trait]
@@ -772,8 +850,19 @@ where
             .or_else(|err| async move {
                 if matches!(err, DockerError::DockerResponseServerError { status_code, .. } if status_code == 404) {
                     let (opts, config) = self.generate_container_config(ctx);
+                    let secrets_key = self.secrets_key_archive(ctx);
                     ctx.docker()
                         .create_container(Some(opts), config)
+                        .and_then(|_| {
+                            ctx.docker().upload_to_container(
+                                &container_name,
+                                Some(UploadToContainerOptions {
+                                    path: DEPLOYER_SECRETS_KEY_DIR,
+                                    no_overwrite_dir_non_dir: "false",
+                                }),
+                                secrets_key.into(),
+                            )
+                        })
                         .and_then(|_| ctx.docker().inspect_container(&container_name, None))
                         .await
                 } else {
@@ -1103,7 +1192,12 @@ where
                     // for an "active" discord will be to close to the 20_000_000 of an idle framework. And
                     // discord will have more traffic in anyway. So using the 100_000_000 threshold of an
                     // active framework for now
-                    if cpu_per_minute < 100_000_000 {
+                    //
+                    // A project holding open connections, such as WebSockets or raw TCP ones, can
+                    // use hardly any CPU while still being in use
+                    if cpu_per_minute < 100_000_000
+                        && !ctx.has_open_connections(&container.project_name()?)
+                    {
                         Ok(Self::Next::Idle(ProjectStopping { container }))
                     } else {
                         Ok(Self::Next::Ready(ProjectReady {
@@ -1638,7 +1732,7 @@ pub mod exec {
 #[cfg(test)]
 pub mod tests {
 
-    use bollard::models::ContainerState;
+    use bollard::models::{ContainerConfig, ContainerState};
     use bollard::service::NetworkSettings;
     use futures::prelude::*;
     use hyper::{Body, Request, StatusCode};
@@ -1663,6 +1757,7 @@ pub mod tests {
                 from: None,
                 recreate_count: 0,
                 idle_minutes: 0,
+                limits: Default::default(),
             }),
             #[assertion = "Container created, attach network"]
             Ok(Project::Attaching(ProjectAttaching {
@@ -1776,4 +1871,80 @@ pub mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn start_project_created_without_secrets_key() -> anyhow::Result<()> {
+        let world = World::new().await;
+
+        let ctx = world.context();
+
+        // A container created before deployers were handed a key to encrypt secrets with
+        let creating = ProjectCreating::new(
+            "my-old-project-test".parse().unwrap(),
+            "test".to_string(),
+            0,
+        );
+        let (_, config) = creating.generate_container_config(&ctx);
+        let mut old_cmd = config.cmd.unwrap();
+        let key_arg = old_cmd
+            .iter()
+            .position(|arg| arg == "--secrets-key-file")
+            .unwrap();
+        old_cmd.drain(key_arg..key_arg + 2);
+
+        let old_container = ContainerInspectResponse {
+            config: Some(ContainerConfig {
+                image: config.image,
+                hostname: config.hostname,
+                labels: config.labels,
+                env: config.env,
+                cmd: Some(old_cmd.clone()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let creating = creating.from(old_container);
+
+        let (_, config) = creating.generate_container_config(&ctx);
+        let cmd = config.cmd.unwrap();
+        assert_eq!(cmd[..old_cmd.len()], old_cmd[..]);
+        assert_eq!(
+            cmd[old_cmd.len()..],
+            [
+                "--secrets-key-file".to_string(),
+                format!("{DEPLOYER_SECRETS_KEY_DIR}/{DEPLOYER_SECRETS_KEY_FILE}")
+            ]
+        );
+
+        // The deployer of the recreated container starts with the key uploaded to it
+        let delay = sleep(Duration::from_secs(60));
+        futures::pin_mut!(delay);
+        let mut project_readying = Project::Creating(creating)
+            .into_stream(&ctx)
+            .take_until(delay)
+            .try_skip_while(|state| future::ready(Ok(!matches!(state, Project::Ready(_)))));
+
+        let project_ready = assert_stream_matches!(
+            project_readying,
+            #[assertion = "Container is ready"]
+            Ok(Project::Ready(ProjectReady { .. })),
+        );
+
+        let project_stopped = assert_matches!(
+            ctx,
+            project_ready.unwrap().stop().unwrap(),
+            #[assertion = "Container is stopped"]
+            Ok(Project::Stopped(ProjectStopped { .. })),
+        );
+
+        assert_matches!(
+            ctx,
+            project_stopped.unwrap().destroy().unwrap(),
+            #[assertion = "Container is destroyed"]
+            Ok(Project::Destroyed(ProjectDestroyed { destroyed: _ })),
+        )
+        .unwrap();
+
+        Ok(())
+    }
 }
@@ -1,40 +1,55 @@
+use std::collections::HashMap;
 use std::convert::Infallible;
 use std::future::Future;
 use std::io;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
-use axum::headers::{HeaderMapExt, Host};
+use axum::headers::{ContentLength, HeaderMapExt, Host};
 use axum::response::{IntoResponse, Response};
 use axum_server::accept::DefaultAcceptor;
 use axum_server::tls_rustls::RustlsAcceptor;
 use fqdn::{fqdn, FQDN};
 use futures::future::{ready, Ready};
 use futures::prelude::*;
-use hyper::body::{Body, HttpBody};
+use hyper::body::{Body, Bytes, HttpBody, SizeHint};
 use hyper::client::connect::dns::GaiResolver;
 use hyper::client::HttpConnector;
 use hyper::server::conn::AddrStream;
-use hyper::{Client, Request};
+use hyper::{Client, HeaderMap, Request};
 use hyper_reverse_proxy::ReverseProxy;
 use once_cell::sync::Lazy;
 use opentelemetry::global;
 use opentelemetry_http::HeaderInjector;
+use shuttle_common::backends::grpc::{is_grpc_request, prepare_for_forwarding};
 use shuttle_common::backends::headers::XShuttleProject;
+use shuttle_common::backends::upgrade::{forward_upgrade, is_upgrade_request};
+use shuttle_common::models::error::ApiError;
 use tokio::sync::mpsc::Sender;
 use tower::{Service, ServiceBuilder};
-use tracing::{debug_span, error, field, trace};
+use tracing::{debug, debug_span, error, field, trace, Span};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use crate::acme::{AcmeClient, ChallengeResponderLayer, CustomDomain};
+use crate::limits::ConnectionGuard;
+use crate::metrics::ProxyMetrics;
+use crate::project::{ContainerInspectResponseExt, Project};
 use crate::service::GatewayService;
 use crate::task::BoxedTask;
-use crate::{Error, ErrorKind};
+use crate::{Error, ErrorKind, ProjectName};
 
 static PROXY_CLIENT: Lazy<ReverseProxy<HttpConnector<GaiResolver>>> =
     Lazy::new(|| ReverseProxy::new(Client::new()));
+static GRPC_PROXY_CLIENT: Lazy<ReverseProxy<HttpConnector<GaiResolver>>> =
+    Lazy::new(|| ReverseProxy::new(Client::builder().http2_only(true).build_http()));
+static UPGRADE_CLIENT: Lazy<Client<HttpConnector<GaiResolver>>> = Lazy::new(Client::new);
+
+/// How long an upgraded connection can stay idle when no other timeout is set
+const UPGRADE_IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
 
 pub trait AsResponderTo<R> {
     fn as_responder_to(&self, req: R) -> Self;
@@ -74,12 +89,17 @@ pub struct UserProxy {
     task_sender: Sender<BoxedTask>,
     remote_addr: SocketAddr,
     public: FQDN,
+    upgrade_idle_timeout: Duration,
+    trusted_proxies: Arc<Vec<IpAddr>>,
+    /// Slots of the connection limits of projects held by the client connection being served
+    connections: Arc<Mutex<HashMap<ProjectName, Arc<ConnectionGuard>>>>,
 }
 
 impl<'r> AsResponderTo<&'r AddrStream> for UserProxy {
     fn as_responder_to(&self, addr_stream: &'r AddrStream) -> Self {
         let mut responder = self.clone();
         responder.remote_addr = addr_stream.remote_addr();
+        responder.connections = Default::default();
         responder
     }
 }
@@ -93,12 +113,16 @@ impl UserProxy {
         let span = debug_span!("proxy", http.method = %req.method(), http.host = ?req.headers().get("Host"), http.uri = %req.uri(), http.status_code = field::Empty, project = field::Empty);
         trace!(?req, "serving proxy request");
 
+        prepare_for_forwarding(&mut req);
+
         let fqdn = req
             .headers()
             .typed_get::<Host>()
             .map(|host| fqdn!(host.hostname()))
             .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotFound))?;
 
+        // Other services of a project are served under a path of `<project>.<public>` rather than
+        // at their own subdomain, which the wildcard certificate of `<public>` would not cover
         let project_name =
             if fqdn.is_subdomain_of(&self.public) && fqdn.depth() - self.public.depth() == 1 {
                 fqdn.labels()
@@ -118,14 +142,69 @@ impl UserProxy {
         req.headers_mut()
             .typed_insert(XShuttleProject(project_name.to_string()));
 
+        let start = Instant::now();
+
+        // Only count traffic of projects which exist, so made up hosts cannot flood the metrics
         let project = self
             .gateway
             .find_or_start_project(&project_name, task_sender)
             .await?;
 
+        let metrics = self.gateway.proxy_metrics().clone();
+
+        let result = self
+            .forward(&project_name, project, req, &span, &metrics)
+            .await;
+
+        let status = match &result {
+            Ok(response) => response.status(),
+            Err(error) => ApiError::from(error.kind()).status(),
+        };
+        metrics.record_request(&project_name, status, start.elapsed());
+
+        result
+    }
+
+    async fn forward(
+        &self,
+        project_name: &ProjectName,
+        project: Project,
+        req: Request<Body>,
+        span: &Span,
+        metrics: &ProxyMetrics,
+    ) -> Result<Response, Error> {
         // Record current project for tracing purposes
         span.record("project", &project_name.to_string());
 
+        let limits = project
+            .container()
+            .map(|container| container.limits())
+            .unwrap_or_default();
+        let limiter = self.gateway.proxy_limiter();
+
+        if let Some(rate) = limits.requests_per_second {
+            let client = client_ip(self.remote_addr.ip(), req.headers(), &self.trusted_proxies);
+
+            if !limiter.try_request(project_name, client, rate) {
+                return Err(Error::from_kind(ErrorKind::RateLimited));
+            }
+        }
+
+        if let Some(max) = limits.max_body_bytes {
+            if req
+                .headers()
+                .typed_get::<ContentLength>()
+                .map_or(false, |ContentLength(length)| length > max)
+            {
+                return Err(Error::from_kind(ErrorKind::PayloadTooLarge));
+            }
+        }
+
+        let connection = limits
+            .max_connections
+            .map(|max| self.connect(project_name, max))
+            .transpose()?;
+
         let target_ip = project
             .target_ip()?
             .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotReady))?;
@@ -134,22 +213,274 @@ impl UserProxy {
 
         let cx = span.context();
 
+        // Bodies without a content length can only be checked against the limit as they stream in
+        let body_too_large = Arc::new(AtomicBool::new(false));
+        let mut req = {
+            let metrics = metrics.clone();
+            let project_name = project_name.clone();
+            let body_too_large = body_too_large.clone();
+            req.map(|body| {
+                forward_request_body(body, limits.max_body_bytes, body_too_large, move |bytes| {
+                    metrics.record_bytes_in(&project_name, bytes)
+                })
+            })
+        };
+
         global::get_text_map_propagator(|propagator| {
             propagator.inject_context(&cx, &mut HeaderInjector(req.headers_mut()))
         });
 
-        let proxy = PROXY_CLIENT
-            .call(self.remote_addr.ip(), &target_url, req)
+        if is_upgrade_request(&req) {
+            let (response, upgrade) = forward_upgrade(
+                &UPGRADE_CLIENT,
+                self.remote_addr.ip(),
+                SocketAddr::new(target_ip, 8000),
+                req,
+            )
             .await
             .map_err(|_| Error::from_kind(ErrorKind::ProjectUnavailable))?;
 
+            if let Some(upgrade) = upgrade {
+                let metrics = metrics.clone();
+                let project_name = project_name.clone();
+                let idle_timeout = self.upgrade_idle_timeout;
+                let open_connection = metrics.open_connection(&project_name);
+
+                tokio::spawn(async move {
+                    // The upgraded connection counts as open for as long as it is being piped
+                    let _connection = connection;
+                    let _open_connection = open_connection;
+
+                    match upgrade.pipe(idle_timeout).await {
+                        Ok((bytes_in, bytes_out)) => {
+                            metrics.record_bytes_in(&project_name, bytes_in);
+                            metrics.record_bytes_out(&project_name, bytes_out);
+                        }
+                        Err(error) => debug!(%error, "upgraded connection failed"),
+                    }
+                });
+            }
+
+            let (parts, body) = response.into_parts();
+            let body = <Body as HttpBody>::map_err(body, axum::Error::new).boxed_unsync();
+
+            span.record("http.status_code", parts.status.as_u16());
+
+            return Ok(Response::from_parts(parts, body));
+        }
+
+        // gRPC calls are forwarded over HTTP/2, which the deployer passes on to the service
+        let client = if is_grpc_request(&req) {
+            &GRPC_PROXY_CLIENT
+        } else {
+            &PROXY_CLIENT
+        };
+
+        let proxy = client
+            .call(self.remote_addr.ip(), &target_url, req)
+            .await
+            .map_err(|_| {
+                if body_too_large.load(Ordering::Relaxed) {
+                    Error::from_kind(ErrorKind::PayloadTooLarge)
+                } else {
+                    Error::from_kind(ErrorKind::ProjectUnavailable)
+                }
+            })?;
+
         let (parts, body) = proxy.into_parts();
-        let body = <Body as HttpBody>::map_err(body, axum::Error::new).boxed_unsync();
+        let body = {
+            let metrics = metrics.clone();
+            let project_name = project_name.clone();
+            CountBytes::new(body, move |bytes| {
+                metrics.record_bytes_out(&project_name, bytes)
+            })
+        };
+        let body = body.map_err(axum::Error::new).boxed_unsync();
 
         span.record("http.status_code", parts.status.as_u16());
 
         Ok(Response::from_parts(parts, body))
     }
+
+    /// Hold a slot of the connection limit of a project for as long as the client connection is
+    /// open. All the requests a client sends over one connection share the same slot.
+    fn connect(&self, project_name: &ProjectName, max: u32) -> Result<Arc<ConnectionGuard>, Error> {
+        let mut connections = self.connections.lock().unwrap();
+
+        if let Some(connection) = connections.get(project_name) {
+            return Ok(connection.clone());
+        }
+
+        let connection = self
+            .gateway
+            .proxy_limiter()
+            .try_connect(project_name, max)
+            .map(Arc::new)
+            .ok_or_else(|| Error::from_kind(ErrorKind::TooManyConnections))?;
+
+        connections.insert(project_name.clone(), connection.clone());
+
+        Ok(connection)
+    }
+}
+
+/// The IP of the client a request came from. Peers in `trusted_proxies`, such as load balancers,
+/// are trusted to name the client in the `X-Forwarded-For` header, which is read from the right
+/// until an address which is not a trusted proxy comes up.
+fn client_ip(remote_ip: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> IpAddr {
+    if !trusted_proxies.contains(&remote_ip) {
+        return remote_ip;
+    }
+
+    let forwarded: Vec<_> = headers
+        .get_all("X-Forwarded-For")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    let mut client = remote_ip;
+
+    for hop in forwarded.into_iter().rev() {
+        match hop.trim().parse() {
+            Ok(ip) if trusted_proxies.contains(&ip) => client = ip,
+            Ok(ip) => return ip,
+            // Anything left of an address which cannot be read could have been made up
+            Err(_) => break,
+        }
+    }
+
+    client
+}
+
+/// Pass a request `body` on, keeping its trailers, which gRPC calls can carry. The body is failed
+/// as soon as more than `max` bytes have passed through, which is flagged in `exceeded`, and
+/// `record` is called once with its size. Bodies which are known to be empty are left alone, so no
+/// chunked encoding is forced onto them.
+fn forward_request_body(
+    mut body: Body,
+    max: Option<u64>,
+    exceeded: Arc<AtomicBool>,
+    record: impl FnOnce(u64) + Send + 'static,
+) -> Body {
+    if body.is_end_stream() {
+        return body;
+    }
+
+    let (mut sender, forwarded) = Body::channel();
+
+    tokio::spawn(async move {
+        let mut counter = ByteCounter::new(record);
+
+        while let Some(chunk) = body.data().await {
+            let Ok(chunk) = chunk else {
+                sender.abort();
+                return;
+            };
+
+            counter.add(&chunk);
+
+            if max.map_or(false, |max| counter.bytes > max) {
+                exceeded.store(true, Ordering::Relaxed);
+                sender.abort();
+                return;
+            }
+
+            if sender.send_data(chunk).await.is_err() {
+                return;
+            }
+        }
+
+        match body.trailers().await {
+            Ok(Some(trailers)) => {
+                let _ = sender.send_trailers(trailers).await;
+            }
+            Ok(None) => {}
+            Err(_) => sender.abort(),
+        }
+    });
+
+    forwarded
+}
+
+/// Adds up the size of the chunks of a body, and hands the total to `record` once it is dropped.
+/// This keeps the shared metrics from being locked for every chunk.
+struct ByteCounter<F: FnOnce(u64)> {
+    bytes: u64,
+    record: Option<F>,
+}
+
+impl<F: FnOnce(u64)> ByteCounter<F> {
+    fn new(record: F) -> Self {
+        Self {
+            bytes: 0,
+            record: Some(record),
+        }
+    }
+
+    fn add(&mut self, chunk: &Bytes) {
+        self.bytes += chunk.len() as u64;
+    }
+}
+
+impl<F: FnOnce(u64)> Drop for ByteCounter<F> {
+    fn drop(&mut self) {
+        if let Some(record) = self.record.take() {
+            record(self.bytes);
+        }
+    }
+}
+
+/// Calls `record` once with the size of a response body, after it has passed through. The trailers
+/// of the body are kept, since gRPC sends the status of calls in them.
+struct CountBytes<B, F: FnOnce(u64)> {
+    inner: B,
+    counter: ByteCounter<F>,
+}
+
+impl<B, F: FnOnce(u64)> CountBytes<B, F> {
+    fn new(inner: B, record: F) -> Self {
+        Self {
+            inner,
+            counter: ByteCounter::new(record),
+        }
+    }
+}
+
+impl<B, F> HttpBody for CountBytes<B, F>
+where
+    B: HttpBody<Data = Bytes> + Unpin,
+    F: FnOnce(u64) + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_data(cx);
+
+        if let Poll::Ready(Some(Ok(chunk))) = &poll {
+            self.counter.add(chunk);
+        }
+
+        poll
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
 }
 
 impl Service<Request<Body>> for UserProxy {
@@ -236,6 +567,8 @@ pub struct UserServiceBuilder {
     bouncer_binds_to: Option<SocketAddr>,
     user_binds_to: Option<SocketAddr>,
     public: Option<FQDN>,
+    upgrade_idle_timeout: Option<Duration>,
+    trusted_proxies: Vec<IpAddr>,
 }
 
 impl Default for UserServiceBuilder {
@@ -254,6 +587,8 @@ impl UserServiceBuilder {
             tls_acceptor: None,
             bouncer_binds_to: None,
             user_binds_to: None,
+            upgrade_idle_timeout: None,
+            trusted_proxies: Vec::new(),
         }
     }
 
@@ -282,6 +617,16 @@ impl UserServiceBuilder {
         self
     }
 
+    pub fn with_upgrade_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.upgrade_idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    pub fn with_trusted_proxies(mut self, trusted_proxies: Vec<IpAddr>) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
     pub fn with_acme(mut self, acme: AcmeClient) -> Self {
         self.acme = Some(acme);
         self
@@ -305,6 +650,9 @@ impl UserServiceBuilder {
             task_sender,
             remote_addr: "127.0.0.1:80".parse().unwrap(),
             public: public.clone(),
+            upgrade_idle_timeout: self.upgrade_idle_timeout.unwrap_or(UPGRADE_IDLE_TIMEOUT),
+            trusted_proxies: Arc::new(self.trusted_proxies),
+            connections: Default::default(),
         };
 
         let bouncer = self.bouncer_binds_to.as_ref().map(|_| Bouncer {
@@ -363,3 +711,314 @@ impl UserServiceBuilder {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+
+    use axum::extract::ws::WebSocketUpgrade;
+    use axum::routing::get;
+    use axum::Router;
+    use futures::{SinkExt, StreamExt};
+    use hyper::body::{Bytes, HttpBody};
+    use hyper::header::HeaderValue;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Client, HeaderMap, Request, Response, Server, StatusCode};
+    use tokio::net::TcpStream;
+    use tokio::sync::oneshot;
+    use tokio_tungstenite::client_async;
+    use tokio_tungstenite::tungstenite::Message;
+
+    use super::{client_ip, forward_request_body, CountBytes};
+    use crate::service::GatewayService;
+    use crate::tests::{
+        loopback_ip, ready_project, spawn_deployer, spawn_user_proxy, Deployment, World,
+    };
+    use crate::ProjectName;
+
+    /// Start a WebSocket service which echoes every message back
+    fn spawn_echo() -> SocketAddr {
+        let router = Router::new().route(
+            "/ws",
+            get(|ws: WebSocketUpgrade| async {
+                ws.on_upgrade(|mut socket| async move {
+                    while let Some(Ok(message)) = socket.recv().await {
+                        if socket.send(message).await.is_err() {
+                            break;
+                        }
+                    }
+                })
+            }),
+        );
+
+        let server =
+            Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(router.into_make_service());
+        let address = server.local_addr();
+        tokio::spawn(server);
+
+        address
+    }
+
+    /// Start an HTTP/2 service which answers like a gRPC one. It echoes the body and the trailers of
+    /// a call back, and sends its status in the trailers of the response.
+    fn spawn_grpc_echo() -> SocketAddr {
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|req: Request<Body>| async move {
+                let mut body = req.into_body();
+                let mut data = Vec::new();
+
+                while let Some(chunk) = body.data().await {
+                    data.extend_from_slice(&chunk?);
+                }
+
+                let mut trailers = body.trailers().await?.unwrap_or_default();
+                trailers.insert("grpc-status", HeaderValue::from_static("0"));
+
+                let (mut sender, response) = Body::channel();
+                tokio::spawn(async move {
+                    let _ = sender.send_data(data.into()).await;
+                    let _ = sender.send_trailers(trailers).await;
+                });
+
+                Ok::<_, hyper::Error>(
+                    Response::builder()
+                        .header("content-type", "application/grpc")
+                        .body(response)
+                        .unwrap(),
+                )
+            }))
+        });
+
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap())
+            .http2_only(true)
+            .serve(make_service);
+        let address = server.local_addr();
+        tokio::spawn(server);
+
+        address
+    }
+
+    /// Serve the ready project `matrix` through the user proxy and the proxy of its deployer, which
+    /// forwards to the service at `service`. Returns the gateway and the address of its user proxy.
+    async fn serve_matrix(world: &World, service: SocketAddr) -> (Arc<GatewayService>, SocketAddr) {
+        let gateway = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+        let matrix: ProjectName = "matrix".parse().unwrap();
+        let target = loopback_ip();
+
+        gateway
+            .create_project(
+                matrix.clone(),
+                "neo".parse().unwrap(),
+                false,
+                0,
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        gateway
+            .update_project(&matrix, &ready_project(&matrix, target, Default::default()))
+            .await
+            .unwrap();
+
+        let deployment = Deployment {
+            http: service,
+            tcp: None,
+        };
+        spawn_deployer(target, &matrix, &world.fqdn(), deployment).await;
+        let user = spawn_user_proxy(gateway.clone(), world.fqdn()).await;
+
+        (gateway, user)
+    }
+
+    /// A response body like the ones of gRPC calls, which ends with trailers
+    struct GrpcBody {
+        chunks: Vec<Bytes>,
+        trailers: Option<HeaderMap>,
+    }
+
+    impl HttpBody for GrpcBody {
+        type Data = Bytes;
+        type Error = Infallible;
+
+        fn poll_data(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+            Poll::Ready(self.chunks.pop().map(Ok))
+        }
+
+        fn poll_trailers(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+            Poll::Ready(Ok(self.trailers.take()))
+        }
+    }
+
+    #[tokio::test]
+    async fn count_bytes_keeps_trailers() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+
+        let counted = Arc::new(AtomicU64::new(0));
+        let mut body = {
+            let counted = counted.clone();
+            CountBytes::new(
+                GrpcBody {
+                    chunks: vec!["hello".into(), "world".into()],
+                    trailers: Some(trailers),
+                },
+                move |bytes| {
+                    counted.fetch_add(bytes, Ordering::Relaxed);
+                },
+            )
+        };
+
+        while let Some(chunk) = body.data().await {
+            chunk.unwrap();
+        }
+        let trailers = body.trailers().await.unwrap().unwrap();
+
+        assert_eq!(trailers["grpc-status"], "0");
+
+        // The size is only recorded once, when the body is done with
+        assert_eq!(counted.load(Ordering::Relaxed), 0);
+        drop(body);
+        assert_eq!(counted.load(Ordering::Relaxed), 10);
+    }
+
+    #[test]
+    fn client_ip_from_trusted_proxies() {
+        let balancer = "10.0.0.1".parse().unwrap();
+        let client = "203.0.113.7".parse().unwrap();
+        let trusted = [balancer];
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Forwarded-For",
+            "198.51.100.1, 203.0.113.7".parse().unwrap(),
+        );
+
+        // Only the rightmost address a trusted proxy added is believed
+        assert_eq!(client_ip(balancer, &headers, &trusted), client);
+
+        // Anyone else could make the header up
+        assert_eq!(client_ip(client, &headers, &trusted), client);
+
+        // Trusted proxies in the chain are skipped
+        headers.insert("X-Forwarded-For", "203.0.113.7, 10.0.0.1".parse().unwrap());
+        assert_eq!(client_ip(balancer, &headers, &trusted), client);
+
+        // And without a usable header the proxy itself is the client
+        headers.insert("X-Forwarded-For", "unknown".parse().unwrap());
+        assert_eq!(client_ip(balancer, &headers, &trusted), balancer);
+        assert_eq!(client_ip(balancer, &HeaderMap::new(), &trusted), balancer);
+    }
+
+    #[tokio::test]
+    async fn websocket_through_gateway_and_deployer() {
+        let world = World::new().await;
+        let (gateway, user) = serve_matrix(&world, spawn_echo()).await;
+
+        let stream = TcpStream::connect(user).await.unwrap();
+        let (mut ws, _) = client_async(format!("ws://matrix.{}/ws", world.fqdn()), stream)
+            .await
+            .unwrap();
+
+        for text in ["hello", "world"] {
+            ws.send(Message::Text(text.to_string())).await.unwrap();
+
+            assert_eq!(
+                ws.next().await.unwrap().unwrap(),
+                Message::Text(text.to_string())
+            );
+        }
+
+        // The upgraded connection is counted as open while it is piped by the gateway
+        assert_eq!(
+            gateway
+                .proxy_metrics()
+                .open_connections(&"matrix".parse().unwrap()),
+            1
+        );
+
+        ws.close(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn request_body_keeps_trailers() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-checksum", "42".parse().unwrap());
+
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            sender.send_data("hello".into()).await.unwrap();
+            sender.send_trailers(trailers).await.unwrap();
+        });
+
+        let (recorded, size) = oneshot::channel();
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let mut body = forward_request_body(body, Some(5), exceeded.clone(), move |bytes| {
+            let _ = recorded.send(bytes);
+        });
+
+        assert_eq!(body.data().await.unwrap().unwrap(), "hello");
+        assert!(body.data().await.is_none());
+        assert_eq!(body.trailers().await.unwrap().unwrap()["x-checksum"], "42");
+
+        assert!(!exceeded.load(Ordering::Relaxed));
+        assert_eq!(size.await.unwrap(), 5);
+    }
+
+    #[tokio::test]
+    async fn request_body_limit() {
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let mut body =
+            forward_request_body(Body::from("hello world"), Some(5), exceeded.clone(), |_| {});
+
+        assert!(body.data().await.unwrap().is_err());
+        assert!(exceeded.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn grpc_trailers_through_gateway_and_deployer() {
+        let world = World::new().await;
+        let (_, user) = serve_matrix(&world, spawn_grpc_echo()).await;
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-checksum", "42".parse().unwrap());
+
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            sender.send_data("hello".into()).await.unwrap();
+            sender.send_trailers(trailers).await.unwrap();
+        });
+
+        let request = Request::post(format!("http://{user}/echo.Echo/Say"))
+            .header("host", format!("matrix.{}", world.fqdn()))
+            .header("content-type", "application/grpc")
+            .body(body)
+            .unwrap();
+        let response = Client::builder()
+            .http2_only(true)
+            .build_http()
+            .request(request)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut body = response.into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), "hello");
+
+        // Both the trailers of the call and the ones of its response made it through both hops
+        let trailers = body.trailers().await.unwrap().unwrap();
+        assert_eq!(trailers["grpc-status"], "0");
+        assert_eq!(trailers["x-checksum"], "42");
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use tracing::{error, info, instrument, warn};
+
+use crate::acme::{AcmeClient, CustomDomain};
+use crate::service::GatewayService;
+use crate::tls::{certificate_expiry, GatewayCertResolver};
+
+/// Time between the checks for certificates which are about to expire
+pub const RENEWAL_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How long before they expire certificates are renewed
+#[derive(Clone, Copy, Debug)]
+pub struct RenewalWindow(pub chrono::Duration);
+
+impl RenewalWindow {
+    pub fn days(days: u32) -> Self {
+        Self(chrono::Duration::days(days.into()))
+    }
+}
+
+/// The certificates which failed to renew, by their domain, along with why they failed
+#[derive(Clone, Default)]
+pub struct RenewalFailures(Arc<Mutex<BTreeMap<String, String>>>);
+
+impl RenewalFailures {
+    /// The domains of the certificates which failed to renew
+    pub fn domains(&self) -> Vec<String> {
+        self.0.lock().unwrap().keys().cloned().collect()
+    }
+
+    fn replace(&self, failures: BTreeMap<String, String>) {
+        *self.0.lock().unwrap() = failures;
+    }
+}
+
+/// Renews the certificates of custom domains in the background before they expire
+pub struct CertificateRenewer {
+    service: Arc<GatewayService>,
+    acme: AcmeClient,
+    resolver: Arc<GatewayCertResolver>,
+    window: RenewalWindow,
+}
+
+impl CertificateRenewer {
+    /// Renew certificates once they expire within the `window`
+    pub fn new(
+        service: Arc<GatewayService>,
+        acme: AcmeClient,
+        resolver: Arc<GatewayCertResolver>,
+        window: RenewalWindow,
+    ) -> Self {
+        Self {
+            service,
+            acme,
+            resolver,
+            window,
+        }
+    }
+
+    /// Check the certificates on every [RENEWAL_CHECK_INTERVAL]
+    pub async fn start(self) {
+        let mut interval = tokio::time::interval(RENEWAL_CHECK_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            self.renew_expiring().await;
+        }
+    }
+
+    /// Renew the certificate of every custom domain which expires within the window, and
+    /// record those which could not be renewed
+    #[instrument(skip(self))]
+    pub async fn renew_expiring(&self) {
+        let custom_domains = match self.service.iter_custom_domains().await {
+            Ok(custom_domains) => custom_domains,
+            Err(error) => {
+                error!(%error, "failed to get the custom domains to renew");
+                return;
+            }
+        };
+
+        let deadline = Utc::now() + self.window.0;
+        let mut failures = BTreeMap::new();
+
+        for CustomDomain {
+            fqdn,
+            project_name,
+            certificate,
+            ..
+        } in custom_domains
+        {
+            let domain = fqdn.to_string();
+
+            match certificate_expiry(&certificate) {
+                Ok(expires) if expires > deadline => continue,
+                Ok(expires) => info!(domain, %expires, "renewing custom domain certificate"),
+                Err(error) => warn!(
+                    domain,
+                    %error,
+                    "failed to get the expiry of a custom domain certificate, renewing it"
+                ),
+            }
+
+            let result = match self.service.try_credentials() {
+                Ok(credentials) => {
+                    self.service
+                        .renew_custom_domain_certificate(
+                            &project_name,
+                            &fqdn,
+                            &self.acme,
+                            &self.resolver,
+                            credentials,
+                        )
+                        .await
+                }
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(()) => info!(domain, "renewed custom domain certificate"),
+                Err(error) => {
+                    error!(domain, %error, "failed to renew custom domain certificate");
+                    failures.insert(domain, error.to_string());
+                }
+            }
+        }
+
+        // The gateway certificate is a wildcard one, which needs a DNS-01 challenge that has to
+        // be completed by hand. So it can only be reported here.
+        match self.service.gateway_certificate_expiry() {
+            Ok(expires) if expires > deadline => {}
+            Ok(expires) => {
+                warn!(
+                    %expires,
+                    "gateway certificate is about to expire, renew it with the admin `acme renew-gateway` command"
+                );
+                failures.insert(
+                    "gateway".to_string(),
+                    format!("gateway certificate expires at {expires}"),
+                );
+            }
+            Err(error) => warn!(%error, "failed to get the expiry of the gateway certificate"),
+        }
+
+        self.service.renewal_failures().replace(failures);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use fqdn::FQDN;
+
+    use super::{CertificateRenewer, RenewalWindow};
+    use crate::acme::AcmeClient;
+    use crate::service::GatewayService;
+    use crate::tests::{certificate, World};
+    use crate::tls::GatewayCertResolver;
+    use crate::{AccountName, ProjectName};
+
+    #[tokio::test]
+    async fn renew_expiring() {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+        let renewer = CertificateRenewer::new(
+            svc.clone(),
+            AcmeClient::new(),
+            Arc::new(GatewayCertResolver::new()),
+            RenewalWindow::days(30),
+        );
+
+        let account: AccountName = "neo".parse().unwrap();
+        let expired: ProjectName = "matrix".parse().unwrap();
+        let valid: ProjectName = "zion".parse().unwrap();
+        let expired_domain: FQDN = "neo.the.matrix".parse().unwrap();
+        let valid_domain: FQDN = "neo.in.zion".parse().unwrap();
+
+        for project_name in [&expired, &valid] {
+            svc.create_project(
+                project_name.clone(),
+                account.clone(),
+                false,
+                0,
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        }
+
+        svc.create_custom_domain(
+            &expired,
+            &expired_domain,
+            &certificate("neo.the.matrix", 2000),
+            "dummy private key",
+        )
+        .await
+        .unwrap();
+        svc.create_custom_domain(
+            &valid,
+            &valid_domain,
+            &certificate("neo.in.zion", 4000),
+            "dummy private key",
+        )
+        .await
+        .unwrap();
+
+        // There are no ACME credentials in the test state, so the expired certificate cannot be
+        // renewed and the gateway has no certificate of its own
+        renewer.renew_expiring().await;
+
+        assert_eq!(
+            svc.renewal_failures().domains(),
+            vec!["neo.the.matrix".to_string()]
+        );
+
+        svc.delete_custom_domain(&expired_domain).await.unwrap();
+        renewer.renew_expiring().await;
+
+        assert!(svc.renewal_failures().domains().is_empty());
+    }
+}
@@ -1,6 +1,7 @@
+use std::collections::HashSet;
 use std::io::Cursor;
-use std::net::Ipv4Addr;
-use std::ops::Sub;
+use std::net::{IpAddr, Ipv4Addr};
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -9,6 +10,7 @@ use axum::headers::HeaderMapExt;
 use axum::http::Request;
 use axum::response::Response;
 use bollard::{Docker, API_DEFAULT_VERSION};
+use chrono::{DateTime, Utc};
 use fqdn::{Fqdn, FQDN};
 use hyper::client::connect::dns::GaiResolver;
 use hyper::client::HttpConnector;
@@ -18,25 +20,26 @@ use instant_acme::{AccountCredentials, ChallengeType};
 use once_cell::sync::Lazy;
 use opentelemetry::global;
 use opentelemetry_http::HeaderInjector;
+use ring::hkdf;
 use shuttle_common::backends::headers::{XShuttleAccountName, XShuttleAdminSecret};
+use shuttle_common::models::project::Limits;
 use sqlx::error::DatabaseError;
 use sqlx::migrate::Migrator;
 use sqlx::sqlite::SqlitePool;
 use sqlx::types::Json as SqlxJson;
 use sqlx::{query, Error as SqlxError, Row};
 use tokio::sync::mpsc::Sender;
-use tracing::{debug, trace, warn, Span};
+use tracing::{debug, error, trace, warn, Span};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
-use x509_parser::nom::AsBytes;
-use x509_parser::parse_x509_certificate;
-use x509_parser::prelude::parse_x509_pem;
-use x509_parser::time::ASN1Time;
 
 use crate::acme::{AccountWrapper, AcmeClient, CustomDomain};
 use crate::args::ContextArgs;
+use crate::limits::ProxyLimiter;
+use crate::metrics::ProxyMetrics;
 use crate::project::{Project, ProjectCreating};
+use crate::renewal::{RenewalFailures, RenewalWindow};
 use crate::task::{self, BoxedTask, TaskBuilder};
-use crate::tls::{ChainAndPrivateKey, GatewayCertResolver, RENEWAL_VALIDITY_THRESHOLD_IN_DAYS};
+use crate::tls::{certificate_expiry, remaining_validity, ChainAndPrivateKey, GatewayCertResolver};
 use crate::worker::TaskRouter;
 use crate::{AccountName, DockerContext, Error, ErrorKind, ProjectDetails, ProjectName};
 
@@ -58,6 +61,7 @@ pub struct ContainerSettingsBuilder {
     auth_uri: Option<String>,
     network_name: Option<String>,
     fqdn: Option<String>,
+    secrets_key: Option<SecretsMasterKey>,
 }
 
 impl Default for ContainerSettingsBuilder {
@@ -75,6 +79,7 @@ impl ContainerSettingsBuilder {
             auth_uri: None,
             network_name: None,
             fqdn: None,
+            secrets_key: None,
         }
     }
 
@@ -86,14 +91,22 @@ impl ContainerSettingsBuilder {
             auth_uri,
             image,
             proxy_fqdn,
+            deployer_secrets_key_file,
             ..
         } = args;
+
+        let secrets_key = std::fs::read_to_string(deployer_secrets_key_file)
+            .expect("the deployer secrets key file to be readable");
+        let secrets_key = SecretsMasterKey::from_base64(&secrets_key)
+            .expect("the deployer secrets key to be a base64 encoded 256-bit key");
+
         self.prefix(prefix)
             .image(image)
             .provisioner_host(provisioner_host)
             .auth_uri(auth_uri)
             .network_name(network_name)
             .fqdn(proxy_fqdn)
+            .secrets_key(secrets_key)
             .build()
             .await
     }
@@ -128,6 +141,11 @@ impl ContainerSettingsBuilder {
         self
     }
 
+    pub fn secrets_key(mut self, secrets_key: SecretsMasterKey) -> Self {
+        self.secrets_key = Some(secrets_key);
+        self
+    }
+
     pub async fn build(mut self) -> ContainerSettings {
         let prefix = self.prefix.take().unwrap();
         let image = self.image.take().unwrap();
@@ -136,6 +154,7 @@ impl ContainerSettingsBuilder {
 
         let network_name = self.network_name.take().unwrap();
         let fqdn = self.fqdn.take().unwrap();
+        let secrets_key = self.secrets_key.take().unwrap();
 
         ContainerSettings {
             prefix,
@@ -144,6 +163,7 @@ impl ContainerSettingsBuilder {
             auth_uri,
             network_name,
             fqdn,
+            secrets_key,
         }
     }
 }
@@ -156,6 +176,7 @@ pub struct ContainerSettings {
     pub auth_uri: String,
     pub network_name: String,
     pub fqdn: String,
+    pub secrets_key: SecretsMasterKey,
 }
 
 impl ContainerSettings {
@@ -164,20 +185,54 @@ impl ContainerSettings {
     }
 }
 
+/// The master key the keys deployers encrypt secrets at rest with are derived from. It never
+/// leaves the gateway, and each deployer only gets the key of its own project, so a leaked
+/// deployer key exposes the secrets of a single project.
+#[derive(Clone)]
+pub struct SecretsMasterKey([u8; 32]);
+
+impl SecretsMasterKey {
+    pub fn from_base64(encoded: &str) -> Option<Self> {
+        let key = base64::decode(encoded.trim()).ok()?;
+
+        key.try_into().ok().map(Self)
+    }
+
+    /// Derive the base64 encoded key the deployer of a project encrypts its secrets with
+    pub fn project_key(&self, project_name: &ProjectName) -> String {
+        let salt = hkdf::Salt::new(hkdf::HKDF_SHA256, b"shuttle-deployer-secrets");
+        let info = [project_name.as_str().as_bytes()];
+        let mut key = [0; 32];
+
+        salt.extract(&self.0)
+            .expand(&info, hkdf::HKDF_SHA256)
+            .and_then(|okm| okm.fill(&mut key))
+            .expect("a 256-bit key to be a valid HKDF-SHA256 output");
+
+        base64::encode(key)
+    }
+}
+
 pub struct GatewayContextProvider {
     docker: Docker,
     settings: ContainerSettings,
+    proxy_metrics: ProxyMetrics,
 }
 
 impl GatewayContextProvider {
-    pub fn new(docker: Docker, settings: ContainerSettings) -> Self {
-        Self { docker, settings }
+    pub fn new(docker: Docker, settings: ContainerSettings, proxy_metrics: ProxyMetrics) -> Self {
+        Self {
+            docker,
+            settings,
+            proxy_metrics,
+        }
     }
 
     pub fn context(&self) -> GatewayContext {
         GatewayContext {
             docker: self.docker.clone(),
             settings: self.settings.clone(),
+            proxy_metrics: self.proxy_metrics.clone(),
         }
     }
 }
@@ -187,6 +242,9 @@ pub struct GatewayService {
     db: SqlitePool,
     task_router: TaskRouter<BoxedTask>,
     state_location: PathBuf,
+    renewal_failures: RenewalFailures,
+    proxy_metrics: ProxyMetrics,
+    proxy_limiter: ProxyLimiter,
 }
 
 impl GatewayService {
@@ -199,7 +257,10 @@ impl GatewayService {
 
         let container_settings = ContainerSettings::builder().from_args(&args).await;
 
-        let provider = GatewayContextProvider::new(docker, container_settings);
+        let proxy_metrics = ProxyMetrics::default();
+
+        let provider =
+            GatewayContextProvider::new(docker, container_settings, proxy_metrics.clone());
 
         let task_router = TaskRouter::new();
 
@@ -208,6 +269,9 @@ impl GatewayService {
             db,
             task_router,
             state_location,
+            renewal_failures: Default::default(),
+            proxy_metrics,
+            proxy_limiter: Default::default(),
         }
     }
 
@@ -373,6 +437,7 @@ impl GatewayService {
         account_name: AccountName,
         is_admin: bool,
         idle_minutes: u64,
+        limits: Limits,
     ) -> Result<Project, Error> {
         if let Some(row) = query(
             r#"
@@ -395,7 +460,8 @@ impl GatewayService {
                 let mut creating = ProjectCreating::new_with_random_initial_key(
                     project_name.clone(),
                     idle_minutes,
-                );
+                )
+                .with_limits(limits);
                 // Restore previous custom domain, if any
                 match self.find_custom_domain_for_project(&project_name).await {
                     Ok(custom_domain) => {
@@ -422,7 +488,7 @@ impl GatewayService {
                 // Otherwise attempt to create a new one. This will fail
                 // outright if the project already exists (this happens if
                 // it belongs to another account).
-                self.insert_project(project_name, account_name, idle_minutes)
+                self.insert_project(project_name, account_name, idle_minutes, limits)
                     .await
             } else {
                 Err(Error::from_kind(ErrorKind::InvalidProjectName))
@@ -435,9 +501,11 @@ impl GatewayService {
         project_name: ProjectName,
         account_name: AccountName,
         idle_minutes: u64,
+        limits: Limits,
     ) -> Result<Project, Error> {
         let project = SqlxJson(Project::Creating(
-            ProjectCreating::new_with_random_initial_key(project_name.clone(), idle_minutes),
+            ProjectCreating::new_with_random_initial_key(project_name.clone(), idle_minutes)
+                .with_limits(limits),
         ));
 
         query("INSERT INTO projects (project_name, account_name, initial_key, project_state) VALUES (?1, ?2, ?3, ?4)")
@@ -537,6 +605,127 @@ impl GatewayService {
         Ok(custom_domain)
     }
 
+    pub async fn delete_custom_domain(&self, fqdn: &Fqdn) -> Result<(), Error> {
+        let result = query("DELETE FROM custom_domains WHERE fqdn = ?1")
+            .bind(fqdn.to_string())
+            .execute(&self.db)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::from(ErrorKind::CustomDomainNotFound));
+        }
+
+        Ok(())
+    }
+
+    /// Allocate the lowest free port in `ports` to a project for its raw TCP connections, or get
+    /// the one it already has
+    pub async fn allocate_tcp_port(
+        &self,
+        project_name: &ProjectName,
+        ports: RangeInclusive<u16>,
+    ) -> Result<u16, Error> {
+        let mut transaction = self.db.begin().await?;
+
+        // Picking the port and taking it happen in a single statement, so two projects can never
+        // be given the same port
+        query(
+            r#"
+        WITH RECURSIVE candidates(port) AS (
+            SELECT ?2
+            UNION ALL
+            SELECT port + 1 FROM candidates WHERE port < ?3
+        )
+        INSERT INTO tcp_ports (project_name, port)
+        SELECT ?1, port FROM candidates
+        WHERE port NOT IN (SELECT port FROM tcp_ports)
+        ORDER BY port
+        LIMIT 1
+        ON CONFLICT (project_name) DO NOTHING
+        "#,
+        )
+        .bind(project_name)
+        .bind(*ports.start())
+        .bind(*ports.end())
+        .execute(&mut transaction)
+        .await?;
+
+        let port = query("SELECT port FROM tcp_ports WHERE project_name = ?1")
+            .bind(project_name)
+            .fetch_optional(&mut transaction)
+            .await?
+            .map(|row| row.get("port"))
+            .ok_or_else(|| Error::from(ErrorKind::TcpPortsExhausted))?;
+
+        transaction.commit().await?;
+
+        Ok(port)
+    }
+
+    pub async fn find_tcp_port(&self, project_name: &ProjectName) -> Result<u16, Error> {
+        query("SELECT port FROM tcp_ports WHERE project_name = ?1")
+            .bind(project_name)
+            .fetch_optional(&self.db)
+            .await?
+            .map(|row| row.get("port"))
+            .ok_or_else(|| Error::from(ErrorKind::TcpPortNotFound))
+    }
+
+    pub async fn iter_tcp_ports(&self) -> Result<impl Iterator<Item = (ProjectName, u16)>, Error> {
+        let iter = query("SELECT project_name, port FROM tcp_ports")
+            .fetch_all(&self.db)
+            .await?
+            .into_iter()
+            .map(|row| (row.get("project_name"), row.get("port")));
+        Ok(iter)
+    }
+
+    /// Give up the port of a project, returning the port it had
+    pub async fn delete_tcp_port(&self, project_name: &ProjectName) -> Result<u16, Error> {
+        let port = self.find_tcp_port(project_name).await?;
+
+        query("DELETE FROM tcp_ports WHERE project_name = ?1")
+            .bind(project_name)
+            .execute(&self.db)
+            .await?;
+
+        Ok(port)
+    }
+
+    /// Check that a custom domain resolves to the same addresses as the shuttle address of the
+    /// project, since the ACME HTTP-01 challenge for it can only be completed when it reaches us.
+    pub async fn verify_custom_domain_target(
+        &self,
+        project_name: &ProjectName,
+        fqdn: &Fqdn,
+    ) -> Result<(), Error> {
+        let public = self.context().settings.fqdn;
+        let domain = fqdn.to_string();
+
+        // Our own domains are covered by the gateway certificate already
+        if domain == public || domain.ends_with(&format!(".{public}")) {
+            return Err(Error::from_kind(ErrorKind::InvalidCustomDomain));
+        }
+
+        let project_address = format!("{project_name}.{public}");
+        let expected = lookup_ips(&project_address).await.map_err(|error| {
+            warn!(%error, project_address, "failed to resolve the address of a project");
+            Error::source(ErrorKind::Internal, error)
+        })?;
+        let actual = lookup_ips(&domain)
+            .await
+            .map_err(|error| Error::source(ErrorKind::CustomDomainMisconfigured, error))?;
+
+        if actual.is_empty() || !actual.is_subset(&expected) {
+            return Err(Error::custom(
+                ErrorKind::CustomDomainMisconfigured,
+                format!("{domain} resolves to {actual:?} instead of {expected:?}"),
+            ));
+        }
+
+        Ok(())
+    }
+
     pub async fn iter_projects_detailed(
         &self,
     ) -> Result<impl Iterator<Item = ProjectDetails>, Error> {
@@ -579,6 +768,33 @@ impl GatewayService {
         }
     }
 
+    /// Create a new certificate for a custom domain with a HTTP-01 challenge, store it and start
+    /// serving it right away
+    pub async fn renew_custom_domain_certificate(
+        &self,
+        project_name: &ProjectName,
+        fqdn: &Fqdn,
+        acme: &AcmeClient,
+        resolver: &GatewayCertResolver,
+        creds: AccountCredentials<'_>,
+    ) -> Result<(), Error> {
+        let (certs, private_key) = acme
+            .create_certificate(&fqdn.to_string(), ChallengeType::Http01, creds)
+            .await?;
+
+        self.create_custom_domain(project_name, fqdn, &certs, &private_key)
+            .await?;
+
+        let mut buf = Vec::new();
+        buf.extend(certs.as_bytes());
+        buf.extend(private_key.as_bytes());
+        resolver
+            .serve_pem(&fqdn.to_string(), Cursor::new(buf))
+            .await?;
+
+        Ok(())
+    }
+
     async fn create_certificate<'a>(
         &self,
         acme: &AcmeClient,
@@ -625,33 +841,33 @@ impl GatewayService {
         }
     }
 
-    /// Renew the gateway certificate if there less than 30 days until the current
-    /// certificate expiration.
+    /// Get when the gateway certificate in the state location expires
+    pub fn gateway_certificate_expiry(&self) -> Result<DateTime<Utc>, Error> {
+        let tls_path = self.state_location.join("ssl.pem");
+        let certs = ChainAndPrivateKey::load_pem(tls_path)?.into_pem()?;
+
+        certificate_expiry(&certs)
+    }
+
+    /// Renew the gateway certificate if it expires within the `renewal_window`
     pub(crate) async fn renew_certificate(
         &self,
         acme: &AcmeClient,
         resolver: Arc<GatewayCertResolver>,
+        renewal_window: RenewalWindow,
         creds: AccountCredentials<'_>,
-    ) {
+    ) -> Result<(), Error> {
         let account = AccountWrapper::from(creds).0;
         let certs = self.fetch_certificate(acme, account.credentials()).await;
-        // Safe to unwrap because a 'ChainAndPrivateKey' is built from a PEM.
-        let chain_and_pk = certs.into_pem().unwrap();
-
-        let (_, pem) = parse_x509_pem(chain_and_pk.as_bytes())
-            .unwrap_or_else(|_| panic!("Malformed existing PEM certificate for the gateway."));
-        let (_, x509_cert) = parse_x509_certificate(pem.contents.as_bytes())
-            .unwrap_or_else(|_| panic!("Malformed existing X509 certificate for the gateway."));
-        let diff = x509_cert.validity().not_after.sub(ASN1Time::now()).unwrap();
-        if diff.whole_days() <= RENEWAL_VALIDITY_THRESHOLD_IN_DAYS {
+
+        if remaining_validity(&certs.into_pem()?, renewal_window.0)?.is_none() {
             let tls_path = self.state_location.join("ssl.pem");
             let certs = self.create_certificate(acme, account.credentials()).await;
-            resolver
-                .serve_default_der(certs.clone())
-                .await
-                .expect("Failed to serve the default certs");
-            certs.save_pem(&tls_path).unwrap();
+            resolver.serve_default_der(certs.clone()).await?;
+            certs.save_pem(&tls_path)?;
         }
+
+        Ok(())
     }
 
     pub fn context(&self) -> GatewayContext {
@@ -696,6 +912,21 @@ impl GatewayService {
         self.task_router.clone()
     }
 
+    /// The certificates which failed to renew on their last attempt
+    pub fn renewal_failures(&self) -> &RenewalFailures {
+        &self.renewal_failures
+    }
+
+    /// The traffic which passed through the user proxy
+    pub fn proxy_metrics(&self) -> &ProxyMetrics {
+        &self.proxy_metrics
+    }
+
+    /// The state of the limits the user proxy enforces on projects
+    pub fn proxy_limiter(&self) -> &ProxyLimiter {
+        &self.proxy_limiter
+    }
+
     pub fn credentials(&self) -> AccountCredentials<'_> {
         let creds_path = self.state_location.join("acme.json");
         if !creds_path.exists() {
@@ -708,12 +939,34 @@ impl GatewayService {
         serde_json::from_reader(std::fs::File::open(creds_path).expect("Invalid credentials path"))
             .expect("Can not parse admin credentials from path")
     }
+
+    /// Like [GatewayService::credentials], but for requests which should fail rather than take
+    /// down the gateway when the credentials are missing
+    pub fn try_credentials(&self) -> Result<AccountCredentials<'_>, Error> {
+        let creds_path = self.state_location.join("acme.json");
+        let file = std::fs::File::open(&creds_path).map_err(|error| {
+            error!(%error, path = %creds_path.display(), "failed to open ACME credentials");
+            Error::source(ErrorKind::Internal, error)
+        })?;
+
+        serde_json::from_reader(file).map_err(|error| {
+            error!(%error, "failed to parse ACME credentials");
+            Error::source(ErrorKind::Internal, error)
+        })
+    }
+}
+
+async fn lookup_ips(host: &str) -> std::io::Result<HashSet<IpAddr>> {
+    let addrs = tokio::net::lookup_host((host, 443)).await?;
+
+    Ok(addrs.map(|addr| addr.ip()).collect())
 }
 
 #[derive(Clone)]
 pub struct GatewayContext {
     docker: Docker,
     settings: ContainerSettings,
+    proxy_metrics: ProxyMetrics,
 }
 
 impl DockerContext for GatewayContext {
@@ -724,6 +977,10 @@ impl DockerContext for GatewayContext {
     fn container_settings(&self) -> &ContainerSettings {
         &self.settings
     }
+
+    fn has_open_connections(&self, project_name: &ProjectName) -> bool {
+        self.proxy_metrics.open_connections(project_name) > 0
+    }
 }
 
 #[cfg(test)]
@@ -736,6 +993,25 @@ pub mod tests {
     use crate::tests::{assert_err_kind, World};
     use crate::{Error, ErrorKind};
 
+    #[test]
+    fn project_secrets_keys() {
+        let master = SecretsMasterKey::from_base64(&base64::encode([7; 32])).unwrap();
+        let other_master = SecretsMasterKey::from_base64(&base64::encode([8; 32])).unwrap();
+
+        let matrix: ProjectName = "matrix".parse().unwrap();
+        let zion: ProjectName = "zion".parse().unwrap();
+
+        let key = master.project_key(&matrix);
+
+        assert_eq!(base64::decode(&key).unwrap().len(), 32);
+        assert_eq!(key, master.project_key(&matrix));
+        assert_ne!(key, master.project_key(&zion));
+        assert_ne!(key, other_master.project_key(&matrix));
+
+        assert!(SecretsMasterKey::from_base64(&base64::encode([7; 16])).is_none());
+        assert!(SecretsMasterKey::from_base64("not base64!").is_none());
+    }
+
     #[tokio::test]
     async fn service_create_find_delete_project() -> anyhow::Result<()> {
         let world = World::new().await;
@@ -753,7 +1029,7 @@ pub mod tests {
         };
 
         let project = svc
-            .create_project(matrix.clone(), neo.clone(), false, 0)
+            .create_project(matrix.clone(), neo.clone(), false, 0, Default::default())
             .await
             .unwrap();
 
@@ -814,8 +1090,14 @@ pub mod tests {
 
         // If recreated by a different user
         assert!(matches!(
-            svc.create_project(matrix.clone(), trinity.clone(), false, 0)
-                .await,
+            svc.create_project(
+                matrix.clone(),
+                trinity.clone(),
+                false,
+                0,
+                Default::default()
+            )
+            .await,
             Err(Error {
                 kind: ErrorKind::ProjectAlreadyExists,
                 ..
@@ -824,7 +1106,8 @@ pub mod tests {
 
         // If recreated by the same user
         assert!(matches!(
-            svc.create_project(matrix.clone(), neo, false, 0).await,
+            svc.create_project(matrix.clone(), neo, false, 0, Default::default())
+                .await,
             Ok(Project::Creating(_))
         ));
 
@@ -845,7 +1128,8 @@ pub mod tests {
 
         // If recreated by an admin
         assert!(matches!(
-            svc.create_project(matrix, trinity, true, 0).await,
+            svc.create_project(matrix, trinity, true, 0, Default::default())
+                .await,
             Ok(Project::Creating(_))
         ));
 
@@ -860,7 +1144,7 @@ pub mod tests {
         let neo: AccountName = "neo".parse().unwrap();
         let matrix: ProjectName = "matrix".parse().unwrap();
 
-        svc.create_project(matrix.clone(), neo.clone(), false, 0)
+        svc.create_project(matrix.clone(), neo.clone(), false, 0, Default::default())
             .await
             .unwrap();
 
@@ -925,7 +1209,13 @@ pub mod tests {
         );
 
         let _ = svc
-            .create_project(project_name.clone(), account.clone(), false, 0)
+            .create_project(
+                project_name.clone(),
+                account.clone(),
+                false,
+                0,
+                Default::default(),
+            )
             .await
             .unwrap();
 
@@ -962,6 +1252,105 @@ pub mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn service_delete_custom_domain() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let account: AccountName = "neo".parse().unwrap();
+        let project_name: ProjectName = "matrix".parse().unwrap();
+        let domain: FQDN = "neo.the.matrix".parse().unwrap();
+
+        assert_err_kind!(
+            svc.delete_custom_domain(&domain).await,
+            ErrorKind::CustomDomainNotFound
+        );
+
+        let _ = svc
+            .create_project(
+                project_name.clone(),
+                account.clone(),
+                false,
+                0,
+                Default::default(),
+            )
+            .await
+            .unwrap();
+
+        svc.create_custom_domain(&project_name, &domain, "dummy certificate", "dummy key")
+            .await
+            .unwrap();
+
+        svc.delete_custom_domain(&domain).await.unwrap();
+
+        assert_err_kind!(
+            svc.find_custom_domain_for_project(&project_name).await,
+            ErrorKind::CustomDomainNotFound
+        );
+
+        // Subdomains of the proxy are never custom domains
+        let own_domain: FQDN = format!("{project_name}.{}", svc.context().settings.fqdn)
+            .parse()
+            .unwrap();
+
+        assert_err_kind!(
+            svc.verify_custom_domain_target(&project_name, &own_domain)
+                .await,
+            ErrorKind::InvalidCustomDomain
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_allocate_delete_tcp_port() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let account: AccountName = "neo".parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
+        let zion: ProjectName = "zion".parse().unwrap();
+        let nebuchadnezzar: ProjectName = "nebuchadnezzar".parse().unwrap();
+
+        for project_name in [&matrix, &zion, &nebuchadnezzar] {
+            svc.create_project(
+                project_name.clone(),
+                account.clone(),
+                false,
+                0,
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        }
+
+        assert_err_kind!(svc.find_tcp_port(&matrix).await, ErrorKind::TcpPortNotFound);
+
+        assert_eq!(svc.allocate_tcp_port(&matrix, 30000..=30001).await?, 30000);
+        assert_eq!(svc.allocate_tcp_port(&zion, 30000..=30001).await?, 30001);
+
+        // Allocating again keeps the port a project already has
+        assert_eq!(svc.allocate_tcp_port(&matrix, 30000..=30001).await?, 30000);
+
+        assert_err_kind!(
+            svc.allocate_tcp_port(&nebuchadnezzar, 30000..=30001).await,
+            ErrorKind::TcpPortsExhausted
+        );
+
+        assert_eq!(svc.delete_tcp_port(&matrix).await?, 30000);
+        assert_eq!(
+            svc.allocate_tcp_port(&nebuchadnezzar, 30000..=30001)
+                .await?,
+            30000
+        );
+
+        let mut ports: Vec<_> = svc.iter_tcp_ports().await?.collect();
+        ports.sort_by_key(|(_, port)| *port);
+        assert_eq!(ports, vec![(nebuchadnezzar, 30000), (zion, 30001)]);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn service_create_custom_domain_destroy_recreate_project() -> anyhow::Result<()> {
         let world = World::new().await;
@@ -979,7 +1368,13 @@ pub mod tests {
         );
 
         let _ = svc
-            .create_project(project_name.clone(), account.clone(), false, 0)
+            .create_project(
+                project_name.clone(),
+                account.clone(),
+                false,
+                0,
+                Default::default(),
+            )
             .await
             .unwrap();
 
@@ -997,7 +1392,13 @@ pub mod tests {
         assert!(matches!(work.poll(()).await, TaskResult::Done(())));
 
         let recreated_project = svc
-            .create_project(project_name.clone(), account.clone(), false, 0)
+            .create_project(
+                project_name.clone(),
+                account.clone(),
+                false,
+                0,
+                Default::default(),
+            )
             .await
             .unwrap();
 
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::Duration;
+
+use fqdn::FQDN;
+use shuttle_common::backends::upgrade::copy_bidirectional;
+use shuttle_common::models::tcp;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::project::ContainerInspectResponseExt;
+use crate::service::GatewayService;
+use crate::task::BoxedTask;
+use crate::{Error, ErrorKind, ProjectName};
+
+/// Forwards the raw TCP connections coming in on the public ports allocated to projects to their
+/// deployers, which pass them on to the service declaring a `[tcp]` table in its Shuttle.toml
+#[derive(Clone)]
+pub struct TcpProxy {
+    service: Arc<GatewayService>,
+    task_sender: Sender<BoxedTask>,
+    bind_ip: IpAddr,
+    ports: RangeInclusive<u16>,
+    public: FQDN,
+    idle_timeout: Duration,
+    listeners: Arc<Mutex<HashMap<u16, JoinHandle<()>>>>,
+}
+
+impl TcpProxy {
+    pub fn new(
+        service: Arc<GatewayService>,
+        task_sender: Sender<BoxedTask>,
+        bind_ip: IpAddr,
+        ports: RangeInclusive<u16>,
+        public: FQDN,
+        idle_timeout: Duration,
+    ) -> Self {
+        Self {
+            service,
+            task_sender,
+            bind_ip,
+            ports,
+            public,
+            idle_timeout,
+            listeners: Default::default(),
+        }
+    }
+
+    /// Listen on the ports which were allocated before the gateway started
+    pub async fn start(&self) -> Result<(), Error> {
+        let mut listeners = self.listeners.lock().await;
+
+        for (project_name, port) in self.service.iter_tcp_ports().await? {
+            if let Err(error) = self
+                .listen(&mut listeners, project_name.clone(), port)
+                .await
+            {
+                error!(%error, %project_name, port, "failed to listen on the TCP port of a project");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Allocate a public port to a project, or get the one it already has, and forward the
+    /// connections coming in on it
+    pub async fn open(&self, project_name: &ProjectName) -> Result<tcp::Response, Error> {
+        // Held until the port is listened on, so a concurrent open of the same project does not
+        // try to bind the port a second time
+        let mut listeners = self.listeners.lock().await;
+
+        let port = self
+            .service
+            .allocate_tcp_port(project_name, self.ports.clone())
+            .await?;
+
+        if !listeners.contains_key(&port) {
+            if let Err(error) = self
+                .listen(&mut listeners, project_name.clone(), port)
+                .await
+            {
+                error!(%error, %project_name, port, "failed to listen on the TCP port of a project");
+
+                self.service.delete_tcp_port(project_name).await?;
+
+                return Err(Error::source(ErrorKind::Internal, error));
+            }
+        }
+
+        Ok(self.response(project_name, port))
+    }
+
+    pub async fn get(&self, project_name: &ProjectName) -> Result<tcp::Response, Error> {
+        let port = self.service.find_tcp_port(project_name).await?;
+
+        Ok(self.response(project_name, port))
+    }
+
+    /// Stop listening on the port of a project and give it up. Connections which are already open
+    /// are left to finish.
+    pub async fn close(&self, project_name: &ProjectName) -> Result<tcp::Response, Error> {
+        let mut listeners = self.listeners.lock().await;
+        let port = self.service.delete_tcp_port(project_name).await?;
+
+        if let Some(listener) = listeners.remove(&port) {
+            listener.abort();
+        }
+
+        Ok(self.response(project_name, port))
+    }
+
+    fn response(&self, project_name: &ProjectName, port: u16) -> tcp::Response {
+        tcp::Response {
+            project_name: project_name.to_string(),
+            port,
+            address: format!("{project_name}.{}:{port}", self.public),
+        }
+    }
+
+    async fn listen(
+        &self,
+        listeners: &mut HashMap<u16, JoinHandle<()>>,
+        project_name: ProjectName,
+        port: u16,
+    ) -> io::Result<()> {
+        let listener = TcpListener::bind(SocketAddr::new(self.bind_ip, port)).await?;
+
+        info!(%project_name, port, "forwarding raw TCP connections");
+
+        let proxy = self.clone();
+        let handle = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, remote_addr)) => {
+                        tokio::spawn(proxy.clone().forward(
+                            project_name.clone(),
+                            stream,
+                            remote_addr,
+                        ));
+                    }
+                    Err(error) => {
+                        warn!(%error, %project_name, port, "failed to accept raw TCP connection")
+                    }
+                }
+            }
+        });
+
+        listeners.insert(port, handle);
+
+        Ok(())
+    }
+
+    #[instrument(name = "proxy_tcp", skip(self, stream), fields(%project_name, %remote_addr))]
+    async fn forward(self, project_name: ProjectName, stream: TcpStream, remote_addr: SocketAddr) {
+        if let Err(error) = self.pipe(&project_name, stream, remote_addr).await {
+            debug!(%error, "raw TCP connection failed");
+        }
+    }
+
+    async fn pipe(
+        &self,
+        project_name: &ProjectName,
+        mut stream: TcpStream,
+        remote_addr: SocketAddr,
+    ) -> Result<(), Error> {
+        // The limits are checked before an idle project is started, so connections which are over
+        // them cannot wake it up
+        let project = self.service.find_project(project_name).await?;
+
+        // Every new connection counts as a request against the limits of the project
+        let limits = project
+            .container()
+            .map(|container| container.limits())
+            .unwrap_or_default();
+        let limiter = self.service.proxy_limiter();
+
+        if let Some(rate) = limits.requests_per_second {
+            if !limiter.try_request(project_name, remote_addr.ip(), rate) {
+                return Err(Error::from_kind(ErrorKind::RateLimited));
+            }
+        }
+
+        let _connection = limits
+            .max_connections
+            .map(|max| {
+                limiter
+                    .try_connect(project_name, max)
+                    .ok_or_else(|| Error::from_kind(ErrorKind::TooManyConnections))
+            })
+            .transpose()?;
+
+        let project = if project.is_stopped() {
+            self.service
+                .find_or_start_project(project_name, self.task_sender.clone())
+                .await?
+        } else {
+            project
+        };
+
+        let target = project
+            .target_tcp_addr()?
+            .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotReady))?;
+
+        let mut upstream = TcpStream::connect(target)
+            .await
+            .map_err(|error| Error::source(ErrorKind::ProjectUnavailable, error))?;
+
+        let metrics = self.service.proxy_metrics();
+        let _open_connection = metrics.open_connection(project_name);
+
+        let (bytes_in, bytes_out) =
+            copy_bidirectional(&mut stream, &mut upstream, self.idle_timeout)
+                .await
+                .map_err(|error| Error::source(ErrorKind::ProjectUnavailable, error))?;
+
+        metrics.record_bytes_in(project_name, bytes_in);
+        metrics.record_bytes_out(project_name, bytes_out);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use shuttle_common::deployment::Tcp;
+    use shuttle_common::models::project::Limits;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc::channel;
+
+    use super::TcpProxy;
+    use crate::service::GatewayService;
+    use crate::tests::{loopback_ip, ready_project, spawn_deployer, Deployment, World};
+    use crate::ProjectName;
+
+    /// Start a TCP service which echoes everything back
+    async fn spawn_echo() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+
+                tokio::spawn(async move {
+                    let (mut read, mut write) = stream.split();
+                    let _ = tokio::io::copy(&mut read, &mut write).await;
+                });
+            }
+        });
+
+        address
+    }
+
+    async fn echoes(port: u16) -> bool {
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await
+            .unwrap();
+        let mut buf = [0; 5];
+
+        stream.write_all(b"hello").await.is_ok()
+            && stream.read_exact(&mut buf).await.is_ok()
+            && &buf == b"hello"
+    }
+
+    #[tokio::test]
+    async fn tcp_through_gateway_and_deployer() {
+        let world = World::new().await;
+        let service = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+        let matrix: ProjectName = "matrix".parse().unwrap();
+        let target = loopback_ip();
+        let limits = Limits {
+            max_connections: Some(1),
+            ..Default::default()
+        };
+
+        service
+            .create_project(matrix.clone(), "neo".parse().unwrap(), false, 0, limits)
+            .await
+            .unwrap();
+        service
+            .update_project(&matrix, &ready_project(&matrix, target, limits))
+            .await
+            .unwrap();
+
+        let echo = spawn_echo().await;
+        let deployment = Deployment {
+            http: echo,
+            tcp: Some((echo, Tcp::default())),
+        };
+        spawn_deployer(target, &matrix, &world.fqdn(), deployment).await;
+
+        let port = portpicker::pick_unused_port().unwrap();
+        let (sender, _) = channel(1);
+        let proxy = TcpProxy::new(
+            service,
+            sender,
+            Ipv4Addr::LOCALHOST.into(),
+            port..=port,
+            world.fqdn(),
+            Duration::from_secs(60),
+        );
+
+        // Opening the port of a project twice at the same time hands out the one port
+        let (first, second) = tokio::join!(proxy.open(&matrix), proxy.open(&matrix));
+        assert_eq!(first.unwrap().port, port);
+        assert_eq!(second.unwrap().port, port);
+
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await
+            .unwrap();
+        let mut buf = [0; 5];
+        stream.write_all(b"hello").await.unwrap();
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        // The project only allows one connection at a time
+        assert!(!echoes(port).await);
+
+        // Until the first one is closed
+        drop(stream);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !echoes(port).await {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
@@ -6,6 +6,7 @@ use std::sync::Arc;
 
 use axum_server::accept::DefaultAcceptor;
 use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
+use chrono::{DateTime, Duration, TimeZone, Utc};
 use futures::executor::block_on;
 use pem::Pem;
 use rustls::server::{ClientHello, ResolvesServerCert};
@@ -15,11 +16,34 @@ use rustls_pemfile::Item;
 use shuttle_common::models::error::ErrorKind;
 use tokio::runtime::Handle;
 use tokio::sync::RwLock;
+use x509_parser::nom::AsBytes;
+use x509_parser::parse_x509_certificate;
+use x509_parser::pem::parse_x509_pem;
 
 use crate::Error;
 
-/// LetsEncrypt recommends to renew a certificate when its close to 30 days validity window.
-pub const RENEWAL_VALIDITY_THRESHOLD_IN_DAYS: i64 = 30;
+/// Get when the first certificate in a PEM chain stops being valid
+pub fn certificate_expiry(certificate: &str) -> Result<DateTime<Utc>, Error> {
+    let (_, pem) = parse_x509_pem(certificate.as_bytes())
+        .map_err(|_err| Error::custom(ErrorKind::Internal, "malformed PEM certificate"))?;
+    let (_, x509_cert) = parse_x509_certificate(pem.contents.as_bytes())
+        .map_err(|_err| Error::custom(ErrorKind::Internal, "malformed X509 certificate"))?;
+
+    Utc.timestamp_opt(x509_cert.validity().not_after.timestamp(), 0)
+        .single()
+        .ok_or_else(|| Error::custom(ErrorKind::Internal, "certificate expiry is out of range"))
+}
+
+/// Get how long the first certificate in a PEM chain stays valid when that is longer than the
+/// `renewal_window`, or `None` when it should be renewed. An expired certificate is always due.
+pub fn remaining_validity(
+    certificate: &str,
+    renewal_window: Duration,
+) -> Result<Option<Duration>, Error> {
+    let remaining = certificate_expiry(certificate)? - Utc::now();
+
+    Ok(Some(remaining).filter(|remaining| *remaining > renewal_window))
+}
 
 #[derive(Clone)]
 pub struct ChainAndPrivateKey {
@@ -136,6 +160,11 @@ impl GatewayCertResolver {
         let certs = ChainAndPrivateKey::parse_pem(rd)?;
         self.serve_der(sni, certs).await
     }
+
+    /// Stop serving the certificate of the given domain, which falls back to the default one
+    pub async fn stop_serving(&self, sni: &str) {
+        self.keys.write().await.remove(sni);
+    }
 }
 
 impl ResolvesServerCert for GatewayCertResolver {
@@ -160,7 +189,8 @@ pub fn make_tls_acceptor() -> (Arc<GatewayCertResolver>, RustlsAcceptor<DefaultA
         .with_safe_defaults()
         .with_no_client_auth()
         .with_cert_resolver(Arc::clone(&resolver) as Arc<dyn ResolvesServerCert>);
-    server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    // HTTP/2 has to be offered for gRPC clients to connect
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
 
     let rustls_config = RustlsConfig::from_config(Arc::new(server_config));
 
@@ -77,6 +77,30 @@ pub struct DatabaseResponse {
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct DatabaseDeletionResponse {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BucketRequest {
+    #[prost(string, tag = "1")]
+    pub project_name: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BucketResponse {
+    #[prost(string, tag = "1")]
+    pub bucket_name: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub access_key_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub secret_access_key: ::prost::alloc::string::String,
+    #[prost(string, tag = "4")]
+    pub session_token: ::prost::alloc::string::String,
+    #[prost(string, tag = "5")]
+    pub region: ::prost::alloc::string::String,
+    #[prost(string, tag = "6")]
+    pub endpoint_private: ::prost::alloc::string::String,
+    #[prost(string, tag = "7")]
+    pub endpoint_public: ::prost::alloc::string::String,
+}
 /// Generated client implementations.
 pub mod provisioner_client {
     #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
@@ -184,6 +208,25 @@ pub mod provisioner_client {
             );
             self.inner.unary(request.into_request(), path, codec).await
         }
+        pub async fn provision_bucket(
+            &mut self,
+            request: impl tonic::IntoRequest<super::BucketRequest>,
+        ) -> Result<tonic::Response<super::BucketResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/provisioner.Provisioner/ProvisionBucket",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -201,6 +244,10 @@ pub mod provisioner_server {
             &self,
             request: tonic::Request<super::DatabaseRequest>,
         ) -> Result<tonic::Response<super::DatabaseDeletionResponse>, tonic::Status>;
+        async fn provision_bucket(
+            &self,
+            request: tonic::Request<super::BucketRequest>,
+        ) -> Result<tonic::Response<super::BucketResponse>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct ProvisionerServer<T: Provisioner> {
@@ -341,6 +388,46 @@ pub mod provisioner_server {
                     };
                     Box::pin(fut)
                 }
+                "/provisioner.Provisioner/ProvisionBucket" => {
+                    #[allow(non_camel_case_types)]
+                    struct ProvisionBucketSvc<T: Provisioner>(pub Arc<T>);
+                    impl<
+                        T: Provisioner,
+                    > tonic::server::UnaryService<super::BucketRequest>
+                    for ProvisionBucketSvc<T> {
+                        type Response = super::BucketResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::BucketRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move {
+                                (*inner).provision_bucket(request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ProvisionBucketSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         Ok(
@@ -36,6 +36,9 @@ pub struct StartRequest {
     /// Address and port to start the service on
     #[prost(string, tag = "1")]
     pub ip: ::prost::alloc::string::String,
+    /// Largest request body a shuttle-next service accepts in bytes, or zero for the default
+    #[prost(uint64, tag = "2")]
+    pub body_limit: u64,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -85,6 +88,9 @@ pub struct LogItem {
     pub target: ::prost::alloc::string::String,
     #[prost(bytes = "vec", tag = "8")]
     pub fields: ::prost::alloc::vec::Vec<u8>,
+    /// Module the log took place in, which unlike the target cannot be set by the caller
+    #[prost(string, optional, tag = "9")]
+    pub module_path: ::core::option::Option<::prost::alloc::string::String>,
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
@@ -7,7 +7,7 @@ pub mod provisioner {
 
     use shuttle_common::{
         database::{self, AwsRdsEngine, SharedEngine},
-        DatabaseReadyInfo,
+        BucketReadyInfo, DatabaseReadyInfo,
     };
 
     include!("generated/provisioner.rs");
@@ -26,6 +26,21 @@ pub mod provisioner {
         }
     }
 
+    impl From<BucketResponse> for BucketReadyInfo {
+        fn from(response: BucketResponse) -> Self {
+            BucketReadyInfo {
+                bucket_name: response.bucket_name,
+                access_key_id: response.access_key_id,
+                secret_access_key: response.secret_access_key,
+                session_token: (!response.session_token.is_empty())
+                    .then_some(response.session_token),
+                region: response.region,
+                endpoint_private: response.endpoint_private,
+                endpoint_public: response.endpoint_public,
+            }
+        }
+    }
+
     impl From<database::Type> for database_request::DbType {
         fn from(db_type: database::Type) -> Self {
             match db_type {
@@ -178,6 +193,7 @@ pub mod runtime {
                 line,
                 target: log.target,
                 fields: log.fields,
+                module_path: None,
             }
         }
     }
@@ -207,7 +223,6 @@ pub mod runtime {
     }
 
     pub async fn start(
-        wasm: bool,
         storage_manager_type: StorageManagerType,
         provisioner_address: &str,
         auth_uri: Option<&String>,
@@ -226,26 +241,20 @@ pub mod runtime {
         let storage_manager_path = &storage_manager_path.display().to_string();
         let runtime_executable_path = get_runtime_executable();
 
-        let args = if wasm {
-            vec!["--port", port]
-        } else {
-            let mut args = vec![
-                "--port",
-                port,
-                "--provisioner-address",
-                provisioner_address,
-                "--storage-manager-type",
-                storage_manager_type,
-                "--storage-manager-path",
-                storage_manager_path,
-            ];
+        let mut args = vec![
+            "--port",
+            port,
+            "--provisioner-address",
+            provisioner_address,
+            "--storage-manager-type",
+            storage_manager_type,
+            "--storage-manager-path",
+            storage_manager_path,
+        ];
 
-            if let Some(auth_uri) = auth_uri {
-                args.append(&mut vec!["--auth-uri", auth_uri]);
-            }
-
-            args
-        };
+        if let Some(auth_uri) = auth_uri {
+            args.append(&mut vec!["--auth-uri", auth_uri]);
+        }
 
         let runtime = process::Command::new(runtime_executable_path)
             .args(&args)
@@ -38,6 +38,55 @@ pub struct Args {
     #[arg(long, env = "PROVISIONER_MONGODB_ADDRESS", default_value = "mongodb")]
     pub internal_mongodb_address: String,
 
+    /// Endpoint of the S3-compatible object storage to provision buckets on
+    #[arg(
+        long,
+        env = "PROVISIONER_OBJECT_STORAGE_ENDPOINT",
+        default_value = "http://localstack:4566"
+    )]
+    pub object_storage_endpoint: String,
+
+    /// Endpoint the object storage can be reached at from outside the internal network
+    #[arg(long, env = "PROVISIONER_OBJECT_STORAGE_PUBLIC_ENDPOINT")]
+    pub object_storage_public_endpoint: String,
+
+    /// Region of the object storage
+    #[arg(
+        long,
+        env = "PROVISIONER_OBJECT_STORAGE_REGION",
+        default_value = "us-east-1"
+    )]
+    pub object_storage_region: String,
+
+    /// Access key of an object storage user which can create buckets and IAM users
+    #[arg(
+        long,
+        env = "PROVISIONER_OBJECT_STORAGE_ACCESS_KEY_ID",
+        hide_env_values = true
+    )]
+    pub object_storage_access_key_id: String,
+
+    /// Secret key of the object storage user
+    #[arg(
+        long,
+        env = "PROVISIONER_OBJECT_STORAGE_SECRET_ACCESS_KEY",
+        hide_env_values = true
+    )]
+    pub object_storage_secret_access_key: String,
+
+    /// Endpoint of the IAM API of the object storage, when the storage endpoint does not serve it
+    #[arg(long, env = "PROVISIONER_OBJECT_STORAGE_IAM_ENDPOINT")]
+    pub object_storage_iam_endpoint: Option<String>,
+
+    /// Start of the name of every bucket, which has to keep them apart from the buckets of
+    /// anyone else on the object storage
+    #[arg(
+        long,
+        env = "PROVISIONER_OBJECT_STORAGE_BUCKET_PREFIX",
+        default_value = "shuttle"
+    )]
+    pub object_storage_bucket_prefix: String,
+
     /// Address to reach the authentication service at
     #[arg(long, default_value = "http://127.0.0.1:8008")]
     pub auth_uri: Uri,
@@ -23,6 +23,12 @@ pub enum Error {
     #[error("failed to drop DB: {0}")]
     DeleteDB(String),
 
+    #[error("failed to create bucket: {0}")]
+    CreateBucket(String),
+
+    #[error("failed to create bucket credentials: {0}")]
+    CreateBucketCredentials(String),
+
     #[error("unexpected sqlx error: {0}")]
     UnexpectedSqlx(#[from] sqlx::Error),
 
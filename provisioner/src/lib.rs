@@ -3,13 +3,16 @@ use std::time::Duration;
 pub use args::Args;
 use aws_config::timeout;
 use aws_sdk_rds::{error::ModifyDBInstanceErrorKind, model::DbInstance, types::SdkError, Client};
+use aws_sdk_s3::model::{BucketLocationConstraint, CreateBucketConfiguration};
 pub use error::Error;
 use mongodb::{bson::doc, options::ClientOptions};
 use rand::Rng;
+use sha2::{Digest, Sha256};
 use shuttle_common::claims::{Claim, Scope};
 pub use shuttle_proto::provisioner::provisioner_server::ProvisionerServer;
 use shuttle_proto::provisioner::{
-    aws_rds, database_request::DbType, shared, AwsRds, DatabaseRequest, DatabaseResponse, Shared,
+    aws_rds, database_request::DbType, shared, AwsRds, BucketRequest, BucketResponse,
+    DatabaseRequest, DatabaseResponse, Shared,
 };
 use shuttle_proto::provisioner::{provisioner_server::Provisioner, DatabaseDeletionResponse};
 use sqlx::{postgres::PgPoolOptions, ConnectOptions, Executor, PgPool};
@@ -23,11 +26,35 @@ mod error;
 const AWS_RDS_CLASS: &str = "db.t4g.micro";
 const MASTER_USERNAME: &str = "master";
 const RDS_SUBNET_GROUP: &str = "shuttle_rds";
+/// Longest name a bucket can have on S3
+const MAX_BUCKET_NAME_LENGTH: usize = 63;
+/// Number of hex digits of the project name hash which end the name of its bucket
+const BUCKET_NAME_HASH_LENGTH: usize = 8;
+/// Name of the inline policy which limits the user of a bucket to that bucket
+const BUCKET_POLICY_NAME: &str = "bucket-access";
+
+/// Where to provision buckets, and where to create the users which can reach them
+pub struct ObjectStorageConfig {
+    /// Endpoint to reach the storage at on the internal network
+    pub endpoint: String,
+    /// Endpoint to reach the storage at from outside the internal network
+    pub public_endpoint: String,
+    /// Endpoint of the IAM API of the storage, when it is not the storage endpoint itself
+    pub iam_endpoint: Option<String>,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Start of every bucket name, to keep them apart from the buckets of anyone else
+    pub bucket_prefix: String,
+}
 
 pub struct MyProvisioner {
     pool: PgPool,
     rds_client: aws_sdk_rds::Client,
     mongodb_client: mongodb::Client,
+    s3_client: aws_sdk_s3::Client,
+    iam_client: aws_sdk_iam::Client,
+    object_storage: ObjectStorageConfig,
     fqdn: String,
     internal_pg_address: String,
     internal_mongodb_address: String,
@@ -40,6 +67,7 @@ impl MyProvisioner {
         fqdn: String,
         internal_pg_address: String,
         internal_mongodb_address: String,
+        object_storage: ObjectStorageConfig,
     ) -> Result<Self, Error> {
         let pool = PgPoolOptions::new()
             .min_connections(4)
@@ -63,10 +91,44 @@ impl MyProvisioner {
 
         let rds_client = aws_sdk_rds::Client::new(&aws_config);
 
+        // The object storage can be any S3-compatible one, so it does not use the AWS config
+        let endpoint = parse_endpoint(&object_storage.endpoint)?;
+        let iam_endpoint = match &object_storage.iam_endpoint {
+            Some(iam_endpoint) => parse_endpoint(iam_endpoint)?,
+            None => endpoint.clone(),
+        };
+        let credentials = aws_sdk_s3::Credentials::new(
+            &object_storage.access_key_id,
+            &object_storage.secret_access_key,
+            None,
+            None,
+            "shuttle-provisioner",
+        );
+        let region = aws_sdk_s3::Region::new(object_storage.region.clone());
+
+        let s3_client = aws_sdk_s3::Client::from_conf(
+            aws_sdk_s3::Config::builder()
+                .region(region)
+                .credentials_provider(credentials.clone())
+                .endpoint_resolver(endpoint.clone())
+                .build(),
+        );
+        // IAM is global, and AWS only takes requests to it which are signed for us-east-1
+        let iam_client = aws_sdk_iam::Client::from_conf(
+            aws_sdk_iam::Config::builder()
+                .region(aws_sdk_iam::Region::new("us-east-1"))
+                .credentials_provider(credentials)
+                .endpoint_resolver(iam_endpoint)
+                .build(),
+        );
+
         Ok(Self {
             pool,
             rds_client,
             mongodb_client,
+            s3_client,
+            iam_client,
+            object_storage,
             fqdn,
             internal_pg_address,
             internal_mongodb_address,
@@ -274,7 +336,9 @@ impl MyProvisioner {
                         .send()
                         .await?
                         .db_instance
-                        .expect("to be able to create instance");
+                        .ok_or_else(|| {
+                            Error::Plain("AWS RDS did not return the created instance".to_string())
+                        })?;
 
                     wait_for_instance(client, &instance_name, "creating").await?;
                 } else {
@@ -298,25 +362,158 @@ impl MyProvisioner {
         // TODO: find private IP somehow
         let address = instance
             .endpoint
-            .expect("instance to have an endpoint")
-            .address
-            .expect("endpoint to have an address");
+            .and_then(|endpoint| endpoint.address)
+            .ok_or_else(|| Error::Plain(format!("{instance_name} has no address")))?;
 
         Ok(DatabaseResponse {
             engine: engine.to_string(),
             username: instance
                 .master_username
-                .expect("instance to have a username"),
+                .ok_or_else(|| Error::Plain(format!("{instance_name} has no username")))?,
             password,
             database_name: instance
                 .db_name
-                .expect("instance to have a default database"),
+                .ok_or_else(|| Error::Plain(format!("{instance_name} has no default database")))?,
             address_private: address.clone(),
             address_public: address,
             port: engine_to_port(engine),
         })
     }
 
+    pub async fn request_bucket(&self, project_name: &str) -> Result<BucketResponse, Error> {
+        let bucket_name = bucket_name(&self.object_storage.bucket_prefix, project_name);
+
+        self.create_bucket(&bucket_name).await?;
+        let (access_key_id, secret_access_key) = self.bucket_credentials(&bucket_name).await?;
+
+        Ok(BucketResponse {
+            bucket_name,
+            access_key_id,
+            secret_access_key,
+            // Access keys of users do not expire, so they come without a session token
+            session_token: String::new(),
+            region: self.object_storage.region.clone(),
+            endpoint_private: self.object_storage.endpoint.clone(),
+            endpoint_public: self.object_storage.public_endpoint.clone(),
+        })
+    }
+
+    async fn create_bucket(&self, bucket_name: &str) -> Result<(), Error> {
+        // Every region but the default one has to be set when creating a bucket on AWS
+        let configuration = (self.object_storage.region != "us-east-1").then(|| {
+            CreateBucketConfiguration::builder()
+                .location_constraint(BucketLocationConstraint::from(
+                    self.object_storage.region.as_str(),
+                ))
+                .build()
+        });
+
+        let result = self
+            .s3_client
+            .create_bucket()
+            .bucket(bucket_name)
+            .set_create_bucket_configuration(configuration)
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => {
+                info!("created bucket");
+                Ok(())
+            }
+            Err(SdkError::ServiceError { err, .. }) if err.is_bucket_already_owned_by_you() => {
+                debug!("bucket already exists");
+                Ok(())
+            }
+            Err(error) => Err(Error::CreateBucket(error.to_string())),
+        }
+    }
+
+    /// Get credentials which can only reach a bucket. They belong to a user of the bucket, and do
+    /// not expire: they only stop working once two newer ones were handed out for the bucket. This
+    /// is why a deployer keeps using the credentials it got for a bucket rather than asking again.
+    async fn bucket_credentials(&self, bucket_name: &str) -> Result<(String, String), Error> {
+        // Bucket names are unique and also valid user names
+        let user_name = bucket_name;
+
+        match self
+            .iam_client
+            .create_user()
+            .user_name(user_name)
+            .send()
+            .await
+        {
+            Ok(_) => info!("created bucket user"),
+            Err(SdkError::ServiceError { err, .. }) if err.is_entity_already_exists_exception() => {
+                debug!("bucket user already exists")
+            }
+            Err(error) => return Err(Error::CreateBucketCredentials(error.to_string())),
+        }
+
+        let policy = format!(
+            r#"{{"Version":"2012-10-17","Statement":[{{"Effect":"Allow","Action":["s3:*"],"Resource":["arn:aws:s3:::{bucket_name}","arn:aws:s3:::{bucket_name}/*"]}}]}}"#
+        );
+
+        self.iam_client
+            .put_user_policy()
+            .user_name(user_name)
+            .policy_name(BUCKET_POLICY_NAME)
+            .policy_document(policy)
+            .send()
+            .await
+            .map_err(|error| Error::CreateBucketCredentials(error.to_string()))?;
+
+        // A user can have at most two access keys. Only keep the newest one, so that a deployment
+        // still running with it is not cut off while its replacement starts.
+        let mut access_keys = self
+            .iam_client
+            .list_access_keys()
+            .user_name(user_name)
+            .send()
+            .await
+            .map_err(|error| Error::CreateBucketCredentials(error.to_string()))?
+            .access_key_metadata
+            .unwrap_or_default();
+
+        access_keys.sort_by_key(|access_key| {
+            access_key
+                .create_date
+                .map(|date| (date.secs(), date.subsec_nanos()))
+        });
+        access_keys.pop();
+
+        for access_key in access_keys {
+            self.iam_client
+                .delete_access_key()
+                .user_name(user_name)
+                .set_access_key_id(access_key.access_key_id)
+                .send()
+                .await
+                .map_err(|error| Error::CreateBucketCredentials(error.to_string()))?;
+        }
+
+        let access_key = self
+            .iam_client
+            .create_access_key()
+            .user_name(user_name)
+            .send()
+            .await
+            .map_err(|error| Error::CreateBucketCredentials(error.to_string()))?
+            .access_key
+            .ok_or_else(|| {
+                Error::CreateBucketCredentials("no access key was returned".to_string())
+            })?;
+
+        match (access_key.access_key_id, access_key.secret_access_key) {
+            (Some(access_key_id), Some(secret_access_key)) => {
+                Ok((access_key_id, secret_access_key))
+            }
+            _ => Err(Error::CreateBucketCredentials(
+                "the access key is incomplete".to_string(),
+            )),
+        }
+    }
+
     async fn delete_shared_db(
         &self,
         project_name: &str,
@@ -453,6 +650,19 @@ impl Provisioner for MyProvisioner {
 
         Ok(Response::new(reply))
     }
+
+    #[tracing::instrument(skip(self))]
+    async fn provision_bucket(
+        &self,
+        request: Request<BucketRequest>,
+    ) -> Result<Response<BucketResponse>, Status> {
+        verify_claim(&request)?;
+
+        let request = request.into_inner();
+        let reply = self.request_bucket(&request.project_name).await?;
+
+        Ok(Response::new(reply))
+    }
 }
 
 /// Verify the claim on the request has the correct scope to call this service
@@ -471,6 +681,35 @@ fn verify_claim<B>(request: &Request<B>) -> Result<(), Status> {
     }
 }
 
+/// Bucket names can only have lowercase letters, digits and hyphens, and are at most 63
+/// characters long. They are shared by everyone on the storage, so every name starts with the
+/// prefix of this platform. It ends with a hash of the project name, since the project name itself
+/// can be cut short or lose its case on the way.
+fn bucket_name(prefix: &str, project_name: &str) -> String {
+    let hash = hex::encode(Sha256::digest(project_name.as_bytes()));
+    let hash = &hash[..BUCKET_NAME_HASH_LENGTH];
+
+    let mut bucket_name: String = format!("{prefix}-{project_name}")
+        .chars()
+        .map(|c| match c {
+            '_' => '-',
+            c => c.to_ascii_lowercase(),
+        })
+        .collect();
+
+    bucket_name.truncate(MAX_BUCKET_NAME_LENGTH - BUCKET_NAME_HASH_LENGTH - 1);
+
+    format!("{}-{hash}", bucket_name.trim_end_matches('-'))
+}
+
+fn parse_endpoint(endpoint: &str) -> Result<aws_sdk_s3::Endpoint, Error> {
+    let uri = endpoint
+        .parse()
+        .map_err(|error| Error::Plain(format!("invalid object storage endpoint: {error}")))?;
+
+    Ok(aws_sdk_s3::Endpoint::immutable(uri))
+}
+
 fn generate_password() -> String {
     rand::thread_rng()
         .sample_iter(&rand::distributions::Alphanumeric)
@@ -492,16 +731,13 @@ async fn wait_for_instance(
             .send()
             .await?
             .db_instances
-            .expect("aws to return instances")
-            .get(0)
-            .expect("to find the instance just created or modified")
-            .clone();
+            .and_then(|instances| instances.into_iter().next())
+            .ok_or_else(|| Error::Plain(format!("AWS RDS did not return {name}")))?;
 
         let status = instance
             .db_instance_status
-            .as_ref()
-            .expect("instance to have a status")
-            .clone();
+            .as_deref()
+            .ok_or_else(|| Error::Plain(format!("{name} has no status")))?;
 
         if status == wait_for {
             return Ok(instance);
@@ -518,3 +754,44 @@ fn engine_to_port(engine: aws_rds::Engine) -> String {
         aws_rds::Engine::Mysql(_) => "3306".to_string(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::bucket_name;
+
+    #[test]
+    fn valid_bucket_names() {
+        let matrix = bucket_name("shuttle", "matrix");
+        assert!(matrix.starts_with("shuttle-matrix-"));
+        assert_eq!(matrix.len(), "shuttle-matrix-".len() + 8);
+        assert!(matrix.ends_with(|c: char| c.is_ascii_hexdigit()));
+
+        let the_matrix = bucket_name("shuttle", "The_Matrix");
+        assert!(the_matrix.starts_with("shuttle-the-matrix-"));
+
+        let long = bucket_name("shuttle", &format!("{}-{}", "a".repeat(45), "b".repeat(10)));
+        assert_eq!(long.len(), 62);
+        assert!(!long.contains("--"));
+    }
+
+    #[test]
+    fn bucket_names_stay_apart() {
+        // Names which only differ in case or hyphens, or after the cut-off, still get their own bucket
+        assert_ne!(
+            bucket_name("shuttle", "the-matrix"),
+            bucket_name("shuttle", "the_matrix")
+        );
+        assert_ne!(
+            bucket_name("shuttle", &format!("{}-first", "a".repeat(60))),
+            bucket_name("shuttle", &format!("{}-second", "a".repeat(60)))
+        );
+        assert_eq!(
+            bucket_name("shuttle", "matrix"),
+            bucket_name("shuttle", "matrix")
+        );
+        assert_ne!(
+            bucket_name("shuttle", "matrix"),
+            bucket_name("other", "matrix")
+        );
+    }
+}
@@ -5,7 +5,7 @@ use shuttle_common::backends::{
     auth::{AuthPublicKey, JwtAuthenticationLayer},
     tracing::{setup_tracing, ExtractPropagationLayer},
 };
-use shuttle_provisioner::{Args, MyProvisioner, ProvisionerServer};
+use shuttle_provisioner::{Args, MyProvisioner, ObjectStorageConfig, ProvisionerServer};
 use tonic::transport::Server;
 
 #[tokio::main]
@@ -20,6 +20,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         fqdn,
         internal_pg_address,
         internal_mongodb_address,
+        object_storage_endpoint,
+        object_storage_public_endpoint,
+        object_storage_region,
+        object_storage_access_key_id,
+        object_storage_secret_access_key,
+        object_storage_iam_endpoint,
+        object_storage_bucket_prefix,
         auth_uri,
     } = Args::parse();
     let addr = SocketAddr::new(ip, port);
@@ -30,6 +37,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         fqdn.to_string(),
         internal_pg_address,
         internal_mongodb_address,
+        ObjectStorageConfig {
+            endpoint: object_storage_endpoint,
+            public_endpoint: object_storage_public_endpoint,
+            iam_endpoint: object_storage_iam_endpoint,
+            region: object_storage_region,
+            access_key_id: object_storage_access_key_id,
+            secret_access_key: object_storage_secret_access_key,
+            bucket_prefix: object_storage_bucket_prefix,
+        },
     )
     .await
     .unwrap();
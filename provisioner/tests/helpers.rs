@@ -7,6 +7,7 @@ use std::{
 
 const PG_CONTAINER_NAME: &str = "shuttle_provisioner_test_pg";
 const MONGODB_CONTAINER_NAME: &str = "shuttle_provisioner_test_mongodb";
+const OBJECT_STORAGE_CONTAINER_NAME: &str = "shuttle_provisioner_test_object_storage";
 
 pub struct DockerInstance {
     pub container_name: &'static str,
@@ -25,6 +26,7 @@ struct Config<'a> {
 pub enum DbType {
     Postgres,
     MongoDb,
+    ObjectStorage,
 }
 
 impl DockerInstance {
@@ -55,9 +57,14 @@ impl DockerInstance {
         sleep(Duration::from_millis(350));
         Self::wait_ready(Duration::from_secs(120), &is_ready_cmd);
 
+        let uri = match engine {
+            "http" => format!("http://localhost:{host_port}"),
+            engine => format!("{engine}://{engine}:password@localhost:{host_port}"),
+        };
+
         Self {
             container_name,
-            uri: format!("{engine}://{engine}:password@localhost:{host_port}"),
+            uri,
         }
     }
 
@@ -127,6 +134,21 @@ impl From<DbType> for Config<'_> {
                     "db",
                 ],
             },
+            DbType::ObjectStorage => Config {
+                container_name: OBJECT_STORAGE_CONTAINER_NAME,
+                image: "docker.io/localstack/localstack:1.4",
+                engine: "http",
+                port: "4566",
+                env: vec!["SERVICES=s3,iam"],
+                is_ready_cmd: vec![
+                    "exec",
+                    OBJECT_STORAGE_CONTAINER_NAME,
+                    "curl",
+                    "--fail",
+                    "--silent",
+                    "http://localhost:4566/_localstack/health",
+                ],
+            },
         }
     }
 }
@@ -179,3 +201,21 @@ pub fn exec_mongosh(command: &str, database_name: Option<&str>) -> String {
 
     String::from_utf8(output).unwrap().trim().to_string()
 }
+
+/// Execute `aws` commands against the object storage via `docker exec`
+pub fn exec_aws(args: &[&str]) -> String {
+    let output = Command::new("docker")
+        .args([
+            "exec",
+            OBJECT_STORAGE_CONTAINER_NAME,
+            "awslocal",
+            "--output",
+            "text",
+        ])
+        .args(args)
+        .output()
+        .unwrap()
+        .stdout;
+
+    String::from_utf8(output).unwrap().trim().to_string()
+}
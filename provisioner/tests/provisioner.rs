@@ -1,18 +1,44 @@
 mod helpers;
 use ctor::dtor;
-use helpers::{exec_mongosh, exec_psql, DbType, DockerInstance};
+use helpers::{exec_aws, exec_mongosh, exec_psql, DbType, DockerInstance};
 use once_cell::sync::Lazy;
 use serde_json::Value;
 use shuttle_proto::provisioner::shared;
-use shuttle_provisioner::MyProvisioner;
+use shuttle_provisioner::{MyProvisioner, ObjectStorageConfig};
+use std::time::Duration;
+use tokio::time::sleep;
 
 static PG: Lazy<DockerInstance> = Lazy::new(|| DockerInstance::new(DbType::Postgres));
 static MONGODB: Lazy<DockerInstance> = Lazy::new(|| DockerInstance::new(DbType::MongoDb));
+static OBJECT_STORAGE: Lazy<DockerInstance> =
+    Lazy::new(|| DockerInstance::new(DbType::ObjectStorage));
 
 #[dtor]
 fn cleanup() {
     PG.cleanup();
     MONGODB.cleanup();
+
+    // Not every test run gets to the object storage, and there is no point in starting it now
+    if let Some(object_storage) = Lazy::get(&OBJECT_STORAGE) {
+        object_storage.cleanup();
+    }
+}
+
+/// Only the bucket tests start the object storage, so the database tests do not wait on it
+fn object_storage() -> ObjectStorageConfig {
+    object_storage_at("http://localhost:4566")
+}
+
+fn object_storage_at(endpoint: &str) -> ObjectStorageConfig {
+    ObjectStorageConfig {
+        endpoint: endpoint.to_string(),
+        public_endpoint: endpoint.to_string(),
+        iam_endpoint: None,
+        region: "us-east-1".to_string(),
+        access_key_id: "test".to_string(),
+        secret_access_key: "test".to_string(),
+        bucket_prefix: "shuttle-test".to_string(),
+    }
 }
 
 #[tokio::test]
@@ -23,6 +49,7 @@ async fn shared_db_role_does_not_exist() {
         "fqdn".to_string(),
         "pg".to_string(),
         "mongodb".to_string(),
+        object_storage(),
     )
     .await
     .unwrap();
@@ -51,6 +78,7 @@ async fn shared_db_role_does_exist() {
         "fqdn".to_string(),
         "pg".to_string(),
         "mongodb".to_string(),
+        object_storage(),
     )
     .await
     .unwrap();
@@ -84,6 +112,7 @@ async fn injection_safe() {
         "fqdn".to_string(),
         "pg".to_string(),
         "mongodb".to_string(),
+        object_storage(),
     )
     .await
     .unwrap();
@@ -105,6 +134,7 @@ async fn shared_db_missing() {
         "fqdn".to_string(),
         "pg".to_string(),
         "mongodb".to_string(),
+        object_storage(),
     )
     .await
     .unwrap();
@@ -133,6 +163,7 @@ async fn shared_db_filled() {
         "fqdn".to_string(),
         "pg".to_string(),
         "mongodb".to_string(),
+        object_storage(),
     )
     .await
     .unwrap();
@@ -163,6 +194,7 @@ async fn shared_mongodb_role_does_not_exist() {
         "fqdn".to_string(),
         "pg".to_string(),
         "mongodb".to_string(),
+        object_storage(),
     )
     .await
     .unwrap();
@@ -187,6 +219,7 @@ async fn shared_mongodb_role_does_exist() {
         "fqdn".to_string(),
         "pg".to_string(),
         "mongodb".to_string(),
+        object_storage(),
     )
     .await
     .unwrap();
@@ -234,3 +267,103 @@ async fn shared_mongodb_role_does_exist() {
     let user_cycled_key = &user["credentials"]["SCRAM-SHA-256"]["storedKey"];
     assert_ne!(user_stored_key, user_cycled_key);
 }
+
+#[tokio::test]
+async fn bucket_with_own_credentials() {
+    let provisioner = MyProvisioner::new(
+        &PG.uri,
+        &MONGODB.uri,
+        "fqdn".to_string(),
+        "pg".to_string(),
+        "mongodb".to_string(),
+        object_storage_at(&OBJECT_STORAGE.uri),
+    )
+    .await
+    .unwrap();
+
+    let bucket = provisioner.request_bucket("matrix").await.unwrap();
+
+    assert!(bucket.bucket_name.starts_with("shuttle-test-matrix-"));
+    assert!(!bucket.access_key_id.is_empty());
+    assert!(!bucket.secret_access_key.is_empty());
+    assert_eq!(bucket.session_token, "");
+    assert_eq!(bucket.endpoint_private, OBJECT_STORAGE.uri);
+
+    assert_eq!(
+        exec_aws(&[
+            "s3api",
+            "list-buckets",
+            "--query",
+            &format!("Buckets[?Name=='{}'].Name", bucket.bucket_name),
+        ]),
+        bucket.bucket_name
+    );
+    assert_eq!(
+        exec_aws(&[
+            "iam",
+            "get-user-policy",
+            "--user-name",
+            &bucket.bucket_name,
+            "--policy-name",
+            "bucket-access",
+            "--query",
+            "PolicyDocument.Statement[0].Resource[0]",
+        ]),
+        format!("arn:aws:s3:::{}", bucket.bucket_name)
+    );
+    assert_eq!(
+        exec_aws(&[
+            "iam",
+            "list-access-keys",
+            "--user-name",
+            &bucket.bucket_name,
+            "--query",
+            "AccessKeyMetadata[].AccessKeyId",
+        ]),
+        bucket.access_key_id
+    );
+}
+
+#[tokio::test]
+async fn bucket_credentials_are_cycled() {
+    let provisioner = MyProvisioner::new(
+        &PG.uri,
+        &MONGODB.uri,
+        "fqdn".to_string(),
+        "pg".to_string(),
+        "mongodb".to_string(),
+        object_storage_at(&OBJECT_STORAGE.uri),
+    )
+    .await
+    .unwrap();
+
+    // Keys are ordered by when they were created, which the storage might only keep to the second
+    let first = provisioner.request_bucket("reloaded").await.unwrap();
+    sleep(Duration::from_secs(1)).await;
+    let second = provisioner.request_bucket("reloaded").await.unwrap();
+    sleep(Duration::from_secs(1)).await;
+    let third = provisioner.request_bucket("reloaded").await.unwrap();
+
+    assert_eq!(first.bucket_name, third.bucket_name);
+    assert_ne!(first.access_key_id, second.access_key_id);
+    assert_ne!(second.access_key_id, third.access_key_id);
+
+    // Only the newest key before the current one keeps working, for the deployment it replaces
+    let mut access_keys: Vec<String> = exec_aws(&[
+        "iam",
+        "list-access-keys",
+        "--user-name",
+        &third.bucket_name,
+        "--query",
+        "AccessKeyMetadata[].AccessKeyId",
+    ])
+    .split_whitespace()
+    .map(ToString::to_string)
+    .collect();
+    access_keys.sort();
+
+    let mut expected = vec![second.access_key_id, third.access_key_id];
+    expected.sort();
+
+    assert_eq!(access_keys, expected);
+}
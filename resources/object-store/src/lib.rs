@@ -0,0 +1,50 @@
+#![doc = include_str!("../README.md")]
+
+use async_trait::async_trait;
+use object_store::aws::{AmazonS3, AmazonS3Builder};
+use serde::Serialize;
+use shuttle_service::{error::CustomError, BucketReadyInfo, Error, Factory, ResourceBuilder, Type};
+
+#[derive(Serialize)]
+pub struct Bucket;
+
+/// Get an [AmazonS3] client for the bucket of a project from any factory
+#[async_trait]
+impl ResourceBuilder<AmazonS3> for Bucket {
+    const TYPE: Type = Type::ObjectStorage;
+
+    type Config = ();
+
+    type Output = BucketReadyInfo;
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    fn config(&self) -> &Self::Config {
+        &()
+    }
+
+    async fn output(self, factory: &mut dyn Factory) -> Result<Self::Output, Error> {
+        factory.get_bucket().await
+    }
+
+    async fn build(build_data: &Self::Output) -> Result<AmazonS3, Error> {
+        let mut builder = AmazonS3Builder::new()
+            .with_bucket_name(&build_data.bucket_name)
+            .with_region(&build_data.region)
+            .with_endpoint(&build_data.endpoint_private)
+            .with_access_key_id(&build_data.access_key_id)
+            .with_secret_access_key(&build_data.secret_access_key)
+            // The internal endpoints of the platform and the local ones are plain HTTP
+            .with_allow_http(true);
+
+        if let Some(session_token) = &build_data.session_token {
+            builder = builder.with_token(session_token);
+        }
+
+        let bucket = builder.build().map_err(CustomError::new)?;
+
+        Ok(bucket)
+    }
+}
@@ -2,10 +2,41 @@ use async_trait::async_trait;
 
 use serde::Serialize;
 pub use shuttle_service::SecretStore;
-use shuttle_service::{Error, Factory, ResourceBuilder, Type};
+use shuttle_service::{
+    secrets::{SecretSchema, SecretSpec},
+    CustomError, Error, Factory, ResourceBuilder, Type,
+};
 
 #[derive(Serialize)]
-pub struct Secrets;
+pub struct Secrets {
+    schema: SecretSchema,
+}
+
+impl Secrets {
+    /// Fail to start the service when any of these comma separated keys is not set
+    pub fn required(mut self, keys: &str) -> Self {
+        for key in keys.split(',').map(str::trim).filter(|key| !key.is_empty()) {
+            self.schema.0.insert(key.to_string(), SecretSpec::default());
+        }
+
+        self
+    }
+
+    /// Fall back to a value for a key when it is not set, given as `KEY=value`
+    pub fn default(mut self, key_value: &str) -> Self {
+        if let Some((key, value)) = key_value.split_once('=') {
+            self.schema.0.insert(
+                key.trim().to_string(),
+                SecretSpec {
+                    default: Some(value.to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+
+        self
+    }
+}
 
 /// Get a store with all the secrets available to a deployment
 #[async_trait]
@@ -17,7 +48,9 @@ impl ResourceBuilder<SecretStore> for Secrets {
     type Output = SecretStore;
 
     fn new() -> Self {
-        Self {}
+        Self {
+            schema: Default::default(),
+        }
     }
 
     fn config(&self) -> &Self::Config {
@@ -25,7 +58,14 @@ impl ResourceBuilder<SecretStore> for Secrets {
     }
 
     async fn output(self, factory: &mut dyn Factory) -> Result<Self::Output, crate::Error> {
-        let secrets = factory.get_secrets().await?;
+        let mut secrets = factory.get_secrets().await?;
+
+        let defaults = self
+            .schema
+            .defaults_for_missing(secrets.keys().map(String::as_str));
+        secrets.extend(defaults);
+
+        self.schema.validate(&secrets).map_err(CustomError::new)?;
 
         Ok(SecretStore::new(secrets))
     }
@@ -4,7 +4,10 @@ use std::{
 };
 
 use clap::Parser;
-use shuttle_common::backends::tracing::{setup_tracing, ExtractPropagationLayer};
+use shuttle_common::backends::{
+    auth::{AuthPublicKey, JwtAuthenticationLayer},
+    tracing::{setup_tracing, ExtractPropagationLayer},
+};
 use shuttle_proto::runtime::runtime_server::RuntimeServer;
 use shuttle_runtime::{AxumWasm, NextArgs};
 use tonic::transport::Server;
@@ -20,11 +23,17 @@ async fn main() {
 
     let addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), args.port);
 
+    let (storage_manager, env) = args.storage_manager();
+    let limits = args.limits();
+
     let mut server_builder = Server::builder()
         .http2_keepalive_interval(Some(Duration::from_secs(60)))
+        .layer(JwtAuthenticationLayer::new(AuthPublicKey::new(
+            args.auth_uri,
+        )))
         .layer(ExtractPropagationLayer);
 
-    let axum = AxumWasm::default();
+    let axum = AxumWasm::new(args.provisioner_address, storage_manager, env, limits);
     let svc = RuntimeServer::new(axum);
     let router = server_builder.add_service(svc);
 
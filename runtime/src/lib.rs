@@ -225,7 +225,7 @@ pub use alpha::{start, Alpha};
 pub use async_trait::async_trait;
 pub use logger::Logger;
 #[cfg(feature = "next")]
-pub use next::{AxumWasm, NextArgs};
+pub use next::{AxumWasm, Limits, NextArgs};
 pub use provisioner_factory::ProvisionerFactory;
 pub use resource_tracker::{get_resource, ResourceTracker};
 pub use shuttle_common::storage_manager::StorageManager;
@@ -44,6 +44,7 @@ where
                     .target
                     .unwrap_or_else(|| metadata.target().to_string()),
                 fields: serde_json::to_vec(&visitor.fields).unwrap(),
+                module_path: metadata.module_path().map(str::to_string),
             }
         };
 
@@ -1,4 +1,15 @@
-use clap::Parser;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{Parser, ValueEnum};
+use shuttle_common::storage_manager::{
+    ArtifactsStorageManager, StorageManager, WorkingDirStorageManager,
+};
+use shuttle_service::Environment;
+use tonic::transport::{Endpoint, Uri};
+
+use super::Limits;
 
 #[derive(Parser, Debug)]
 #[command(version)]
@@ -6,4 +17,71 @@ pub struct NextArgs {
     /// Port to start runtime on
     #[arg(long)]
     pub port: u16,
+
+    /// Address to reach provisioner at
+    #[arg(long, default_value = "http://localhost:5000")]
+    pub provisioner_address: Endpoint,
+
+    /// Type of storage manager to start
+    #[arg(long, value_enum)]
+    pub storage_manager_type: StorageManagerType,
+
+    /// Path to use for storage manager
+    #[arg(long)]
+    pub storage_manager_path: PathBuf,
+
+    /// Address to reach the authentication service at
+    #[arg(long, default_value = "http://127.0.0.1:8008")]
+    pub auth_uri: Uri,
+
+    /// Fuel a module gets for each request, where a unit is about one wasm instruction
+    #[arg(long, default_value_t = Limits::default().fuel)]
+    pub fuel: u64,
+
+    /// Largest linear memory a module can grow to while handling a request, in bytes
+    #[arg(long, default_value_t = Limits::default().memory)]
+    pub memory_limit: usize,
+
+    /// Seconds a module gets to handle a request, up to the end of its response body
+    #[arg(long, default_value_t = Limits::default().timeout.as_secs())]
+    pub request_timeout: u64,
+}
+
+impl NextArgs {
+    /// The storage manager to give to the resources of a module, along with the environment it
+    /// implies
+    pub fn storage_manager(&self) -> (Arc<dyn StorageManager>, Environment) {
+        match self.storage_manager_type {
+            StorageManagerType::Artifacts => (
+                Arc::new(ArtifactsStorageManager::new(
+                    self.storage_manager_path.clone(),
+                )),
+                Environment::Production,
+            ),
+            StorageManagerType::WorkingDir => (
+                Arc::new(WorkingDirStorageManager::new(
+                    self.storage_manager_path.clone(),
+                )),
+                Environment::Local,
+            ),
+        }
+    }
+
+    /// The limits on what a module can use to handle a single request
+    pub fn limits(&self) -> Limits {
+        Limits {
+            fuel: self.fuel,
+            memory: self.memory_limit,
+            timeout: Duration::from_secs(self.request_timeout),
+        }
+    }
+}
+
+#[derive(Clone, Debug, ValueEnum)]
+pub enum StorageManagerType {
+    /// Use a deployer artifacts directory
+    Artifacts,
+
+    /// Use a local working directory
+    WorkingDir,
 }
@@ -0,0 +1,182 @@
+use std::time::{Duration, SystemTime};
+
+use hyper::StatusCode;
+use prost_types::Timestamp;
+use serde_json::json;
+use shuttle_proto::runtime::{LogItem, LogLevel};
+use wasmtime::{ResourceLimiter, Trap};
+
+/// Size of a page of linear memory
+const WASM_PAGE_SIZE: usize = 64 * 1024;
+
+/// Limits on what a module can use to handle a single request, so that one which loops forever or
+/// allocates without bounds cannot stall the runtime
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// Fuel a module gets for each request, where a unit is about one wasm instruction
+    pub fuel: u64,
+    /// Largest linear memory a module can grow to, in bytes
+    pub memory: usize,
+    /// Time a module gets to handle a request, up to the end of its response body
+    pub timeout: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            fuel: 10_000_000_000,
+            memory: 64 * 1024 * 1024,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Limits {
+    /// The memory limit in pages, rounded up
+    pub(crate) fn memory_pages(&self) -> u64 {
+        ((self.memory + WASM_PAGE_SIZE - 1) / WASM_PAGE_SIZE) as u64
+    }
+}
+
+/// A limit a module ran into while handling a request
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+pub(crate) enum LimitExceeded {
+    #[error("request ran out of fuel after {0} units")]
+    Fuel(u64),
+    #[error("request tried to grow memory past {0} bytes")]
+    Memory(usize),
+    #[error("request was not handled within {0:?}")]
+    Timeout(Duration),
+}
+
+impl LimitExceeded {
+    /// Find the limit a call into a module failed on, if it failed on one
+    pub(crate) fn from_error(error: &anyhow::Error, limits: &Limits) -> Option<Self> {
+        if let Some(exceeded) = error.downcast_ref::<Self>() {
+            return Some(*exceeded);
+        }
+
+        match error.downcast_ref::<Trap>() {
+            Some(Trap::OutOfFuel) => Some(Self::Fuel(limits.fuel)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn status(&self) -> StatusCode {
+        match self {
+            Self::Fuel(_) | Self::Memory(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+
+    /// A log explaining which limit the module ran into, for the logs of the deployment
+    pub(crate) fn to_log(self) -> LogItem {
+        LogItem {
+            timestamp: Some(Timestamp::from(SystemTime::now())),
+            level: LogLevel::Warn as i32,
+            file: None,
+            line: None,
+            target: "shuttle_runtime::next".to_string(),
+            fields: serde_json::to_vec(&json!({ "message": self.to_string() }))
+                .expect("a json object to serialize"),
+            module_path: None,
+        }
+    }
+}
+
+/// Traps a module which tries to grow its memory past the limit, instead of letting the
+/// allocation fail inside the module
+pub(crate) struct MemoryLimiter {
+    max: usize,
+}
+
+impl MemoryLimiter {
+    pub(crate) fn new(limits: &Limits) -> Self {
+        Self { max: limits.memory }
+    }
+}
+
+impl ResourceLimiter for MemoryLimiter {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        if desired > self.max {
+            return Err(LimitExceeded::Memory(self.max).into());
+        }
+
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: u32,
+        _desired: u32,
+        _maximum: Option<u32>,
+    ) -> anyhow::Result<bool> {
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use wasmtime::{ResourceLimiter, Trap};
+
+    use super::{LimitExceeded, Limits, MemoryLimiter};
+
+    #[test]
+    fn limit_from_error() {
+        let limits = Limits {
+            fuel: 100,
+            memory: 1024,
+            timeout: Duration::from_secs(1),
+        };
+
+        assert_eq!(
+            LimitExceeded::from_error(&Trap::OutOfFuel.into(), &limits),
+            Some(LimitExceeded::Fuel(100))
+        );
+
+        let error =
+            anyhow::Error::from(LimitExceeded::Timeout(limits.timeout)).context("wasm call failed");
+        assert_eq!(
+            LimitExceeded::from_error(&error, &limits),
+            Some(LimitExceeded::Timeout(Duration::from_secs(1)))
+        );
+
+        assert_eq!(
+            LimitExceeded::from_error(&Trap::UnreachableCodeReached.into(), &limits),
+            None
+        );
+    }
+
+    #[test]
+    fn memory_limiter() {
+        let mut limiter = MemoryLimiter::new(&Limits {
+            memory: 1024,
+            ..Default::default()
+        });
+
+        assert!(limiter.memory_growing(0, 1024, None).unwrap());
+
+        let error = limiter.memory_growing(1024, 2048, None).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<LimitExceeded>(),
+            Some(&LimitExceeded::Memory(1024))
+        );
+    }
+
+    #[test]
+    fn memory_pages() {
+        let limits = Limits {
+            memory: 64 * 1024 + 1,
+            ..Default::default()
+        };
+
+        assert_eq!(limits.memory_pages(), 2);
+    }
+}
@@ -1,44 +1,85 @@
+use std::collections::BTreeMap;
 use std::convert::Infallible;
-use std::io::{BufReader, Read, Write};
-use std::net::{Shutdown, SocketAddr};
+use std::io::{BufReader, Write};
+use std::net::SocketAddr;
 use std::ops::DerefMut;
 use std::os::unix::prelude::RawFd;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use async_trait::async_trait;
 use cap_std::os::unix::net::UnixStream;
-use futures::TryStreamExt;
-use hyper::body::HttpBody;
+use hyper::body::{Bytes, HttpBody};
 use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Request, Response};
-use shuttle_common::wasm::{Bytesable, Log, RequestWrapper, ResponseWrapper};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json::Value;
+use shuttle_common::claims::{Claim, ClaimLayer, InjectPropagationLayer};
+use shuttle_common::deployment::Next;
+use shuttle_common::resource;
+use shuttle_common::storage_manager::StorageManager;
+use shuttle_common::wasm::{
+    Bytesable, FactoryRequest, FactoryResponse, Log, RequestWrapper, ResponseWrapper,
+};
+use shuttle_proto::provisioner::provisioner_client::ProvisionerClient;
 use shuttle_proto::runtime::runtime_server::Runtime;
 use shuttle_proto::runtime::{
     self, LoadRequest, LoadResponse, StartRequest, StartResponse, StopReason, StopRequest,
     StopResponse, SubscribeLogsRequest, SubscribeStopRequest, SubscribeStopResponse,
 };
+use shuttle_service::{Environment, Factory, ServiceName};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::runtime::Handle;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::{broadcast, mpsc, oneshot};
 use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Endpoint;
 use tonic::Status;
+use tower::ServiceBuilder;
 use tracing::{error, trace, warn};
 use wasi_common::file::FileCaps;
-use wasmtime::{Engine, Linker, Module, Store};
+use wasmtime::{
+    Config, Engine, InstanceAllocationStrategy, InstancePre, Linker, Module,
+    PoolingAllocationConfig, Store, TypedFunc,
+};
 use wasmtime_wasi::sync::net::UnixStream as WasiUnixStream;
 use wasmtime_wasi::{WasiCtx, WasiCtxBuilder};
 
+use crate::{ProvisionerFactory, ResourceTracker};
+
+use self::limits::{LimitExceeded, MemoryLimiter};
+
 mod args;
+mod limits;
 
 pub use self::args::NextArgs;
+pub use self::limits::Limits;
 
 extern crate rmp_serde as rmps;
 
 const LOGS_FD: u32 = 20;
 const PARTS_FD: u32 = 3;
 const BODY_FD: u32 = 4;
+const RESOURCES_FD: u32 = 5;
+const FACTORY_FD: u32 = 6;
+
+/// Size of the chunks a response body is read from a module in
+const BODY_CHUNK_SIZE: usize = 8 * 1024;
+
+/// Instances the pooling allocator keeps ready, which bounds how many requests are handled at the
+/// same time since every request being handled holds an instance of its own
+const INSTANCE_POOL_SIZE: u32 = 1000;
+
+/// How often calls into a module check whether they went over the wall-clock limit
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Fuel for calls into a module which are not limited to the fuel of a request, which is as much
+/// as wasmtime can hold
+const UNMETERED_FUEL: u64 = i64::MAX as u64;
 
 pub struct AxumWasm {
     router: Mutex<Option<Router>>,
@@ -46,10 +87,20 @@ pub struct AxumWasm {
     logs_tx: Sender<Result<runtime::LogItem, Status>>,
     kill_tx: Mutex<Option<oneshot::Sender<String>>>,
     stopped_tx: broadcast::Sender<(StopReason, String)>,
+    provisioner_address: Endpoint,
+    storage_manager: Arc<dyn StorageManager>,
+    env: Environment,
+    limits: Limits,
+    reuse_instances: bool,
 }
 
 impl AxumWasm {
-    pub fn new() -> Self {
+    pub fn new(
+        provisioner_address: Endpoint,
+        storage_manager: Arc<dyn StorageManager>,
+        env: Environment,
+        limits: Limits,
+    ) -> Self {
         // Allow about 2^15 = 32k logs of backpressure
         // We know the wasm currently handles about 16k requests per second (req / sec) so 16k seems to be a safe number
         // As we make performance gains elsewhere this might eventually become the new bottleneck to increase :D
@@ -66,13 +117,19 @@ impl AxumWasm {
             logs_tx: tx,
             kill_tx: Mutex::new(None),
             stopped_tx,
+            provisioner_address,
+            storage_manager,
+            env,
+            limits,
+            reuse_instances: true,
         }
     }
-}
 
-impl Default for AxumWasm {
-    fn default() -> Self {
-        Self::new()
+    /// Start a new instance of the module for every request, and drop it once the request is done.
+    /// This is how requests used to be handled, and is kept to compare against.
+    pub fn without_instance_reuse(mut self) -> Self {
+        self.reuse_instances = false;
+        self
     }
 }
 
@@ -82,21 +139,107 @@ impl Runtime for AxumWasm {
         &self,
         request: tonic::Request<LoadRequest>,
     ) -> Result<tonic::Response<LoadResponse>, Status> {
-        let wasm_path = request.into_inner().path;
-        trace!(wasm_path, "loading shuttle-next project");
+        let claim = request.extensions().get::<Claim>().map(Clone::clone);
+
+        let LoadRequest {
+            path,
+            resources,
+            secrets,
+            service_name,
+        } = request.into_inner();
+        trace!(path, "loading shuttle-next project");
 
-        let router = RouterBuilder::new()
+        let mut router = RouterBuilder::new(self.limits)
             .map_err(|err| Status::from_error(err.into()))?
-            .src(wasm_path)
+            .src(path)
             .build()
             .map_err(|err| Status::from_error(err.into()))?;
+        router.reuse_instances = self.reuse_instances;
+
+        let secrets = BTreeMap::from_iter(secrets.into_iter());
+
+        // Only modules which ask for resources need the provisioner
+        let channel = self.provisioner_address.clone().connect_lazy();
+        let channel = ServiceBuilder::new()
+            .layer(ClaimLayer)
+            .layer(InjectPropagationLayer)
+            .service(channel);
+
+        let provisioner_client = ProvisionerClient::new(channel);
+
+        let service_name = ServiceName::from_str(service_name.as_str())
+            .map_err(|err| Status::from_error(Box::new(err)))?;
+
+        let past_resources = resources
+            .into_iter()
+            .map(resource::Response::from_bytes)
+            .collect();
+        let new_resources = Arc::new(Mutex::new(Vec::new()));
+        let resource_tracker = ResourceTracker::new(past_resources, new_resources.clone());
+
+        let factory = ProvisionerFactory::new(
+            provisioner_client,
+            service_name,
+            secrets,
+            self.storage_manager.clone(),
+            self.env,
+            claim,
+        );
+
+        let loaded = router
+            .load(factory, resource_tracker, self.logs_tx.clone())
+            .await;
+
+        let new_resources = new_resources
+            .lock()
+            .expect("to get lock no new resources")
+            .clone();
+        let resources = new_resources
+            .iter()
+            .map(resource::Response::to_bytes)
+            .collect();
+
+        if let Err(error) = loaded {
+            error!(%error, "loading service failed");
+
+            let message = LoadResponse {
+                success: false,
+                message: error.to_string(),
+                resources,
+            };
+
+            return Ok(tonic::Response::new(message));
+        }
+
+        router
+            .set_resources(
+                new_resources
+                    .into_iter()
+                    .map(|resource| resource.data)
+                    .collect(),
+            )
+            .map_err(|err| Status::from_error(err.into()))?;
+
+        // Build the router of the app once upfront, so an app which cannot build it fails to load
+        // rather than failing every request
+        if let Err(error) = router.start_idle_instance(&self.logs_tx).await {
+            error!(%error, "starting service failed");
+
+            let message = LoadResponse {
+                success: false,
+                message: error.to_string(),
+                resources,
+            };
+
+            return Ok(tonic::Response::new(message));
+        }
 
         *self.router.lock().unwrap() = Some(router);
 
         let message = LoadResponse {
             success: true,
             message: String::new(),
-            resources: Vec::new(),
+            resources,
         };
 
         Ok(tonic::Response::new(message))
@@ -106,7 +249,7 @@ impl Runtime for AxumWasm {
         &self,
         request: tonic::Request<StartRequest>,
     ) -> Result<tonic::Response<StartResponse>, Status> {
-        let StartRequest { ip } = request.into_inner();
+        let StartRequest { ip, body_limit } = request.into_inner();
 
         let address = SocketAddr::from_str(&ip)
             .context("invalid socket address")
@@ -118,7 +261,7 @@ impl Runtime for AxumWasm {
 
         *self.kill_tx.lock().unwrap() = Some(kill_tx);
 
-        let router = self
+        let mut router = self
             .router
             .lock()
             .unwrap()
@@ -126,6 +269,10 @@ impl Runtime for AxumWasm {
             .context("tried to start a service that was not loaded")
             .map_err(|err| Status::internal(err.to_string()))?;
 
+        if body_limit != 0 {
+            router.body_limit = body_limit;
+        }
+
         let stopped_tx = self.stopped_tx.clone();
 
         tokio::spawn(run_until_stopped(
@@ -199,23 +346,76 @@ impl Runtime for AxumWasm {
         Ok(tonic::Response::new(ReceiverStream::new(rx)))
     }
 }
+/// The data of the store of every call into a module
+struct ModuleState {
+    wasi: WasiCtx,
+    limiter: MemoryLimiter,
+}
+
 struct RouterBuilder {
     engine: Engine,
-    linker: Linker<WasiCtx>,
+    ticker: Arc<EpochTicker>,
+    linker: Linker<ModuleState>,
     src: Option<PathBuf>,
+    limits: Limits,
+}
+
+/// Advances the epoch of an engine, so calls into its modules can check how long they ran for.
+/// The ticker stops once the last router holding it is dropped, along with its engine.
+struct EpochTicker(tokio::task::JoinHandle<()>);
+
+impl EpochTicker {
+    fn start(engine: Engine) -> Self {
+        Self(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(EPOCH_TICK);
+
+            loop {
+                interval.tick().await;
+                engine.increment_epoch();
+            }
+        }))
+    }
+}
+
+impl Drop for EpochTicker {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
 }
 
 impl RouterBuilder {
-    fn new() -> anyhow::Result<Self> {
-        let engine = Engine::default();
+    fn new(limits: Limits) -> anyhow::Result<Self> {
+        // Every request being handled holds an instance of its own, so take them from a pool
+        // rather than allocating the memory of each one on demand
+        let mut pooling = PoolingAllocationConfig::default();
+        pooling
+            .instance_count(INSTANCE_POOL_SIZE)
+            .instance_memory_pages(limits.memory_pages());
+
+        let mut config = Config::new();
+        config
+            .allocation_strategy(InstanceAllocationStrategy::Pooling(pooling))
+            .consume_fuel(true)
+            .epoch_interruption(true);
+
+        // Wasmtime keys the compilations it caches by a hash of the module and of the settings of
+        // the engine, so a module rebuilt in place is always compiled again
+        if let Err(error) = config.cache_config_load_default() {
+            warn!(%error, "failed to enable the cache of compiled modules");
+        }
 
-        let mut linker: Linker<WasiCtx> = Linker::new(&engine);
-        wasmtime_wasi::add_to_linker(&mut linker, |s| s)?;
+        let engine = Engine::new(&config)?;
+        let ticker = Arc::new(EpochTicker::start(engine.clone()));
+
+        let mut linker: Linker<ModuleState> = Linker::new(&engine);
+        wasmtime_wasi::add_to_linker(&mut linker, |state: &mut ModuleState| &mut state.wasi)?;
 
         Ok(Self {
             engine,
+            ticker,
             linker,
             src: None,
+            limits,
         })
     }
 
@@ -232,67 +432,317 @@ impl RouterBuilder {
             trace!("export: {}", export.name());
         }
 
+        // Resolve the imports of the module once, so each request only has to instantiate it
+        let instance_pre = self
+            .linker
+            .instantiate_pre(&module)
+            .context("failed to link module")?;
+
         Ok(Router {
-            linker: self.linker,
             engine: self.engine,
-            module,
+            _ticker: self.ticker,
+            instance_pre,
+            resources: Arc::new(rmps::to_vec(&Vec::<Value>::new())?),
+            body_limit: Next::default().body_limit,
+            limits: self.limits,
+            idle: Default::default(),
+            reuse_instances: true,
         })
     }
 }
 
 #[derive(Clone)]
 struct Router {
-    linker: Linker<WasiCtx>,
     engine: Engine,
-    module: Module,
+    _ticker: Arc<EpochTicker>,
+    /// The module with its imports already resolved, ready to be instantiated whenever every
+    /// instance is busy
+    instance_pre: InstancePre<ModuleState>,
+    /// The outputs of the resources of the module, serialized to be sent to every new instance
+    resources: Arc<Vec<u8>>,
+    /// Largest request body to stream to the module, in bytes
+    body_limit: u64,
+    limits: Limits,
+    /// Instances which built their router and are not handling a request right now
+    idle: Arc<Mutex<Vec<ReadyInstance>>>,
+    /// Whether instances go back to being idle once they are done with a request
+    reuse_instances: bool,
+}
+
+/// An instance of the module which built the router of the app from its resources, so it can
+/// handle one request after another without building them again
+struct ReadyInstance {
+    store: Store<ModuleState>,
+    call: TypedFunc<(RawFd, RawFd), ()>,
+}
+
+/// What happened to a request while the module handled it, as seen by the tasks streaming its
+/// bodies and by the call into the module
+#[derive(Default)]
+struct RequestState {
+    /// The request body went over the limit and was cut off
+    too_large: AtomicBool,
+    /// The call into the module failed, possibly halfway through the response body
+    failed: AtomicBool,
 }
 
 impl Router {
-    /// Send a HTTP request with body to given endpoint on the axum-wasm router and return the response
-    async fn handle_request(
-        &mut self,
-        req: hyper::Request<Body>,
-        logs_tx: Sender<Result<runtime::LogItem, Status>>,
-    ) -> anyhow::Result<Response<Body>> {
+    /// Make a store for an instance of the module, which traps once the instance grows its memory
+    /// past the limit
+    fn new_store(&self) -> anyhow::Result<Store<ModuleState>> {
         let wasi = WasiCtxBuilder::new()
             .inherit_stdio()
             .inherit_args()
             .context("failed to read args")?
             .build();
 
-        let mut store = Store::new(&self.engine, wasi);
-        self.linker.module(&mut store, "axum", &self.module)?;
+        let mut store = Store::new(
+            &self.engine,
+            ModuleState {
+                wasi,
+                limiter: MemoryLimiter::new(&self.limits),
+            },
+        );
+
+        store.limiter(|state| &mut state.limiter);
+
+        Ok(store)
+    }
+
+    /// Give the next call into a store `fuel` to run on, whatever earlier calls left over, and
+    /// make it trap once it runs out or runs for longer than `timeout`, if it has one
+    fn arm(
+        &self,
+        store: &mut Store<ModuleState>,
+        fuel: u64,
+        timeout: Option<Duration>,
+    ) -> anyhow::Result<()> {
+        let left = store.consume_fuel(0)?;
+        if left < fuel {
+            store.add_fuel(fuel - left)?;
+        } else {
+            store.consume_fuel(left - fuel)?;
+        }
+
+        let started = Instant::now();
+        store.epoch_deadline_callback(move |_| match timeout {
+            Some(timeout) if started.elapsed() >= timeout => {
+                Err(LimitExceeded::Timeout(timeout).into())
+            }
+            _ => Ok(1),
+        });
+        store.set_epoch_deadline(1);
+
+        Ok(())
+    }
+
+    /// Have the module provision the resources it needs through `factory`, if it needs any. The
+    /// outputs of the resources are recorded in `resource_tracker`.
+    async fn load(
+        &self,
+        factory: ProvisionerFactory,
+        resource_tracker: ResourceTracker,
+        logs_tx: Sender<Result<runtime::LogItem, Status>>,
+    ) -> anyhow::Result<()> {
+        // Provisioning can take a lot longer than a request, and is not paid for by one
+        let mut store = self.new_store()?;
+        self.arm(&mut store, UNMETERED_FUEL, None)?;
+        let instance = self.instance_pre.instantiate(&mut store)?;
+
+        let Some(load) = instance.get_func(&mut store, "__SHUTTLE_Axum_load") else {
+            trace!("module does not need any resources");
+            return Ok(());
+        };
+
+        let load = load.typed::<(RawFd, RawFd), ()>(&store)?;
 
         let (logs_stream, logs_client) =
-            UnixStream::pair().context("failed to open logs unixstream")?;
-        let (mut parts_stream, parts_client) =
-            UnixStream::pair().context("failed to open parts unixstream")?;
-        let (mut body_stream, body_client) =
-            UnixStream::pair().context("failed to open body write unixstream")?;
+            std::os::unix::net::UnixStream::pair().context("failed to open logs unixstream")?;
+        let (factory_stream, factory_client) =
+            UnixStream::pair().context("failed to open factory unixstream")?;
 
-        let logs_client = WasiUnixStream::from_cap_std(logs_client);
-        let parts_client = WasiUnixStream::from_cap_std(parts_client);
-        let body_client = WasiUnixStream::from_cap_std(body_client);
+        let logs_client = WasiUnixStream::from_cap_std(UnixStream::from_std(logs_client));
+        let factory_client = WasiUnixStream::from_cap_std(factory_client);
+
+        store
+            .data_mut()
+            .wasi
+            .insert_file(LOGS_FD, Box::new(logs_client), FileCaps::all());
+        store
+            .data_mut()
+            .wasi
+            .insert_file(FACTORY_FD, Box::new(factory_client), FileCaps::all());
+
+        forward_logs(logs_stream, logs_tx)?;
+
+        let handle = Handle::current();
+        let bridge = tokio::task::spawn_blocking(move || {
+            serve_factory(factory_stream, factory, resource_tracker, handle)
+        });
+
+        trace!("loading resources of module");
+        // The store holds the module side of the factory stream, so dropping it once the module
+        // is done ends the bridge
+        let loaded = tokio::task::spawn_blocking(move || {
+            load.call(&mut store, (LOGS_FD as i32, FACTORY_FD as i32))
+        })
+        .await
+        .context("loading module panicked")?;
+
+        let last_error = bridge.await.context("factory bridge panicked")?;
+
+        match (loaded, last_error) {
+            (Ok(()), _) => Ok(()),
+            (Err(_), Some(error)) => Err(anyhow!("failed to provision resource: {error}")),
+            (Err(error), None) => Err(error.context("failed to load module")),
+        }
+    }
+
+    /// Set the resource outputs to send to every new instance of the module
+    fn set_resources(&mut self, resources: Vec<Value>) -> anyhow::Result<()> {
+        self.resources =
+            Arc::new(rmps::to_vec(&resources).context("failed to serialize resource outputs")?);
+
+        Ok(())
+    }
+
+    /// Start an instance of the module and have it build the router of the app from the outputs
+    /// of its resources, which only happens once per instance
+    async fn start_instance(
+        &self,
+        logs_tx: &Sender<Result<runtime::LogItem, Status>>,
+    ) -> anyhow::Result<ReadyInstance> {
+        // Building the router is not paid for by the request which happens to need a new instance,
+        // but it still has to finish within the time of one
+        let mut store = self.new_store()?;
+        self.arm(&mut store, UNMETERED_FUEL, Some(self.limits.timeout))?;
+        let instance = self.instance_pre.instantiate(&mut store)?;
+
+        let init = instance
+            .get_typed_func::<(RawFd, RawFd), i32>(&mut store, "__SHUTTLE_Axum_init")
+            .context("init function should be exported by the module")?;
+        let call = instance
+            .get_typed_func::<(RawFd, RawFd), ()>(&mut store, "__SHUTTLE_Axum_call")
+            .context("router function should be exported by the module")?;
+
+        // The instance keeps its logs stream for as long as it lives
+        let (logs_stream, logs_client) =
+            std::os::unix::net::UnixStream::pair().context("failed to open logs unixstream")?;
+        let (mut resources_stream, resources_client) = std::os::unix::net::UnixStream::pair()
+            .context("failed to open resources unixstream")?;
+
+        let logs_client = WasiUnixStream::from_cap_std(UnixStream::from_std(logs_client));
+        let resources_client = WasiUnixStream::from_cap_std(UnixStream::from_std(resources_client));
 
         store
             .data_mut()
+            .wasi
             .insert_file(LOGS_FD, Box::new(logs_client), FileCaps::all());
+        store.data_mut().wasi.insert_file(
+            RESOURCES_FD,
+            Box::new(resources_client),
+            FileCaps::all(),
+        );
+
+        forward_logs(logs_stream, logs_tx.clone())?;
+
+        // Resource outputs larger than the buffer of the stream are only written as the module
+        // reads them, so write them alongside the call rather than before it
+        let resources = self.resources.clone();
+        let write_resources =
+            tokio::task::spawn_blocking(move || resources_stream.write_all(&resources));
+
+        trace!("starting instance of module");
+        let (store, started) = tokio::task::spawn_blocking(move || {
+            let started = init.call(&mut store, (LOGS_FD as i32, RESOURCES_FD as i32));
+
+            (store, started)
+        })
+        .await
+        .context("starting instance panicked")?;
+
+        // Fails when the module stopped before it read all of them, in which case the call failed
+        if let Err(error) = write_resources
+            .await
+            .context("writing resources panicked")?
+        {
+            trace!(%error, "failed to write resources to wasm");
+        }
+
+        match started? {
+            1 => Ok(ReadyInstance { store, call }),
+            _ => Err(anyhow!(
+                "failed to build the router of the app, see the logs of the deployment"
+            )),
+        }
+    }
+
+    /// Start an instance which waits for the first request, so that a module which cannot build
+    /// its router fails before it is started
+    async fn start_idle_instance(
+        &self,
+        logs_tx: &Sender<Result<runtime::LogItem, Status>>,
+    ) -> anyhow::Result<()> {
+        let instance = self.start_instance(logs_tx).await?;
+        self.idle.lock().unwrap().push(instance);
+
+        Ok(())
+    }
+
+    /// Send a HTTP request to given endpoint on the axum-wasm router and return its response. Both
+    /// bodies are streamed in chunks, so the response can start before the request body has ended.
+    ///
+    /// The request is handled by an idle instance, or by a new one when every instance is busy.
+    /// Instances go back to being idle once they are done with a request, unless the call failed
+    /// since the state of a failed instance cannot be trusted.
+    async fn handle_request(
+        &self,
+        req: hyper::Request<Body>,
+        logs_tx: Sender<Result<runtime::LogItem, Status>>,
+    ) -> anyhow::Result<Response<Body>> {
+        // Requests which say upfront that their body is too large are not even sent to the module
+        if req.body().size_hint().lower() > self.body_limit {
+            return Ok(payload_too_large());
+        }
+
+        let idle = self.idle.lock().unwrap().pop();
+        let ReadyInstance {
+            mut store,
+            call: router,
+        } = match idle {
+            Some(instance) => instance,
+            None => match self.start_instance(&logs_tx).await {
+                Ok(instance) => instance,
+                Err(error) => {
+                    // Such as a module which starts out with more memory than the limit
+                    return match LimitExceeded::from_error(&error, &self.limits) {
+                        Some(limit) => Ok(limit_exceeded(limit, &logs_tx).await),
+                        None => Err(error.context("failed to start instance of module")),
+                    };
+                }
+            },
+        };
+
+        let request = Arc::new(RequestState::default());
+        self.arm(&mut store, self.limits.fuel, Some(self.limits.timeout))?;
+
+        let (mut parts_stream, parts_client) =
+            UnixStream::pair().context("failed to open parts unixstream")?;
+        let (body_stream, body_client) =
+            std::os::unix::net::UnixStream::pair().context("failed to open body unixstream")?;
+
+        let parts_client = WasiUnixStream::from_cap_std(parts_client);
+        let body_client = WasiUnixStream::from_cap_std(UnixStream::from_std(body_client));
 
         store
             .data_mut()
+            .wasi
             .insert_file(PARTS_FD, Box::new(parts_client), FileCaps::all());
         store
             .data_mut()
+            .wasi
             .insert_file(BODY_FD, Box::new(body_client), FileCaps::all());
 
-        tokio::task::spawn_blocking(move || {
-            let mut iter = logs_stream.bytes().filter_map(Result::ok);
-
-            while let Some(log) = Log::from_bytes(&mut iter) {
-                logs_tx.blocking_send(Ok(log.into())).expect("to send log");
-            }
-        });
-
         let (parts, body) = req.into_parts();
 
         // Serialise request parts to rmp
@@ -300,64 +750,109 @@ impl Router {
             .into_rmp()
             .context("failed to make request wrapper")?;
 
-        // Write request parts to wasm module
-        parts_stream
-            .write_all(&request_rmp)
-            .context("failed to write http parts to wasm")?;
+        body_stream
+            .set_nonblocking(true)
+            .context("failed to make body unixstream non-blocking")?;
+        let (body_reader, body_writer) = tokio::net::UnixStream::from_std(body_stream)
+            .context("failed to register body unixstream")?
+            .into_split();
+
+        // Stream the request body to wasm while the module is handling the request
+        tokio::spawn(send_body(
+            body,
+            body_writer,
+            self.body_limit,
+            request.clone(),
+        ));
 
-        // To protect our server, reject requests with bodies larger than
-        // 64kbs of data.
-        let body_size = body.size_hint().upper().unwrap_or(u64::MAX);
+        // Call our function in wasm, telling it to route the request we've written to it
+        // and write back a response. The module closes its side of the streams of the request
+        // once it is done with them, so the instance can take the next request.
+        trace!("calling Router");
+        let call_request = request.clone();
+        let idle = self.idle.clone();
+        let reuse_instances = self.reuse_instances;
+        let call = tokio::task::spawn_blocking(move || {
+            let result = router.call(&mut store, (PARTS_FD as i32, BODY_FD as i32));
+
+            match result {
+                Ok(()) if reuse_instances => idle.lock().unwrap().push(ReadyInstance {
+                    store,
+                    call: router,
+                }),
+                Ok(()) => drop(store),
+                Err(_) => {
+                    // Flag the failure before the module side of the body stream is closed with
+                    // the store
+                    call_request.failed.store(true, Ordering::Release);
+                    drop(store);
+                }
+            }
 
-        if body_size > 1024 * 64 {
-            let response = Response::builder()
-                .status(hyper::http::StatusCode::PAYLOAD_TOO_LARGE)
-                .body(Body::empty())
-                .expect("building request with empty body should not fail");
+            result
+        });
 
-            // Return early if body is too big
-            return Ok(response);
-        }
+        // Write the request parts and read the response parts, which are written before the
+        // response body. Either can block on the module, so neither happens on the runtime.
+        let parts = tokio::task::spawn_blocking(move || {
+            parts_stream
+                .write_all(&request_rmp)
+                .context("failed to write http parts to wasm")?;
 
-        let body_bytes = hyper::body::to_bytes(body)
-            .await
-            .context("failed to concatenate request body buffers")?;
+            let reader = BufReader::new(&mut parts_stream);
 
-        // Write body to wasm
-        body_stream
-            .write_all(body_bytes.as_ref())
-            .context("failed to write body to wasm")?;
+            // Deserialize response parts from rust messagepack
+            rmps::from_read::<_, ResponseWrapper>(reader)
+                .context("failed to deserialize response parts")
+        });
 
-        // Shut down the write part of the stream to signal EOF
-        body_stream
-            .shutdown(Shutdown::Write)
-            .expect("failed to shut down body write half");
+        // The module might be blocked on something other than wasm, such as a slow request body,
+        // where the epoch of the engine cannot interrupt it
+        let wrapper = match tokio::time::timeout(self.limits.timeout, parts).await {
+            Ok(wrapper) => wrapper.context("reading response parts panicked")?,
+            Err(_) => {
+                let limit = LimitExceeded::Timeout(self.limits.timeout);
 
-        // Call our function in wasm, telling it to route the request we've written to it
-        // and write back a response
-        trace!("calling Router");
-        self.linker
-            .get(&mut store, "axum", "__SHUTTLE_Axum_call")
-            .context("wasm module should be loaded and the router function should be available")?
-            .into_func()
-            .context("router function should be a function")?
-            .typed::<(RawFd, RawFd, RawFd), ()>(&store)?
-            .call(
-                &mut store,
-                (LOGS_FD as i32, PARTS_FD as i32, BODY_FD as i32),
-            )?;
-
-        // Read response parts from wasm
-        let reader = BufReader::new(&mut parts_stream);
-
-        // Deserialize response parts from rust messagepack
-        let wrapper: ResponseWrapper =
-            rmps::from_read(reader).context("failed to deserialize response parts")?;
-
-        // Read response body from wasm, convert it to a Stream and pass it to hyper
-        let reader = BufReader::new(body_stream);
-        let stream = futures::stream::iter(reader.bytes()).try_chunks(2);
-        let body = hyper::Body::wrap_stream(stream);
+                return Ok(limit_exceeded(limit, &logs_tx).await);
+            }
+        };
+
+        let wrapper = match wrapper {
+            Ok(wrapper) => wrapper,
+            Err(error) => {
+                // The module most likely failed before it could respond
+                return match call.await.context("wasm call panicked")? {
+                    Ok(()) => Err(error),
+                    Err(error) => match LimitExceeded::from_error(&error, &self.limits) {
+                        Some(limit) => Ok(limit_exceeded(limit, &logs_tx).await),
+                        None => Err(error.context("wasm call failed")),
+                    },
+                };
+            }
+        };
+
+        if request.too_large.load(Ordering::Acquire) {
+            return Ok(payload_too_large());
+        }
+
+        let limits = self.limits;
+        tokio::spawn(async move {
+            match call.await {
+                Ok(Ok(())) => trace!("wasm call finished"),
+                Ok(Err(error)) => match LimitExceeded::from_error(&error, &limits) {
+                    Some(limit) => {
+                        warn!(%limit, "module ran into a limit while streaming response");
+
+                        let _ = logs_tx.send(Ok(limit.to_log())).await;
+                    }
+                    None => error!(%error, "wasm call failed while streaming response"),
+                },
+                Err(error) => error!(%error, "wasm call panicked while streaming response"),
+            }
+        });
+
+        // Stream the response body from wasm as the module writes it
+        let body = hyper::Body::wrap_stream(receive_body(body_reader, request));
 
         let response: Response<Body> = wrapper
             .into_response_builder()
@@ -368,6 +863,241 @@ impl Router {
     }
 }
 
+fn payload_too_large() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::PAYLOAD_TOO_LARGE)
+        .body(Body::empty())
+        .expect("building request with empty body should not fail")
+}
+
+/// Answer a request for which the module ran into a limit, and explain which one in its logs
+async fn limit_exceeded(
+    limit: LimitExceeded,
+    logs_tx: &Sender<Result<runtime::LogItem, Status>>,
+) -> Response<Body> {
+    warn!(%limit, "module ran into a limit");
+
+    let _ = logs_tx.send(Ok(limit.to_log())).await;
+
+    Response::builder()
+        .status(limit.status())
+        .body(Body::empty())
+        .expect("building request with empty body should not fail")
+}
+
+/// Send a request body to a module in frames, each a chunk prefixed with its length as a
+/// big-endian `u64`, and signal its end with a frame of length zero.
+///
+/// Bodies larger than `body_limit` are cut off and flagged in the `request` state, as are bodies
+/// the client failed to send in full. Either closes the stream without the last frame, which the
+/// module sees as an error rather than as the end of the body.
+async fn send_body(
+    mut body: Body,
+    mut stream: OwnedWriteHalf,
+    body_limit: u64,
+    request: Arc<RequestState>,
+) {
+    let mut sent = 0;
+
+    while let Some(chunk) = body.data().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(error) => {
+                warn!(%error, "failed to read request body");
+                return;
+            }
+        };
+
+        // Hyper can hand out empty chunks, which would otherwise end the body early
+        if chunk.is_empty() {
+            continue;
+        }
+
+        sent += chunk.len() as u64;
+
+        if sent > body_limit {
+            request.too_large.store(true, Ordering::Release);
+            return;
+        }
+
+        // Modules are free to respond without reading the whole body, which closes the stream
+        let written = match stream.write_u64(chunk.len() as u64).await {
+            Ok(()) => stream.write_all(&chunk).await,
+            Err(error) => Err(error),
+        };
+
+        if let Err(error) = written {
+            trace!(%error, "module stopped reading request body");
+            return;
+        }
+    }
+
+    if let Err(error) = stream.write_u64(0).await {
+        trace!(%error, "module stopped reading request body");
+        return;
+    }
+
+    if let Err(error) = stream.shutdown().await {
+        trace!(%error, "failed to shut down body write half");
+    }
+}
+
+/// Receive a response body from a module in chunks, until the module closes the stream.
+///
+/// A module which started responding before its request body went over the limit might have
+/// responded to a cut off body, and one which failed halfway through left its response unfinished.
+/// Either response ends with an error instead of ending cleanly.
+fn receive_body(
+    stream: OwnedReadHalf,
+    request: Arc<RequestState>,
+) -> impl futures::Stream<Item = std::io::Result<Bytes>> + Send + 'static {
+    futures::stream::unfold(Some(stream), move |stream| {
+        let request = request.clone();
+
+        async move {
+            let mut stream = stream?;
+            let mut buf = vec![0; BODY_CHUNK_SIZE];
+
+            match stream.read(&mut buf).await {
+                Ok(0) if request.too_large.load(Ordering::Acquire) => Some((
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "request body is larger than the limit",
+                    )),
+                    None,
+                )),
+                Ok(0) if request.failed.load(Ordering::Acquire) => Some((
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        "module failed while writing the response body",
+                    )),
+                    None,
+                )),
+                Ok(0) => None,
+                Ok(read) => {
+                    buf.truncate(read);
+                    Some((Ok(Bytes::from(buf)), Some(stream)))
+                }
+                Err(error) => Some((Err(error), None)),
+            }
+        }
+    })
+}
+
+/// Forward the logs a module writes to its logs stream to the logs subscriber, until the module
+/// closes its side of the stream. Instances live for many requests, so this does not hold on to a
+/// blocking thread for as long as they do.
+fn forward_logs(
+    logs_stream: std::os::unix::net::UnixStream,
+    logs_tx: Sender<Result<runtime::LogItem, Status>>,
+) -> anyhow::Result<()> {
+    logs_stream
+        .set_nonblocking(true)
+        .context("failed to make logs unixstream non-blocking")?;
+    let mut logs_stream = tokio::net::UnixStream::from_std(logs_stream)
+        .context("failed to register logs unixstream")?;
+
+    tokio::spawn(async move {
+        let mut buf = Vec::new();
+
+        while let Ok(read) = logs_stream.read_buf(&mut buf).await {
+            if read == 0 {
+                break;
+            }
+
+            // A read can end halfway through a log, whose rest comes with the next read
+            loop {
+                let mut iter = buf.iter().copied();
+                let Some(log) = Log::from_bytes(&mut iter) else {
+                    break;
+                };
+                let parsed = buf.len() - iter.len();
+                buf.drain(..parsed);
+
+                if logs_tx.send(Ok(log.into())).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Answer the requests a module makes to provision its resources while it is being loaded, until
+/// the module closes its side of the stream. Returns the last error sent to the module, since that
+/// is most likely why it failed to load.
+fn serve_factory(
+    mut stream: UnixStream,
+    mut factory: ProvisionerFactory,
+    mut resource_tracker: ResourceTracker,
+    handle: Handle,
+) -> Option<String> {
+    let mut last_error = None;
+
+    while let Ok(request) = rmps::from_read::<_, FactoryRequest>(&mut stream) {
+        let response = handle
+            .block_on(answer_factory_request(
+                request,
+                &mut factory,
+                &mut resource_tracker,
+            ))
+            .unwrap_or_else(|error| {
+                let error = error.to_string();
+                last_error = Some(error.clone());
+
+                FactoryResponse::Error(error)
+            });
+
+        let response = match response.into_rmp() {
+            Ok(response) => response,
+            Err(error) => {
+                error!(%error, "failed to serialize factory response");
+                break;
+            }
+        };
+
+        if let Err(error) = stream.write_all(&response) {
+            error!(%error, "failed to write factory response to wasm");
+            break;
+        }
+    }
+
+    last_error
+}
+
+async fn answer_factory_request(
+    request: FactoryRequest,
+    factory: &mut ProvisionerFactory,
+    resource_tracker: &mut ResourceTracker,
+) -> Result<FactoryResponse, shuttle_service::Error> {
+    let response = match request {
+        FactoryRequest::DbConnection(db_type) => {
+            FactoryResponse::DbConnection(factory.get_db_connection(db_type).await?)
+        }
+        FactoryRequest::Bucket => FactoryResponse::Bucket(factory.get_bucket().await?),
+        FactoryRequest::Secrets => FactoryResponse::Secrets(factory.get_secrets().await?),
+        FactoryRequest::ServiceName => {
+            FactoryResponse::ServiceName(factory.get_service_name().to_string())
+        }
+        FactoryRequest::Environment => FactoryResponse::Environment(factory.get_environment()),
+        FactoryRequest::CachedOutput { r#type, config } => {
+            FactoryResponse::CachedOutput(resource_tracker.get_cached_output(r#type, &config))
+        }
+        FactoryRequest::RecordResource {
+            r#type,
+            config,
+            output,
+        } => {
+            resource_tracker.record_resource(r#type, config, output);
+
+            FactoryResponse::Recorded
+        }
+    };
+
+    Ok(response)
+}
+
 /// Start a hyper server with a service that calls an axum router in WASM,
 /// and a kill receiver for stopping the server.
 async fn run_until_stopped(
@@ -382,7 +1112,7 @@ async fn run_until_stopped(
         let logs_tx = logs_tx.clone();
         async move {
             Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
-                let mut router = router.clone();
+                let router = router.clone();
                 let logs_tx = logs_tx.clone();
                 async move {
                     Ok::<_, Infallible>(match router.handle_request(req, logs_tx).await {
@@ -432,13 +1162,13 @@ pub mod tests {
     use super::*;
     use hyper::{http::HeaderValue, Method, Request, StatusCode, Version};
 
-    // Compile axum wasm module
-    fn compile_module() {
+    // Compile an axum wasm module from the test resources
+    fn compile_module(name: &str) {
         Command::new("cargo")
             .arg("build")
             .arg("--target")
             .arg("wasm32-wasi")
-            .current_dir("tests/resources/axum-wasm-expanded")
+            .current_dir(format!("tests/resources/{name}"))
             .spawn()
             .unwrap()
             .wait()
@@ -447,9 +1177,9 @@ pub mod tests {
 
     #[tokio::test(flavor = "multi_thread")]
     async fn axum() {
-        compile_module();
+        compile_module("axum-wasm-expanded");
 
-        let router = RouterBuilder::new()
+        let router = RouterBuilder::new(Limits::default())
             .unwrap()
             .src("tests/resources/axum-wasm-expanded/target/wasm32-wasi/debug/shuttle_axum_expanded.wasm")
             .build()
@@ -542,7 +1272,11 @@ pub mod tests {
             .body("this should be uppercased".into())
             .unwrap();
 
-        let res = router.clone().handle_request(request, tx).await.unwrap();
+        let res = router
+            .clone()
+            .handle_request(request, tx.clone())
+            .await
+            .unwrap();
 
         assert_eq!(res.status(), StatusCode::OK);
         assert_eq!(
@@ -555,5 +1289,275 @@ pub mod tests {
                 .as_ref(),
             b"THIS SHOULD BE UPPERCASED"
         );
+
+        // POST /uppercase with a chunked body larger than a single chunk
+        let chunks = vec![Ok::<_, std::io::Error>("streamed ".repeat(1024)); 4];
+        let request: Request<Body> = Request::builder()
+            .method(Method::POST)
+            .version(Version::HTTP_11)
+            .uri("https://axum-wasm.example/uppercase")
+            .body(Body::wrap_stream(futures::stream::iter(chunks)))
+            .unwrap();
+
+        let res = router
+            .clone()
+            .handle_request(request, tx.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            hyper::body::to_bytes(res.into_body()).await.unwrap(),
+            "STREAMED ".repeat(4 * 1024).as_bytes()
+        );
+
+        // Bodies over the limit are rejected, whether their size is known upfront or not
+        let mut router = router;
+        router.body_limit = 1024;
+
+        let request: Request<Body> = Request::builder()
+            .method(Method::POST)
+            .version(Version::HTTP_11)
+            .uri("https://axum-wasm.example/uppercase")
+            .body("too large".repeat(1024).into())
+            .unwrap();
+
+        let res = router
+            .clone()
+            .handle_request(request, tx.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // This module responds before reading the body, so only send the part of the body which
+        // goes over the limit once it did
+        let (mut body_tx, body) = Body::channel();
+        let request: Request<Body> = Request::builder()
+            .method(Method::POST)
+            .version(Version::HTTP_11)
+            .uri("https://axum-wasm.example/uppercase")
+            .body(body)
+            .unwrap();
+
+        body_tx
+            .send_data("too large".repeat(64).into())
+            .await
+            .unwrap();
+
+        let res = router
+            .clone()
+            .handle_request(request, tx.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+
+        body_tx
+            .send_data("too large".repeat(64).into())
+            .await
+            .unwrap();
+        drop(body_tx);
+
+        // The module sees the body was cut off, so its response is never mistaken for a whole one
+        assert!(hyper::body::to_bytes(res.into_body()).await.is_err());
+
+        // As is a body the client fails to send in full
+        let (body_tx, body) = Body::channel();
+        let request: Request<Body> = Request::builder()
+            .method(Method::POST)
+            .version(Version::HTTP_11)
+            .uri("https://axum-wasm.example/uppercase")
+            .body(body)
+            .unwrap();
+
+        let res = router.clone().handle_request(request, tx).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+
+        body_tx.abort();
+
+        assert!(hyper::body::to_bytes(res.into_body()).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn state_and_layers() {
+        compile_module("axum-wasm-state");
+
+        let router = RouterBuilder::new(Limits::default())
+            .unwrap()
+            .src("tests/resources/axum-wasm-state/target/wasm32-wasi/debug/shuttle_axum_state.wasm")
+            .build()
+            .unwrap();
+
+        let (tx, mut rx) = mpsc::channel(16);
+
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+        router.start_idle_instance(&tx).await.unwrap();
+
+        // The instance keeps its state from one request to the next
+        for expected in ["1", "2"] {
+            // Wait for the instance to be done with the last request
+            while router.idle.lock().unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+
+            let request: Request<Body> = Request::builder()
+                .method(Method::GET)
+                .version(Version::HTTP_11)
+                .uri("https://axum-wasm.example/count")
+                .body(Body::empty())
+                .unwrap();
+
+            let res = router
+                .clone()
+                .handle_request(request, tx.clone())
+                .await
+                .unwrap();
+
+            assert_eq!(res.status(), StatusCode::OK);
+
+            // The layer declared last is the outermost, so it sets the header last
+            assert_eq!(res.headers()[hyper::header::SERVER], "outer");
+            assert_eq!(
+                hyper::body::to_bytes(res.into_body()).await.unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn limits() {
+        compile_module("axum-wasm-expanded");
+
+        let router = RouterBuilder::new(Limits {
+            fuel: 1000,
+            ..Default::default()
+        })
+        .unwrap()
+        .src("tests/resources/axum-wasm-expanded/target/wasm32-wasi/debug/shuttle_axum_expanded.wasm")
+        .build()
+        .unwrap();
+
+        let (tx, mut rx) = mpsc::channel(16);
+
+        let request: Request<Body> = Request::builder()
+            .method(Method::GET)
+            .version(Version::HTTP_11)
+            .uri("https://axum-wasm.example/hello")
+            .body(Body::empty())
+            .unwrap();
+
+        let res = router.handle_request(request, tx).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // The logs of the deployment explain which limit the request ran into
+        explained(&mut rx, "request ran out of fuel after 1000 units").await;
+    }
+
+    /// Wait for the logs of the deployment to explain a limit
+    async fn explained(rx: &mut Receiver<Result<runtime::LogItem, Status>>, message: &str) {
+        while let Some(Ok(log)) = rx.recv().await {
+            let fields: serde_json::Value = serde_json::from_slice(&log.fields).unwrap();
+
+            if fields["message"] == message {
+                return;
+            }
+        }
+
+        panic!("the logs never explained: {message}");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn memory_limit() {
+        compile_module("axum-wasm-expanded");
+
+        let router = RouterBuilder::new(Limits {
+            memory: 16 * 1024 * 1024,
+            ..Default::default()
+        })
+        .unwrap()
+        .src("tests/resources/axum-wasm-expanded/target/wasm32-wasi/debug/shuttle_axum_expanded.wasm")
+        .build()
+        .unwrap();
+
+        let (tx, mut rx) = mpsc::channel(16);
+
+        let request: Request<Body> = Request::builder()
+            .method(Method::GET)
+            .version(Version::HTTP_11)
+            .uri("https://axum-wasm.example/allocate")
+            .body(Body::empty())
+            .unwrap();
+
+        let res = router
+            .clone()
+            .handle_request(request, tx.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        explained(&mut rx, "request tried to grow memory past 16777216 bytes").await;
+
+        // The instance which ran into the limit is dropped, and a new one takes the next request
+        let request: Request<Body> = Request::builder()
+            .method(Method::GET)
+            .version(Version::HTTP_11)
+            .uri("https://axum-wasm.example/hello")
+            .body(Body::empty())
+            .unwrap();
+
+        let res = router.handle_request(request, tx).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn timeout() {
+        compile_module("axum-wasm-expanded");
+
+        let router = RouterBuilder::new(Limits {
+            timeout: Duration::from_millis(500),
+            ..Default::default()
+        })
+        .unwrap()
+        .src("tests/resources/axum-wasm-expanded/target/wasm32-wasi/debug/shuttle_axum_expanded.wasm")
+        .build()
+        .unwrap();
+
+        let (tx, mut rx) = mpsc::channel(16);
+
+        let request: Request<Body> = Request::builder()
+            .method(Method::GET)
+            .version(Version::HTTP_11)
+            .uri("https://axum-wasm.example/spin")
+            .body(Body::empty())
+            .unwrap();
+
+        let started = Instant::now();
+        let res = router
+            .clone()
+            .handle_request(request, tx.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        explained(&mut rx, "request was not handled within 500ms").await;
+
+        // The instance stuck on the first request was interrupted and dropped, and a new one takes
+        // the next request
+        let request: Request<Body> = Request::builder()
+            .method(Method::GET)
+            .version(Version::HTTP_11)
+            .uri("https://axum-wasm.example/hello")
+            .body(Body::empty())
+            .unwrap();
+
+        let res = router.handle_request(request, tx).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
     }
 }
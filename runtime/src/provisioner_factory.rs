@@ -5,9 +5,11 @@ use shuttle_common::{
     claims::{Claim, ClaimService, InjectPropagation},
     database,
     storage_manager::StorageManager,
-    DatabaseReadyInfo,
+    BucketReadyInfo, DatabaseReadyInfo,
+};
+use shuttle_proto::provisioner::{
+    provisioner_client::ProvisionerClient, BucketRequest, DatabaseRequest,
 };
-use shuttle_proto::provisioner::{provisioner_client::ProvisionerClient, DatabaseRequest};
 use shuttle_service::{Environment, Factory, ServiceName};
 use tonic::{transport::Channel, Request};
 use tracing::info;
@@ -73,6 +75,31 @@ impl Factory for ProvisionerFactory {
         Ok(info)
     }
 
+    async fn get_bucket(&mut self) -> Result<BucketReadyInfo, shuttle_service::Error> {
+        info!("Provisioning a bucket");
+
+        let mut request = Request::new(BucketRequest {
+            project_name: self.service_name.to_string(),
+        });
+
+        if let Some(claim) = &self.claim {
+            request.extensions_mut().insert(claim.clone());
+        }
+
+        let response = self
+            .provisioner_client
+            .provision_bucket(request)
+            .await
+            .map_err(shuttle_service::error::CustomError::new)?
+            .into_inner();
+
+        let info: BucketReadyInfo = response.into();
+
+        info!("Done provisioning bucket");
+
+        Ok(info)
+    }
+
     async fn get_secrets(&mut self) -> Result<BTreeMap<String, String>, shuttle_service::Error> {
         Ok(self.secrets.clone())
     }
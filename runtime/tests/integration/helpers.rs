@@ -10,7 +10,7 @@ use shuttle_common::claims::{ClaimService, InjectPropagation};
 use shuttle_proto::{
     provisioner::{
         provisioner_server::{Provisioner, ProvisionerServer},
-        DatabaseDeletionResponse, DatabaseRequest, DatabaseResponse,
+        BucketRequest, BucketResponse, DatabaseDeletionResponse, DatabaseRequest, DatabaseResponse,
     },
     runtime::{self, runtime_client::RuntimeClient},
 };
@@ -44,9 +44,7 @@ pub async fn spawn_runtime(project_path: String, service_name: &str) -> Result<T
     let secrets: HashMap<String, String> = Default::default();
 
     let BuiltService {
-        executable_path,
-        is_wasm,
-        ..
+        executable_path, ..
     } = runtimes[0].clone();
 
     start_provisioner(DummyProvisioner, provisioner_address);
@@ -55,7 +53,6 @@ pub async fn spawn_runtime(project_path: String, service_name: &str) -> Result<T
     let runtime_path = || executable_path.clone();
 
     let (runtime, runtime_client) = runtime::start(
-        is_wasm,
         runtime::StorageManagerType::WorkingDir(PathBuf::from(project_path.clone())),
         &format!("http://{}", provisioner_address),
         None,
@@ -105,4 +102,11 @@ impl Provisioner for DummyProvisioner {
     ) -> Result<Response<DatabaseDeletionResponse>, Status> {
         panic!("did not expect any runtime test to delete dbs")
     }
+
+    async fn provision_bucket(
+        &self,
+        _request: Request<BucketRequest>,
+    ) -> Result<Response<BucketResponse>, Status> {
+        panic!("did not expect any runtime test to use buckets")
+    }
 }
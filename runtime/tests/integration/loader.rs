@@ -32,6 +32,7 @@ async fn bind_panic() {
 
     let start_request = StartRequest {
         ip: runtime_address.to_string(),
+        ..Default::default()
     };
 
     runtime_client
@@ -1,2 +1,6 @@
 pub mod helpers;
 pub mod loader;
+#[cfg(feature = "next")]
+pub mod next_bench;
+#[cfg(feature = "next")]
+pub mod next_resources;
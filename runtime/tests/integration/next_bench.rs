@@ -0,0 +1,168 @@
+//! Measures how fast the shuttle-next runtime loads and serves the axum-wasm-expanded module,
+//! both with instances reused across requests and with a new instance for every request, like the
+//! runtime used to do. It is ignored by default, since the numbers only mean something in release
+//! mode:
+//!
+//! ```sh
+//! cargo test --release -p shuttle-runtime --features next --test integration -- next_bench --ignored --nocapture
+//! ```
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+use hyper::client::HttpConnector;
+use hyper::{Client, StatusCode, Uri};
+use shuttle_common::storage_manager::WorkingDirStorageManager;
+use shuttle_proto::runtime::runtime_server::Runtime;
+use shuttle_proto::runtime::{LoadRequest, StartRequest, SubscribeLogsRequest};
+use shuttle_runtime::{AxumWasm, Limits};
+use shuttle_service::Environment;
+use tokio::net::TcpStream;
+use tonic::transport::Endpoint;
+
+const SEQUENTIAL_REQUESTS: usize = 1000;
+const CONCURRENT_TASKS: usize = 32;
+const REQUESTS_PER_TASK: usize = 250;
+
+fn compile_module() -> PathBuf {
+    let project_path =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/resources/axum-wasm-expanded");
+
+    let status = Command::new("cargo")
+        .arg("build")
+        .arg("--release")
+        .arg("--target")
+        .arg("wasm32-wasi")
+        .current_dir(&project_path)
+        .status()
+        .unwrap();
+    assert!(status.success(), "failed to compile axum-wasm-expanded");
+
+    project_path.join("target/wasm32-wasi/release/shuttle_axum_expanded.wasm")
+}
+
+async fn load(axum: &AxumWasm, path: &Path) -> Duration {
+    let start = Instant::now();
+
+    let response = axum
+        .load(tonic::Request::new(LoadRequest {
+            path: path.display().to_string(),
+            service_name: "axum-wasm-expanded".to_string(),
+            resources: Default::default(),
+            secrets: Default::default(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(response.success, "{}", response.message);
+
+    start.elapsed()
+}
+
+fn new_runtime(path: &Path) -> AxumWasm {
+    AxumWasm::new(
+        Endpoint::from_static("http://localhost:5000"),
+        Arc::new(WorkingDirStorageManager::new(
+            path.parent().unwrap().to_path_buf(),
+        )),
+        Environment::Local,
+        Limits::default(),
+    )
+}
+
+/// Send `count` requests one after the other, and return how many were answered per second
+async fn requests_per_second(client: &Client<HttpConnector>, uri: &Uri, count: usize) -> f64 {
+    let start = Instant::now();
+
+    for _ in 0..count {
+        let response = client.get(uri.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        hyper::body::to_bytes(response.into_body()).await.unwrap();
+    }
+
+    count as f64 / start.elapsed().as_secs_f64()
+}
+
+/// Start serving a loaded module, and measure how many requests it answers per second one after
+/// the other and from concurrent tasks
+async fn serve(axum: &AxumWasm) -> (f64, f64) {
+    // Nothing reads the logs of the module otherwise, which would stall it once the logs channel
+    // is full
+    let mut logs = axum
+        .subscribe_logs(tonic::Request::new(SubscribeLogsRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    tokio::spawn(async move { while logs.next().await.is_some() {} });
+
+    let address = SocketAddr::new(
+        Ipv4Addr::LOCALHOST.into(),
+        portpicker::pick_unused_port().unwrap(),
+    );
+    axum.start(tonic::Request::new(StartRequest {
+        ip: address.to_string(),
+        ..Default::default()
+    }))
+    .await
+    .unwrap();
+
+    while TcpStream::connect(address).await.is_err() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let client = Client::new();
+    let uri: Uri = format!("http://{address}/hello").parse().unwrap();
+
+    // Warm up the connection and the instance pool
+    requests_per_second(&client, &uri, 100).await;
+
+    let sequential = requests_per_second(&client, &uri, SEQUENTIAL_REQUESTS).await;
+
+    let start = Instant::now();
+    let tasks: Vec<_> = (0..CONCURRENT_TASKS)
+        .map(|_| {
+            let client = client.clone();
+            let uri = uri.clone();
+            let task = async move { requests_per_second(&client, &uri, REQUESTS_PER_TASK).await };
+
+            tokio::spawn(task)
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    let concurrent = (CONCURRENT_TASKS * REQUESTS_PER_TASK) as f64 / elapsed;
+
+    (sequential, concurrent)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "benchmark, run it in release mode"]
+async fn next_bench() {
+    let path = compile_module();
+
+    // The first load compiles the module unless an earlier run cached its compilation, and the
+    // ones after it load the cached compilation
+    let cold_load = load(&new_runtime(&path), &path).await;
+
+    let axum = new_runtime(&path);
+    let cached_load = load(&axum, &path).await;
+    let (sequential, concurrent) = serve(&axum).await;
+
+    let baseline = new_runtime(&path).without_instance_reuse();
+    load(&baseline, &path).await;
+    let (baseline_sequential, baseline_concurrent) = serve(&baseline).await;
+
+    println!("first load:         {cold_load:?}");
+    println!("load with cache:    {cached_load:?}");
+    println!("                    reused instances    instance per request");
+    println!("sequential:         {sequential:>10.0} req/s    {baseline_sequential:>10.0} req/s");
+    println!(
+        "{CONCURRENT_TASKS} concurrent:      {concurrent:>10.0} req/s    {baseline_concurrent:>10.0} req/s"
+    );
+}
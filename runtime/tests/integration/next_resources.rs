@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::{Client, StatusCode, Uri};
+use shuttle_common::storage_manager::WorkingDirStorageManager;
+use shuttle_proto::runtime::runtime_server::Runtime;
+use shuttle_proto::runtime::{LoadRequest, StartRequest};
+use shuttle_runtime::{AxumWasm, Limits};
+use shuttle_service::Environment;
+use tokio::net::TcpStream;
+use tonic::transport::Endpoint;
+
+fn compile_module() -> PathBuf {
+    let project_path =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/resources/axum-wasm-secrets");
+
+    let status = Command::new("cargo")
+        .arg("build")
+        .arg("--target")
+        .arg("wasm32-wasi")
+        .current_dir(&project_path)
+        .status()
+        .unwrap();
+    assert!(status.success(), "failed to compile axum-wasm-secrets");
+
+    project_path.join("target/wasm32-wasi/debug/shuttle_axum_secrets.wasm")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn next_secrets() {
+    let path = compile_module();
+
+    // Secrets are answered by the runtime itself, so the provisioner is never reached
+    let axum = AxumWasm::new(
+        Endpoint::from_static("http://localhost:5000"),
+        Arc::new(WorkingDirStorageManager::new(
+            path.parent().unwrap().to_path_buf(),
+        )),
+        Environment::Local,
+        Limits::default(),
+    );
+
+    let response = axum
+        .load(tonic::Request::new(LoadRequest {
+            path: path.display().to_string(),
+            service_name: "axum-wasm-secrets".to_string(),
+            resources: Default::default(),
+            secrets: HashMap::from([("GREETING".to_string(), "Hello, secrets!".to_string())]),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(response.success, "{}", response.message);
+
+    let address = SocketAddr::new(
+        Ipv4Addr::LOCALHOST.into(),
+        portpicker::pick_unused_port().unwrap(),
+    );
+    axum.start(tonic::Request::new(StartRequest {
+        ip: address.to_string(),
+        ..Default::default()
+    }))
+    .await
+    .unwrap();
+
+    while TcpStream::connect(address).await.is_err() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let client = Client::new();
+    let uri: Uri = format!("http://{address}/secret").parse().unwrap();
+
+    // Every request sees the secrets recorded when the module was loaded, whether it is handled
+    // by the instance started while loading or by a later one
+    for _ in 0..3 {
+        let response = client.get(uri.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, "Hello, secrets!");
+    }
+}
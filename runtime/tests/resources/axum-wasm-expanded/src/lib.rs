@@ -6,21 +6,17 @@ use shuttle_next::{
 };
 use tracing::debug;
 
-pub fn handle_request(req: shuttle_next::Request<BoxBody>) -> shuttle_next::response::Response {
-    shuttle_next::block_on(app(req))
-}
-
-async fn app(request: shuttle_next::Request<BoxBody>) -> shuttle_next::response::Response {
-    use shuttle_next::Service;
-
-    let mut router = shuttle_next::Router::new()
+async fn app(
+    _resources: Vec<shuttle_next::Value>,
+) -> Result<shuttle_next::Router<(), BoxBody>, shuttle_next::BoxError> {
+    let router = shuttle_next::Router::new()
         .route("/hello", shuttle_next::routing::get(hello))
         .route("/goodbye", shuttle_next::routing::get(goodbye))
-        .route("/uppercase", shuttle_next::routing::post(uppercase));
-
-    let response = router.call(request).await.unwrap();
+        .route("/uppercase", shuttle_next::routing::post(uppercase))
+        .route("/allocate", shuttle_next::routing::get(allocate))
+        .route("/spin", shuttle_next::routing::get(spin));
 
-    response
+    Ok(router)
 }
 
 async fn hello() -> &'static str {
@@ -45,26 +41,59 @@ async fn uppercase(body: BodyStream) -> impl IntoResponse {
     Response::new(shuttle_next::body::StreamBody::new(chunk_stream))
 }
 
+// Allocate far more memory than the runtime gives a module in its tests.
+async fn allocate() -> String {
+    debug!("in allocate()");
+    let bytes = vec![1u8; 256 * 1024 * 1024];
+    bytes
+        .iter()
+        .map(|byte| *byte as usize)
+        .sum::<usize>()
+        .to_string()
+}
+
+// Keep the module busy for much longer than the runtime gives a request in its tests.
+async fn spin() -> String {
+    debug!("in spin()");
+    let mut count: u64 = 0;
+    while std::hint::black_box(count) != u64::MAX {
+        count += 1;
+    }
+    count.to_string()
+}
+
 #[no_mangle]
 #[allow(non_snake_case)]
-pub extern "C" fn __SHUTTLE_Axum_call(
+pub extern "C" fn __SHUTTLE_Axum_init(
     logs_fd: std::os::wasi::prelude::RawFd,
-    parts_fd: std::os::wasi::prelude::RawFd,
-    body_fd: std::os::wasi::prelude::RawFd,
-) {
-    use shuttle_next::body::{Body, HttpBody};
+    resources_fd: std::os::wasi::prelude::RawFd,
+) -> i32 {
     use shuttle_next::tracing_prelude::*;
     use shuttle_next::Logger;
-    use std::io::{Read, Write};
     use std::os::wasi::io::FromRawFd;
 
-    // file descriptor 2 for writing logs to
+    // file descriptor for writing the logs of the instance, for every request it handles
     let logs_fd = unsafe { std::fs::File::from_raw_fd(logs_fd) };
 
     shuttle_next::tracing_registry()
         .with(Logger::new(logs_fd))
         .init(); // this sets the subscriber as the global default and also adds a compatibility layer for capturing `log::Record`s
 
+    // file descriptor for reading the outputs of the resources recorded at load
+    let resources_fd = unsafe { std::fs::File::from_raw_fd(resources_fd) };
+
+    shuttle_next::init_router(resources_fd, app) as i32
+}
+
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn __SHUTTLE_Axum_call(
+    parts_fd: std::os::wasi::prelude::RawFd,
+    body_fd: std::os::wasi::prelude::RawFd,
+) {
+    use std::io::Write;
+    use std::os::wasi::io::FromRawFd;
+
     // file descriptor 3 for reading and writing http parts
     let mut parts_fd = unsafe { std::fs::File::from_raw_fd(parts_fd) };
 
@@ -73,23 +102,17 @@ pub extern "C" fn __SHUTTLE_Axum_call(
     // deserialize request parts from rust messagepack
     let wrapper: shuttle_next::RequestWrapper = shuttle_next::from_read(reader).unwrap();
 
-    // file descriptor 4 for reading and writing http body
-    let mut body_stream = unsafe { std::fs::File::from_raw_fd(body_fd) };
-
-    let mut reader = std::io::BufReader::new(&mut body_stream);
-    let mut body_buf = Vec::new();
-    reader.read_to_end(&mut body_buf).unwrap();
-
-    let body = Body::from(body_buf);
+    // file descriptor 4 for streaming the http body in and out
+    let body_stream = std::sync::Arc::new(unsafe { std::fs::File::from_raw_fd(body_fd) });
 
     let request = wrapper
         .into_request_builder()
-        .body(shuttle_next::body::boxed(body))
+        .body(shuttle_next::request_body(body_stream.clone()))
         .unwrap();
 
-    let res = handle_request(request);
+    let res = shuttle_next::call_router(request);
 
-    let (parts, mut body) = res.into_parts();
+    let (parts, body) = res.into_parts();
 
     // wrap and serialize response parts as rmp
     let response_parts = shuttle_next::ResponseWrapper::from(parts)
@@ -99,8 +122,9 @@ pub extern "C" fn __SHUTTLE_Axum_call(
     // write response parts
     parts_fd.write_all(&response_parts).unwrap();
 
-    // write body if there is one
-    if let Some(body) = shuttle_next::block_on(body.data()) {
-        body_stream.write_all(body.unwrap().as_ref()).unwrap();
+    // write the body as the endpoint produces it, until the client goes away
+    match shuttle_next::write_body(body, &body_stream) {
+        Err(error) if error.kind() == std::io::ErrorKind::BrokenPipe => {}
+        result => result.unwrap(),
     }
 }
@@ -0,0 +1,8 @@
+use shuttle_secrets::SecretStore;
+
+shuttle_next::app! {
+    #[shuttle_next::endpoint(method = get, route = "/secret")]
+    async fn secret(#[shuttle_secrets::Secrets] secrets: SecretStore) -> String {
+        secrets.get("GREETING").unwrap_or_default()
+    }
+}
@@ -0,0 +1,33 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use shuttle_next::extract::State;
+use shuttle_next::http::{header, HeaderValue};
+use tower_http::set_header::SetResponseHeaderLayer;
+
+#[derive(Clone, Default)]
+struct AppState {
+    requests: Arc<AtomicUsize>,
+}
+
+shuttle_next::app! {
+    #[shuttle_next::app_state]
+    fn state() -> AppState {
+        AppState::default()
+    }
+
+    #[shuttle_next::layer]
+    fn inner() -> SetResponseHeaderLayer<HeaderValue> {
+        SetResponseHeaderLayer::overriding(header::SERVER, HeaderValue::from_static("inner"))
+    }
+
+    #[shuttle_next::layer]
+    async fn outer() -> SetResponseHeaderLayer<HeaderValue> {
+        SetResponseHeaderLayer::overriding(header::SERVER, HeaderValue::from_static("outer"))
+    }
+
+    #[shuttle_next::endpoint(method = get, route = "/count")]
+    async fn count(State(state): State<AppState>) -> String {
+        (state.requests.fetch_add(1, Ordering::Relaxed) + 1).to_string()
+    }
+}
@@ -9,7 +9,8 @@ pub use error::{CustomError, Error};
 
 use serde::{de::DeserializeOwned, Serialize};
 pub use shuttle_common::{
-    database, resource::Type, DatabaseReadyInfo, DbInput, DbOutput, SecretStore,
+    database, resource::Type, secrets, BucketReadyInfo, DatabaseReadyInfo, DbInput, DbOutput,
+    SecretStore,
 };
 
 #[cfg(feature = "codegen")]
@@ -85,6 +86,13 @@ pub trait Factory: Send + Sync {
         db_type: database::Type,
     ) -> Result<DatabaseReadyInfo, crate::Error>;
 
+    /// Get a bucket on the object storage, along with credentials which can only reach that bucket
+    ///
+    /// Factories which cannot provision buckets do not have to implement this.
+    async fn get_bucket(&mut self) -> Result<BucketReadyInfo, crate::Error> {
+        Err(CustomError::msg("this factory cannot provision buckets").into())
+    }
+
     /// Get all the secrets for a service
     async fn get_secrets(&mut self) -> Result<BTreeMap<String, String>, crate::Error>;
 
@@ -0,0 +1,236 @@
+//! Shuttle service integration for scheduled jobs.
+//!
+//! The job is run on the given [cron](https://docs.rs/cron) schedule, which has a field for the
+//! seconds, minutes, hours, day of the month, month, day of the week and an optional year. A
+//! run which is still going when the next one is due causes that next run to be skipped.
+//! ## Example
+//! ```rust,no_run
+//! use shuttle_runtime::Error;
+//! use tracing::info;
+//!
+//! async fn send_report() -> Result<(), Error> {
+//!     info!("sending the daily report");
+//!
+//!     Ok(())
+//! }
+//!
+//! #[shuttle_runtime::main]
+//! async fn cron() -> shuttle_cron::ShuttleCron {
+//!     // Every day at 09:00 UTC
+//!     let service = shuttle_cron::CronService::new("0 0 9 * * *", send_report)?;
+//!
+//!     Ok(service)
+//! }
+//! ```
+use std::{future::Future, net::SocketAddr, pin::Pin, str::FromStr, sync::Arc, time::Instant};
+
+use chrono::{SecondsFormat, Utc};
+use cron::Schedule;
+use shuttle_runtime::{CustomError, Error};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+type Job = Arc<dyn Fn() -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> + Send + Sync>;
+
+/// A job with the schedule it should run on, so we can implement [shuttle_runtime::Service] for it.
+pub struct CronService {
+    schedule: Schedule,
+    job: Job,
+}
+
+impl CronService {
+    /// Create a service which runs `job` on the cron `schedule`
+    pub fn new<F, Fut>(schedule: &str, job: F) -> Result<Self, Error>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        let schedule = Schedule::from_str(schedule)
+            .map_err(|error| CustomError::msg(format!("invalid cron schedule: {error}")))?;
+
+        Ok(Self {
+            schedule,
+            job: Arc::new(move || Box::pin(job())),
+        })
+    }
+}
+
+#[shuttle_runtime::async_trait]
+impl shuttle_runtime::Service for CronService {
+    /// Takes the job that is returned by the user in their [shuttle_runtime::main] function
+    /// and runs it on its schedule.
+    async fn bind(self, _addr: SocketAddr) -> Result<(), Error> {
+        let mut previous_run: Option<JoinHandle<()>> = None;
+        let mut last_due = Utc::now();
+
+        loop {
+            // Never schedule a run twice, even when the clock is slightly behind the timer
+            let Some(next_run) = self.schedule.after(&last_due.max(Utc::now())).next() else {
+                info!(cron = "exhausted", "schedule has no more runs");
+
+                return Ok(());
+            };
+
+            info!(
+                cron = "scheduled",
+                next_run = %next_run.to_rfc3339_opts(SecondsFormat::Secs, true),
+                "next run is scheduled"
+            );
+
+            // A time in the past means the run is due already
+            let wait = (next_run - Utc::now()).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+            last_due = next_run;
+
+            if previous_run
+                .as_ref()
+                .map_or(false, |previous| !previous.is_finished())
+            {
+                warn!(
+                    cron = "skipped",
+                    "previous run is still going, skipping this run"
+                );
+
+                continue;
+            }
+
+            previous_run = Some(tokio::spawn(run(self.job.clone())));
+        }
+    }
+}
+
+/// Run the job once and log its outcome
+async fn run(job: Job) {
+    info!(cron = "started", "run started");
+
+    let start = Instant::now();
+    // Run the job on its own task so that a panic in the job can be reported as its outcome
+    let result = tokio::spawn(job()).await;
+    let duration_ms = start.elapsed().as_millis() as u64;
+
+    match result {
+        Ok(Ok(())) => info!(
+            cron = "finished",
+            outcome = "success",
+            duration_ms,
+            "run finished"
+        ),
+        Ok(Err(error)) => error!(
+            cron = "finished",
+            outcome = "failure",
+            duration_ms,
+            error = %error,
+            "run failed"
+        ),
+        Err(error) => error!(
+            cron = "finished",
+            outcome = "panic",
+            duration_ms,
+            error = %error,
+            "run panicked"
+        ),
+    }
+}
+
+/// The return type that should be returned from the [shuttle_runtime::main] function.
+pub type ShuttleCron = Result<CronService, Error>;
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use shuttle_runtime::{CustomError, Service};
+
+    use super::CronService;
+
+    /// Run the service for a while, and get how many times its job was started
+    async fn runs_within(service: CronService, runs: Arc<AtomicUsize>, within: Duration) -> usize {
+        let address = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 8000);
+        let handle = tokio::spawn(service.bind(address));
+
+        tokio::time::sleep(within).await;
+        handle.abort();
+
+        runs.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn invalid_schedule() {
+        assert!(CronService::new("every day", || async { Ok(()) }).is_err());
+        assert!(CronService::new("0 0 9 * * *", || async { Ok(()) }).is_ok());
+    }
+
+    #[tokio::test]
+    async fn runs_on_schedule() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let job_runs = runs.clone();
+        let service = CronService::new("* * * * * *", move || {
+            let runs = job_runs.clone();
+
+            async move {
+                runs.fetch_add(1, Ordering::SeqCst);
+
+                Ok(())
+            }
+        })
+        .unwrap();
+
+        let runs = runs_within(service, runs, Duration::from_millis(3500)).await;
+        assert!((2..=4).contains(&runs), "ran {runs} times in 3.5 seconds");
+    }
+
+    #[tokio::test]
+    async fn skips_overlapping_runs() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let job_runs = runs.clone();
+        let service = CronService::new("* * * * * *", move || {
+            let runs = job_runs.clone();
+
+            async move {
+                runs.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(2500)).await;
+
+                Ok(())
+            }
+        })
+        .unwrap();
+
+        // A run takes up the two next seconds after it starts, so at most two runs fit
+        let runs = runs_within(service, runs, Duration::from_millis(4500)).await;
+        assert!(
+            (1..=2).contains(&runs),
+            "overlapping runs should be skipped, but ran {runs} times"
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_running_after_failed_runs() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let job_runs = runs.clone();
+        let service = CronService::new("* * * * * *", move || {
+            let runs = job_runs.clone();
+
+            async move {
+                if runs.fetch_add(1, Ordering::SeqCst) % 2 == 0 {
+                    Err(CustomError::msg("job failed").into())
+                } else {
+                    panic!("job panicked")
+                }
+            }
+        })
+        .unwrap();
+
+        let runs = runs_within(service, runs, Duration::from_millis(3500)).await;
+        assert!(
+            runs >= 2,
+            "the job should run again after failing, but ran {runs} times"
+        );
+    }
+}
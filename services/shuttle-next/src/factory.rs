@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use shuttle_common::wasm::{FactoryRequest, FactoryResponse};
+use shuttle_service::{
+    database, BucketReadyInfo, CustomError, DatabaseReadyInfo, Environment, Error, Factory,
+    ResourceBuilder, ServiceName, Type,
+};
+
+/// A factory which asks the shuttle-next runtime to provision resources, over the file
+/// descriptor it gives to the module while loading it
+pub struct WasiFactory {
+    stream: File,
+    service_name: ServiceName,
+    environment: Environment,
+}
+
+impl WasiFactory {
+    /// Ask the runtime for the service name and environment upfront, since the factory cannot
+    /// fail to give those once it exists
+    pub fn new(stream: File) -> Result<Self, Error> {
+        let service_name = match request(&stream, FactoryRequest::ServiceName)? {
+            FactoryResponse::ServiceName(name) => ServiceName::from_str(&name)
+                .map_err(|error| CustomError::new(error).context("invalid service name"))?,
+            response => return Err(unexpected(response)),
+        };
+
+        let environment = match request(&stream, FactoryRequest::Environment)? {
+            FactoryResponse::Environment(environment) => environment,
+            response => return Err(unexpected(response)),
+        };
+
+        Ok(Self {
+            stream,
+            service_name,
+            environment,
+        })
+    }
+
+    fn request(&self, request: FactoryRequest) -> Result<FactoryResponse, Error> {
+        self::request(&self.stream, request)
+    }
+
+    fn get_cached_output(&self, r#type: Type, config: Value) -> Result<Option<Value>, Error> {
+        match self.request(FactoryRequest::CachedOutput { r#type, config })? {
+            FactoryResponse::CachedOutput(output) => Ok(output),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    fn record_resource(&self, r#type: Type, config: Value, output: Value) -> Result<(), Error> {
+        match self.request(FactoryRequest::RecordResource {
+            r#type,
+            config,
+            output,
+        })? {
+            FactoryResponse::Recorded => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+}
+
+fn request(mut stream: &File, request: FactoryRequest) -> Result<FactoryResponse, Error> {
+    let request = request
+        .into_rmp()
+        .map_err(|error| CustomError::new(error).context("failed to serialize request"))?;
+    stream.write_all(&request)?;
+
+    let response = rmp_serde::from_read(stream)
+        .map_err(|error| CustomError::new(error).context("failed to read response"))?;
+
+    match response {
+        FactoryResponse::Error(error) => Err(CustomError::msg(error).into()),
+        response => Ok(response),
+    }
+}
+
+fn unexpected(response: FactoryResponse) -> Error {
+    CustomError::msg(format!(
+        "unexpected response from the runtime: {response:?}"
+    ))
+    .into()
+}
+
+#[async_trait]
+impl Factory for WasiFactory {
+    async fn get_db_connection(
+        &mut self,
+        db_type: database::Type,
+    ) -> Result<DatabaseReadyInfo, Error> {
+        match self.request(FactoryRequest::DbConnection(db_type))? {
+            FactoryResponse::DbConnection(info) => Ok(info),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    async fn get_bucket(&mut self) -> Result<BucketReadyInfo, Error> {
+        match self.request(FactoryRequest::Bucket)? {
+            FactoryResponse::Bucket(info) => Ok(info),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    async fn get_secrets(&mut self) -> Result<BTreeMap<String, String>, Error> {
+        match self.request(FactoryRequest::Secrets)? {
+            FactoryResponse::Secrets(secrets) => Ok(secrets),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    fn get_service_name(&self) -> ServiceName {
+        self.service_name.clone()
+    }
+
+    fn get_environment(&self) -> Environment {
+        self.environment
+    }
+
+    fn get_build_path(&self) -> Result<PathBuf, Error> {
+        Err(CustomError::msg("shuttle-next services have no build path").into())
+    }
+
+    fn get_storage_path(&self) -> Result<PathBuf, Error> {
+        Err(CustomError::msg("shuttle-next services have no storage path").into())
+    }
+}
+
+/// Provision a resource while the module is being loaded, and have the runtime record its output.
+///
+/// This function is called by the codegen for each resource the endpoints need.
+pub async fn get_resource<B, T, O>(builder: B, factory: &mut WasiFactory) -> Result<(), Error>
+where
+    B: ResourceBuilder<T, Output = O>,
+    O: Serialize + DeserializeOwned,
+{
+    let config = serde_json::to_value(builder.config()).map_err(|error| {
+        CustomError::new(error).context("failed to turn builder config into a value")
+    })?;
+
+    // Outputs from the past which no longer fit the builder are provisioned again
+    let cached_output = factory
+        .get_cached_output(B::TYPE, config.clone())?
+        .and_then(|output| serde_json::from_value::<O>(output).ok());
+
+    let output = match cached_output {
+        Some(output) => output,
+        None => builder.output(factory).await?,
+    };
+
+    let output = serde_json::to_value(&output).map_err(|error| {
+        CustomError::new(error).context("failed to turn builder output into a value")
+    })?;
+
+    factory.record_resource(B::TYPE, config, output)
+}
+
+/// Build a resource from the output the runtime recorded for it when the module was loaded.
+///
+/// This function is called by the codegen when an instance of the module builds its router, for
+/// each resource the endpoints need.
+pub async fn build_resource<B, T>(output: Option<Value>) -> Result<T, Error>
+where
+    B: ResourceBuilder<T>,
+{
+    let output = output.ok_or_else(|| CustomError::msg("the runtime sent no output for it"))?;
+    let output: B::Output = serde_json::from_value(output)
+        .map_err(|error| CustomError::new(error).context("failed to read builder output"))?;
+
+    B::build(&output).await
+}
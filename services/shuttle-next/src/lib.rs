@@ -1,8 +1,17 @@
+mod factory;
+mod router;
+mod stream;
+
 pub use axum::*;
+pub use factory::{build_resource, get_resource, WasiFactory};
 pub use futures_executor::block_on;
 pub use http::Request;
 pub use rmp_serde::from_read;
+pub use router::{call_router, init_router};
+pub use serde_json::Value;
 pub use shuttle_codegen::app;
 pub use shuttle_common::wasm::{Logger, RequestWrapper, ResponseWrapper};
+pub use shuttle_service::ResourceBuilder;
+pub use stream::{request_body, write_body};
 pub use tower_service::Service;
 pub use tracing_subscriber::{prelude as tracing_prelude, registry as tracing_registry};
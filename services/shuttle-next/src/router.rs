@@ -0,0 +1,62 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::future::Future;
+
+use axum::body::BoxBody;
+use axum::http::{Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{BoxError, Router};
+use futures_executor::block_on;
+use serde_json::Value;
+use tower_service::Service;
+use tracing::error;
+
+thread_local! {
+    /// The router of this instance of the module, which handles every request sent to the instance
+    static ROUTER: RefCell<Option<Router<(), BoxBody>>> = RefCell::new(None);
+}
+
+/// Build the router of this instance from the outputs of the resources the runtime recorded when
+/// it loaded the module. The runtime starts an instance once and sends it many requests, so the
+/// resources, state and layers of the router are only built once per instance. Returns whether
+/// the router could be built.
+///
+/// This function is called by the codegen when the runtime starts an instance of the module.
+pub fn init_router<F, Fut>(mut resources: File, app: F) -> bool
+where
+    F: FnOnce(Vec<Value>) -> Fut,
+    Fut: Future<Output = Result<Router<(), BoxBody>, BoxError>>,
+{
+    let router = rmp_serde::from_read(&mut resources)
+        .map_err(BoxError::from)
+        .and_then(|resources| block_on(app(resources)));
+
+    match router {
+        Ok(router) => {
+            ROUTER.with(|cell| *cell.borrow_mut() = Some(router));
+
+            true
+        }
+        Err(error) => {
+            error!(%error, "failed to build the router of the app");
+
+            false
+        }
+    }
+}
+
+/// Route a request with the router of this instance.
+///
+/// This function is called by the codegen for every request the runtime sends to the instance.
+pub fn call_router(request: Request<BoxBody>) -> Response {
+    let Some(mut router) = ROUTER.with(|cell| cell.borrow().clone()) else {
+        error!("the router of this instance was never built");
+
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    match block_on(router.call(request)) {
+        Ok(response) => response,
+        Err(infallible) => match infallible {},
+    }
+}
@@ -0,0 +1,84 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use axum::body::{boxed, BoxBody, Bytes, HttpBody, StreamBody};
+use futures_executor::block_on;
+
+/// Turn the body file descriptor into a request body, which reads the body from the runtime one
+/// frame at a time as the endpoint asks for it.
+///
+/// Every frame is a chunk of the body prefixed with its length as a big-endian `u64`, and the
+/// body ends with a frame of length zero. A body cut off by the runtime, such as one which went
+/// over the body limit, closes the stream before that last frame and ends with an error, so an
+/// endpoint cannot mistake it for the whole body.
+///
+/// This function is called by the codegen, which writes the response body to the same file
+/// descriptor with [write_body].
+pub fn request_body(stream: Arc<File>) -> BoxBody {
+    let mut ended = false;
+    let chunks = std::iter::from_fn(move || {
+        if ended {
+            return None;
+        }
+
+        match read_frame(&stream) {
+            Ok(Some(chunk)) => Some(Ok(chunk)),
+            Ok(None) => {
+                ended = true;
+                None
+            }
+            Err(error) => {
+                ended = true;
+                Some(Err(error))
+            }
+        }
+    });
+
+    boxed(StreamBody::new(futures_util::stream::iter(chunks)))
+}
+
+/// Read the next frame of a request body, which is `None` for the frame ending the body
+fn read_frame(mut stream: &File) -> io::Result<Option<Bytes>> {
+    let mut len = [0; 8];
+    stream.read_exact(&mut len).map_err(cut_off)?;
+
+    let len = u64::from_be_bytes(len);
+    if len == 0 {
+        return Ok(None);
+    }
+
+    let mut chunk = vec![0; len as usize];
+    stream.read_exact(&mut chunk).map_err(cut_off)?;
+
+    Ok(Some(Bytes::from(chunk)))
+}
+
+fn cut_off(error: io::Error) -> io::Error {
+    match error.kind() {
+        io::ErrorKind::UnexpectedEof => io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "request body was cut off before it ended",
+        ),
+        _ => error,
+    }
+}
+
+/// Write a response body to the runtime chunk by chunk, so the runtime can pass each chunk on as
+/// soon as the endpoint produces it.
+pub fn write_body<B>(mut body: B, stream: &File) -> io::Result<()>
+where
+    B: HttpBody<Data = Bytes> + Unpin,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    let mut stream = stream;
+
+    while let Some(chunk) = block_on(body.data()) {
+        let chunk = chunk.map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+
+        stream.write_all(&chunk)?;
+        stream.flush()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,119 @@
+//! Shuttle service integration for the Tonic gRPC framework.
+//! ## Example
+//! ```rust,no_run
+//! use tonic::transport::Server;
+//! use tonic_health::server::health_reporter;
+//! use tonic_health::ServingStatus;
+//!
+//! #[shuttle_runtime::main]
+//! async fn tonic() -> shuttle_tonic::ShuttleTonic {
+//!     let (mut reporter, health_service) = health_reporter();
+//!     reporter.set_service_status("", ServingStatus::Serving).await;
+//!
+//!     let router = Server::builder().add_service(health_service);
+//!
+//!     Ok(router.into())
+//! }
+//! ```
+use tonic::transport::server::Router;
+
+/// A wrapper type for [tonic::transport::server::Router] so we can implement
+/// [shuttle_runtime::Service] for it.
+pub struct TonicService(pub Router);
+
+#[shuttle_runtime::async_trait]
+impl shuttle_runtime::Service for TonicService {
+    async fn bind(mut self, addr: std::net::SocketAddr) -> Result<(), shuttle_runtime::Error> {
+        self.0
+            .serve(addr)
+            .await
+            .map_err(shuttle_runtime::CustomError::new)?;
+
+        Ok(())
+    }
+}
+
+impl From<Router> for TonicService {
+    fn from(router: Router) -> Self {
+        Self(router)
+    }
+}
+
+/// The return type that should be returned from the [shuttle_runtime::main] function.
+pub type ShuttleTonic = Result<TonicService, shuttle_runtime::Error>;
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, TcpListener};
+    use std::time::Duration;
+
+    use shuttle_runtime::Service;
+    use tonic::transport::{Channel, Server};
+    use tonic::Code;
+    use tonic_health::proto::health_check_response::ServingStatus as ResponseStatus;
+    use tonic_health::proto::health_client::HealthClient;
+    use tonic_health::proto::HealthCheckRequest;
+    use tonic_health::server::health_reporter;
+    use tonic_health::ServingStatus;
+
+    use super::TonicService;
+
+    fn free_address() -> SocketAddr {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    async fn connect(address: SocketAddr) -> Channel {
+        let endpoint = Channel::from_shared(format!("http://{address}")).unwrap();
+
+        loop {
+            match endpoint.connect().await {
+                Ok(channel) => return channel,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_grpc() {
+        let (mut reporter, health_service) = health_reporter();
+        reporter
+            .set_service_status("", ServingStatus::Serving)
+            .await;
+
+        let service: TonicService = Server::builder().add_service(health_service).into();
+        let address = free_address();
+        tokio::spawn(service.bind(address));
+
+        let mut client = HealthClient::new(connect(address).await);
+
+        let response = client
+            .check(HealthCheckRequest {
+                service: String::new(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.into_inner().status, ResponseStatus::Serving as i32);
+
+        // The status of a call comes in the trailers of its response
+        let status = client
+            .check(HealthCheckRequest {
+                service: "unknown".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn bind_error() {
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (_, health_service) = health_reporter();
+
+        let service: TonicService = Server::builder().add_service(health_service).into();
+
+        assert!(service.bind(taken.local_addr().unwrap()).await.is_err());
+    }
+}